@@ -1,9 +1,16 @@
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use clap::command;
-use clap::{Parser, Subcommand};
+use clap::builder::FalseyValueParser;
+use clap::{ArgAction, Command, CommandFactory, Parser, Subcommand};
 
 use edpg::chunk_type::ChunkType;
+use edpg::payload;
+
+use crate::render::Format;
+use crate::shred;
 
 #[derive(Parser)]
 #[command(
@@ -12,50 +19,1204 @@ use edpg::chunk_type::ChunkType;
 	about = "Hide secret information in .png",
 	long_about = "A cli for encoding, decoding, and managing PNG metadata"
 )]
-
 pub struct Cli {
 	#[arg(short, long, action = clap::ArgAction::Count)]
 	debug: u8,
 
+	/// Give up on any file access that takes longer than this many seconds.
+	#[arg(long, global = true, value_name = "SECONDS", value_parser = parse_seconds)]
+	pub timeout: Option<Duration>,
+
+	/// If the input fails to parse, write a minimized copy of it (failing
+	/// chunk plus context, pixel data zeroed) here for bug reports.
+	#[arg(long, global = true, value_name = "PATH")]
+	pub emit_repro: Option<PathBuf>,
+
+	/// Don't check chunk crcs while parsing, faster for bulk scans that
+	/// don't care about integrity.
+	#[arg(long, global = true)]
+	pub no_verify_crc: bool,
+
+	/// Look this many bytes past the start for the png signature, for files
+	/// some tool put a BOM or HTTP headers in front of. The junk is reported
+	/// by `check` and `guard`, and dropped from rewritten files.
+	#[arg(long, global = true, value_name = "BYTES", default_value_t = 0)]
+	pub max_prefix: usize,
+
+	/// Let rewritten files take fresh timestamps and default permissions,
+	/// instead of keeping those of the file they replace.
+	#[arg(long, global = true)]
+	pub no_preserve_metadata: bool,
+
+	/// Give every written png the access and modification times of this
+	/// file, eg: to blend in with the rest of a directory.
+	#[arg(long, global = true, value_name = "REFERENCE")]
+	pub match_timestamps: Option<PathBuf>,
+
+	/// Which image of an ICO/ICNS icon to work on, counted from 0 in file
+	/// order. Defaults to the first one stored as a png.
+	#[arg(long, global = true, value_name = "N")]
+	pub inner_index: Option<usize>,
+
+	/// Work on `.png.gz` files and zips holding a single png as they are,
+	/// instead of unpacking them and packing the result again.
+	#[arg(long, global = true)]
+	pub no_unwrap: bool,
+
+	/// Also look for the tool signatures in this TOML file, see
+	/// `edpg::signature`, on top of the builtin ones.
+	#[arg(long, global = true, value_name = "SIGNATURES.toml", conflicts_with = "cache")]
+	pub signatures: Option<PathBuf>,
+
+	/// Let `check` answer from the results of earlier runs for files whose
+	/// size and modification time are unchanged, see `vanish cache`.
+	#[arg(long, global = true)]
+	pub cache: bool,
+
+	/// Where the file list for `-` is read from, a path or `-` for stdin.
+	/// Avoids argument length limits on very large batches.
+	#[arg(long, global = true, value_name = "PATH", default_value = "-")]
+	pub files_from: PathBuf,
+
+	/// Paths of the file list are separated by NUL instead of newlines,
+	/// eg: for `find -print0`. Detected on its own when the list has a NUL
+	/// early on.
+	#[arg(long, short = '0', global = true)]
+	pub null: bool,
+
+	/// Stop a batch at the first file that fails.
+	#[arg(long, global = true, conflicts_with = "keep_going")]
+	pub fail_fast: bool,
+
+	/// Keep processing a batch when a file fails, the default.
+	#[arg(long, global = true)]
+	pub keep_going: bool,
+
+	/// Write a JSON summary of the batch (status and error of every file) to
+	/// this path, `-` for stdout.
+	#[arg(long, global = true, value_name = "PATH")]
+	pub summary: Option<PathBuf>,
+
+	/// How `check` and `guard` print what they found. All but text print
+	/// once every file is done.
+	#[arg(long, global = true, value_enum, default_value_t)]
+	pub format: Format,
+
+	/// Write what `check` and `guard` found as SARIF to this path, `-` for
+	/// stdout, for code scanning dashboards.
+	#[arg(long, global = true, value_name = "PATH")]
+	pub sarif: Option<PathBuf>,
+
+	/// Checkpoint batch progress to this file, files it lists as done are
+	/// skipped so an interrupted run picks up where it left off.
+	#[arg(long, global = true, value_name = "STATE.json")]
+	pub resume_from: Option<PathBuf>,
+
+	/// Process at most this many files per second in a batch, to go easy on
+	/// network filesystems.
+	#[arg(long, global = true, value_name = "FILES/SEC", value_parser = parse_rate)]
+	pub rate: Option<f64>,
+
+	/// Append a JSON line per command (time, file hashes before and after,
+	/// chunk types touched) to this file.
+	#[arg(long, global = true, value_name = "PATH")]
+	pub audit_log: Option<PathBuf>,
+
+	/// Shell command run after a successful encode. Gets
+	/// VANISH_HOOK_OPERATION, VANISH_HOOK_FILE, VANISH_HOOK_OUTPUT_FILE,
+	/// VANISH_HOOK_CHUNK_TYPE and VANISH_HOOK_PAYLOAD_SIZE in its environment.
+	#[arg(long, global = true, value_name = "CMD")]
+	pub post_encode_hook: Option<String>,
+
+	/// Shell command run after a successful decode, same environment as
+	/// `--post-encode-hook`.
+	#[arg(long, global = true, value_name = "CMD")]
+	pub post_decode_hook: Option<String>,
+
+	/// Shell command run after a successful remove, same environment as
+	/// `--post-encode-hook`.
+	#[arg(long, global = true, value_name = "CMD")]
+	pub post_remove_hook: Option<String>,
+
+	/// Uppercase the third letter of chunk types given in lowercase, eg:
+	/// `rust` becomes `ruSt`. The png spec reserves it, pngs with it
+	/// lowercase don't parse.
+	#[arg(long, global = true)]
+	pub auto_correct: bool,
+
+	/// Go ahead without asking before overwriting or destroying files.
+	#[arg(long, short, global = true)]
+	pub yes: bool,
+
+	/// Never ask anything, fail instead where a confirmation is needed, for
+	/// scripts. Without it, a stdin that isn't a terminal means `--yes`.
+	#[arg(long, global = true)]
+	pub no_input: bool,
+
+	/// Forensic mode: guarantee nothing is written anywhere. Commands that
+	/// change or create files are refused, so are the options that write on
+	/// the side, files are only opened for reading and their access times
+	/// are left alone where possible. Output goes to stdout.
+	#[arg(
+		long,
+		global = true,
+		conflicts_with_all = [
+			"cache",
+			"audit_log",
+			"emit_repro",
+			"resume_from",
+			"match_timestamps",
+			"post_encode_hook",
+			"post_decode_hook",
+			"post_remove_hook",
+		]
+	)]
+	pub read_only: bool,
+
+	/// Append what was run, when, and the sha256 of every file it read to
+	/// this log, a JSON line per run. `vanish custody verify` checks the
+	/// files against it later.
+	#[arg(long, global = true, value_name = "PATH", requires = "read_only")]
+	pub custody_log: Option<PathBuf>,
+
 	#[command(subcommand)]
 	pub command: Commands,
 }
 
 // Help me figure out how to avoid repeated documentation here.
 
-#[derive(Subcommand)]
+#[derive(Subcommand, Clone)]
 pub enum Commands {
 	/// Encode data in a png.
 	/// `chunk_type` double as label to refer the hidden data.
+	#[command(visible_alias = "enc")]
 	Encode {
-		/// Accepts a valid .png file.
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		/// With `--carrier-url` this is where the result is written instead.
 		file: PathBuf,
 		/// Accepts an exact 4byte ASCII(alphabetic only) sequence. eg: [rust, bOAT].
 		chunk_type: ChunkType,
 		/// The data you want to hide.
-		message: String,
+		#[arg(required_unless_present = "input_file", conflicts_with = "input_file")]
+		message: Option<String>,
+		/// Optionally a output path to store the new encoded png.
+		output_file: Option<PathBuf>,
+		/// Hide the contents of this file instead of `message`.
+		#[arg(long, value_name = "PATH")]
+		input_file: Option<PathBuf>,
+		/// Overwrite and delete `--input-file` once it is hidden, see `shred`.
+		#[arg(long, requires = "input_file")]
+		shred_source: bool,
+		/// Encrypt the data with `--passphrase` first, see `edpg::crypto`.
+		/// Without it anyone can read the message back.
+		#[arg(long, requires = "passphrase")]
+		encrypt: bool,
+		/// Passphrase the key is derived from, needs `--encrypt`.
+		#[arg(long, requires = "encrypt")]
+		passphrase: Option<String>,
+		/// Also ask for a time based one time password on decode, as
+		/// `lock-chunk --totp`. Prints the secret to add to an authenticator
+		/// app. No extra protection: the passphrase alone decrypts the data
+		/// with other tools.
+		#[arg(long, requires = "encrypt")]
+		totp: bool,
+		/// Let editors keep the chunk when they modify the image, the
+		/// default. Lowercases the fourth letter of `chunk_type`.
+		#[arg(long, overrides_with = "not_safe_to_copy")]
+		safe_to_copy: bool,
+		/// Have editors drop the chunk when they modify the image.
+		/// Uppercases the fourth letter of `chunk_type`.
+		#[arg(long, overrides_with = "safe_to_copy")]
+		not_safe_to_copy: bool,
+		/// Where to hide the data. With `lsb` no chunk is added, `chunk_type`
+		/// is only passed on to hooks.
+		#[arg(long, value_enum, default_value_t)]
+		mode: EmbedMode,
+		/// Write a standard text chunk under this keyword, readable by other
+		/// tools. `chunk_type` has to be tEXt, zTXt or iTXt, see `edpg::text`.
+		#[arg(long, value_name = "KEYWORD", conflicts_with_all = ["encrypt", "mode"])]
+		text: Option<String>,
+		/// Spread the data over the pixels of `file` and these pngs, in that
+		/// order, eg: `--spread frames/*.png`. Implies `--mode lsb`, every
+		/// image is written in place.
+		#[arg(long, value_name = "FRAMES", num_args = 1.., conflicts_with_all = ["output_file", "text"])]
+		spread: Vec<PathBuf>,
+		/// Keep the xor of the other pieces in the last image, so `decode
+		/// --spread` can do without any one of them.
+		#[arg(long, requires = "spread")]
+		parity: bool,
+		/// Download the carrier png from this url instead of reading `file`.
+		#[cfg(feature = "http")]
+		#[arg(long, value_name = "URL")]
+		carrier_url: Option<String>,
+		/// Take the carrier from the image on the clipboard, eg: a
+		/// screenshot, and copy the result back. Clipboards only keep pixels,
+		/// so this implies `--mode lsb`, and `file` is where a copy of the
+		/// result is written.
+		#[cfg(feature = "clipboard")]
+		#[arg(long, conflicts_with_all = ["spread", "text"])]
+		#[cfg_attr(feature = "http", arg(conflicts_with = "carrier_url"))]
+		from_screen: bool,
+	},
+	/// Hide any file in a png, split over as many `chunk_type` chunks as it
+	/// needs, its name, size and checksum kept alongside for `extract`.
+	EncodeFile {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Accepts an exact 4byte ASCII(alphabetic only) sequence. eg: [rust, bOAT].
+		chunk_type: ChunkType,
+		/// The file you want to hide.
+		payload_path: PathBuf,
 		/// Optionally a output path to store the new encoded png.
 		output_file: Option<PathBuf>,
+		/// Most payload bytes per chunk.
+		#[arg(long, value_name = "BYTES", default_value_t = payload::DEFAULT_CHUNK_DATA_BYTES)]
+		chunk_size: usize,
+		/// Compress the file with zstd first, see `edpg::compress`.
+		#[cfg(feature = "zstd")]
+		#[arg(long)]
+		compress: bool,
+		/// Compress against this dictionary, made by `dict train` out of
+		/// files like this one. `extract` needs it too.
+		#[cfg(feature = "zstd")]
+		#[arg(long, value_name = "DICT", requires = "compress")]
+		dict: Option<PathBuf>,
+	},
+	/// Hide a message in a png and write an email with it attached, ready to
+	/// send. `file` itself is left as it is.
+	MailWrap {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Accepts an exact 4byte ASCII(alphabetic only) sequence. eg: [rust, bOAT].
+		chunk_type: ChunkType,
+		/// The data you want to hide.
+		#[arg(required_unless_present = "input_file", conflicts_with = "input_file")]
+		message: Option<String>,
+		/// Hide the contents of this file instead of `message`.
+		#[arg(long, value_name = "PATH")]
+		input_file: Option<PathBuf>,
+		/// Encrypt the data with `--passphrase` first, see `encode --encrypt`.
+		#[arg(long, requires = "passphrase")]
+		encrypt: bool,
+		/// Passphrase the key is derived from, needs `--encrypt`.
+		#[arg(long, requires = "encrypt")]
+		passphrase: Option<String>,
+		/// Recipient, repeat it for more.
+		#[arg(long, value_name = "ADDR", required = true)]
+		to: Vec<String>,
+		/// Sender, left for the mail client to fill in without it.
+		#[arg(long, value_name = "ADDR")]
+		from: Option<String>,
+		#[arg(long, default_value = "")]
+		subject: String,
+		/// Text of the email.
+		#[arg(long, default_value = "")]
+		body: String,
+		/// Where to write the .eml, `-` for stdout. Defaults to `file` with
+		/// an .eml extension.
+		#[arg(short, long)]
+		output_file: Option<PathBuf>,
+		/// Sign the email with S/MIME, this certificate (PEM) and
+		/// `--sign-key`. Needs `openssl` on the PATH.
+		#[arg(long, value_name = "PATH", requires = "sign_key")]
+		sign_cert: Option<PathBuf>,
+		/// Private key (PEM) of `--sign-cert`.
+		#[arg(long, value_name = "PATH", requires = "sign_cert")]
+		sign_key: Option<PathBuf>,
+	},
+	/// Get back a file hidden with `encode-file`, checked against its size
+	/// and checksum.
+	#[command(visible_alias = "x")]
+	Extract {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Accepts an exact 4byte ASCII(alphabetic only) sequence. eg: [rust, bOAT].
+		chunk_type: String,
+		/// Where to write the file, `-` for stdout. Defaults to the name it was
+		/// hidden under, in the current directory.
+		#[arg(short, long)]
+		output_file: Option<PathBuf>,
+		/// Dictionary the file was compressed with, see `encode-file --dict`.
+		#[cfg(feature = "zstd")]
+		#[arg(long, value_name = "DICT")]
+		dict: Option<PathBuf>,
+		/// Threads to checksum the chunks on, all cores by default.
+		#[arg(long, short)]
+		jobs: Option<NonZeroUsize>,
 	},
 	/// Encode data in a png.
 	/// use `chunk_type` to refer to the hidden message.
+	#[command(visible_alias = "dec")]
 	Decode {
-		/// Accepts a valid .png file.
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
 		file: PathBuf,
 		/// Accepts an exact 4byte ASCII(alphabetic only) sequence. eg: [rust, bOAT].
 		chunk_type: String,
+		/// Passphrase the message was encrypted with, see `encode --encrypt`.
+		#[arg(long)]
+		passphrase: Option<String>,
+		/// Current 6 digit code, needed if the message was encoded with
+		/// `--totp`.
+		#[arg(long, requires = "passphrase")]
+		totp_code: Option<u32>,
+		/// Where the data was hidden, see `encode --mode`.
+		#[arg(long, value_enum, default_value_t)]
+		mode: EmbedMode,
+		/// The text chunk with this keyword rather than the first one. Text
+		/// chunks are printed as `keyword: text` either way.
+		#[arg(long, value_name = "KEYWORD", conflicts_with = "mode")]
+		text: Option<String>,
+		/// Put back data spread over `file` and these pngs with
+		/// `encode --spread`, in any order. Damaged or missing images are
+		/// reported, a message spread with `--parity` can do without one.
+		#[arg(long, value_name = "FRAMES", num_args = 1.., conflicts_with = "text")]
+		spread: Vec<PathBuf>,
 	},
 	/// Remove a chunk from a png.
 	/// Must provide the `chunk_type` which act as label.
+	#[command(visible_alias = "rm")]
 	Remove {
-		/// Accepts a valid .png file.
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
 		file: PathBuf,
 		/// Accepts an exact 4byte ASCII(alphabetic only) sequence. eg: [rust, bOAT].
 		chunk_type: String,
+		/// Also write the removed chunk, length, type, data and crc, to this
+		/// path, `-` for stdout. `add-chunk --raw` puts it back.
+		#[arg(long, value_name = "PATH")]
+		save_removed: Option<PathBuf>,
+	},
+	/// Encrypt the data of an existing chunk in place.
+	/// The chunk is relabeled as `label`, its original type is kept inside the
+	/// ciphertext.
+	LockChunk {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Type of the chunk to lock. eg: [tEXt, iTXt].
+		chunk_type: String,
+		/// Passphrase used to derive the encryption key.
+		#[arg(long)]
+		password: String,
+		/// Private chunk type the locked chunk is stored under.
+		#[arg(long, default_value = "lcKd")]
+		label: ChunkType,
+		/// Also ask for a time based one time password on unlock. Prints the
+		/// secret to add to an authenticator app. No extra protection: the
+		/// passphrase alone decrypts the chunk with other tools.
+		#[arg(long)]
+		totp: bool,
+		/// Optionally a output path to store the new png.
+		#[arg(short, long)]
+		output_file: Option<PathBuf>,
+	},
+	/// Decrypt a chunk locked with `lock-chunk`, restoring its original type.
+	UnlockChunk {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Label the chunk was locked under.
+		#[arg(default_value = "lcKd")]
+		label: String,
+		/// Passphrase the chunk was locked with.
+		#[arg(long)]
+		password: String,
+		/// Current 6 digit code, needed if the chunk was locked with `--totp`.
+		#[arg(long)]
+		totp_code: Option<u32>,
+		/// Optionally a output path to store the new png.
+		#[arg(short, long)]
+		output_file: Option<PathBuf>,
+	},
+	/// Seal a message `encode --encrypt` hid with an older vanish again in the
+	/// current format, see `edpg::format`. Current ones are left alone unless
+	/// given a new passphrase.
+	UpgradePayload {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Accepts an exact 4byte ASCII(alphabetic only) sequence. eg: [rust, bOAT].
+		chunk_type: String,
+		/// Passphrase the message was encrypted with.
+		#[arg(long)]
+		passphrase: String,
+		/// Encrypt it under this passphrase instead. A one time password it
+		/// was guarded by stays the same.
+		#[arg(long)]
+		new_passphrase: Option<String>,
+		/// Optionally a output path to store the new png.
+		#[arg(short, long)]
+		output_file: Option<PathBuf>,
+	},
+	/// Dump the raw data of the selected chunks to stdout, in file order.
+	/// eg: `cat-chunks file.png --types IDAT > idat.zlib`
+	CatChunks {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Chunk types to dump, comma separated or repeated.
+		#[arg(long, value_delimiter = ',', required = true)]
+		types: Vec<String>,
+	},
+	/// Wrap the bytes of a file into a new chunk, no envelope, CRC computed.
+	/// Inserted right before IEND unless told otherwise.
+	AddChunk {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Type of the new chunk. Not needed with `--raw`.
+		#[arg(required_unless_present = "raw", conflicts_with = "raw")]
+		chunk_type: Option<ChunkType>,
+		/// File holding the chunk data.
+		#[arg(long)]
+		data_file: PathBuf,
+		/// `data_file` holds a whole serialized chunk (length, type, data, crc),
+		/// eg: one saved by `remove --save-removed`.
+		#[arg(long)]
+		raw: bool,
+		/// Insert at this chunk index.
+		#[arg(long, conflicts_with = "before")]
+		at: Option<usize>,
+		/// Insert before the first chunk of this type.
+		#[arg(long)]
+		before: Option<String>,
+		/// Optionally a output path to store the new png.
+		#[arg(short, long)]
+		output_file: Option<PathBuf>,
+	},
+	/// Copy the text fields of the eXIf chunk (author, copyright, camera...)
+	/// into tEXt chunks, or the other way round with `--to-exif`.
+	MigrateExif {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Build the eXIf chunk from the tEXt chunks instead.
+		#[arg(long)]
+		to_exif: bool,
+		/// Optionally a output path to store the new png.
+		#[arg(short, long)]
+		output_file: Option<PathBuf>,
+	},
+	/// Describe every chunk as TOML, for reviewing metadata changes in diffs.
+	/// `restore` turns it back into the exact same png.
+	Dump {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Where to write the TOML, stdout if not given. eg: `file.png.toml`
+		#[arg(short, long)]
+		output_file: Option<PathBuf>,
+	},
+	/// Rebuild a png from the TOML written by `dump`.
+	Restore {
+		/// Accepts a .png.toml written by `dump`, `-` to read a list of files
+		/// from stdin.
+		file: PathBuf,
+		/// Path of the rebuilt png.
+		#[arg(short, long)]
+		output_file: PathBuf,
+	},
+	/// Validate pngs (structure, crc, hidden data). Several files are checked
+	/// in parallel and reported together, errors first.
+	Check {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// More files to check.
+		#[arg(value_name = "FILES")]
+		more: Vec<PathBuf>,
+		/// Threads to check on, all cores by default.
+		#[arg(long, short)]
+		jobs: Option<usize>,
+		/// Parse every file in a child process that can only read its input
+		/// and write its answer, for files that may be crafted to break
+		/// parsers. A parser that crashes is reported as an error. Linux
+		/// only, see `seccomp`.
+		#[arg(long)]
+		sandbox: bool,
+	},
+	/// Look for likely hidden payloads: unknown ancillary chunks, encrypted
+	/// vanish chunks, data after IEND. eg: as a pre-commit hook
+	Guard {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// More files, checked as a batch.
+		#[arg(value_name = "FILES")]
+		more: Vec<PathBuf>,
+		/// Fail every file with a finding, instead of only listing them.
+		#[arg(long)]
+		deny_hidden: bool,
+		/// Also hold every file to the rules of this TOML policy, see
+		/// `edpg::policy`. Violations always fail the file.
+		#[arg(long, value_name = "POLICY.toml")]
+		policy: Option<PathBuf>,
+		/// Offer the safe fixes for what was found, see `edpg::fix`: strip
+		/// data after IEND, remove suspicious ancillary chunks, rewrite wrong
+		/// crcs. Each is shown and confirmed before the file is rewritten,
+		/// `--yes` applies them all.
+		#[arg(long)]
+		fix: bool,
+	},
+	/// Overwrite a file several times and delete it, best effort: copy on
+	/// write filesystems and SSDs may keep the old data around.
+	Shred {
+		/// The file to destroy, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// How many times to overwrite it.
+		#[arg(long, default_value_t = shred::DEFAULT_PASSES)]
+		passes: usize,
+	},
+	/// Write systematically corrupted copies of a png, for testing other
+	/// decoders.
+	FuzzGen {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// How many variants to write.
+		#[arg(long, default_value_t = 100)]
+		mutations: usize,
+		/// Directory the variants are written to.
+		#[arg(short, long)]
+		output_dir: PathBuf,
+	},
+	/// Embed or check a C2PA manifest store, the `caBX` chunk of content
+	/// credentials.
+	#[cfg(feature = "c2pa")]
+	C2pa {
+		#[command(subcommand)]
+		command: C2paCommand,
+	},
+	/// Run rhai scripts over the chunks of a png.
+	#[cfg(feature = "script")]
+	Script {
+		#[command(subcommand)]
+		command: ScriptCommand,
 	},
-	/// Displays PNG in bytes form.
+	/// Parse a png and write it back in memory, telling whether the result is
+	/// byte for byte the file and where it isn't. Anything that differs is
+	/// lost by an in place edit.
+	VerifyIdentity {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Print the report as JSON.
+		#[arg(long)]
+		json: bool,
+	},
+	/// Tell what a file is, whether vanish can hide data in it, and how.
+	Identify {
+		/// Any file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Print the report as JSON.
+		#[arg(long)]
+		json: bool,
+	},
+	/// Append a zip archive after IEND. The result is a png to image viewers
+	/// and a zip to unzip tools. Some mail and upload scanners flag or strip
+	/// such polyglots.
+	Polyglot {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Zip archive to append.
+		#[arg(required_unless_present = "extract")]
+		archive: Option<PathBuf>,
+		/// Write the zip archive appended to `file` out as a standalone zip
+		/// instead.
+		#[arg(long, conflicts_with = "archive")]
+		extract: bool,
+		/// Path of the polyglot, or of the zip with `--extract`.
+		#[arg(short, long)]
+		output_file: PathBuf,
+	},
+	/// Hide data in plain text instead of a png, as zero width characters.
+	Textstego {
+		#[command(subcommand)]
+		command: TextstegoCommand,
+	},
+	/// Stamp a png with who made it and what its pixels hash to, or check
+	/// such a stamp.
+	Provenance {
+		#[command(subcommand)]
+		command: ProvenanceCommand,
+	},
+	/// Make zstd dictionaries for `encode-file --compress --dict`.
+	#[cfg(feature = "zstd")]
+	Dict {
+		#[command(subcommand)]
+		command: DictCommand,
+	},
+	/// Check the files of a `--custody-log` are still those that were
+	/// examined.
+	Custody {
+		#[command(subcommand)]
+		command: CustodyCommand,
+	},
+	/// Manage the results `--cache` keeps between runs of `check`.
+	Cache {
+		#[command(subcommand)]
+		command: CacheCommand,
+	},
+	/// Serve encode, decode and inspect as JSON-RPC over a UNIX socket, one
+	/// request per line, until a `shutdown` request.
+	Daemon {
+		/// Socket to listen on, `$XDG_RUNTIME_DIR/vanish.sock` by default.
+		#[arg(long)]
+		socket: Option<PathBuf>,
+	},
+	/// Serve the requests of `daemon`, plus `applyEdit`, on stdin and stdout
+	/// for editor plugins, until the end of the input.
+	Ipc,
+	/// Write man pages for vanish and every subcommand.
+	Man {
+		/// Directory the pages are written to.
+		#[arg(short, long, default_value = ".")]
+		output_dir: PathBuf,
+	},
+	/// Lists the chunks of a PNG. What `vanish file.png` does.
+	#[command(visible_aliases = ["ls", "list"])]
 	Print {
-		/// Accepts a valid .png file.
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
 		file: PathBuf,
+		/// Dump every chunk in raw hex instead.
+		#[arg(long)]
+		hex: bool,
+	},
+}
+
+/// Where `encode` hides the data and `decode` looks for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum EmbedMode {
+	/// A chunk of its own, `chunk_type`.
+	#[default]
+	Chunk,
+	/// The least significant bits of the pixels, see `edpg::steg`. Only 8
+	/// and 16 bit non-interlaced grayscale or truecolor pngs.
+	Lsb,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum CacheCommand {
+	/// Delete the cache.
+	Clear,
+	/// Where the cache is, how many files it knows and how many of them
+	/// changed since.
+	Stats {
+		/// Print the stats as JSON.
+		#[arg(long)]
+		json: bool,
+	},
+}
+
+#[cfg(feature = "zstd")]
+#[derive(Subcommand, Clone)]
+pub enum DictCommand {
+	/// Train a dictionary on files like those that will be hidden, eg:
+	/// `dict train telemetry/*.json -o telemetry.dict`. The more the better,
+	/// a few dozen at least.
+	Train {
+		#[arg(required = true, value_name = "SAMPLES")]
+		samples: Vec<PathBuf>,
+		/// Where to write the dictionary.
+		#[arg(short, long)]
+		output_file: PathBuf,
+		/// Largest the dictionary may get.
+		#[arg(long, value_name = "BYTES", default_value_t = edpg::compress::DEFAULT_DICTIONARY_BYTES)]
+		max_size: usize,
 	},
 }
+
+#[derive(Subcommand, Clone)]
+pub enum CustodyCommand {
+	/// Hash every file the log recorded again and tell which changed. Fails
+	/// if any did or can't be read anymore.
+	Verify {
+		/// The log written by `--custody-log`.
+		log: PathBuf,
+		#[arg(long)]
+		json: bool,
+	},
+}
+
+#[derive(Subcommand, Clone)]
+pub enum ProvenanceCommand {
+	/// Write creator, copyright, claims and a hash of the pixels as text
+	/// chunks, see `edpg::provenance`. Stamping again replaces them.
+	Stamp {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Optionally a output path to store the stamped png.
+		output_file: Option<PathBuf>,
+		#[arg(long)]
+		creator: Option<String>,
+		/// eg: `© 2026 Ada, CC BY 4.0`.
+		#[arg(long)]
+		copyright: Option<String>,
+		/// Any other field, repeat it for more. eg:
+		/// `c2pa.training-mining=notAllowed`.
+		#[arg(long, value_name = "KEYWORD=TEXT", value_parser = parse_claim)]
+		claim: Vec<(String, String)>,
+		/// Seal the fields with this passphrase, `verify --passphrase` tells
+		/// whether they changed since.
+		#[arg(long)]
+		passphrase: Option<String>,
+	},
+	/// Check a stamp: the pixels against the hash it recorded and, with
+	/// `--passphrase`, its seal. Fails if either doesn't hold.
+	Verify {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Passphrase the stamp was sealed with.
+		#[arg(long)]
+		passphrase: Option<String>,
+		#[arg(long)]
+		json: bool,
+	},
+}
+
+#[derive(Subcommand, Clone)]
+pub enum TextstegoCommand {
+	/// Weave the message into the cover text, printed unless `-o` is given.
+	Encode {
+		/// Text the message is hidden in, `-` to read a list of files from
+		/// stdin.
+		#[arg(long)]
+		cover: PathBuf,
+		/// The data you want to hide.
+		#[arg(required_unless_present = "input_file", conflicts_with = "input_file")]
+		message: Option<String>,
+		/// Hide the contents of this file instead of `message`.
+		#[arg(long, value_name = "PATH")]
+		input_file: Option<PathBuf>,
+		/// Encrypt the message with this passphrase first.
+		#[arg(long)]
+		password: Option<String>,
+		/// Where to write the resulting text.
+		#[arg(short, long)]
+		output_file: Option<PathBuf>,
+	},
+	/// Print the message hidden in a text.
+	Decode {
+		/// Accepts a text written by `textstego encode`, `-` to read a list of
+		/// files from stdin.
+		file: PathBuf,
+		/// Passphrase the message was encrypted with.
+		#[arg(long)]
+		password: Option<String>,
+	},
+}
+
+#[cfg(feature = "c2pa")]
+#[derive(Subcommand, Clone)]
+pub enum C2paCommand {
+	/// Put a manifest store made by a C2PA tool, eg: `c2patool`, in the png,
+	/// replacing any there was.
+	Embed {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// The manifest store, a .c2pa JUMBF file.
+		manifest_store: PathBuf,
+		/// Optionally a output path to store the new png.
+		#[arg(short, long)]
+		output_file: Option<PathBuf>,
+	},
+	/// Check the png has a manifest store laid out as C2PA wants and list its
+	/// manifests. Signatures and hash bindings aren't checked, leave those to
+	/// a C2PA validator.
+	Verify {
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Also write the raw manifest store to this path, `-` for stdout.
+		#[arg(long, value_name = "PATH")]
+		extract: Option<PathBuf>,
+		#[arg(long)]
+		json: bool,
+	},
+}
+
+#[cfg(feature = "script")]
+#[derive(Subcommand, Clone)]
+pub enum ScriptCommand {
+	/// Run a script with the png in scope as `png` and write back whatever
+	/// it leaves there.
+	/// eg: `for c in png.chunks() { print(c.type) }`
+	Run {
+		/// The .rhai script to run.
+		script: PathBuf,
+		/// Accepts a valid .png file, `-` to read a list of files from stdin.
+		file: PathBuf,
+		/// Optionally a output path to store the new png.
+		#[arg(short, long)]
+		output_file: Option<PathBuf>,
+	},
+}
+
+impl Commands {
+	/// The png the command operates on.
+	pub fn file(&self) -> &Path {
+		match self {
+			Self::Encode { file, .. }
+			| Self::Decode { file, .. }
+			| Self::EncodeFile { file, .. }
+			| Self::MailWrap { file, .. }
+			| Self::Extract { file, .. }
+			| Self::Remove { file, .. }
+			| Self::LockChunk { file, .. }
+			| Self::UnlockChunk { file, .. }
+			| Self::UpgradePayload { file, .. }
+			| Self::CatChunks { file, .. }
+			| Self::AddChunk { file, .. }
+			| Self::MigrateExif { file, .. }
+			| Self::Dump { file, .. }
+			| Self::Restore { file, .. }
+			| Self::Polyglot { file, .. }
+			| Self::Identify { file, .. }
+			| Self::VerifyIdentity { file, .. }
+			| Self::Guard { file, .. }
+			| Self::Check { file, .. }
+			| Self::Shred { file, .. }
+			| Self::FuzzGen { file, .. }
+			| Self::Print { file, .. } => file,
+			Self::Textstego {
+				command: TextstegoCommand::Encode { cover: file, .. },
+			}
+			| Self::Textstego {
+				command: TextstegoCommand::Decode { file, .. },
+			}
+			| Self::Provenance {
+				command: ProvenanceCommand::Stamp { file, .. },
+			}
+			| Self::Provenance {
+				command: ProvenanceCommand::Verify { file, .. },
+			} => file,
+			#[cfg(feature = "script")]
+			Self::Script {
+				command: ScriptCommand::Run { file, .. },
+			} => file,
+			#[cfg(feature = "c2pa")]
+			Self::C2pa {
+				command: C2paCommand::Embed { file, .. },
+			}
+			| Self::C2pa {
+				command: C2paCommand::Verify { file, .. },
+			} => file,
+			// handled before any file is looked at
+			Self::Cache { .. }
+			| Self::Custody { .. }
+			| Self::Daemon { .. }
+			| Self::Ipc
+			| Self::Man { .. } => Path::new(""),
+			#[cfg(feature = "zstd")]
+			Self::Dict { .. } => Path::new(""),
+		}
+	}
+
+	/// Name the command is invoked by.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Encode { .. } => "encode",
+			Self::Decode { .. } => "decode",
+			Self::EncodeFile { .. } => "encode-file",
+			Self::MailWrap { .. } => "mail-wrap",
+			Self::Extract { .. } => "extract",
+			Self::Remove { .. } => "remove",
+			Self::LockChunk { .. } => "lock-chunk",
+			Self::UnlockChunk { .. } => "unlock-chunk",
+			Self::UpgradePayload { .. } => "upgrade-payload",
+			Self::CatChunks { .. } => "cat-chunks",
+			Self::AddChunk { .. } => "add-chunk",
+			Self::MigrateExif { .. } => "migrate-exif",
+			Self::Dump { .. } => "dump",
+			Self::Restore { .. } => "restore",
+			Self::Polyglot { .. } => "polyglot",
+			Self::Identify { .. } => "identify",
+			Self::VerifyIdentity { .. } => "verify-identity",
+			Self::Guard { .. } => "guard",
+			Self::Check { .. } => "check",
+			Self::Shred { .. } => "shred",
+			Self::FuzzGen { .. } => "fuzz-gen",
+			#[cfg(feature = "script")]
+			Self::Script { .. } => "script run",
+			#[cfg(feature = "c2pa")]
+			Self::C2pa {
+				command: C2paCommand::Embed { .. },
+			} => "c2pa embed",
+			#[cfg(feature = "c2pa")]
+			Self::C2pa {
+				command: C2paCommand::Verify { .. },
+			} => "c2pa verify",
+			Self::Textstego {
+				command: TextstegoCommand::Encode { .. },
+			} => "textstego encode",
+			Self::Textstego {
+				command: TextstegoCommand::Decode { .. },
+			} => "textstego decode",
+			Self::Provenance {
+				command: ProvenanceCommand::Stamp { .. },
+			} => "provenance stamp",
+			Self::Provenance {
+				command: ProvenanceCommand::Verify { .. },
+			} => "provenance verify",
+			Self::Cache {
+				command: CacheCommand::Clear,
+			} => "cache clear",
+			Self::Cache {
+				command: CacheCommand::Stats { .. },
+			} => "cache stats",
+			Self::Custody {
+				command: CustodyCommand::Verify { .. },
+			} => "custody verify",
+			#[cfg(feature = "zstd")]
+			Self::Dict {
+				command: DictCommand::Train { .. },
+			} => "dict train",
+			Self::Daemon { .. } => "daemon",
+			Self::Ipc => "ipc",
+			Self::Man { .. } => "man",
+			Self::Print { .. } => "print",
+		}
+	}
+
+	/// Whether the command changes or creates files whatever it is given,
+	/// refused with `--read-only`. Those that only write where they're told
+	/// to, eg: `extract`, are stopped when they get there.
+	pub fn writes(&self) -> bool {
+		match self {
+			Self::Remove { .. }
+			| Self::MailWrap { .. }
+			| Self::Polyglot { .. }
+			| Self::Shred { .. }
+			| Self::FuzzGen { .. }
+			| Self::Cache {
+				command: CacheCommand::Clear,
+			}
+			| Self::Daemon { .. }
+			| Self::Man { .. } => true,
+			#[cfg(feature = "zstd")]
+			Self::Dict { .. } => true,
+			_ => self.output_file().is_some(),
+		}
+	}
+
+	/// The png the command writes back, `None` for commands that only read
+	/// or write elsewhere.
+	pub fn output_file(&self) -> Option<&Path> {
+		match self {
+			Self::Encode {
+				file, output_file, ..
+			}
+			| Self::EncodeFile {
+				file, output_file, ..
+			}
+			| Self::LockChunk {
+				file, output_file, ..
+			}
+			| Self::UnlockChunk {
+				file, output_file, ..
+			}
+			| Self::UpgradePayload {
+				file, output_file, ..
+			}
+			| Self::AddChunk {
+				file, output_file, ..
+			}
+			| Self::MigrateExif {
+				file, output_file, ..
+			} => Some(output_file.as_deref().unwrap_or(file)),
+			Self::Restore { output_file, .. }
+			| Self::Polyglot {
+				output_file,
+				extract: false,
+				..
+			} => Some(output_file),
+			Self::Guard {
+				file, fix: true, ..
+			} => Some(file),
+			Self::Provenance {
+				command: ProvenanceCommand::Stamp {
+					file, output_file, ..
+				},
+			} => Some(output_file.as_deref().unwrap_or(file)),
+			#[cfg(feature = "script")]
+			Self::Script {
+				command: ScriptCommand::Run {
+					file, output_file, ..
+				},
+			} => Some(output_file.as_deref().unwrap_or(file)),
+			#[cfg(feature = "c2pa")]
+			Self::C2pa {
+				command: C2paCommand::Embed {
+					file, output_file, ..
+				},
+			} => Some(output_file.as_deref().unwrap_or(file)),
+			#[cfg(feature = "c2pa")]
+			Self::C2pa {
+				command: C2paCommand::Verify { .. },
+			} => None,
+			#[cfg(feature = "zstd")]
+			Self::Dict { .. } => None,
+			Self::Decode { .. }
+			| Self::Extract { .. }
+			| Self::Remove { .. }
+			| Self::MailWrap { .. }
+			| Self::CatChunks { .. }
+			| Self::Dump { .. }
+			| Self::Polyglot { .. }
+			| Self::Identify { .. }
+			| Self::VerifyIdentity { .. }
+			| Self::Guard { .. }
+			| Self::Check { .. }
+			| Self::Shred { .. }
+			| Self::Textstego { .. }
+			| Self::Provenance { .. }
+			| Self::FuzzGen { .. }
+			| Self::Cache { .. }
+			| Self::Custody { .. }
+			| Self::Daemon { .. }
+			| Self::Ipc
+			| Self::Man { .. }
+			| Self::Print { .. } => None,
+		}
+	}
+}
+
+/// `Cli`'s command, every flag also read from a `VANISH_*` variable named
+/// after it, eg: `VANISH_NO_VERIFY_CRC=1` for `--no-verify-crc`. Hooks get
+/// `VANISH_HOOK_*`, so a vanish run from a hook doesn't pick those up as flags.
+pub fn command() -> Command {
+	with_env(Cli::command())
+}
+
+fn with_env(command: Command) -> Command {
+	let subcommands: Vec<String> = command
+		.get_subcommands()
+		.map(|c| c.get_name().to_owned())
+		.collect();
+	let command = command.mut_args(|arg| match arg.is_positional() {
+		true => arg,
+		false => {
+			let name = format!("VANISH_{}", arg.get_id().as_str().to_uppercase());
+			match arg.get_action() {
+				// switches take 1/yes/on too, and are off when empty
+				ArgAction::SetTrue => arg.env(name).value_parser(FalseyValueParser::new()),
+				_ => arg.env(name),
+			}
+		},
+	});
+	subcommands
+		.iter()
+		.fold(command, |command, name| command.mut_subcommand(name, with_env))
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+	s.parse::<f64>()
+		.ok()
+		.and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+		.filter(|d| !d.is_zero())
+		.ok_or_else(|| format!("`{s}` is not a positive number of seconds"))
+}
+
+fn parse_rate(s: &str) -> Result<f64, String> {
+	s.parse::<f64>()
+		.ok()
+		.filter(|rate| *rate > 0.0 && Duration::try_from_secs_f64(1.0 / rate).is_ok())
+		.ok_or_else(|| format!("`{s}` is not a usable number of files per second"))
+}
+
+fn parse_claim(s: &str) -> Result<(String, String), String> {
+	s.split_once('=')
+		.map(|(keyword, text)| (keyword.to_owned(), text.to_owned()))
+		.ok_or_else(|| format!("`{s}` is not KEYWORD=TEXT"))
+}
+
+/// How deep argument files may include each other, a loop stops here.
+const MAX_ARGFILE_DEPTH: usize = 8;
+
+/// `args` with every `@path` replaced by the arguments in that file, one per
+/// line, blank lines and `#` comments skipped. `@@x` is a literal `@x`,
+/// nothing after `--` is expanded.
+pub fn expand_argfiles(
+	args: impl IntoIterator<Item = OsString>,
+) -> Result<Vec<OsString>, std::io::Error> {
+	let mut expanded = Vec::new();
+	expand(args.into_iter().collect(), 0, &mut expanded)?;
+	Ok(expanded)
+}
+
+fn expand(
+	args: Vec<OsString>,
+	depth: usize,
+	out: &mut Vec<OsString>,
+) -> Result<(), std::io::Error> {
+	let mut args = args.into_iter();
+	while let Some(arg) = args.next() {
+		let Some(path) = arg.to_str().and_then(|a| a.strip_prefix('@')) else {
+			let done = arg == "--";
+			out.push(arg);
+			if done {
+				out.extend(args);
+				return Ok(());
+			}
+			continue;
+		};
+		if path.starts_with('@') {
+			out.push(path.into());
+			continue;
+		}
+		if depth == MAX_ARGFILE_DEPTH {
+			return Err(std::io::Error::other(format!(
+				"Argument files nested deeper than {MAX_ARGFILE_DEPTH}, at @{path}"
+			)));
+		}
+
+		let text = std::fs::read_to_string(path).map_err(|e| {
+			std::io::Error::new(e.kind(), format!("Can't read argument file {path}. {e}"))
+		})?;
+		let lines = text
+			.lines()
+			.map(str::trim)
+			.filter(|l| !l.is_empty() && !l.starts_with('#'))
+			.map(OsString::from)
+			.collect();
+		expand(lines, depth + 1, out)?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn args(args: &[&str]) -> Vec<OsString> {
+		args.iter().map(OsString::from).collect()
+	}
+
+	/// A fresh, empty directory for `name`.
+	fn dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("vanish-args-{name}-{}", std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		std::fs::create_dir_all(&dir).unwrap();
+		dir
+	}
+
+	#[test]
+	fn test_expand_argfiles() {
+		let dir = dir("expand");
+		let inner = dir.join("inner.args");
+		let outer = dir.join("outer.args");
+		std::fs::write(&inner, "--json\n").unwrap();
+		std::fs::write(&outer, format!("# comment\n\n  print  \n@{}\r\n", inner.display())).unwrap();
+
+		let outer = format!("@{}", outer.display());
+		assert_eq!(
+			expand_argfiles(args(&["vanish", &outer, "a.png"])).unwrap(),
+			args(&["vanish", "print", "--json", "a.png"])
+		);
+		// escaped, and nothing after `--`
+		assert_eq!(
+			expand_argfiles(args(&["vanish", "@@x", "--", &outer])).unwrap(),
+			args(&["vanish", "@x", "--", &outer])
+		);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn test_argfile_depth() {
+		let dir = dir("depth");
+		let looped = dir.join("loop.args");
+		std::fs::write(&looped, format!("@{}\n", looped.display())).unwrap();
+
+		let e = expand_argfiles(args(&["vanish", &format!("@{}", looped.display())])).unwrap_err();
+		assert!(e.to_string().contains("deeper than"), "{e}");
+		assert!(expand_argfiles(args(&["vanish", "@/nonexistent.args"])).is_err());
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+}
@@ -0,0 +1,110 @@
+// Append-only record of what was done to which file, one JSON object per line.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use edpg::png::Png;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::batch::Status;
+
+#[derive(Debug, Serialize)]
+pub struct Entry {
+	/// Seconds since the unix epoch.
+	pub timestamp: u64,
+	pub command: &'static str,
+	pub file: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub output_file: Option<String>,
+	/// Hex sha256 of the input before the command ran, missing if unreadable.
+	pub sha256_before: Option<String>,
+	/// Hex sha256 of the written file, only for commands that write one.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub sha256_after: Option<String>,
+	/// Types with a chunk added, removed or changed.
+	pub chunk_types: Vec<String>,
+	pub status: Status,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+}
+
+impl Entry {
+	/// `before` is the input as it was, `after` the written output if any.
+	pub fn new(command: &'static str, file: &Path, before: Option<&[u8]>) -> Self {
+		Self {
+			timestamp: SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map_or(0, |d| d.as_secs()),
+			command,
+			file: file.to_string_lossy().into_owned(),
+			output_file: None,
+			sha256_before: before.map(sha256),
+			sha256_after: None,
+			chunk_types: Vec::new(),
+			status: Status::Ok,
+			error: None,
+		}
+	}
+
+	pub fn written(&mut self, output_file: &Path, before: Option<&[u8]>, after: &[u8]) {
+		self.output_file = Some(output_file.to_string_lossy().into_owned());
+		self.sha256_after = Some(sha256(after));
+		self.chunk_types = before.map(|before| touched(before, after)).unwrap_or_default();
+	}
+
+	pub fn failed(&mut self, error: &dyn Error) {
+		self.status = Status::Failed;
+		self.error = Some(error.to_string());
+	}
+}
+
+fn sha256(bytes: &[u8]) -> String {
+	Sha256::digest(bytes).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Chunk types whose chunks differ between the two files, compared by crc in
+/// file order. Unparsable input counts as having no chunks.
+pub fn touched(before: &[u8], after: &[u8]) -> Vec<String> {
+	fn by_type(bytes: &[u8]) -> BTreeMap<String, Vec<u32>> {
+		let mut types: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+		if let Ok(png) = Png::try_from(bytes) {
+			for chunk in png.chunks() {
+				types
+					.entry(chunk.chunk_type().to_string())
+					.or_default()
+					.push(chunk.crc());
+			}
+		}
+		types
+	}
+
+	let (before, after) = (by_type(before), by_type(after));
+	let mut types: Vec<String> = before
+		.keys()
+		.chain(after.keys())
+		.filter(|t| before.get(*t) != after.get(*t))
+		.cloned()
+		.collect();
+	types.sort();
+	types.dedup();
+	types
+}
+
+/// Appends `entry` as a single line. The line goes out in one write so
+/// concurrent runs appending to the same log don't interleave.
+pub fn append(path: &Path, entry: &Entry) -> Result<(), Box<dyn Error>> {
+	let mut line = serde_json::to_vec(entry)?;
+	line.push(b'\n');
+
+	crate::io::writable(path)?;
+	std::fs::OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(path)?
+		.write_all(&line)?;
+	Ok(())
+}
@@ -0,0 +1,219 @@
+// Runs a command over many files and keeps score.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use edpg::bmff::BmffError;
+use edpg::chunk::ChunkError;
+use edpg::crypto::CryptoError;
+use edpg::exif::ExifError;
+use edpg::flac::FlacError;
+use edpg::pdf::PdfError;
+use edpg::png::PngError;
+use edpg::polyglot::PolyglotError;
+use edpg::svg::SvgError;
+use edpg::textstego::TextStegoError;
+use edpg::wav::WavError;
+use serde::{Deserialize, Serialize};
+
+use crate::io;
+use crate::wrappers::WrapperError;
+
+/// Exit code when some, but not all, files of a batch failed.
+pub const EXIT_PARTIAL_FAILURE: i32 = 3;
+/// Exit code when every file of a batch failed.
+pub const EXIT_FAILURE: i32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+	/// Stop at the first failing file.
+	FailFast,
+	/// Process every file, failures included.
+	KeepGoing,
+}
+
+/// Progress is written to the resume state every this many files, and once
+/// more when the batch ends.
+pub const CHECKPOINT_EVERY: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+	pub mode: Mode,
+	/// Upper bound on files started per second.
+	pub rate: Option<f64>,
+	/// Where progress is checkpointed, files already recorded there are skipped.
+	pub resume_from: Option<PathBuf>,
+}
+
+/// Files a previous run already processed successfully, as kept in
+/// `--resume-from`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+	pub done: HashSet<String>,
+}
+
+impl State {
+	/// Loads the state at `path`, a missing file is a fresh start.
+	pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+		match std::fs::read(path) {
+			Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	/// Written to a sibling file first and renamed over, so an interrupted
+	/// checkpoint doesn't leave a truncated state behind.
+	pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+		let mut tmp = path.as_os_str().to_owned();
+		tmp.push(".tmp");
+		crate::io::writable(path)?;
+		std::fs::write(&tmp, serde_json::to_vec(self)?)?;
+		std::fs::rename(&tmp, path)?;
+		Ok(())
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+	Ok,
+	Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+	pub path: String,
+	pub status: Status,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub error_code: Option<&'static str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+	pub ok: usize,
+	pub failed: usize,
+	/// Files skipped because the resume state had them done already.
+	pub skipped: usize,
+	pub files: Vec<FileReport>,
+}
+
+impl Summary {
+	pub fn exit_code(&self) -> i32 {
+		match (self.ok, self.failed) {
+			(_, 0) => 0,
+			(0, _) => EXIT_FAILURE,
+			_ => EXIT_PARTIAL_FAILURE,
+		}
+	}
+}
+
+/// Stable, machine readable name for what went wrong.
+pub fn error_code(e: &(dyn Error + 'static)) -> &'static str {
+	if let Some(e) = e.downcast_ref::<io::Error>() {
+		return match e {
+			io::Error::TimedOut(..) => "timed_out",
+			_ => "io",
+		};
+	}
+	if e.is::<std::io::Error>() {
+		return "io";
+	}
+	if e.is::<PngError>()
+		|| e.is::<BmffError>()
+		|| e.is::<WavError>()
+		|| e.is::<FlacError>()
+		|| e.is::<PdfError>()
+		|| e.is::<SvgError>()
+		|| e.is::<ChunkError>()
+		|| e.is::<ExifError>()
+		|| e.is::<WrapperError>()
+		|| e.is::<PolyglotError>()
+		|| e.is::<TextStegoError>()
+	{
+		return "parse";
+	}
+	if e.is::<CryptoError>() {
+		return "crypto";
+	}
+
+	"other"
+}
+
+/// Feeds every path of `files` to `op`, recording how each one went.
+/// Errors reading the list itself end the batch.
+pub fn run<I, F>(files: I, options: &Options, mut op: F) -> Result<Summary, Box<dyn Error>>
+where
+	I: Iterator<Item = Result<PathBuf, io::Error>>,
+	F: FnMut(&Path) -> Result<(), Box<dyn Error>>,
+{
+	let mut summary = Summary::default();
+	let mut state = match &options.resume_from {
+		Some(path) => State::load(path)?,
+		None => State::default(),
+	};
+	let interval = options
+		.rate
+		.filter(|rate| *rate > 0.0)
+		.and_then(|rate| Duration::try_from_secs_f64(1.0 / rate).ok());
+	let mut last_start: Option<Instant> = None;
+
+	let checkpoint = |state: &State| match &options.resume_from {
+		Some(path) => state.save(path),
+		None => Ok(()),
+	};
+
+	for file in files {
+		let file = file?;
+		let path = file.to_string_lossy().into_owned();
+
+		if state.done.contains(&path) {
+			summary.skipped += 1;
+			continue;
+		}
+
+		if let (Some(interval), Some(last)) = (interval, last_start) {
+			std::thread::sleep(interval.saturating_sub(last.elapsed()));
+		}
+		last_start = Some(Instant::now());
+
+		match op(&file) {
+			Ok(()) => {
+				// only successes count as done, failures are retried on resume
+				state.done.insert(path.clone());
+				if state.done.len() % CHECKPOINT_EVERY == 0 {
+					checkpoint(&state)?;
+				}
+
+				summary.ok += 1;
+				summary.files.push(FileReport {
+					path,
+					status: Status::Ok,
+					error_code: None,
+					error: None,
+				});
+			},
+			Err(e) => {
+				eprintln!("{path}: {e}");
+				summary.failed += 1;
+				summary.files.push(FileReport {
+					path,
+					status: Status::Failed,
+					error_code: Some(error_code(e.as_ref())),
+					error: Some(e.to_string()),
+				});
+
+				if options.mode == Mode::FailFast {
+					break;
+				}
+			},
+		}
+	}
+
+	checkpoint(&state)?;
+	Ok(summary)
+}
@@ -0,0 +1,151 @@
+// What `check` found in files it has seen before, so re-scans of a directory
+// only parse what changed. Entries are keyed by path and thrown away as soon
+// as the size or modification time of the file doesn't match anymore, a png
+// that only grew gets just its new trailer checked.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, UNIX_EPOCH};
+
+use edpg::report::{Issue, Report};
+use serde::{Deserialize, Serialize};
+
+use crate::io::{self, Error};
+
+/// Size and modification time, a file with the same stamp is taken to be
+/// unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stamp {
+	pub size: u64,
+	/// Nanoseconds since the unix epoch.
+	pub modified: u64,
+}
+
+impl Stamp {
+	pub fn of(path: &Path, timeout: Option<Duration>) -> Result<Stamp, Error> {
+		let metadata = io::metadata(path, timeout)?;
+		let modified = metadata
+			.modified()
+			.ok()
+			.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+			.map_or(0, |d| d.as_nanos() as u64);
+		Ok(Stamp {
+			size: metadata.len(),
+			modified,
+		})
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+	pub stamp: Stamp,
+	/// Whether crcs were checked, an entry made with `--no-verify-crc`
+	/// doesn't answer a check that wants them.
+	pub verified: bool,
+	pub report: Report,
+	/// Set for plain pngs, see `Image`.
+	#[serde(default)]
+	pub image: Option<Image>,
+}
+
+/// Where the image of a png ends, after IEND, and the issues found up to
+/// there. A file that only grew past it needs just the new trailer checked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Image {
+	pub len: u64,
+	pub issues: Vec<Issue>,
+}
+
+#[derive(Debug)]
+pub struct Cache {
+	path: PathBuf,
+	entries: Mutex<BTreeMap<PathBuf, Entry>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+	pub path: PathBuf,
+	pub entries: usize,
+	/// Entries whose file changed or is gone, dropped on the next save.
+	pub stale: usize,
+	pub bytes: u64,
+}
+
+/// `$XDG_CACHE_HOME/vanish/check.json`, falling back to `~/.cache`.
+pub fn default_path() -> Option<PathBuf> {
+	let base = std::env::var_os("XDG_CACHE_HOME")
+		.filter(|dir| !dir.is_empty())
+		.map(PathBuf::from)
+		.or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+	Some(base.join("vanish").join("check.json"))
+}
+
+impl Cache {
+	/// The cache stored at `path`. A missing or unreadable cache file makes
+	/// for an empty cache, it only costs a full scan.
+	pub fn open(path: PathBuf, timeout: Option<Duration>) -> Cache {
+		let entries = io::read(&path, timeout)
+			.ok()
+			.and_then(|bytes| serde_json::from_slice(&bytes).ok())
+			.unwrap_or_default();
+		Cache {
+			path,
+			entries: Mutex::new(entries),
+		}
+	}
+
+	/// What is cached for `file`, whether or not it changed since. Entries
+	/// made with `--no-verify-crc` don't count when `verify_crc` is set.
+	pub fn get(&self, file: &Path, verify_crc: bool) -> Option<Entry> {
+		self.entries
+			.lock()
+			.expect("cache lock poisoned")
+			.get(file)
+			.filter(|e| e.verified || !verify_crc)
+			.cloned()
+	}
+
+	pub fn insert(&self, file: PathBuf, entry: Entry) {
+		self.entries
+			.lock()
+			.expect("cache lock poisoned")
+			.insert(file, entry);
+	}
+
+	/// Writes the cache back, without entries of files that changed or are
+	/// gone.
+	pub fn save(&self, timeout: Option<Duration>) -> Result<(), Error> {
+		let mut entries = self.entries.lock().expect("cache lock poisoned");
+		entries.retain(|file, e| Stamp::of(file, timeout).is_ok_and(|s| s == e.stamp));
+		let json = serde_json::to_vec(&*entries).map_err(std::io::Error::from)?;
+
+		io::writable(&self.path)?;
+		if let Some(dir) = self.path.parent() {
+			std::fs::create_dir_all(dir)?;
+		}
+		io::write(&self.path, json, timeout)
+	}
+
+	pub fn stats(&self, timeout: Option<Duration>) -> Stats {
+		let entries = self.entries.lock().expect("cache lock poisoned");
+		Stats {
+			path: self.path.clone(),
+			entries: entries.len(),
+			stale: entries
+				.iter()
+				.filter(|(file, e)| !Stamp::of(file, timeout).is_ok_and(|s| s == e.stamp))
+				.count(),
+			bytes: io::len(&self.path, timeout).unwrap_or(0),
+		}
+	}
+}
+
+/// Deletes the cache file at `path`, a missing one is fine.
+pub fn clear(path: &Path) -> Result<(), Error> {
+	io::writable(path)?;
+	match std::fs::remove_file(path) {
+		Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+		_ => Ok(()),
+	}
+}
@@ -0,0 +1,247 @@
+// Validates many files at once, spread over a thread pool. Zip and tar
+// archives are looked into.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use edpg::chunk::Chunk;
+use edpg::detect;
+use edpg::png::{ParseOptions, Png};
+use edpg::report::{Issue, Report, Severity};
+use edpg::signature::Signature;
+use rayon::prelude::*;
+
+use crate::cache::{self, Cache, Stamp};
+use crate::io;
+use crate::sandbox;
+
+/// An IEND chunk, the same in every png.
+const IEND: &[u8] = b"\0\0\0\0IEND\xAE\x42\x60\x82";
+
+/// How far past the start an unparsable file is searched for a png
+/// signature, to point at `--max-prefix`.
+const PREFIX_HINT_BYTES: usize = 4096;
+
+/// How files are checked.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+	pub parse: ParseOptions,
+	/// Signatures of known tools, see `edpg::signature`.
+	pub signatures: Vec<Signature>,
+	/// Parse in a locked down child process, see `sandbox`.
+	pub sandbox: bool,
+}
+
+/// Report of a file that couldn't be checked.
+fn failed(name: &str, rule: &str, message: String) -> Report {
+	Report::file(vec![Issue::failed(name, rule, message)])
+}
+
+/// Issue of `name` from a `detect` finding.
+fn warning(name: &str, finding: &detect::Finding) -> Issue {
+	Issue::from_finding(name, Severity::Warning, finding)
+}
+
+/// Issues of a png read into `bytes`, reported under `name`, and where its
+/// image ends for the cache.
+fn scan(name: &str, bytes: &[u8], options: &Options) -> (Report, Option<cache::Image>) {
+	let findings = match detect::scan_matching(bytes, &options.parse, &options.signatures) {
+		Ok(findings) => findings,
+		Err(e) => {
+			let message = match Png::signature(bytes, PREFIX_HINT_BYTES) {
+				Some(start) if start > 0 => {
+					format!("{e} The png signature is at offset {start}, see --max-prefix.")
+				},
+				_ => e.to_string(),
+			};
+			return (failed(name, "invalid-png", message), None);
+		},
+	};
+
+	let image = Png::image_len(bytes).ok().map(|len| cache::Image {
+		len: len as u64,
+		issues: findings
+			.iter()
+			.filter(|f| !f.is_trailer())
+			.map(|f| warning(name, f))
+			.collect(),
+	});
+	let issues = findings.iter().map(|f| warning(name, f)).collect();
+	(Report::file(issues), image)
+}
+
+/// Issues of the pngs in a zip or tar archive, each reported as
+/// `archive!entry`. Entries are read one at a time, nothing is extracted to
+/// disk. `None` if `bytes` is no archive.
+fn archive(path: &str, bytes: &[u8], options: &Options) -> Option<Report> {
+	let entry = |name: &str, data: &[u8]| match data.starts_with(&Png::STANDARD_HEADER) {
+		true => scan(&format!("{path}!{name}"), data, options).0,
+		false => Report::default(),
+	};
+	let broken = |e: &dyn std::fmt::Display| {
+		failed(path, "unreadable-archive", format!("Unreadable archive. {e}"))
+	};
+
+	if bytes.starts_with(b"PK\x03\x04") {
+		let mut zip = match zip::ZipArchive::new(std::io::Cursor::new(bytes)) {
+			Ok(zip) => zip,
+			Err(e) => return Some(broken(&e)),
+		};
+		let mut report = Report::default();
+		for i in 0..zip.len() {
+			let mut data = Vec::new();
+			match zip.by_index(i) {
+				Ok(file) if !file.is_file() => continue,
+				Ok(mut file) => match file.read_to_end(&mut data) {
+					Ok(_) => report = report.merge(entry(file.name(), &data)),
+					Err(e) => report = report.merge(broken(&e)),
+				},
+				Err(e) => report = report.merge(broken(&e)),
+			}
+		}
+		return Some(report);
+	}
+
+	if bytes.get(257..262) == Some(b"ustar") {
+		let mut tar = tar::Archive::new(bytes);
+		let entries = match tar.entries() {
+			Ok(entries) => entries,
+			Err(e) => return Some(broken(&e)),
+		};
+		let mut report = Report::default();
+		for file in entries {
+			let mut data = Vec::new();
+			let read = file.and_then(|mut file| {
+				file.read_to_end(&mut data)?;
+				Ok(file.path()?.to_string_lossy().into_owned())
+			});
+			report = report.merge(match read {
+				Ok(name) => entry(&name, &data),
+				Err(e) => broken(&e),
+			});
+		}
+		return Some(report);
+	}
+
+	None
+}
+
+/// Issues of a single file, or of every png in it if it is an archive.
+/// Unchanged files are answered from `cache` if there is one, pngs that only
+/// grew since get only their trailer checked. Files without errors go into
+/// it.
+pub fn file(
+	path: &Path,
+	options: &Options,
+	timeout: Option<Duration>,
+	cache: Option<&Cache>,
+) -> Report {
+	let Some(cache) = cache else {
+		return read(path, options, timeout).0;
+	};
+	// stamp before reading, a file changing in between is scanned again
+	// next time rather than cached with the wrong contents
+	let Ok(stamp) = Stamp::of(path, timeout) else {
+		return read(path, options, timeout).0;
+	};
+	// keyed by absolute path, the cache outlives the working directory
+	let key = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+	let cached = cache.get(&key, options.parse.verify_crc);
+	if let Some(entry) = cached.as_ref().filter(|e| e.stamp == stamp) {
+		return entry.report.clone();
+	}
+
+	let grown = cached.and_then(|e| {
+		e.image
+			.filter(|image| stamp.size > e.stamp.size.max(image.len))
+	});
+	let (report, image) = match grown.and_then(|image| appended(path, image, options, timeout)) {
+		Some((report, image)) => (report, Some(image)),
+		None => read(path, options, timeout),
+	};
+	if report.count(Severity::Error) == 0 {
+		let entry = cache::Entry {
+			stamp,
+			verified: options.parse.verify_crc,
+			report: report.clone(),
+			image,
+		};
+		cache.insert(key, entry);
+	}
+	report
+}
+
+/// Report of a png that was `image` when cached and has been appended to
+/// since, from reading only its IEND chunk on. `None` if IEND isn't where it
+/// was, the file was rewritten rather than appended to.
+fn appended(
+	path: &Path,
+	image: cache::Image,
+	options: &Options,
+	timeout: Option<Duration>,
+) -> Option<(Report, cache::Image)> {
+	let iend = image.len.checked_sub(Chunk::METADATA_BYTES as u64)?;
+	let bytes = io::read_from(path, iend, timeout).ok()?;
+	let (end, trailer) = bytes.split_at_checked(Chunk::METADATA_BYTES)?;
+	if end != IEND {
+		return None;
+	}
+
+	let name = path.to_string_lossy();
+	let mut issues = image.issues.clone();
+	issues.extend(
+		detect::scan_trailer(image.len as usize, trailer, &options.signatures)
+			.iter()
+			.map(|f| warning(&name, f)),
+	);
+	Some((Report::file(issues), image))
+}
+
+fn read(
+	path: &Path,
+	options: &Options,
+	timeout: Option<Duration>,
+) -> (Report, Option<cache::Image>) {
+	let name = path.to_string_lossy();
+	match io::read(path, timeout) {
+		Err(e) => (failed(&name, "unreadable", e.to_string()), None),
+		Ok(bytes) if options.sandbox => match sandbox::inspect(&name, &bytes, options, timeout) {
+			Ok(found) => found,
+			Err(e) => (failed(&name, "sandboxed-parse", e.to_string()), None),
+		},
+		Ok(bytes) => inspect(&name, &bytes, options),
+	}
+}
+
+/// Issues of `bytes` read from `name`, every png in it if it is an archive.
+pub fn inspect(name: &str, bytes: &[u8], options: &Options) -> (Report, Option<cache::Image>) {
+	match archive(name, bytes, options) {
+		Some(report) => (report, None),
+		None => scan(name, bytes, options),
+	}
+}
+
+/// Checks `files` on `jobs` threads, all cores if not given. The report is
+/// sorted by severity, then path, whatever order the files finished in.
+pub fn run(
+	files: Vec<PathBuf>,
+	jobs: Option<usize>,
+	options: &Options,
+	timeout: Option<Duration>,
+	cache: Option<&Cache>,
+) -> Result<Report, rayon::ThreadPoolBuildError> {
+	let pool = rayon::ThreadPoolBuilder::new()
+		.num_threads(jobs.unwrap_or(0))
+		.build()?;
+
+	let mut report = pool.install(|| {
+		files
+			.par_iter()
+			.map(|path| file(path, options, timeout, cache))
+			.reduce(Report::default, Report::merge)
+	});
+	report.sort();
+
+	Ok(report)
+}
@@ -0,0 +1,94 @@
+// The clipboard image as a carrier, see `encode --from-screen`. Clipboards
+// hand images around as pixels, whatever chunks a png had are gone once it's
+// copied, so only what is hidden in the pixels survives the trip.
+//
+// On Linux the clipboard is served by the process that set it, the image is
+// only there while vanish runs. `copy` waits for a clipboard manager, or the
+// next copy, to take it over.
+
+use std::borrow::Cow;
+
+use arboard::{Clipboard, ImageData};
+use edpg::png::{Png, PngError};
+use image::{DynamicImage, RgbaImage};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+	#[error("Clipboard unavailable. {0}")]
+	Clipboard(#[from] arboard::Error),
+	#[error("The clipboard image is {width}x{height} but holds {len} bytes.")]
+	BadImage { width: usize, height: usize, len: usize },
+	#[error("{0}")]
+	Png(#[from] PngError),
+}
+
+/// The image on the clipboard, as a png.
+pub fn paste() -> Result<Png, Error> {
+	to_png(Clipboard::new()?.get_image()?)
+}
+
+/// Puts the pixels of `png` on the clipboard.
+pub fn copy(png: &Png) -> Result<(), Error> {
+	let image = from_png(png)?;
+	let mut clipboard = Clipboard::new()?;
+	#[cfg(target_os = "linux")]
+	{
+		use arboard::SetExtLinux;
+		eprintln!("Keeping the image on the clipboard until something else takes it");
+		clipboard.set().wait().image(image)?;
+	}
+	#[cfg(not(target_os = "linux"))]
+	clipboard.set_image(image)?;
+	Ok(())
+}
+
+fn to_png(image: ImageData) -> Result<Png, Error> {
+	let bad = || Error::BadImage {
+		width: image.width,
+		height: image.height,
+		len: image.bytes.len(),
+	};
+	let width = u32::try_from(image.width).map_err(|_| bad())?;
+	let height = u32::try_from(image.height).map_err(|_| bad())?;
+	let rgba = RgbaImage::from_raw(width, height, image.bytes.to_vec()).ok_or_else(bad)?;
+	Ok(Png::from_image(&DynamicImage::ImageRgba8(rgba))?)
+}
+
+fn from_png(png: &Png) -> Result<ImageData<'static>, Error> {
+	let rgba = DynamicImage::try_from(png)?.into_rgba8();
+	Ok(ImageData {
+		width: rgba.width() as usize,
+		height: rgba.height() as usize,
+		bytes: Cow::Owned(rgba.into_raw()),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use edpg::steg;
+
+	use super::*;
+
+	#[test]
+	fn test_pixels_round_trip() {
+		let image = ImageData {
+			width: 8,
+			height: 8,
+			bytes: (0..=255).collect::<Vec<u8>>().into(),
+		};
+		let mut png = to_png(image).unwrap();
+		steg::embed(&mut png, b"hidden").unwrap();
+
+		// all the clipboard keeps
+		let copied = to_png(from_png(&png).unwrap()).unwrap();
+		assert_eq!(steg::extract(&copied).unwrap(), b"hidden");
+
+		let short = ImageData {
+			width: 8,
+			height: 8,
+			bytes: vec![0; 10].into(),
+		};
+		assert!(matches!(to_png(short), Err(Error::BadImage { len: 10, .. })));
+	}
+}
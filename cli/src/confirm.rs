@@ -0,0 +1,65 @@
+// Asking before anything is overwritten or destroyed. Every mutating command
+// goes through here, so they all answer to `--yes` and `--no-input` the same
+// way.
+
+use std::io::{IsTerminal, Write};
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+	/// Ask on stderr, read the answer from stdin.
+	#[default]
+	Ask,
+	/// Take yes for an answer without asking, `--yes`.
+	Yes,
+	/// Never ask, whatever needs an answer fails, `--no-input`.
+	NoInput,
+}
+
+impl Mode {
+	/// Mode for the `--yes` and `--no-input` flags. Without a terminal on
+	/// stdin there is nobody to ask, it is likely the file list anyway, so a
+	/// script goes ahead unless it asked for `--no-input`.
+	pub fn new(yes: bool, no_input: bool) -> Mode {
+		match (yes, no_input) {
+			(true, _) => Mode::Yes,
+			(false, true) => Mode::NoInput,
+			(false, false) if !std::io::stdin().is_terminal() => Mode::Yes,
+			(false, false) => Mode::Ask,
+		}
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+	#[error("{0}? Needs an answer, pass --yes to go ahead without asking")]
+	NoInput(String),
+	#[error("{0}? Declined, nothing done")]
+	Declined(String),
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+}
+
+/// Asks `question` on stderr, true for an answer starting with `y`.
+pub fn ask(question: &str, mode: Mode) -> Result<bool, Error> {
+	match mode {
+		Mode::Yes => return Ok(true),
+		Mode::NoInput => return Err(Error::NoInput(question.to_owned())),
+		Mode::Ask => {},
+	}
+
+	eprint!("{question}? [y/N] ");
+	std::io::stderr().flush()?;
+	let mut answer = String::new();
+	std::io::stdin().read_line(&mut answer)?;
+	Ok(answer.trim_start().starts_with(['y', 'Y']))
+}
+
+/// `ask`, a no being an error.
+pub fn require(question: &str, mode: Mode) -> Result<(), Error> {
+	match ask(question, mode)? {
+		true => Ok(()),
+		false => Err(Error::Declined(question.to_owned())),
+	}
+}
@@ -0,0 +1,166 @@
+// Chain of custody for `--read-only` runs: what was run, when, and the
+// sha256 of every file it looked at, one JSON object per line.
+// The files are hashed by `io` as they are read, `custody verify` hashes
+// them again to show they are still the ones that were examined.
+
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::batch::Status;
+use crate::io;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+	/// Seconds since the unix epoch, when the command started.
+	pub timestamp: u64,
+	/// The command line as typed, argument files not expanded.
+	pub command: Vec<String>,
+	/// Where it ran, relative paths in `command` are relative to it.
+	pub cwd: String,
+	pub inputs: Vec<Input>,
+	pub status: Status,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Input {
+	/// Absolute, so the log can be verified from anywhere.
+	pub path: PathBuf,
+	pub size: Option<u64>,
+	/// Hex sha256, missing if the file couldn't be read.
+	pub sha256: Option<String>,
+}
+
+/// What `verify` made of an input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+	Matches,
+	Changed,
+	/// Gone or unreadable now.
+	Unreadable,
+	/// Couldn't be read when it was logged, nothing to compare to.
+	Unrecorded,
+}
+
+impl std::fmt::Display for Check {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str(match self {
+			Check::Matches => "matches",
+			Check::Changed => "CHANGED",
+			Check::Unreadable => "UNREADABLE",
+			Check::Unrecorded => "not recorded",
+		})
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct Verified {
+	/// Of the entry the input was logged in.
+	pub timestamp: u64,
+	pub path: PathBuf,
+	pub check: Check,
+}
+
+impl Entry {
+	/// Started now, by the command line of this process.
+	pub fn new() -> Self {
+		Self {
+			timestamp: SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map_or(0, |d| d.as_secs()),
+			command: std::env::args_os()
+				.map(|a| a.to_string_lossy().into_owned())
+				.collect(),
+			cwd: std::env::current_dir()
+				.map(|d| d.to_string_lossy().into_owned())
+				.unwrap_or_default(),
+			inputs: Vec::new(),
+			status: Status::Ok,
+			error: None,
+		}
+	}
+
+	/// The files `io` read so far, as hashed when they were read.
+	pub fn examined(&mut self) {
+		self.inputs = io::examined()
+			.into_iter()
+			.map(|(path, hashed)| Input {
+				path: std::path::absolute(&path).unwrap_or(path),
+				size: hashed.map(|h| h.size),
+				sha256: hashed.map(|h| hex(&h.sha256)),
+			})
+			.collect();
+	}
+
+	pub fn failed(&mut self, error: &dyn Error) {
+		self.status = Status::Failed;
+		self.error = Some(error.to_string());
+	}
+}
+
+impl Default for Entry {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Appends `entry` as a single line. The one write `--read-only` lets
+/// through, so it doesn't go through `io`, but it won't touch one of the
+/// files `entry` examined.
+pub fn append(path: &Path, entry: &Entry) -> Result<(), Box<dyn Error>> {
+	if let Ok(log) = std::fs::canonicalize(path) {
+		let examined = entry
+			.inputs
+			.iter()
+			.any(|input| std::fs::canonicalize(&input.path).is_ok_and(|p| p == log));
+		if examined {
+			let message = format!("Refusing to append to {}, it is one of the files examined", path.display());
+			return Err(message.into());
+		}
+	}
+
+	let mut line = serde_json::to_vec(entry)?;
+	line.push(b'\n');
+
+	std::fs::OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(path)?
+		.write_all(&line)?;
+	Ok(())
+}
+
+/// Hashes every input of the log at `path` again.
+pub fn verify(path: &Path, timeout: Option<Duration>) -> Result<Vec<Verified>, Box<dyn Error>> {
+	let log = String::from_utf8(io::read(path, timeout)?)?;
+	let mut verified = Vec::new();
+	for (n, line) in log.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+		let entry: Entry = serde_json::from_str(line)
+			.map_err(|e| format!("{}:{}: not a custody entry. {e}", path.display(), n + 1))?;
+		for input in entry.inputs {
+			let check = match (&input.sha256, io::read(&input.path, timeout)) {
+				(None, _) => Check::Unrecorded,
+				(Some(_), Err(_)) => Check::Unreadable,
+				(Some(recorded), Ok(bytes)) if *recorded == hex(&Sha256::digest(&bytes)) => Check::Matches,
+				(Some(_), Ok(_)) => Check::Changed,
+			};
+			verified.push(Verified {
+				timestamp: entry.timestamp,
+				path: input.path,
+				check,
+			});
+		}
+	}
+	Ok(verified)
+}
@@ -0,0 +1,80 @@
+// `vanish daemon`: serves `rpc` over a UNIX socket so editor plugins and
+// scripts keep one warm process instead of spawning vanish per file. One
+// thread per connection, every connection shares the parsed pngs.
+
+use std::io::BufReader;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::rpc::Server;
+
+#[derive(Debug, Error)]
+pub enum Error {
+	#[error("A daemon is already listening on {0}")]
+	Running(PathBuf),
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+}
+
+/// `$XDG_RUNTIME_DIR/vanish.sock`, or in the temp dir without one.
+pub fn default_socket() -> PathBuf {
+	std::env::var_os("XDG_RUNTIME_DIR")
+		.map(PathBuf::from)
+		.unwrap_or_else(std::env::temp_dir)
+		.join("vanish.sock")
+}
+
+/// Serves requests on `socket` until one asks for `shutdown`.
+pub fn serve(socket: &Path, server: Server) -> Result<(), Error> {
+	crate::io::writable(socket)?;
+	if socket.exists() {
+		if UnixStream::connect(socket).is_ok() {
+			return Err(Error::Running(socket.to_owned()));
+		}
+		// left behind by a daemon that didn't get to clean up
+		std::fs::remove_file(socket)?;
+	}
+	let listener = UnixListener::bind(socket)?;
+	// the socket can read and write whatever its user can
+	std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))?;
+	eprintln!("Listening on {}", socket.display());
+
+	let server = Arc::new(server);
+	let stopping = Arc::new(AtomicBool::new(false));
+	for stream in listener.incoming() {
+		if stopping.load(Ordering::SeqCst) {
+			break;
+		}
+		let stream = match stream {
+			Ok(stream) => stream,
+			Err(e) => {
+				eprintln!("Failed to accept a connection. {e}");
+				continue;
+			},
+		};
+		let (server, stopping, socket) = (Arc::clone(&server), Arc::clone(&stopping), socket.to_owned());
+		std::thread::spawn(move || match connection(stream, &server) {
+			Ok(true) => {
+				stopping.store(true, Ordering::SeqCst);
+				// wakes up the accept loop
+				let _ = UnixStream::connect(socket);
+			},
+			Ok(false) => {},
+			Err(e) => eprintln!("Connection closed. {e}"),
+		});
+	}
+
+	std::fs::remove_file(socket)?;
+	Ok(())
+}
+
+/// Answers `stream`, true if it asked for `shutdown`.
+fn connection(stream: UnixStream, server: &Server) -> std::io::Result<bool> {
+	let writer = stream.try_clone()?;
+	server.serve(BufReader::new(stream), writer)
+}
@@ -0,0 +1,139 @@
+// Textual form of a png for code review: every chunk as a TOML table, data in
+// base64, well known chunks also decoded into readable fields.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use edpg::chunk::Chunk;
+use edpg::png::Png;
+use serde::{Deserialize, Serialize};
+use toml::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Dump {
+	#[serde(rename = "chunk")]
+	pub chunks: Vec<Entry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+	#[serde(rename = "type")]
+	pub chunk_type: String,
+	/// Informational, the data decides.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub length: Option<u32>,
+	/// Hex, checked against the data on restore when present.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub crc: Option<String>,
+	pub data: String,
+	/// Decoded view of the data, ignored on restore.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub fields: BTreeMap<String, Value>,
+}
+
+fn int(n: impl Into<i64>) -> Value {
+	Value::Integer(n.into())
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+	Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Readable fields of the chunk types reviewers usually care about, empty
+/// for everything else or when the data doesn't have the expected shape.
+fn fields(chunk: &Chunk) -> BTreeMap<String, Value> {
+	let data = chunk.data();
+	let mut fields = BTreeMap::new();
+	let mut set = |k: &str, v: Value| fields.insert(k.to_owned(), v);
+
+	match chunk.chunk_type().to_string().as_str() {
+		"IHDR" if data.len() == 13 => {
+			set("width", int(be_u32(data, 0).unwrap_or_default()));
+			set("height", int(be_u32(data, 4).unwrap_or_default()));
+			set("bit_depth", int(data[8]));
+			set("color_type", int(data[9]));
+			set("compression", int(data[10]));
+			set("filter", int(data[11]));
+			set("interlace", int(data[12]));
+		},
+		"tEXt" => {
+			if let Some(nul) = data.iter().position(|&b| b == 0) {
+				set("keyword", String::from_utf8_lossy(&data[..nul]).as_ref().into());
+				set("text", String::from_utf8_lossy(&data[nul + 1..]).as_ref().into());
+			}
+		},
+		"gAMA" if data.len() == 4 => {
+			set("gamma", int(be_u32(data, 0).unwrap_or_default()));
+		},
+		"pHYs" if data.len() == 9 => {
+			set("x", int(be_u32(data, 0).unwrap_or_default()));
+			set("y", int(be_u32(data, 4).unwrap_or_default()));
+			set("unit", int(data[8]));
+		},
+		"tIME" if data.len() == 7 => {
+			let year = u16::from_be_bytes([data[0], data[1]]);
+			set(
+				"time",
+				format!(
+					"{year:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+					data[2], data[3], data[4], data[5], data[6]
+				)
+				.into(),
+			);
+		},
+		_ => {},
+	};
+
+	fields
+}
+
+impl From<&Png> for Dump {
+	fn from(png: &Png) -> Self {
+		Self {
+			chunks: png
+				.chunks()
+				.iter()
+				.map(|chunk| Entry {
+					chunk_type: chunk.chunk_type().to_string(),
+					length: Some(chunk.length()),
+					crc: Some(format!("{:08x}", chunk.crc())),
+					data: BASE64.encode(chunk.data()),
+					fields: fields(chunk),
+				})
+				.collect(),
+		}
+	}
+}
+
+impl Dump {
+	pub fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+		Ok(toml::to_string(self)?)
+	}
+
+	pub fn from_toml(s: &str) -> Result<Self, Box<dyn Error>> {
+		Ok(toml::from_str(s)?)
+	}
+
+	/// Rebuilds the png, byte for byte what was dumped unless the data was
+	/// edited.
+	pub fn to_png(&self) -> Result<Png, Box<dyn Error>> {
+		let mut chunks = Vec::with_capacity(self.chunks.len());
+		for (i, entry) in self.chunks.iter().enumerate() {
+			let chunk = Chunk::new(entry.chunk_type.parse()?, BASE64.decode(&entry.data)?);
+
+			if let Some(crc) = &entry.crc {
+				if u32::from_str_radix(crc, 16)? != chunk.crc() {
+					return Err(format!(
+						"Chunk {i} ({}): crc {crc} doesn't match its data, update or drop it.",
+						entry.chunk_type
+					)
+					.into());
+				}
+			}
+			chunks.push(chunk);
+		}
+		Ok(Png::from_chunks(chunks))
+	}
+}
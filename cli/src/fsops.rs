@@ -0,0 +1,59 @@
+// Filesystem capabilities that make big copies cheap, where there are any.
+//
+// There is no asking a filesystem whether it can clone files (btrfs, XFS,
+// APFS can, ext4 can't), trying is the probe: `reflink` fails with
+// `Unsupported` or the os error and the caller copies the bytes instead.
+
+use std::io;
+use std::path::Path;
+
+/// Creates `to` as a copy on write clone of `from`. No data is copied, both
+/// share their extents until one of them is written to.
+#[cfg(target_os = "linux")]
+pub fn reflink(from: &Path, to: &Path) -> io::Result<()> {
+	use std::os::fd::AsRawFd;
+
+	let source = std::fs::File::open(from)?;
+	let target = std::fs::File::create(to)?;
+	// SAFETY: both descriptors stay open for the whole call, FICLONE takes
+	// the source descriptor as its only argument.
+	let res = unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) };
+	if res == -1 {
+		let e = io::Error::last_os_error();
+		drop(target);
+		let _ = std::fs::remove_file(to);
+		return Err(e);
+	}
+
+	Ok(())
+}
+
+/// Creates `to` as a copy on write clone of `from`. No data is copied, both
+/// share their extents until one of them is written to.
+#[cfg(target_os = "macos")]
+pub fn reflink(from: &Path, to: &Path) -> io::Result<()> {
+	use std::ffi::CString;
+	use std::os::unix::ffi::OsStrExt;
+
+	let c_path = |p: &Path| {
+		CString::new(p.as_os_str().as_bytes())
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+	};
+	let (source, target) = (c_path(from)?, c_path(to)?);
+
+	// clonefile refuses to replace anything
+	match std::fs::remove_file(to) {
+		Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+		_ => {},
+	}
+	// SAFETY: both are valid nul terminated paths that outlive the call.
+	match unsafe { libc::clonefile(source.as_ptr(), target.as_ptr(), 0) } {
+		0 => Ok(()),
+		_ => Err(io::Error::last_os_error()),
+	}
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn reflink(_from: &Path, _to: &Path) -> io::Result<()> {
+	Err(io::ErrorKind::Unsupported.into())
+}
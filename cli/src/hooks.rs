@@ -0,0 +1,48 @@
+// User configured shell commands run after a successful operation.
+
+use std::path::Path;
+use std::process::Command;
+
+/// What a hook gets told about the operation, passed as `VANISH_HOOK_*`
+/// environment variables.
+#[derive(Debug)]
+pub struct Event<'a> {
+	pub operation: &'static str,
+	pub file: &'a Path,
+	pub output_file: Option<&'a Path>,
+	pub chunk_type: &'a str,
+	pub payload_size: usize,
+}
+
+/// Runs `cmd` through the platform shell. A failing hook is reported but
+/// doesn't fail the operation, that already happened.
+pub fn run(cmd: &str, event: &Event) {
+	#[cfg(unix)]
+	let mut command = {
+		let mut command = Command::new("sh");
+		command.arg("-c").arg(cmd);
+		command
+	};
+	#[cfg(windows)]
+	let mut command = {
+		let mut command = Command::new("cmd");
+		command.arg("/C").arg(cmd);
+		command
+	};
+
+	command
+		.env("VANISH_HOOK_OPERATION", event.operation)
+		.env("VANISH_HOOK_FILE", event.file)
+		.env(
+			"VANISH_HOOK_OUTPUT_FILE",
+			event.output_file.unwrap_or(event.file),
+		)
+		.env("VANISH_HOOK_CHUNK_TYPE", event.chunk_type)
+		.env("VANISH_HOOK_PAYLOAD_SIZE", event.payload_size.to_string());
+
+	match command.status() {
+		Ok(status) if status.success() => {},
+		Ok(status) => eprintln!("{} hook `{cmd}` exited with {status}", event.operation),
+		Err(e) => eprintln!("{} hook `{cmd}` failed to start. {e}", event.operation),
+	}
+}
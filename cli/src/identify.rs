@@ -0,0 +1,199 @@
+// Tells what a file is and how vanish can hide something in it, if at all.
+
+use std::fmt::Display;
+
+use edpg::icon::{self, Icon};
+use edpg::png::Png;
+use edpg::{bmff, flac, pdf, polyglot, svg, wav};
+use serde::Serialize;
+
+/// Data of a single png chunk can't be longer than this, per the spec.
+pub const MAX_CHUNK_BYTES: usize = i32::MAX as usize;
+
+#[derive(Debug, Serialize)]
+pub struct Mode {
+	pub name: &'static str,
+	/// Payload bytes it takes, `None` when there is no practical limit.
+	pub capacity: Option<usize>,
+	pub note: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+	pub container: &'static str,
+	pub supported: bool,
+	pub modes: Vec<Mode>,
+}
+
+impl Display for Report {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let supported = match self.supported {
+			true => "supported",
+			false => "not supported",
+		};
+		write!(f, "{}, {supported}", self.container)?;
+		for mode in &self.modes {
+			let capacity = match mode.capacity {
+				Some(bytes) => format!("{bytes} bytes"),
+				None => "unlimited".to_owned(),
+			};
+			write!(f, "\n  {:<10} {capacity:<18} {}", mode.name, mode.note)?;
+		}
+		Ok(())
+	}
+}
+
+fn chunk_mode(note: String) -> Mode {
+	Mode {
+		name: "chunk",
+		capacity: Some(MAX_CHUNK_BYTES),
+		note,
+	}
+}
+
+fn unsupported(container: &'static str) -> Report {
+	Report {
+		container,
+		supported: false,
+		modes: Vec::new(),
+	}
+}
+
+/// What `bytes` is, judged by its magic bytes.
+pub fn identify(bytes: &[u8]) -> Report {
+	if bytes.starts_with(&Png::STANDARD_HEADER) {
+		let mut modes = vec![chunk_mode("per chunk, as many chunks as needed".to_owned())];
+		modes.push(Mode {
+			name: "polyglot",
+			capacity: None,
+			note: match polyglot::find_zip(bytes) {
+				Ok(Some(offset)) => format!("zip archive already appended at offset {offset}"),
+				_ => "zip archive after IEND".to_owned(),
+			},
+		});
+		return Report {
+			container: "PNG",
+			supported: true,
+			modes,
+		};
+	}
+
+	if let Some(format) = Png::multi_image_format(bytes) {
+		// same chunk layout, but not a png any tool here writes
+		return unsupported(match format {
+			"MNG" => "MNG (multiple-image network graphics)",
+			_ => "JNG (jpeg network graphics)",
+		});
+	}
+
+	if icon::is_icon(bytes) {
+		let container = match bytes.starts_with(b"icns") {
+			true => "ICNS icon",
+			false => "ICO icon",
+		};
+		let Ok(icon) = Icon::parse(bytes) else {
+			return unsupported(container);
+		};
+		let pngs: Vec<String> = (0..icon.images().len())
+			.filter(|&i| icon.png(i).is_ok())
+			.map(|i| i.to_string())
+			.collect();
+		return Report {
+			container,
+			supported: !pngs.is_empty(),
+			modes: match pngs.is_empty() {
+				true => Vec::new(),
+				false => vec![chunk_mode(format!(
+					"per chunk, png images for --inner-index: {}",
+					pngs.join(", ")
+				))],
+			},
+		};
+	}
+
+	if bmff::is_bmff(bytes) {
+		return Report {
+			container: "ISO base media (MP4/MOV)",
+			supported: bmff::boxes(bytes).is_ok(),
+			modes: vec![Mode {
+				name: "uuid-box",
+				capacity: None,
+				note: "uuid box at the end of the file".to_owned(),
+			}],
+		};
+	}
+
+	if wav::is_wav(bytes) {
+		return Report {
+			container: "WAV",
+			supported: wav::chunks(bytes).is_ok(),
+			modes: vec![Mode {
+				name: "info-entry",
+				capacity: None,
+				note: "LIST/INFO entry at the end of the file".to_owned(),
+			}],
+		};
+	}
+
+	if flac::is_flac(bytes) {
+		return Report {
+			container: "FLAC",
+			supported: flac::blocks(bytes).is_ok(),
+			modes: vec![Mode {
+				name: "application-block",
+				capacity: Some(flac::MAX_BLOCK_BYTES - 8),
+				note: "APPLICATION metadata block".to_owned(),
+			}],
+		};
+	}
+
+	if pdf::is_pdf(bytes) {
+		return Report {
+			container: "PDF",
+			supported: pdf::trailer(bytes).is_ok(),
+			modes: vec![Mode {
+				name: "incremental-update",
+				capacity: None,
+				note: "unreferenced stream in an appended update".to_owned(),
+			}],
+		};
+	}
+
+	if svg::is_svg(bytes) {
+		return Report {
+			container: "SVG",
+			supported: svg::chunk_types(bytes).is_ok(),
+			modes: vec![Mode {
+				name: "metadata-element",
+				capacity: None,
+				note: "base64 in a <metadata> element before the root closes".to_owned(),
+			}],
+		};
+	}
+
+	let container = match bytes {
+		[0xFF, 0xD8, 0xFF, ..] => "JPEG",
+		[b'G', b'I', b'F', b'8', ..] => "GIF",
+		[b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "WebP",
+		[b'P', b'K', 3, 4, ..] => "ZIP archive (can be appended to a png with `polyglot`)",
+		[0x1F, 0x8B, ..] => "gzip",
+		_ => match std::str::from_utf8(bytes) {
+			Ok(text) if !text.is_empty() && !text.contains('\0') => {
+				return Report {
+					container: "text",
+					supported: true,
+					modes: vec![Mode {
+						name: "textstego",
+						capacity: None,
+						note: format!(
+							"zero width characters over {} spaces, 24 bytes per payload byte",
+							text.matches(' ').count()
+						),
+					}],
+				};
+			},
+			_ => "unknown",
+		},
+	};
+	unsupported(container)
+}
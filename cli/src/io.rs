@@ -0,0 +1,601 @@
+// Every file access of the cli goes through here so a stuck mount (NFS, fuse,
+// ...) can't hang a command forever.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use edpg::chunk::Chunk;
+use edpg::png::{ChunkReader, ParseOptions, Png, PngError};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::fsops;
+
+#[derive(Debug, Error)]
+pub enum Error {
+	#[error("Timed out after {0:?} while accessing {1}")]
+	TimedOut(Duration, PathBuf),
+	#[error("{0}")]
+	Io(#[from] std::io::Error),
+	#[cfg(feature = "http")]
+	#[error("Failed to fetch {0}. {1}")]
+	Http(String, Box<ureq::Error>),
+	#[cfg(feature = "http")]
+	#[error("{0} is larger than {MAX_DOWNLOAD_BYTES} bytes")]
+	TooLarge(String),
+	#[cfg(feature = "s3")]
+	#[error("{0}")]
+	S3(#[from] crate::s3::Error),
+}
+
+/// Refuse downloads bigger than this, a carrier image has no business being
+/// larger.
+#[cfg(feature = "http")]
+pub const MAX_DOWNLOAD_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Set by `--read-only`, every write below fails from then on.
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Files opened for reading with `--read-only`, for the custody log.
+static EXAMINED: Mutex<BTreeMap<PathBuf, Option<Hashed>>> = Mutex::new(BTreeMap::new());
+
+/// A file as it was read with `--read-only`.
+#[derive(Debug, Clone, Copy)]
+pub struct Hashed {
+	pub size: u64,
+	pub sha256: [u8; 32],
+}
+
+impl Hashed {
+	fn new(bytes: &[u8]) -> Self {
+		Self {
+			size: bytes.len() as u64,
+			sha256: Sha256::digest(bytes).into(),
+		}
+	}
+}
+
+/// Makes every write of the cli fail until it exits, there is no way back.
+pub fn set_read_only() {
+	READ_ONLY.store(true, Ordering::SeqCst);
+}
+
+/// Fails if `path` is not to be written, with `--read-only`. Anything that
+/// writes checks it first, here or where it goes around this module.
+pub fn writable(path: &Path) -> std::io::Result<()> {
+	match READ_ONLY.load(Ordering::SeqCst) {
+		true => Err(std::io::Error::new(
+			std::io::ErrorKind::PermissionDenied,
+			format!("Refusing to write {} with --read-only", path.display()),
+		)),
+		false => Ok(()),
+	}
+}
+
+/// Every file opened for reading since `set_read_only`, see `custody`. `None`
+/// for those that couldn't be read.
+pub fn examined() -> Vec<(PathBuf, Option<Hashed>)> {
+	EXAMINED
+		.lock()
+		.expect("not poisoned")
+		.iter()
+		.map(|(path, hashed)| (path.clone(), *hashed))
+		.collect()
+}
+
+/// Records what `path` was the first time it was read in full, with
+/// `--read-only`. `hashed` is only called then.
+fn examine(path: &Path, hashed: impl FnOnce() -> Option<Hashed>) {
+	if READ_ONLY.load(Ordering::SeqCst) {
+		let mut examined = EXAMINED.lock().expect("not poisoned");
+		let entry = examined.entry(path.to_owned()).or_default();
+		if entry.is_none() {
+			*entry = hashed();
+		}
+	}
+}
+
+/// Opens `path` for reading. With `--read-only` it goes into `examined`,
+/// hashed through this very file before anything else reads it, so whatever
+/// part of it the command looks at was in the hash.
+fn open(path: &Path) -> std::io::Result<std::fs::File> {
+	use std::io::Seek;
+
+	let opened = open_untracked(path);
+	let examined = EXAMINED
+		.lock()
+		.expect("not poisoned")
+		.get(path)
+		.is_some_and(Option::is_some);
+	if !READ_ONLY.load(Ordering::SeqCst) || examined {
+		return opened;
+	}
+
+	let hashed = opened.and_then(|mut file| {
+		let mut hasher = Sha256::new();
+		let size = std::io::copy(&mut file, &mut hasher)?;
+		file.rewind()?;
+		Ok((file, Hashed {
+			size,
+			sha256: hasher.finalize().into(),
+		}))
+	});
+	examine(path, || hashed.as_ref().ok().map(|(_, hashed)| *hashed));
+	hashed.map(|(file, _)| file)
+}
+
+/// `open` without `examined`. With `--read-only` the access time is left
+/// alone where the system lets us, on linux only for files we own.
+fn open_untracked(path: &Path) -> std::io::Result<std::fs::File> {
+	#[cfg(target_os = "linux")]
+	if READ_ONLY.load(Ordering::SeqCst) {
+		use std::os::unix::fs::OpenOptionsExt;
+
+		let opened = std::fs::OpenOptions::new()
+			.read(true)
+			.custom_flags(libc::O_NOATIME)
+			.open(path);
+		match opened {
+			Err(e) if e.raw_os_error() == Some(libc::EPERM) => {},
+			opened => return opened,
+		}
+	}
+	std::fs::File::open(path)
+}
+
+/// Runs `op` on a worker thread and waits at most `timeout` for it.
+/// With no timeout the operation runs on the current thread.
+///
+/// On timeout the worker is left behind, there is no way to cancel a blocked
+/// syscall, but the cli is free to bail out.
+fn with_deadline<T, F>(path: &Path, timeout: Option<Duration>, op: F) -> Result<T, Error>
+where
+	T: Send + 'static,
+	F: FnOnce() -> std::io::Result<T> + Send + 'static,
+{
+	let Some(timeout) = timeout else {
+		return Ok(op()?);
+	};
+
+	let (tx, rx) = mpsc::channel();
+	thread::spawn(move || {
+		// receiver might be gone already, nothing to do about it
+		let _ = tx.send(op());
+	});
+
+	match rx.recv_timeout(timeout) {
+		Ok(res) => Ok(res?),
+		Err(_) => Err(Error::TimedOut(timeout, path.to_path_buf())),
+	}
+}
+
+/// Whether `path` is an `s3://` object rather than a file, see `s3`. Objects
+/// have no timestamps or permissions to keep and are never streamed from.
+pub fn is_remote(path: &Path) -> bool {
+	#[cfg(feature = "s3")]
+	return crate::s3::is_s3(path);
+	#[cfg(not(feature = "s3"))]
+	{
+		let _ = path;
+		false
+	}
+}
+
+/// Whether something is at `path`, giving up after `timeout`.
+pub fn exists(path: &Path, timeout: Option<Duration>) -> Result<bool, Error> {
+	#[cfg(feature = "s3")]
+	if is_remote(path) {
+		return Ok(crate::s3::len(path, timeout)?.is_some());
+	}
+	let owned = path.to_path_buf();
+	with_deadline(path, timeout, move || owned.try_exists())
+}
+
+/// Reads the whole file, giving up after `timeout`.
+pub fn read(path: &Path, timeout: Option<Duration>) -> Result<Vec<u8>, Error> {
+	#[cfg(feature = "s3")]
+	if is_remote(path) {
+		let bytes = crate::s3::read(path, timeout)?;
+		examine(path, || Some(Hashed::new(&bytes)));
+		return Ok(bytes);
+	}
+	let owned = path.to_path_buf();
+	with_deadline(path, timeout, move || {
+		use std::io::Read;
+
+		// hashed as read rather than read twice
+		let bytes = open_untracked(&owned).and_then(|mut file| {
+			let mut bytes = Vec::new();
+			file.read_to_end(&mut bytes)?;
+			Ok(bytes)
+		});
+		examine(&owned, || bytes.as_deref().ok().map(Hashed::new));
+		bytes
+	})
+}
+
+/// Size of the file in bytes, giving up after `timeout`.
+pub fn len(path: &Path, timeout: Option<Duration>) -> Result<u64, Error> {
+	#[cfg(feature = "s3")]
+	if is_remote(path) {
+		return crate::s3::len(path, timeout)?
+			.ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound).into());
+	}
+	let owned = path.to_path_buf();
+	with_deadline(path, timeout, move || Ok(std::fs::metadata(owned)?.len()))
+}
+
+/// Metadata of the file, giving up after `timeout`.
+pub fn metadata(path: &Path, timeout: Option<Duration>) -> Result<std::fs::Metadata, Error> {
+	let owned = path.to_path_buf();
+	with_deadline(path, timeout, move || std::fs::metadata(owned))
+}
+
+/// The first `len` bytes of the file, fewer if it is shorter, giving up after
+/// `timeout`. Enough to tell what kind of file it is.
+pub fn read_head(path: &Path, len: usize, timeout: Option<Duration>) -> Result<Vec<u8>, Error> {
+	#[cfg(feature = "s3")]
+	if is_remote(path) {
+		return Ok(crate::s3::read_head(path, len, timeout)?);
+	}
+	let owned = path.to_path_buf();
+	with_deadline(path, timeout, move || {
+		use std::io::Read;
+
+		let mut head = Vec::with_capacity(len);
+		open(&owned)?
+			.take(len as u64)
+			.read_to_end(&mut head)?;
+		Ok(head)
+	})
+}
+
+/// The file from `offset` on, giving up after `timeout`.
+pub fn read_from(path: &Path, offset: u64, timeout: Option<Duration>) -> Result<Vec<u8>, Error> {
+	let owned = path.to_path_buf();
+	with_deadline(path, timeout, move || {
+		use std::io::{Read, Seek, SeekFrom};
+
+		let mut file = open(&owned)?;
+		file.seek(SeekFrom::Start(offset))?;
+		let mut rest = Vec::new();
+		file.read_to_end(&mut rest)?;
+		Ok(rest)
+	})
+}
+
+/// Reads `path` only as far as the first chunk of `chunk_type`, giving up
+/// after `timeout`.
+pub fn find_chunk(
+	path: &Path,
+	chunk_type: &str,
+	options: &ParseOptions,
+	timeout: Option<Duration>,
+) -> Result<Result<Option<Chunk>, PngError>, Error> {
+	let owned = path.to_path_buf();
+	let chunk_type = chunk_type.to_owned();
+	let options = *options;
+	with_deadline(path, timeout, move || {
+		let file = std::io::BufReader::new(open(&owned)?);
+		Ok(Png::find_chunk_streaming(file, &chunk_type, &options))
+	})
+}
+
+/// Chunks of the png at `path`, read one at a time, see
+/// `Png::chunks_from_reader`. Only opening it is bound by `timeout`.
+pub fn chunks(
+	path: &Path,
+	options: &ParseOptions,
+	timeout: Option<Duration>,
+) -> Result<Result<ChunkReader<std::io::BufReader<std::fs::File>>, PngError>, Error> {
+	let owned = path.to_path_buf();
+	let options = *options;
+	with_deadline(path, timeout, move || {
+		let file = std::io::BufReader::new(open(&owned)?);
+		Ok(Png::chunks_from_reader(file, &options))
+	})
+}
+
+/// Creates (or truncates) `path` and writes `bytes` into it, giving up after
+/// `timeout`.
+pub fn write(path: &Path, bytes: Vec<u8>, timeout: Option<Duration>) -> Result<(), Error> {
+	writable(path)?;
+	#[cfg(feature = "s3")]
+	if is_remote(path) {
+		return Ok(crate::s3::write(path, timeout, |upload| {
+			std::io::Write::write_all(upload, &bytes)
+		})?);
+	}
+	let owned = path.to_path_buf();
+	with_deadline(path, timeout, move || std::fs::write(owned, bytes))
+}
+
+/// Streams `png` into a sibling temp file and renames it over `path`, so an
+/// interrupted write never leaves a half written png behind. Gives up after
+/// `timeout`.
+///
+/// With `preserve_metadata` a file being replaced keeps its timestamps,
+/// permissions, owner and extended attributes, in-place edits don't show up
+/// as freshly modified.
+pub fn write_png(
+	path: &Path,
+	png: Png,
+	timeout: Option<Duration>,
+	preserve_metadata: bool,
+) -> Result<(), Error> {
+	#[cfg(feature = "s3")]
+	if is_remote(path) {
+		writable(path)?;
+		return Ok(crate::s3::write(path, timeout, |upload| png.write_to(upload))?);
+	}
+	replace_with(path, timeout, preserve_metadata, move |tmp| {
+		create_with(tmp, |file| png.write_to(file))
+	})
+}
+
+/// Like `write_png`, for bytes that are not a png of their own, eg: an icon
+/// wrapping one.
+pub fn replace(
+	path: &Path,
+	bytes: Vec<u8>,
+	timeout: Option<Duration>,
+	preserve_metadata: bool,
+) -> Result<(), Error> {
+	#[cfg(feature = "s3")]
+	if is_remote(path) {
+		return write(path, bytes, timeout);
+	}
+	replace_with(path, timeout, preserve_metadata, move |tmp| {
+		create_with(tmp, |mut file| {
+			use std::io::Write;
+
+			file.write_all(&bytes)?;
+			file.flush()
+		})
+	})
+}
+
+/// Writes `from` to `to` with `chunks` put in before IEND, without parsing or
+/// holding the png in memory: the bytes around them are copied as they
+/// are. Same temp file and rename as `write_png`, `from` and `to` may be the
+/// same file.
+///
+/// Where the filesystem can clone files (see `fsops`) the bytes before IEND
+/// aren't even copied, only the tail is written.
+pub fn insert_before_iend(
+	from: &Path,
+	to: &Path,
+	chunks: Vec<Chunk>,
+	timeout: Option<Duration>,
+	preserve_metadata: bool,
+) -> Result<Result<(), PngError>, Error> {
+	let owned = from.to_path_buf();
+	let iend = with_deadline(from, timeout, move || {
+		let file = std::io::BufReader::new(open(&owned)?);
+		Ok(Png::iend_offset(file))
+	})?;
+	let iend = match iend {
+		Ok(Some(iend)) => iend,
+		// no IEND, the file is cut short
+		Ok(None) => return Ok(Err(PngError::InvalidLength)),
+		Err(e) => return Ok(Err(e)),
+	};
+
+	let owned = from.to_path_buf();
+	replace_with(to, timeout, preserve_metadata, move |tmp| {
+		use std::io::{Read, Seek, SeekFrom, Write};
+
+		let mut original = open(&owned)?;
+		if fsops::reflink(&owned, tmp).is_ok() {
+			let file = std::fs::OpenOptions::new().write(true).open(tmp)?;
+			let mut out = std::io::BufWriter::new(&file);
+			out.seek(SeekFrom::Start(iend))?;
+			original.seek(SeekFrom::Start(iend))?;
+			for chunk in &chunks {
+				chunk.write_to(&mut out)?;
+			}
+			std::io::copy(&mut original, &mut out)?;
+			out.flush()?;
+			drop(out);
+			return Ok(file);
+		}
+
+		create_with(tmp, |mut out| {
+			std::io::copy(&mut (&mut original).take(iend), &mut out)?;
+			for chunk in &chunks {
+				chunk.write_to(&mut out)?;
+			}
+			std::io::copy(&mut original, &mut out)?;
+			out.flush()
+		})
+	})
+	.map(Ok)
+}
+
+/// Creates `path` and fills it through `write`.
+fn create_with<F>(path: &Path, write: F) -> std::io::Result<std::fs::File>
+where
+	F: FnOnce(std::io::BufWriter<&std::fs::File>) -> std::io::Result<()>,
+{
+	let file = std::fs::File::create(path)?;
+	write(std::io::BufWriter::new(&file))?;
+	Ok(file)
+}
+
+/// Has `write` fill a sibling temp file of `path`, handed its path, then
+/// syncs it and renames it over `path`.
+fn replace_with<F>(
+	path: &Path,
+	timeout: Option<Duration>,
+	preserve_metadata: bool,
+	write: F,
+) -> Result<(), Error>
+where
+	F: FnOnce(&Path) -> std::io::Result<std::fs::File> + Send + 'static,
+{
+	writable(path)?;
+	let owned = path.to_path_buf();
+	with_deadline(path, timeout, move || {
+		let mut name = std::ffi::OsString::from(".");
+		name.push(owned.file_name().unwrap_or_default());
+		name.push(".vanish-tmp");
+		let tmp = owned.with_file_name(name);
+
+		let written = (|| {
+			let file = write(&tmp)?;
+			file.sync_all()?;
+
+			if preserve_metadata {
+				match std::fs::metadata(&owned) {
+					Ok(metadata) => copy_metadata(&owned, &metadata, &tmp)?,
+					Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+					Err(e) => return Err(e),
+				}
+			}
+			std::fs::rename(&tmp, &owned)
+		})();
+
+		if written.is_err() {
+			let _ = std::fs::remove_file(&tmp);
+		}
+		written
+	})
+}
+
+/// Gives `to` the access and modification times of `reference`, giving up
+/// after `timeout`.
+pub fn copy_times(reference: &Path, to: &Path, timeout: Option<Duration>) -> Result<(), Error> {
+	writable(to)?;
+	let (reference, owned) = (reference.to_path_buf(), to.to_path_buf());
+	with_deadline(to, timeout, move || {
+		let metadata = std::fs::metadata(reference)?;
+		filetime::set_file_times(
+			owned,
+			filetime::FileTime::from_last_access_time(&metadata),
+			filetime::FileTime::from_last_modification_time(&metadata),
+		)
+	})
+}
+
+/// Gives `to` the timestamps, permissions, owner and xattrs of `from`.
+/// Owner and xattrs are best effort, they need privileges or filesystem
+/// support the user may not have.
+fn copy_metadata(from: &Path, metadata: &std::fs::Metadata, to: &Path) -> std::io::Result<()> {
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+
+		let _ = std::os::unix::fs::chown(to, Some(metadata.uid()), Some(metadata.gid()));
+		if let Ok(names) = xattr::list(from) {
+			for name in names {
+				if let Ok(Some(value)) = xattr::get(from, &name) {
+					let _ = xattr::set(to, &name, &value);
+				}
+			}
+		}
+	}
+	#[cfg(not(unix))]
+	let _ = from;
+
+	std::fs::set_permissions(to, metadata.permissions())?;
+	// last, anything above may have bumped them
+	filetime::set_file_times(
+		to,
+		filetime::FileTime::from_last_access_time(metadata),
+		filetime::FileTime::from_last_modification_time(metadata),
+	)
+}
+
+/// Downloads `url` into memory. `timeout` bounds the whole request.
+#[cfg(feature = "http")]
+pub fn fetch(url: &str, timeout: Option<Duration>) -> Result<Vec<u8>, Error> {
+	use std::io::Read;
+
+	let mut agent = ureq::AgentBuilder::new();
+	if let Some(timeout) = timeout {
+		agent = agent.timeout(timeout);
+	}
+
+	let response = agent
+		.build()
+		.get(url)
+		.call()
+		.map_err(|e| Error::Http(url.to_owned(), Box::new(e)))?;
+
+	let mut bytes = Vec::new();
+	response
+		.into_reader()
+		.take(MAX_DOWNLOAD_BYTES + 1)
+		.read_to_end(&mut bytes)?;
+
+	if bytes.len() as u64 > MAX_DOWNLOAD_BYTES {
+		return Err(Error::TooLarge(url.to_owned()));
+	}
+
+	Ok(bytes)
+}
+
+/// Paths from `reader`, one per `separator` delimited record, yielded as they
+/// arrive. Without a separator NUL is used if the first buffered bytes hold
+/// one, newlines otherwise. Empty records are skipped. Paths are taken as raw
+/// bytes so names that aren't valid UTF-8 survive on unix.
+pub fn file_list<R: std::io::BufRead>(
+	mut reader: R,
+	separator: Option<u8>,
+) -> Result<impl Iterator<Item = Result<PathBuf, Error>>, Error> {
+	let separator = match separator {
+		Some(separator) => separator,
+		None if reader.fill_buf()?.contains(&0) => b'\0',
+		None => b'\n',
+	};
+
+	Ok(reader
+		.split(separator)
+		.map(move |record| {
+			let mut bytes = record?;
+			if separator == b'\n' && bytes.last() == Some(&b'\r') {
+				bytes.pop();
+			}
+			Ok::<_, Error>(bytes)
+		})
+		.filter(|record| !matches!(record, Ok(bytes) if bytes.is_empty()))
+		.map(|record| bytes_to_path(record?)))
+}
+
+#[cfg(unix)]
+fn bytes_to_path(bytes: Vec<u8>) -> Result<PathBuf, Error> {
+	use std::os::unix::ffi::OsStringExt;
+	Ok(std::ffi::OsString::from_vec(bytes).into())
+}
+
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: Vec<u8>) -> Result<PathBuf, Error> {
+	String::from_utf8(bytes)
+		.map(PathBuf::from)
+		.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e).into())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn paths(input: &[u8], separator: Option<u8>) -> Vec<PathBuf> {
+		file_list(input, separator).unwrap().collect::<Result<_, _>>().unwrap()
+	}
+
+	#[test]
+	fn test_file_list() {
+		let expected = [PathBuf::from("a.png"), PathBuf::from("b c.png")];
+		assert_eq!(paths(b"a.png\nb c.png\n", None), expected);
+		assert_eq!(paths(b"a.png\r\nb c.png\r\n\r\n", None), expected);
+		assert_eq!(paths(b"a.png\0b c.png\0", None), expected);
+		// NUL separated names may hold newlines and carriage returns
+		assert_eq!(paths(b"a\n.png\0b\r\0", None), [PathBuf::from("a\n.png"), PathBuf::from("b\r")]);
+		assert_eq!(paths(b"a.png\nb c.png", Some(b'\0')), [PathBuf::from("a.png\nb c.png")]);
+		assert!(paths(b"\n\n", None).is_empty());
+	}
+}
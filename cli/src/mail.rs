@@ -0,0 +1,243 @@
+// Emails with a png attached, for `mail-wrap`. The little MIME it takes is
+// written by hand, signing is left to `openssl smime`.
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// Longest line base64 is wrapped at, RFC 2045.
+const LINE_LEN: usize = 76;
+
+#[derive(Debug, Error)]
+pub enum Error {
+	/// Would let whatever follows the line break pass as more headers.
+	#[error("{0} can't contain line breaks")]
+	LineBreak(&'static str),
+	#[error("Failed to run openssl. {0}")]
+	Openssl(std::io::Error),
+	#[error("openssl failed to sign. {0}")]
+	Sign(String),
+}
+
+#[derive(Debug)]
+pub struct Attachment {
+	pub name: String,
+	pub content_type: &'static str,
+	pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct Mail {
+	pub from: Option<String>,
+	pub to: Vec<String>,
+	pub subject: String,
+	pub body: String,
+	pub attachment: Attachment,
+}
+
+/// Certificate and key to sign with, PEM files as `openssl smime` takes them.
+#[derive(Debug)]
+pub struct Signer<'a> {
+	pub cert: &'a Path,
+	pub key: &'a Path,
+}
+
+impl Mail {
+	/// The whole message, as an .eml file has it.
+	pub fn to_eml(&self, signer: Option<&Signer>) -> Result<Vec<u8>, Error> {
+		let mut eml = self.headers()?.into_bytes();
+		match signer {
+			Some(signer) => eml.extend(sign(&self.content(), signer)?),
+			None => eml.extend(self.content()),
+		}
+		Ok(eml)
+	}
+
+	fn headers(&self) -> Result<String, Error> {
+		let mut headers = String::new();
+		if let Some(from) = &self.from {
+			headers += &format!("From: {}\r\n", single_line("From", from)?);
+		}
+		let to: Vec<&str> = self
+			.to
+			.iter()
+			.map(|to| single_line("To", to))
+			.collect::<Result<_, _>>()?;
+		headers += &format!("To: {}\r\n", to.join(", "));
+		headers += &format!("Subject: {}\r\n", encoded_word(single_line("Subject", &self.subject)?));
+		headers += &format!("Date: {}\r\n", date(SystemTime::now()));
+		headers += "MIME-Version: 1.0\r\n";
+		Ok(headers)
+	}
+
+	/// The multipart body with its own headers, what gets signed.
+	fn content(&self) -> Vec<u8> {
+		let boundary = self.boundary();
+		let (body_encoding, body) = match self.body.is_ascii() && self.body.lines().all(|l| l.len() <= 998) {
+			true => ("7bit", self.body.replace("\r\n", "\n").replace('\n', "\r\n")),
+			false => ("base64", base64_lines(self.body.as_bytes())),
+		};
+
+		let mut content = format!("Content-Type: multipart/mixed; boundary=\"{boundary}\"\r\n\r\n");
+		content += &format!("--{boundary}\r\n");
+		content += "Content-Type: text/plain; charset=utf-8\r\n";
+		content += &format!("Content-Transfer-Encoding: {body_encoding}\r\n\r\n{body}\r\n");
+		content += &format!("--{boundary}\r\n");
+		let name = parameter("name", &self.attachment.name);
+		content += &format!("Content-Type: {}; {name}\r\n", self.attachment.content_type);
+		content += "Content-Transfer-Encoding: base64\r\n";
+		let filename = parameter("filename", &self.attachment.name);
+		content += &format!("Content-Disposition: attachment; {filename}\r\n\r\n");
+		content += &base64_lines(&self.attachment.data);
+		content += &format!("\r\n--{boundary}--\r\n");
+		content.into_bytes()
+	}
+
+	/// Taken from the attachment, base64 never contains `=_` so it can't
+	/// clash with it, the body is unlikely to.
+	fn boundary(&self) -> String {
+		let digest: String = Sha256::digest(&self.attachment.data)
+			.iter()
+			.take(12)
+			.map(|b| format!("{b:02x}"))
+			.collect();
+		format!("=_vanish_{digest}")
+	}
+}
+
+fn single_line<'a>(header: &'static str, value: &'a str) -> Result<&'a str, Error> {
+	match value.contains(['\r', '\n']) {
+		true => Err(Error::LineBreak(header)),
+		false => Ok(value),
+	}
+}
+
+/// `value` as is if it's ascii, an RFC 2047 encoded word otherwise.
+fn encoded_word(value: &str) -> String {
+	match value.is_ascii() {
+		true => value.to_owned(),
+		false => format!(
+			"=?utf-8?B?{}?=",
+			base64::engine::general_purpose::STANDARD.encode(value)
+		),
+	}
+}
+
+/// `key="value"`, or RFC 2231 percent encoded for anything but ascii.
+fn parameter(key: &str, value: &str) -> String {
+	if value.is_ascii() && !value.contains(|c: char| c.is_ascii_control()) {
+		return format!("{key}=\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""));
+	}
+	let encoded: String = value
+		.bytes()
+		.map(|b| match b.is_ascii_alphanumeric() || b".-_".contains(&b) {
+			true => (b as char).to_string(),
+			false => format!("%{b:02X}"),
+		})
+		.collect();
+	format!("{key}*=utf-8''{encoded}")
+}
+
+fn base64_lines(data: &[u8]) -> String {
+	let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+	let lines: Vec<&str> = encoded
+		.as_bytes()
+		.chunks(LINE_LEN)
+		.map(|l| std::str::from_utf8(l).expect("base64 is ascii"))
+		.collect();
+	lines.join("\r\n")
+}
+
+/// RFC 5322 date of `time`, in UTC.
+fn date(time: SystemTime) -> String {
+	const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+	const MONTHS: [&str; 12] = [
+		"Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+	];
+
+	let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+	let (days, secs) = (secs / 86400, secs % 86400);
+	// civil from days, http://howardhinnant.github.io/date_algorithms.html
+	let z = days + 719_468;
+	let era = z / 146_097;
+	let doe = z % 146_097;
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = yoe + era * 400 + u64::from(month <= 2);
+
+	format!(
+		"{}, {day:02} {} {year} {:02}:{:02}:{:02} +0000",
+		DAYS[(days % 7) as usize],
+		MONTHS[month as usize - 1],
+		secs / 3600,
+		secs / 60 % 60,
+		secs % 60
+	)
+}
+
+/// `content` signed, as a multipart/signed body with its headers.
+fn sign(content: &[u8], signer: &Signer) -> Result<Vec<u8>, Error> {
+	let mut child = Command::new("openssl")
+		.args(["smime", "-sign", "-binary", "-crlfeol", "-signer"])
+		.arg(signer.cert)
+		.arg("-inkey")
+		.arg(signer.key)
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(Error::Openssl)?;
+
+	let mut stdin = child.stdin.take().expect("piped");
+	// fed from another thread, openssl may write before it read it all
+	let (written, output) = std::thread::scope(|s| {
+		let writer = s.spawn(move || stdin.write_all(content));
+		let output = child.wait_with_output();
+		(writer.join().expect("doesn't panic"), output)
+	});
+	let output = output.map_err(Error::Openssl)?;
+	if !output.status.success() {
+		let message = String::from_utf8_lossy(&output.stderr);
+		return Err(Error::Sign(message.trim().to_owned()));
+	}
+	written.map_err(Error::Openssl)?;
+
+	// the headers carry MIME-Version already
+	let signed = output.stdout;
+	Ok(match signed.strip_prefix(b"MIME-Version: 1.0\r\n") {
+		Some(rest) => rest.to_vec(),
+		None => signed,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::*;
+
+	#[test]
+	fn test_date() {
+		let at = |secs| date(UNIX_EPOCH + Duration::from_secs(secs));
+		assert_eq!(at(0), "Thu, 01 Jan 1970 00:00:00 +0000");
+		assert_eq!(at(951_782_400), "Tue, 29 Feb 2000 00:00:00 +0000");
+		assert_eq!(at(1_709_251_199), "Thu, 29 Feb 2024 23:59:59 +0000");
+		assert_eq!(at(4_107_542_400), "Mon, 01 Mar 2100 00:00:00 +0000");
+	}
+
+	#[test]
+	fn test_parameter() {
+		assert_eq!(parameter("filename", "a.png"), r#"filename="a.png""#);
+		assert_eq!(parameter("filename", r#"say "hi"\.png"#), r#"filename="say \"hi\"\\.png""#);
+		assert_eq!(parameter("filename", "naïve a.png"), "filename*=utf-8''na%C3%AFve%20a.png");
+		assert_eq!(parameter("filename", "a\nb"), "filename*=utf-8''a%0Ab");
+	}
+}
@@ -1,62 +1,1564 @@
-use std::{
-	fs::{read, File},
-	io::Write,
-};
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use args::Cli;
-use clap::{error::Result, Parser};
-use edpg::{chunk::Chunk, png::Png};
+use args::{Cli, Commands, EmbedMode};
+use media::Media;
+use wrappers::Wrapper;
+use bytes::Bytes;
+use clap::error::{ContextKind, ContextValue, ErrorKind, Result};
+use clap::FromArgMatches;
+use edpg::{
+	chunk::Chunk,
+	chunk_type::ChunkType,
+	crypto::{self, totp::Totp},
+	detect,
+	exif,
+	fix,
+	format,
+	iter::ChunkIter,
+	mutate,
+	payload,
+	png::{ParseOptions, Png},
+	policy::Policy,
+	polyglot,
+	provenance::{self, Provenance, Seal},
+	repro,
+	roundtrip,
+	report::{Issue, Report, Severity},
+	signature::{self, Signature, SignatureFile},
+	steg,
+	text::Text,
+	textstego,
+};
 
 pub mod args;
+pub mod audit;
+pub mod batch;
+pub mod cache;
+pub mod check;
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+pub mod confirm;
+pub mod custody;
+#[cfg(unix)]
+pub mod daemon;
+pub mod dump;
+pub mod fsops;
+pub mod hooks;
+pub mod identify;
+pub mod io;
+pub mod mail;
+pub mod man;
+pub mod media;
+pub mod render;
+pub mod rpc;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod sandbox;
+pub mod sarif;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod shred;
+pub mod wrappers;
+
+/// Pngs from this size on get chunks appended without being parsed.
+const DIFFERENTIAL_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Global options every command runs with.
+struct Ctx {
+	timeout: Option<Duration>,
+	emit_repro: Option<PathBuf>,
+	parse: ParseOptions,
+	preserve_metadata: bool,
+	match_timestamps: Option<PathBuf>,
+	wrappers: wrappers::Options,
+	/// What the last loaded png came out of, so `save` can put it back.
+	wrapped: RefCell<Vec<Wrapper>>,
+	audit_log: Option<PathBuf>,
+	/// Builtin tool signatures and those of `--signatures`.
+	signatures: Vec<Signature>,
+	/// How `check` and `guard` print what they found.
+	format: render::Format,
+	/// Where to write what `check` and `guard` found, see `--sarif`.
+	sarif: Option<PathBuf>,
+	/// Everything `check` and `guard` found so far, for output formats that
+	/// print it all at the end.
+	found: RefCell<Report>,
+	/// Whether to ask before overwriting or destroying files.
+	confirm: confirm::Mode,
+	/// Fix chunk types with a lowercase third letter, see `--auto-correct`.
+	auto_correct: bool,
+	/// Earlier `check` results, with `--cache`.
+	cache: Option<cache::Cache>,
+	post_encode_hook: Option<String>,
+	post_decode_hook: Option<String>,
+	post_remove_hook: Option<String>,
+}
+
+impl Ctx {
+	/// Reads the png at `file`, taking it out of whatever it is wrapped in,
+	/// see `wrappers`.
+	fn load(&self, file: &Path) -> Result<Png, Box<dyn std::error::Error>> {
+		self.wrapped.borrow_mut().clear();
+		let (wrapped, bytes) = wrappers::unwrap_all(io::read(file, self.timeout)?, &self.wrappers)?;
+		*self.wrapped.borrow_mut() = wrapped;
+
+		// chunks keep slices of the file as read, no copy
+		let bytes = Bytes::from(bytes);
+		Png::parse_bytes(&bytes, &self.parse).map_err(|e| {
+			if let Some(path) = &self.emit_repro {
+				match repro::minimize(&bytes).map(|repro| io::write(path, repro, self.timeout)) {
+					Some(Ok(())) => eprintln!("Wrote reproduction to {}", path.display()),
+					Some(Err(write_err)) => eprintln!("Failed to write reproduction. {write_err}"),
+					None => {},
+				}
+			}
+			e.into()
+		})
+	}
+
+	/// Whether appending to `file` is better done by `io::insert_before_iend`,
+	/// for plain pngs big enough that parsing and rewriting them hurts.
+	fn is_differential(&self, file: &Path) -> Result<bool, io::Error> {
+		Ok(!io::is_remote(file)
+			&& io::len(file, self.timeout)? >= DIFFERENTIAL_BYTES
+			&& io::read_head(file, 8, self.timeout)? == Png::STANDARD_HEADER)
+	}
+
+	/// What `daemon` and `ipc` answer requests with, parsing and writing
+	/// pngs the way the command line says.
+	fn rpc_server(&self) -> rpc::Server {
+		rpc::Server::new(rpc::Options {
+			parse: self.parse,
+			timeout: self.timeout,
+			preserve_metadata: self.preserve_metadata,
+		})
+	}
+
+	/// Which kind of media `file` is, `None` for pngs and anything else.
+	fn media(&self, file: &Path) -> Result<Option<Media>, io::Error> {
+		Ok(Media::detect(&io::read_head(file, media::HEAD_BYTES, self.timeout)?))
+	}
+
+	/// First chunk of `chunk_type` in `file`. Plain pngs are only read as far
+	/// as the chunk, icons and other media have to be loaded whole.
+	fn find_chunk(
+		&self,
+		file: &Path,
+		chunk_type: &str,
+	) -> Result<Option<Chunk>, Box<dyn std::error::Error>> {
+		if let Some(media) = self.media(file)? {
+			let bytes = io::read(file, self.timeout)?;
+			return match media.find(&bytes, chunk_type)? {
+				Some(data) => Ok(Some(Chunk::new(chunk_type.parse()?, data))),
+				None => Ok(None),
+			};
+		}
+		if self.wrappers.inner_index.is_none() && !io::is_remote(file) {
+			if let Ok(found) = io::find_chunk(file, chunk_type, &self.parse, self.timeout)? {
+				return Ok(found);
+			}
+		}
+
+		// not a plain png, or a broken one, `load` tells which
+		let png = self.load(file)?;
+		Ok(png
+			.find_nth_by_type(chunk_type, 0)
+			.map(|i| png.chunks()[i].clone()))
+	}
+
+	/// Writes `png` to `path`, see `io::write_png`. A png that was loaded
+	/// out of an archive or icon goes back into it.
+	fn save(&self, path: &Path, png: Png) -> Result<(), Box<dyn std::error::Error>> {
+		self.overwrite(path)?;
+		let wrapped = std::mem::take(&mut *self.wrapped.borrow_mut());
+		match wrapped.is_empty() {
+			true => io::write_png(path, png, self.timeout, self.preserve_metadata)?,
+			false => return self.write_bytes(path, wrappers::wrap_all(wrapped, png.as_bytes())?),
+		}
+		self.match_timestamps(path)
+	}
+
+	/// `save` for files that aren't a plain png.
+	fn save_bytes(&self, path: &Path, bytes: Vec<u8>) -> Result<(), Box<dyn std::error::Error>> {
+		self.overwrite(path)?;
+		self.write_bytes(path, bytes)
+	}
+
+	/// `save_bytes` without asking, for changes confirmed some other way.
+	fn write_bytes(&self, path: &Path, bytes: Vec<u8>) -> Result<(), Box<dyn std::error::Error>> {
+		io::replace(path, bytes, self.timeout, self.preserve_metadata)?;
+		self.match_timestamps(path)
+	}
+
+	/// Asks before `path` is written over, if it exists.
+	fn overwrite(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+		match io::exists(path, self.timeout)? {
+			true => Ok(confirm::require(&format!("Overwrite {}", path.display()), self.confirm)?),
+			false => Ok(()),
+		}
+	}
+
+	/// `chunk_type` if its reserved bit is valid, corrected with
+	/// `--auto-correct`. Written with it invalid, the png wouldn't parse.
+	fn chunk_type(&self, chunk_type: ChunkType) -> Result<ChunkType, Box<dyn std::error::Error>> {
+		match chunk_type.is_reserved_bit_valid() {
+			Ok(_) => Ok(chunk_type),
+			Err(_) if self.auto_correct => {
+				eprintln!("Using `{}` for `{chunk_type}`", chunk_type.corrected());
+				Ok(chunk_type.corrected())
+			},
+			Err(e) => Err(format!("{e} Pass --auto-correct to use that.").into()),
+		}
+	}
+
+	/// Prints `report` right away in text format, keeps it for `finish`
+	/// otherwise.
+	fn found(&self, report: Report) -> Result<(), Box<dyn std::error::Error>> {
+		if self.format.is_streaming() {
+			print!("{}", render::render(&report, self.format)?);
+		}
+		if self.sarif.is_some() || !self.format.is_streaming() {
+			let found = std::mem::take(&mut *self.found.borrow_mut());
+			*self.found.borrow_mut() = found.merge(report);
+		}
+		Ok(())
+	}
+
+	/// Prints everything found in formats that need it all at once, and
+	/// writes it to `--sarif`.
+	fn finish(&self) -> Result<(), Box<dyn std::error::Error>> {
+		let found = self.found.borrow();
+		if !self.format.is_streaming() && found.files > 0 {
+			print!("{}", render::render(&found, self.format)?);
+		}
+		if let Some(path) = &self.sarif {
+			emit(path, render::render(&found, render::Format::Sarif)?.into_bytes(), self.timeout)?;
+		}
+		Ok(())
+	}
+
+	fn match_timestamps(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+		if let Some(reference) = &self.match_timestamps {
+			io::copy_times(reference, path, self.timeout)?;
+		}
+		Ok(())
+	}
+}
+
+/// The command line, `vanish file.png` meaning `vanish print file.png`.
+fn parse_args() -> Result<Cli, Box<dyn std::error::Error>> {
+	let mut argv = args::expand_argfiles(std::env::args_os())?;
+	let matches = match args::command().try_get_matches_from(&argv) {
+		Ok(matches) => matches,
+		Err(e) if e.kind() == ErrorKind::InvalidSubcommand => {
+			// only an existing file makes it a file rather than a typo
+			let file = match e.get(ContextKind::InvalidSubcommand) {
+				Some(ContextValue::String(file)) if Path::new(file).exists() => file,
+				_ => e.exit(),
+			};
+			let at = argv.iter().skip(1).position(|a| a == file.as_str()).map_or(1, |i| i + 1);
+			argv.insert(at, "print".into());
+			args::command().get_matches_from(argv)
+		},
+		Err(e) => e.exit(),
+	};
+	Ok(Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit()))
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-	let args = Cli::parse();
+	if std::env::args_os().nth(1).is_some_and(|a| a == sandbox::WORKER_ARG) {
+		sandbox::worker();
+	}
+	let args = parse_args()?;
+	if args.read_only {
+		read_only(&args)?;
+	}
+	let ctx = Ctx {
+		timeout: args.timeout,
+		emit_repro: args.emit_repro.clone(),
+		parse: ParseOptions {
+			verify_crc: !args.no_verify_crc,
+			max_prefix: args.max_prefix,
+		},
+		preserve_metadata: !args.no_preserve_metadata,
+		match_timestamps: args.match_timestamps.clone(),
+		wrappers: wrappers::Options {
+			keep_archives: args.no_unwrap,
+			inner_index: args.inner_index,
+		},
+		wrapped: RefCell::new(Vec::new()),
+		audit_log: args.audit_log.clone(),
+		signatures: signatures(args.signatures.as_deref(), args.timeout)?,
+		format: args.format,
+		sarif: args.sarif.clone(),
+		found: RefCell::new(Report::default()),
+		cache: match args.cache {
+			true => Some(cache::Cache::open(cache_path()?, args.timeout)),
+			false => None,
+		},
+		confirm: confirm::Mode::new(args.yes, args.no_input),
+		auto_correct: args.auto_correct,
+		post_encode_hook: args.post_encode_hook.clone(),
+		post_decode_hook: args.post_decode_hook.clone(),
+		post_remove_hook: args.post_remove_hook.clone(),
+	};
+
+	let Some(log) = args.custody_log.clone() else {
+		return exit(dispatch(args, &ctx)?);
+	};
+	let mut entry = custody::Entry::new();
+	let code = dispatch(args, &ctx);
+	entry.examined();
+	match &code {
+		Ok(0) => {},
+		Ok(_) => entry.status = batch::Status::Failed,
+		Err(e) => entry.failed(e.as_ref()),
+	}
+	custody::append(&log, &entry)?;
+	exit(code?)
+}
+
+/// Exits with `code`, through `main` returning for success.
+fn exit(code: i32) -> Result<(), Box<dyn std::error::Error>> {
+	match code {
+		0 => Ok(()),
+		code => std::process::exit(code),
+	}
+}
+
+/// Runs the command line, on a single file or as a batch. Returns the exit
+/// code.
+fn dispatch(args: Cli, ctx: &Ctx) -> Result<i32, Box<dyn std::error::Error>> {
+	// several files on the command line are a batch of their own
+	let named: Vec<PathBuf> = match &args.command {
+		Commands::Guard { file, more, .. } if !more.is_empty() => {
+			std::iter::once(file).chain(more).cloned().collect()
+		},
+		_ => Vec::new(),
+	};
+
+	if let Commands::Check {
+		file,
+		more,
+		jobs,
+		sandbox,
+	} = &args.command
+	{
+		if *sandbox && !sandbox::SUPPORTED {
+			return Err("`--sandbox` needs seccomp, only there on Linux (x86_64 and aarch64)".into());
+		}
+		if !more.is_empty() || file == Path::new("-") {
+			let files = match file == Path::new("-") {
+				true => listed_files(&args.files_from, args.null)?.collect::<Result<_, _>>()?,
+				false => std::iter::once(file).chain(more).cloned().collect(),
+			};
+			return check_all(files, *jobs, *sandbox, args.summary.as_deref(), ctx);
+		}
+	}
+
+	// commands that don't take a png, see `Commands::file`
+	if args.command.file() == Path::new("") {
+		return run(args.command, Path::new(""), ctx).map(|()| 0);
+	}
+
+	if named.is_empty() && args.command.file() != Path::new("-") {
+		let file = args.command.file().to_owned();
+		let res = audited(args.command, &file, ctx);
+		ctx.finish()?;
+		return res.map(|()| 0);
+	}
+
+	let options = batch::Options {
+		mode: match args.fail_fast {
+			true => batch::Mode::FailFast,
+			false => batch::Mode::KeepGoing,
+		},
+		rate: args.rate,
+		resume_from: args.resume_from.clone(),
+	};
+
+	let files = match named.is_empty() {
+		true => listed_files(&args.files_from, args.null)?,
+		false => Box::new(named.into_iter().map(Ok)),
+	};
+	let summary = batch::run(files, &options, |file| audited(args.command.clone(), file, ctx))?;
+
+	if let Some(path) = &args.summary {
+		emit(path, serde_json::to_vec_pretty(&summary)?, ctx.timeout)?;
+	}
+	ctx.finish()?;
+	if summary.failed > 0 {
+		eprintln!("{} ok, {} failed", summary.ok, summary.failed);
+	}
+	if summary.skipped > 0 {
+		eprintln!("{} skipped, already done", summary.skipped);
+	}
+	Ok(summary.exit_code())
+}
+
+/// Refuses what `--read-only` forbids up front, then has `io` fail any
+/// write that would still get through.
+fn read_only(args: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+	if args.command.writes() {
+		return Err(format!("`{}` writes files, it can't run with --read-only", args.command.name()).into());
+	}
+	io::set_read_only();
+	// before any work is done rather than when it's written at the end
+	for path in [&args.summary, &args.sarif].into_iter().flatten() {
+		if path != Path::new("-") {
+			io::writable(path)?;
+		}
+	}
+	Ok(())
+}
+
+/// The builtin signatures, and those in `path` if given.
+fn signatures(
+	path: Option<&Path>,
+	timeout: Option<Duration>,
+) -> Result<Vec<Signature>, Box<dyn std::error::Error>> {
+	let mut signatures = signature::builtin();
+	if let Some(path) = path {
+		let file: SignatureFile = toml::from_str(&String::from_utf8(io::read(path, timeout)?)?)?;
+		for s in &file.signatures {
+			s.validate()?;
+		}
+		signatures.extend(file.signatures);
+	}
+	Ok(signatures)
+}
+
+/// How `check` looks at files.
+fn check_options(ctx: &Ctx) -> check::Options {
+	check::Options {
+		parse: ctx.parse,
+		signatures: ctx.signatures.clone(),
+		sandbox: false,
+	}
+}
+
+fn cache_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+	Ok(cache::default_path().ok_or("No cache directory, neither XDG_CACHE_HOME nor HOME is set.")?)
+}
+
+/// `bytes` of `file` with the fixes for what guard finds in it applied,
+/// after showing and confirming each one, see `confirm`. The file is
+/// rewritten in place if any fix was taken.
+fn fixed(file: &Path, bytes: Vec<u8>, ctx: &Ctx) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+	// wrong crcs are something to fix, not a reason to give up
+	let lenient = ParseOptions {
+		verify_crc: false,
+		..ctx.parse
+	};
+	let findings = detect::scan_matching(&bytes, &lenient, &ctx.signatures)?;
+	let fixes = fix::plan(&bytes, &findings)?;
+
+	let mut picked = Vec::new();
+	for f in fixes {
+		let question = format!("{}: {f}", file.display());
+		if ctx.confirm == confirm::Mode::Yes {
+			eprintln!("{question}");
+		}
+		if confirm::ask(&question, ctx.confirm)? {
+			picked.push(f);
+		}
+	}
+	if picked.is_empty() {
+		return Ok(bytes);
+	}
 
-	match args.command {
+	// every fix was confirmed, no need to ask about the overwrite too
+	let bytes = fix::apply(&bytes, &picked)?;
+	ctx.write_bytes(file, bytes.clone())?;
+	eprintln!("{}: applied {} fixes", file.display(), picked.len());
+	Ok(bytes)
+}
+
+/// The zstd dictionary at `path`, if given.
+#[cfg(feature = "zstd")]
+fn dictionary(
+	path: Option<&Path>,
+	timeout: Option<Duration>,
+) -> Result<Option<edpg::compress::Dictionary>, Box<dyn std::error::Error>> {
+	match path {
+		Some(path) => Ok(Some(edpg::compress::Dictionary::parse(io::read(path, timeout)?)?)),
+		None => Ok(None),
+	}
+}
+
+/// Writes `bytes` to `path`, or to stdout for `-`.
+fn emit(path: &Path, bytes: Vec<u8>, timeout: Option<Duration>) -> Result<(), io::Error> {
+	match path == Path::new("-") {
+		true => Ok(std::io::stdout().write_all(&bytes)?),
+		false => io::write(path, bytes, timeout),
+	}
+}
+
+/// Message spread over `file` and `spread`, reporting on stderr whatever
+/// couldn't be read back intact.
+fn gather(file: &Path, spread: &[PathBuf], ctx: &Ctx) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+	let mut paths = Vec::new();
+	let mut pngs = Vec::new();
+	let mut issues = Vec::new();
+	for frame in spread_frames(file, spread) {
+		let path = frame.display().to_string();
+		match ctx.load(frame) {
+			Ok(png) => {
+				paths.push(path);
+				pngs.push(png);
+			},
+			Err(e) => issues.push(Issue::failed(&path, "unreadable", e.to_string())),
+		}
+	}
+
+	let gathered = steg::gather_damaged(&pngs)?;
+	issues.extend(gathered.issues(&paths.iter().map(String::as_str).collect::<Vec<_>>()));
+	if !issues.is_empty() {
+		eprint!("{}", render::render(&Report::file(issues), ctx.format)?);
+	}
+	Ok(gathered
+		.message
+		.ok_or("Too many pieces missing to put the message back together")?)
+}
+
+/// `file` followed by the pngs of `--spread`, `file` only once if the list
+/// has it too, eg: out of the same glob.
+fn spread_frames<'a>(file: &'a Path, spread: &'a [PathBuf]) -> impl Iterator<Item = &'a Path> {
+	std::iter::once(file).chain(spread.iter().map(PathBuf::as_path).filter(move |f| *f != file))
+}
+
+type FileList = Box<dyn Iterator<Item = Result<PathBuf, io::Error>>>;
+
+/// The paths listed on stdin or in `--files-from`. Paths come out as they are
+/// read, no need to wait for the whole list.
+fn listed_files(
+	files_from: &Path,
+	null: bool,
+) -> Result<FileList, Box<dyn std::error::Error>> {
+	let list: Box<dyn std::io::BufRead> = if files_from == Path::new("-") {
+		Box::new(std::io::stdin().lock())
+	} else {
+		Box::new(std::io::BufReader::new(std::fs::File::open(files_from)?))
+	};
+	Ok(Box::new(io::file_list(list, null.then_some(b'\0'))?))
+}
+
+/// `check` over several files, in parallel. Returns the exit code.
+fn check_all(
+	files: Vec<PathBuf>,
+	jobs: Option<usize>,
+	sandbox: bool,
+	summary: Option<&Path>,
+	ctx: &Ctx,
+) -> Result<i32, Box<dyn std::error::Error>> {
+	let options = check::Options {
+		sandbox,
+		..check_options(ctx)
+	};
+	let report = check::run(files, jobs, &options, ctx.timeout, ctx.cache.as_ref())?;
+	if let Some(cache) = &ctx.cache {
+		cache.save(ctx.timeout)?;
+	}
+	print!("{}", render::render(&report, ctx.format)?);
+
+	if let Some(path) = summary {
+		emit(path, serde_json::to_vec_pretty(&report)?, ctx.timeout)?;
+	}
+	if let Some(path) = &ctx.sarif {
+		emit(path, render::render(&report, render::Format::Sarif)?.into_bytes(), ctx.timeout)?;
+	}
+
+	let errors = report.count(Severity::Error);
+	eprintln!(
+		"{} files, {errors} errors, {} warnings",
+		report.files,
+		report.count(Severity::Warning)
+	);
+	Ok(match errors {
+		0 => 0,
+		_ => batch::EXIT_FAILURE,
+	})
+}
+
+/// Runs the command, recording it in the audit log if there is one.
+fn audited(command: Commands, file: &Path, ctx: &Ctx) -> Result<(), Box<dyn std::error::Error>> {
+	let Some(log) = &ctx.audit_log else {
+		return run(command, file, ctx);
+	};
+
+	let name = command.name();
+	// in a batch the command still names `-`, the output is the file at hand
+	let output_file = command.output_file().map(|output| match output == Path::new("-") {
+		true => file.to_owned(),
+		false => output.to_owned(),
+	});
+	let before = io::read(file, ctx.timeout).ok();
+	let mut entry = audit::Entry::new(name, file, before.as_deref());
+
+	let result = run(command, file, ctx);
+	match &result {
+		Ok(()) => {
+			if let Some(output_file) = output_file {
+				let after = io::read(&output_file, ctx.timeout)?;
+				entry.written(&output_file, before.as_deref(), &after);
+			}
+		},
+		Err(e) => entry.failed(e.as_ref()),
+	}
+	audit::append(log, &entry)?;
+
+	result
+}
+
+fn run(command: Commands, file: &Path, ctx: &Ctx) -> Result<(), Box<dyn std::error::Error>> {
+	let timeout = ctx.timeout;
+
+	match command {
 		args::Commands::Encode {
-			file,
+			file: _,
 			chunk_type,
 			message,
+			input_file,
+			shred_source,
+			encrypt,
+			passphrase,
+			totp,
+			safe_to_copy: _,
+			not_safe_to_copy,
+			mode,
+			text,
+			spread,
+			parity,
 			output_file,
+			#[cfg(feature = "http")]
+			carrier_url,
+			#[cfg(feature = "clipboard")]
+			from_screen,
 		} => {
-			let file_as_bytes = read(&file)?;
-			let mut file_as_png = Png::try_from(file_as_bytes.as_ref())?;
+			// the clipboard only keeps pixels
+			#[cfg(feature = "clipboard")]
+			let mode = if from_screen { EmbedMode::Lsb } else { mode };
+			let requested = ctx.chunk_type(chunk_type)?;
+			let chunk_type = requested.with_safe_to_copy(!not_safe_to_copy);
+			if chunk_type != requested {
+				eprintln!("Using `{chunk_type}` for `{requested}`");
+			}
+			let mut data = match (&input_file, message) {
+				(Some(path), _) => io::read(path, timeout)?,
+				(None, message) => message.unwrap_or_default().into_bytes(),
+			};
+			if let Some(keyword) = text {
+				let text = Text::new(&chunk_type, &keyword, &String::from_utf8(data)?)?;
+				data = text.to_chunk().data().to_vec();
+			}
+			if let (true, Some(passphrase)) = (encrypt, passphrase) {
+				data = match totp.then(Totp::generate) {
+					Some(totp) => {
+						println!("TOTP secret: {}", totp.to_base32());
+						println!("{}", totp.otpauth_uri(&chunk_type.to_string()));
+						crypto::encrypt_with_totp(passphrase.as_bytes(), &data, &totp)?
+					},
+					None => crypto::encrypt(passphrase.as_bytes(), &data)?,
+				};
+			}
+			let payload_size = data.len();
+			let output = output_file.as_deref().unwrap_or(file);
+			if let (Some(path), true) = (&input_file, shred_source) {
+				confirm::require(&format!("Shred {} once hidden", path.display()), ctx.confirm)?;
+			}
 
-			let new_data = Chunk::new(chunk_type, message.into_bytes());
+			#[cfg(feature = "http")]
+			let local = carrier_url.is_none();
+			#[cfg(not(feature = "http"))]
+			let local = true;
+			#[cfg(feature = "clipboard")]
+			let local = local && !from_screen;
+			// pixels can only be rewritten in a parsed png
+			let spliced = local && mode == EmbedMode::Chunk && spread.is_empty();
+			let media = match spliced {
+				true => ctx.media(file)?,
+				false => None,
+			};
 
-			if let Some(x) = output_file {
-				let mut copy = file_as_png.clone();
-				copy.append_chunk(new_data);
-				let mut new_file = File::create(x)?;
-				new_file.write_all(copy.as_bytes().as_ref())?;
+			if !spread.is_empty() {
+				let mut frames = Vec::new();
+				let mut pngs = Vec::new();
+				for frame in spread_frames(file, &spread) {
+					pngs.push(ctx.load(frame)?);
+					// each frame goes back into whatever it came out of
+					frames.push((frame, ctx.wrapped.take()));
+				}
+				steg::spread(&mut pngs, &data, parity)?;
+				for ((frame, wrapped), png) in frames.into_iter().zip(pngs) {
+					*ctx.wrapped.borrow_mut() = wrapped;
+					ctx.save(frame, png)?;
+				}
+			} else if let Some(media) = media {
+				// other media carry it in a place of their own, see `media`
+				let bytes = media.append(&io::read(file, timeout)?, &chunk_type, &data)?;
+				ctx.save_bytes(output, bytes)?;
+			} else if spliced && ctx.is_differential(file)? {
+				// big pngs get the chunk spliced in, no need to parse them
+				let chunk = Chunk::new(chunk_type, data);
+				ctx.overwrite(output)?;
+				io::insert_before_iend(file, output, vec![chunk], timeout, ctx.preserve_metadata)??;
+				ctx.match_timestamps(output)?;
 			} else {
-				file_as_png.append_chunk(new_data);
-				let mut original_file = File::create(&file)?;
-				original_file.write_all(&file_as_png.as_bytes())?;
+				#[cfg(feature = "clipboard")]
+				let pasted = from_screen.then(clipboard::paste).transpose()?;
+				#[cfg(not(feature = "clipboard"))]
+				let pasted: Option<Png> = None;
+				#[cfg(feature = "http")]
+				let mut file_as_png = match (pasted, carrier_url) {
+					(Some(png), _) => png,
+					(None, Some(url)) => Png::parse_bytes(&io::fetch(&url, timeout)?.into(), &ctx.parse)?,
+					(None, None) => ctx.load(file)?,
+				};
+				#[cfg(not(feature = "http"))]
+				let mut file_as_png = match pasted {
+					Some(png) => png,
+					None => ctx.load(file)?,
+				};
+
+				match mode {
+					EmbedMode::Chunk => file_as_png.append_chunk(Chunk::new(chunk_type, data)),
+					EmbedMode::Lsb => steg::embed(&mut file_as_png, &data)?,
+				}
+				ctx.save(output, file_as_png.clone())?;
+				// copied back once it is safely on disk
+				#[cfg(feature = "clipboard")]
+				if from_screen {
+					clipboard::copy(&file_as_png)?;
+				}
+			}
+
+			// only once the payload is safely on disk
+			if let (Some(path), true) = (&input_file, shred_source) {
+				shred::shred(path, shred::DEFAULT_PASSES)?;
+			}
+
+			if let Some(cmd) = &ctx.post_encode_hook {
+				hooks::run(cmd, &hooks::Event {
+					operation: "encode",
+					file,
+					output_file: output_file.as_deref(),
+					chunk_type: &chunk_type.to_string(),
+					payload_size,
+				});
 			}
 		},
 
-		args::Commands::Decode { file, chunk_type } => {
-			let png = Png::try_from(file)?;
+		args::Commands::MailWrap {
+			chunk_type,
+			message,
+			input_file,
+			encrypt,
+			passphrase,
+			to,
+			from,
+			subject,
+			body,
+			output_file,
+			sign_cert,
+			sign_key,
+			..
+		} => {
+			let chunk_type = ctx.chunk_type(chunk_type)?;
+			let mut data = match (&input_file, message) {
+				(Some(path), _) => io::read(path, timeout)?,
+				(None, message) => message.unwrap_or_default().into_bytes(),
+			};
+			if let (true, Some(passphrase)) = (encrypt, passphrase) {
+				data = crypto::encrypt(passphrase.as_bytes(), &data)?;
+			}
+			let payload_size = data.len();
 
-			let idx = png
-				.find_by_chunk(&chunk_type)
-				.expect("Failed to find such chunk");
+			let mut png = ctx.load(file)?;
+			png.append_chunk(Chunk::new(chunk_type, data));
+			let mail = mail::Mail {
+				from,
+				to,
+				subject,
+				body,
+				attachment: mail::Attachment {
+					name: file
+						.file_name()
+						.map_or("image.png".into(), |n| n.to_string_lossy().into_owned()),
+					content_type: "image/png",
+					data: png.as_bytes(),
+				},
+			};
+			let signer = match (&sign_cert, &sign_key) {
+				(Some(cert), Some(key)) => Some(mail::Signer { cert, key }),
+				_ => None,
+			};
+			let output = output_file.unwrap_or_else(|| file.with_extension("eml"));
+			ctx.overwrite(&output)?;
+			emit(&output, mail.to_eml(signer.as_ref())?, timeout)?;
+
+			if let Some(cmd) = &ctx.post_encode_hook {
+				hooks::run(cmd, &hooks::Event {
+					operation: "mail-wrap",
+					file,
+					output_file: Some(&output),
+					chunk_type: &chunk_type.to_string(),
+					payload_size,
+				});
+			}
+		},
+
+		args::Commands::Decode {
+			chunk_type,
+			passphrase,
+			totp_code,
+			mode,
+			text,
+			spread,
+			..
+		} => {
+			let msg = match (mode, text) {
+				_ if !spread.is_empty() => gather(file, &spread, ctx)?,
+				(EmbedMode::Chunk, Some(keyword)) => ctx
+					.load(file)?
+					.chunks()
+					.of_type(&chunk_type)
+					.find(|c| Text::try_from(*c).is_ok_and(|t| t.keyword() == keyword))
+					.ok_or(format!(
+						"No `{chunk_type}` chunk with keyword `{keyword}` in {}",
+						file.display()
+					))?
+					.data()
+					.to_vec(),
+				(EmbedMode::Chunk, None) => ctx
+					.find_chunk(file, &chunk_type)?
+					.ok_or(format!("No `{chunk_type}` chunk in {}", file.display()))?
+					.data()
+					.to_vec(),
+				(EmbedMode::Lsb, _) => steg::extract(&ctx.load(file)?)?,
+			};
 
-			let msg = png.chunks().get(idx).expect("Nothing here!");
+			match passphrase {
+				Some(passphrase) => {
+					let data = crypto::decrypt_with_code(passphrase.as_bytes(), &msg, totp_code)?;
+					println!("{}", String::from_utf8_lossy(&data));
+				},
+				None => match chunk_type.parse::<ChunkType>() {
+					// keyword and inflated text rather than the raw bytes
+					Ok(t) if mode == EmbedMode::Chunk && spread.is_empty() && Text::is_text(&t) => {
+						println!("{}", Text::try_from(&Chunk::from_slice(t, &msg))?)
+					},
+					_ => println!("{}", String::from_utf8_lossy(&msg)),
+				},
+			}
 
-			println!("{}", msg);
+			if let Some(cmd) = &ctx.post_decode_hook {
+				hooks::run(cmd, &hooks::Event {
+					operation: "decode",
+					file,
+					output_file: None,
+					chunk_type: &chunk_type,
+					payload_size: msg.len(),
+				});
+			}
 		},
 
-		args::Commands::Remove { file, chunk_type } => {
-			let mut png = Png::try_from(file)?;
+		args::Commands::EncodeFile {
+			file: _,
+			chunk_type,
+			payload_path,
+			output_file,
+			chunk_size,
+			#[cfg(feature = "zstd")]
+			compress,
+			#[cfg(feature = "zstd")]
+			dict,
+		} => {
+			let chunk_type = ctx.chunk_type(chunk_type)?;
+			let data = io::read(&payload_path, timeout)?;
+			#[cfg(feature = "zstd")]
+			let (data, encoding) = match compress {
+				true => {
+					let dictionary = dictionary(dict.as_deref(), timeout)?;
+					let compressed = edpg::compress::compress(&data, dictionary.as_ref())?;
+					eprintln!("Compressed {} bytes to {}", data.len(), compressed.len());
+					(compressed, payload::Encoding::Zstd {
+						dictionary: dictionary.map(|d| d.id()),
+					})
+				},
+				false => (data, payload::Encoding::Plain),
+			};
+			#[cfg(not(feature = "zstd"))]
+			let encoding = payload::Encoding::Plain;
+			let name = payload_path
+				.file_name()
+				.map(|name| name.to_string_lossy().into_owned())
+				.unwrap_or_default();
+
+			let output = output_file.as_deref().unwrap_or(file);
+			if ctx.is_differential(file)? {
+				let chunks = payload::file_chunks(chunk_type, &name, &data, encoding, chunk_size);
+				ctx.overwrite(output)?;
+				io::insert_before_iend(file, output, chunks, timeout, ctx.preserve_metadata)??;
+				ctx.match_timestamps(output)?;
+			} else {
+				let mut png = ctx.load(file)?;
+				payload::embed(&mut png, chunk_type, &name, &data, encoding, chunk_size);
+				ctx.save(output, png)?;
+			}
+
+			if let Some(cmd) = &ctx.post_encode_hook {
+				hooks::run(cmd, &hooks::Event {
+					operation: "encode-file",
+					file,
+					output_file: output_file.as_deref(),
+					chunk_type: &chunk_type.to_string(),
+					payload_size: data.len(),
+				});
+			}
+		},
+		args::Commands::Extract {
+			chunk_type,
+			output_file,
+			#[cfg(feature = "zstd")]
+			dict,
+			jobs,
+			..
+		} => {
+			let png = match ctx.is_differential(file)? {
+				// big pngs are read a chunk at a time, only the file's are kept
+				true => Png::from_chunks(
+					io::chunks(file, &ctx.parse, timeout)??
+						.filter(|c| c.as_ref().map_or(true, |c| {
+							let t = c.chunk_type().to_string();
+							t == chunk_type || t == payload::MANIFEST_CHUNK_TYPE
+						}))
+						.collect::<Result<_, _>>()?,
+				),
+				false => ctx.load(file)?,
+			};
+			let (manifest, data) = match jobs {
+				Some(threads) => payload::extract_with(&png, &chunk_type, threads)?,
+				None => payload::extract(&png, &chunk_type)?,
+			};
+			let data = match manifest.encoding {
+				payload::Encoding::Plain => data,
+				#[cfg(feature = "zstd")]
+				payload::Encoding::Zstd { dictionary: id } => {
+					edpg::compress::decompress(&data, id, dictionary(dict.as_deref(), timeout)?.as_ref())?
+				},
+				#[cfg(not(feature = "zstd"))]
+				payload::Encoding::Zstd { .. } => {
+					return Err(format!("`{chunk_type}` is compressed, vanish was built without zstd").into())
+				},
+			};
+
+			// only the name, whatever path the png claims
+			let output = match output_file {
+				Some(path) => path,
+				None => Path::new(&manifest.name)
+					.file_name()
+					.map(PathBuf::from)
+					.ok_or(format!("`{chunk_type}` has no file name, pass --output-file"))?,
+			};
+			ctx.overwrite(&output)?;
+			emit(&output, data, timeout)?;
+		},
+
+		args::Commands::Remove {
+			chunk_type,
+			save_removed,
+			..
+		} => {
+			let mut png = ctx.load(file)?;
 
 			let popped = png.remove_first_chunk(&chunk_type)?;
-			println!("{popped}");
+			// kept somewhere before it is gone from the file
+			match save_removed {
+				Some(path) => {
+					ctx.overwrite(&path)?;
+					emit(&path, popped.as_bytes(), timeout)?;
+				},
+				None => println!("{popped}"),
+			}
+			ctx.save(file, png)?;
+
+			if let Some(cmd) = &ctx.post_remove_hook {
+				hooks::run(cmd, &hooks::Event {
+					operation: "remove",
+					file,
+					output_file: None,
+					chunk_type: &chunk_type,
+					payload_size: popped.data().len(),
+				});
+			}
 		},
-		args::Commands::Print { file } => {
-			let png = Png::try_from(file)?;
-			println!("{png}");
+		args::Commands::LockChunk {
+			file: _,
+			chunk_type,
+			password,
+			label,
+			totp,
+			output_file,
+		} => {
+			let label = ctx.chunk_type(label)?;
+			let mut png = ctx.load(file)?;
+
+			let idx = png
+				.find_nth_by_type(&chunk_type, 0)
+				.ok_or(format!("No `{chunk_type}` chunk in {}", file.display()))?;
+			let totp = totp.then(Totp::generate);
+			let locked = crypto::lock_chunk(
+				&png.chunks()[idx],
+				label,
+				password.as_bytes(),
+				totp.as_ref(),
+			)?;
+			png.replace_chunk(idx, locked);
+
+			if let Some(totp) = totp {
+				println!("TOTP secret: {}", totp.to_base32());
+				println!("{}", totp.otpauth_uri(&label.to_string()));
+			}
+
+			ctx.save(output_file.as_deref().unwrap_or(file), png)?;
+		},
+		args::Commands::UnlockChunk {
+			file: _,
+			label,
+			password,
+			totp_code,
+			output_file,
+		} => {
+			let mut png = ctx.load(file)?;
+
+			let idx = png
+				.find_nth_by_type(&label, 0)
+				.ok_or(format!("No `{label}` chunk in {}", file.display()))?;
+			let unlocked = crypto::unlock_chunk(&png.chunks()[idx], password.as_bytes(), totp_code)?;
+			png.replace_chunk(idx, unlocked);
+
+			ctx.save(output_file.as_deref().unwrap_or(file), png)?;
+		},
+		args::Commands::UpgradePayload {
+			file: _,
+			chunk_type,
+			passphrase,
+			new_passphrase,
+			output_file,
+		} => {
+			let mut png = ctx.load(file)?;
+
+			let idx = png
+				.find_nth_by_type(&chunk_type, 0)
+				.ok_or(format!("No `{chunk_type}` chunk in {}", file.display()))?;
+			let upgraded = format::upgrade(
+				passphrase.as_bytes(),
+				png.chunks()[idx].data(),
+				new_passphrase.as_deref().map(str::as_bytes),
+			)?;
+			match upgraded {
+				Some((version, sealed)) => {
+					let chunk_type = *png.chunks()[idx].chunk_type();
+					png.replace_chunk(idx, Chunk::new(chunk_type, sealed));
+					ctx.save(output_file.as_deref().unwrap_or(file), png)?;
+					println!("Sealed `{chunk_type}` again, it was {version}");
+				},
+				None => println!("`{chunk_type}` is already in the current format"),
+			}
+		},
+		args::Commands::CatChunks { types, .. } => {
+			let wanted = |chunk: &Chunk| types.contains(&chunk.chunk_type().to_string());
+			let mut stdout = std::io::stdout().lock();
+			if ctx.is_differential(file)? {
+				// big pngs are read a chunk at a time
+				for chunk in io::chunks(file, &ctx.parse, timeout)?? {
+					let chunk = chunk?;
+					if wanted(&chunk) {
+						stdout.write_all(chunk.data())?;
+					}
+				}
+			} else {
+				for chunk in ctx.load(file)?.chunks().iter().filter(|c| wanted(c)) {
+					stdout.write_all(chunk.data())?;
+				}
+			}
+			stdout.flush()?;
+		},
+		args::Commands::AddChunk {
+			file: _,
+			chunk_type,
+			data_file,
+			raw,
+			at,
+			before,
+			output_file,
+		} => {
+			let data = io::read(&data_file, timeout)?;
+			let chunk = match chunk_type {
+				Some(chunk_type) if !raw => Chunk::new(ctx.chunk_type(chunk_type)?, data),
+				_ => Chunk::try_from(data.as_ref())?,
+			};
+
+			let before = before.as_deref().unwrap_or("IEND");
+			let output = output_file.as_deref().unwrap_or(file);
+			if at.is_none() && before == "IEND" && ctx.is_differential(file)? {
+				ctx.overwrite(output)?;
+				io::insert_before_iend(file, output, vec![chunk], timeout, ctx.preserve_metadata)??;
+				ctx.match_timestamps(output)?;
+			} else {
+				let mut png = ctx.load(file)?;
+				let idx = match at {
+					Some(idx) => idx,
+					None => png
+						.find_nth_by_type(before, 0)
+						.ok_or(format!("No `{before}` chunk in {}", file.display()))?,
+				};
+				png.insert_chunk(idx, chunk)?;
+				ctx.save(output, png)?;
+			}
+		},
+		args::Commands::MigrateExif {
+			to_exif,
+			output_file,
+			..
+		} => {
+			let mut png = ctx.load(file)?;
+			let texts: Vec<(usize, String, String)> = png
+				.positions_of("tEXt")
+				.into_iter()
+				.filter_map(|i| {
+					let data = png.chunks()[i].data();
+					let nul = data.iter().position(|&b| b == 0)?;
+					let keyword = String::from_utf8_lossy(&data[..nul]).into_owned();
+					let text = String::from_utf8_lossy(&data[nul + 1..]).into_owned();
+					Some((i, keyword, text))
+				})
+				.collect();
+			let exif_idx = png.find_nth_by_type("eXIf", 0);
+
+			if to_exif {
+				let mut fields = match exif_idx {
+					Some(i) => exif::read(png.chunks()[i].data())?,
+					None => Vec::new(),
+				};
+				for (_, keyword, text) in &texts {
+					if let Some(tag) = exif::tag(keyword) {
+						fields.retain(|(t, _)| *t != tag);
+						fields.push((tag, text.clone()));
+					}
+				}
+				let chunk = Chunk::new("eXIf".parse()?, exif::write(&fields));
+
+				match exif_idx {
+					Some(i) => {
+						png.replace_chunk(i, chunk);
+					},
+					// eXIf has to come before the image data
+					None => {
+						let idx = png.find_nth_by_type("IDAT", 0).unwrap_or(1);
+						png.insert_chunk(idx, chunk)?;
+					},
+				}
+			} else {
+				let exif_idx =
+					exif_idx.ok_or(format!("No `eXIf` chunk in {}", file.display()))?;
+				for (tag, value) in exif::read(png.chunks()[exif_idx].data())? {
+					let keyword = exif::keyword(tag).expect("read only returns known tags");
+					let chunk = Chunk::new(
+						"tEXt".parse()?,
+						[keyword.as_bytes(), b"\0", value.as_bytes()].concat(),
+					);
+					match texts.iter().find(|(_, k, _)| k == keyword) {
+						Some((i, ..)) => {
+							png.replace_chunk(*i, chunk);
+						},
+						None => png.append_chunk(chunk),
+					}
+				}
+			}
+
+			ctx.save(output_file.as_deref().unwrap_or(file), png)?;
+		},
+		args::Commands::Dump { output_file, .. } => {
+			let bytes = io::read(file, timeout)?;
+			let png = ctx.load(file)?;
+			if ctx.wrapped.borrow().is_empty() && png.as_bytes() != bytes {
+				eprintln!(
+					"{} has bytes outside of its chunks, they won't survive a restore",
+					file.display()
+				);
+			}
+
+			let toml = dump::Dump::from(&png).to_toml()?;
+			match output_file {
+				Some(path) => {
+					ctx.overwrite(&path)?;
+					emit(&path, toml.into_bytes(), timeout)?;
+				},
+				None => print!("{toml}"),
+			}
+		},
+		args::Commands::Cache { command } => {
+			let path = cache_path()?;
+			match command {
+				args::CacheCommand::Clear => cache::clear(&path)?,
+				args::CacheCommand::Stats { json } => {
+					let stats = cache::Cache::open(path, timeout).stats(timeout);
+					match json {
+						true => println!("{}", serde_json::to_string_pretty(&stats)?),
+						false => println!(
+							"{}: {} files, {} changed since, {} bytes",
+							stats.path.display(),
+							stats.entries,
+							stats.stale,
+							stats.bytes
+						),
+					}
+				},
+			}
+		},
+		#[cfg(feature = "zstd")]
+		args::Commands::Dict {
+			command: args::DictCommand::Train {
+				samples,
+				output_file,
+				max_size,
+			},
+		} => {
+			let samples = samples
+				.iter()
+				.map(|sample| io::read(sample, timeout))
+				.collect::<Result<Vec<_>, _>>()?;
+			let dictionary = edpg::compress::train(&samples, max_size)?;
+			ctx.overwrite(&output_file)?;
+			emit(&output_file, dictionary.as_bytes().to_vec(), timeout)?;
+			eprintln!(
+				"Dictionary {} from {} samples, {} bytes",
+				dictionary.id(),
+				samples.len(),
+				dictionary.as_bytes().len()
+			);
+		},
+		args::Commands::Custody {
+			command: args::CustodyCommand::Verify { log, json },
+		} => {
+			let verified = custody::verify(&log, timeout)?;
+			match json {
+				true => println!("{}", serde_json::to_string_pretty(&verified)?),
+				false => {
+					for v in &verified {
+						println!("{}: {}", v.path.display(), v.check);
+					}
+				},
+			}
+			let failed = verified
+				.iter()
+				.filter(|v| matches!(v.check, custody::Check::Changed | custody::Check::Unreadable))
+				.count();
+			if failed > 0 {
+				return Err(format!("{failed} of {} files changed or are gone", verified.len()).into());
+			}
+		},
+		args::Commands::Daemon { socket } => {
+			let server = ctx.rpc_server();
+			#[cfg(unix)]
+			daemon::serve(&socket.unwrap_or_else(daemon::default_socket), server)?;
+			#[cfg(not(unix))]
+			{
+				let _ = (socket, server);
+				return Err("`daemon` needs UNIX sockets".into());
+			}
+		},
+		args::Commands::Ipc => {
+			let server = ctx.rpc_server();
+			server.serve(std::io::stdin().lock(), std::io::stdout().lock())?;
+		},
+		args::Commands::Man { output_dir } => {
+			for path in man::write_all(&output_dir)? {
+				println!("{}", path.display());
+			}
+		},
+		args::Commands::Identify { json, .. } => {
+			let report = identify::identify(&io::read(file, timeout)?);
+			match json {
+				true => println!("{}", serde_json::to_string_pretty(&report)?),
+				false => println!("{}: {report}", file.display()),
+			}
+		},
+		args::Commands::VerifyIdentity { json, .. } => {
+			let roundtrip = roundtrip::compare(&io::read(file, timeout)?, &ctx.parse)?;
+			match json {
+				true => println!("{}", serde_json::to_string_pretty(&roundtrip)?),
+				false => println!("{}: {roundtrip}", file.display()),
+			}
+			if !roundtrip.is_identical() {
+				return Err(format!("{} doesn't survive a round trip", file.display()).into());
+			}
+		},
+		args::Commands::Polyglot {
+			archive,
+			extract,
+			output_file,
+			..
+		} => {
+			let bytes = io::read(file, timeout)?;
+			match (archive, extract) {
+				(_, true) => {
+					let archive = polyglot::extract(&bytes)?;
+					ctx.overwrite(&output_file)?;
+					io::write(&output_file, archive, timeout)?;
+				},
+				(Some(archive), false) => {
+					let both = polyglot::create(&bytes, &io::read(&archive, timeout)?)?;
+					ctx.overwrite(&output_file)?;
+					io::write(&output_file, both, timeout)?;
+					eprintln!(
+						"Wrote {}, note that some mail and upload scanners flag or strip png/zip polyglots",
+						output_file.display()
+					);
+				},
+				(None, false) => unreachable!("clap requires an archive without --extract"),
+			}
+		},
+		args::Commands::Restore { output_file, .. } => {
+			let toml = String::from_utf8(io::read(file, timeout)?)?;
+			let png = dump::Dump::from_toml(&toml)?.to_png()?;
+
+			ctx.save(&output_file, png)?;
+		},
+		args::Commands::Check { sandbox, .. } => {
+			let options = check::Options {
+				sandbox,
+				..check_options(ctx)
+			};
+			let report = check::file(file, &options, timeout, ctx.cache.as_ref());
+			if let Some(cache) = &ctx.cache {
+				cache.save(timeout)?;
+			}
+			let errors = report.count(Severity::Error);
+			ctx.found(report)?;
+			if errors > 0 {
+				return Err(format!("{} is not a valid png", file.display()).into());
+			}
+		},
+		args::Commands::Guard {
+			deny_hidden,
+			policy,
+			fix,
+			..
+		} => {
+			let mut bytes = io::read(file, timeout)?;
+			if fix {
+				bytes = fixed(file, bytes, ctx)?;
+			}
+			let findings = detect::scan_matching(&bytes, &ctx.parse, &ctx.signatures)?;
+
+			let violations = match policy {
+				Some(policy) => {
+					let policy: Policy = toml::from_str(&String::from_utf8(io::read(&policy, timeout)?)?)?;
+					policy.check(&bytes)?
+				},
+				None => Vec::new(),
+			};
+
+			// findings only fail the file with --deny-hidden
+			let severity = match deny_hidden {
+				true => Severity::Error,
+				false => Severity::Warning,
+			};
+			let path = file.to_string_lossy();
+			let issues = findings
+				.iter()
+				.map(|f| Issue::from_finding(&path, severity, f))
+				.chain(violations.iter().map(|v| Issue::from_violation(&path, v)))
+				.collect();
+			ctx.found(Report::file(issues))?;
+
+			if !violations.is_empty() {
+				return Err(format!("{} policy violations", violations.len()).into());
+			}
+			if deny_hidden && !findings.is_empty() {
+				return Err(format!("{} likely hidden payloads", findings.len()).into());
+			}
+		},
+		args::Commands::Shred { passes, .. } => {
+			confirm::require(&format!("Shred {}", file.display()), ctx.confirm)?;
+			shred::shred(file, passes)?;
+		},
+		args::Commands::FuzzGen {
+			file: _,
+			mutations,
+			output_dir,
+		} => {
+			let png = ctx.load(file)?;
+			let stem = file.file_stem().unwrap_or_default();
+
+			io::writable(&output_dir)?;
+			std::fs::create_dir_all(&output_dir)?;
+			for (i, mutation) in mutate::plan(&png, mutations).into_iter().enumerate() {
+				let mut name = stem.to_owned();
+				name.push(format!("-{i:04}-{}-{}.png", mutation.kind, mutation.chunk));
+				let path = output_dir.join(name);
+				ctx.overwrite(&path)?;
+				io::write(&path, mutate::apply(&png, mutation), timeout)?;
+			}
+		},
+		#[cfg(feature = "script")]
+		args::Commands::Script {
+			command: args::ScriptCommand::Run {
+				script,
+				output_file,
+				..
+			},
+		} => {
+			let source = String::from_utf8(io::read(&script, timeout)?)?;
+			let png = script::run(&source, ctx.load(file)?)?;
+
+			ctx.save(output_file.as_deref().unwrap_or(file), png)?;
+		},
+		args::Commands::Textstego {
+			command:
+				args::TextstegoCommand::Encode {
+					message,
+					input_file,
+					password,
+					output_file,
+					..
+				},
+		} => {
+			let cover = String::from_utf8(io::read(file, timeout)?)?;
+			let mut data = match (&input_file, message) {
+				(Some(path), _) => io::read(path, timeout)?,
+				(None, message) => message.unwrap_or_default().into_bytes(),
+			};
+			if let Some(password) = password {
+				data = crypto::encrypt(password.as_bytes(), &data)?;
+			}
+
+			let text = textstego::encode(&cover, &data)?;
+			match output_file {
+				Some(path) => {
+					ctx.overwrite(&path)?;
+					emit(&path, text.into_bytes(), timeout)?;
+				},
+				None => print!("{text}"),
+			}
+		},
+		args::Commands::Textstego {
+			command: args::TextstegoCommand::Decode { password, .. },
+		} => {
+			let text = String::from_utf8(io::read(file, timeout)?)?;
+			let mut data = textstego::decode(&text)?;
+			if let Some(password) = password {
+				data = crypto::decrypt(password.as_bytes(), &data)?;
+			}
+
+			std::io::stdout().write_all(&data)?;
+		},
+		#[cfg(feature = "c2pa")]
+		args::Commands::C2pa {
+			command:
+				args::C2paCommand::Embed {
+					manifest_store,
+					output_file,
+					..
+				},
+		} => {
+			let mut png = ctx.load(file)?;
+			let store = edpg::c2pa::embed(&mut png, io::read(&manifest_store, timeout)?)?;
+			ctx.save(output_file.as_deref().unwrap_or(file), png)?;
+			eprintln!("Embedded {} manifests", store.manifests.len());
+		},
+		#[cfg(feature = "c2pa")]
+		args::Commands::C2pa {
+			command: args::C2paCommand::Verify { extract, json, .. },
+		} => {
+			let png = ctx.load(file)?;
+			let bytes = edpg::c2pa::manifest_store(&png)?;
+			if let Some(path) = extract {
+				ctx.overwrite(&path)?;
+				emit(&path, bytes.to_vec(), timeout)?;
+			}
+			let store = edpg::c2pa::parse(bytes)?;
+			match json {
+				true => println!("{}", serde_json::to_string_pretty(&store)?),
+				false => {
+					println!("{}: {} manifests, signatures not checked", file.display(), store.manifests.len());
+					for manifest in &store.manifests {
+						let kind = if manifest.update { "update manifest" } else { "manifest" };
+						println!("  {kind} {}", manifest.label);
+						for assertion in &manifest.assertions {
+							println!("    {assertion}");
+						}
+					}
+				},
+			}
+		},
+		args::Commands::Provenance {
+			command:
+				args::ProvenanceCommand::Stamp {
+					output_file,
+					creator,
+					copyright,
+					claim,
+					passphrase,
+					..
+				},
+		} => {
+			let mut png = ctx.load(file)?;
+			let stamp = Provenance {
+				creator,
+				copyright,
+				claims: claim,
+			};
+			stamp.stamp(&mut png, passphrase.as_deref().map(str::as_bytes))?;
+			ctx.save(output_file.as_deref().unwrap_or(file), png)?;
+		},
+		args::Commands::Provenance {
+			command: args::ProvenanceCommand::Verify { passphrase, json, .. },
+		} => {
+			let verification = provenance::verify(&ctx.load(file)?, passphrase.as_deref().map(str::as_bytes))?;
+			match json {
+				true => println!("{}", serde_json::to_string_pretty(&verification)?),
+				false => {
+					println!("{}:", file.display());
+					for (keyword, text) in &verification.fields {
+						println!("  {keyword}: {text}");
+					}
+					let pixels = match verification.source_hash_matches {
+						Some(true) => "match the recorded hash",
+						Some(false) => "changed since the stamp",
+						None => "have no recorded hash, not stamped",
+					};
+					println!("  pixels {pixels}");
+					match verification.seal {
+						Seal::Unsealed => println!("  not sealed"),
+						Seal::Unchecked => println!("  sealed, pass --passphrase to check it"),
+						Seal::Valid => println!("  seal is valid"),
+						Seal::Invalid => println!("  seal is broken, wrong passphrase or fields changed"),
+					}
+				},
+			}
+			if !verification.is_valid() {
+				return Err(format!("{} doesn't hold up to its provenance stamp", file.display()).into());
+			}
+		},
+		args::Commands::Print { hex, .. } => {
+			let png = ctx.load(file)?;
+			if hex {
+				for chunk in png.chunks() {
+					println!("{}:", chunk.chunk_type());
+					for line in chunk.as_bytes().chunks(16) {
+						let line: Vec<String> = line.iter().map(|b| format!("{b:02x}")).collect();
+						println!("  {}", line.join(" "));
+					}
+				}
+			} else {
+				print!("{png}");
+			}
 		},
 	};
 
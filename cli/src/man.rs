@@ -0,0 +1,42 @@
+// Man pages generated from `args`, a page for vanish and one per
+// subcommand, git style: vanish-textstego-encode.1 and the like.
+
+use std::path::{Path, PathBuf};
+
+use clap::Command;
+
+use crate::args;
+
+/// Name the pages are filed under.
+const NAME: &str = "vanish";
+
+/// Writes the pages into `dir`, returns their paths.
+pub fn write_all(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+	let mut command = args::command().name(NAME);
+	// global flags make it into every subcommand's page
+	command.build();
+
+	crate::io::writable(dir)?;
+	std::fs::create_dir_all(dir)?;
+	let mut written = Vec::new();
+	write(command, dir, &mut written)?;
+	Ok(written)
+}
+
+fn write(command: Command, dir: &Path, written: &mut Vec<PathBuf>) -> std::io::Result<()> {
+	let name = command.get_name().to_owned();
+	let path = dir.join(format!("{name}.1"));
+	let mut page = Vec::new();
+	clap_mangen::Man::new(command.clone()).render(&mut page)?;
+	std::fs::write(&path, page)?;
+	written.push(path);
+
+	for sub in command.get_subcommands().filter(|c| c.get_name() != "help") {
+		let mut sub = sub.clone().name(format!("{name}-{}", sub.get_name()));
+		if let Some(version) = command.get_version() {
+			sub = sub.version(version.to_owned());
+		}
+		write(sub, dir, written)?;
+	}
+	Ok(())
+}
@@ -0,0 +1,64 @@
+// Carriers that aren't pngs and take a payload whole, in a place of their own
+// that players and readers skip: videos, audio, documents and vector
+// images. Loaded and written in one go, each format's own module does the
+// work.
+
+use std::error::Error;
+
+use edpg::chunk_type::ChunkType;
+use edpg::{bmff, flac, pdf, svg, wav};
+
+/// Bytes `detect` needs to see, an svg's root can come after a prolog.
+pub const HEAD_BYTES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Media {
+	Bmff,
+	Wav,
+	Flac,
+	Pdf,
+	Svg,
+}
+
+impl Media {
+	/// Which kind of media `head` starts, if any.
+	pub fn detect(head: &[u8]) -> Option<Media> {
+		if bmff::is_bmff(head) {
+			Some(Media::Bmff)
+		} else if wav::is_wav(head) {
+			Some(Media::Wav)
+		} else if flac::is_flac(head) {
+			Some(Media::Flac)
+		} else if pdf::is_pdf(head) {
+			Some(Media::Pdf)
+		} else if svg::is_svg(head) {
+			Some(Media::Svg)
+		} else {
+			None
+		}
+	}
+
+	/// `bytes` with `data` added under `chunk_type`.
+	pub fn append(self, bytes: &[u8], chunk_type: &ChunkType, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+		Ok(match self {
+			Media::Bmff => bmff::append(bytes, chunk_type, data)?,
+			Media::Wav => wav::append(bytes, chunk_type, data)?,
+			Media::Flac => flac::append(bytes, chunk_type, data)?,
+			Media::Pdf => pdf::append(bytes, chunk_type, data)?,
+			Media::Svg => svg::append(bytes, chunk_type, data)?,
+		})
+	}
+
+	/// Data of the first payload under `chunk_type`.
+	pub fn find(self, bytes: &[u8], chunk_type: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+		let found = match self {
+			Media::Bmff => bmff::find(bytes, chunk_type)?,
+			Media::Wav => wav::find(bytes, chunk_type)?,
+			Media::Flac => flac::find(bytes, chunk_type)?,
+			Media::Pdf => pdf::find(bytes, chunk_type)?,
+			// base64 in the document, decoded
+			Media::Svg => return Ok(svg::find(bytes, chunk_type)?),
+		};
+		Ok(found.map(<[u8]>::to_vec))
+	}
+}
@@ -0,0 +1,33 @@
+// Output formats of what `check` and `guard` found, all over the same
+// `edpg::report::Report`.
+
+use edpg::report::Report;
+
+use crate::sarif;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Format {
+	/// A line per issue, printed as files are done.
+	#[default]
+	Text,
+	Json,
+	Yaml,
+	Sarif,
+}
+
+impl Format {
+	/// Whether issues are printed as they come rather than all at the end.
+	pub fn is_streaming(self) -> bool {
+		self == Format::Text
+	}
+}
+
+/// `report` in `format`.
+pub fn render(report: &Report, format: Format) -> Result<String, Box<dyn std::error::Error>> {
+	Ok(match format {
+		Format::Text => report.issues.iter().map(|i| format!("{i}\n")).collect(),
+		Format::Json => serde_json::to_string_pretty(report)? + "\n",
+		Format::Yaml => serde_yaml_ng::to_string(report)?,
+		Format::Sarif => serde_json::to_string_pretty(&sarif::to_sarif(report))? + "\n",
+	})
+}
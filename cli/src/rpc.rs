@@ -0,0 +1,429 @@
+// JSON-RPC 2.0 over newline delimited JSON, one request per line and one
+// response per line, for tools that keep a vanish process around instead of
+// spawning one per file. Transports (`daemon`, `ipc` on stdin and stdout)
+// only move lines, everything else happens here.
+//
+// Methods:
+// - `encode` {file, chunk_type, message | data (base64), output_file?, passphrase?}
+// - `decode` {file, chunk_type, passphrase?}
+// - `inspect` {file}
+// - `applyEdit` {file, edits: [Edit], output_file?}, all the edits or none,
+//   answered like `inspect` with the png as written.
+// - `shutdown` {}, answered before the transport stops.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use base64::Engine;
+use bytes::Bytes;
+use edpg::chunk::Chunk;
+use edpg::chunk_type::ChunkType;
+use edpg::png::{ParseOptions, Png};
+use edpg::report::{Issue, Severity};
+use edpg::{crypto, detect};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::cache::Stamp;
+use crate::io;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Anything that goes wrong carrying out a valid request.
+const FAILED: i64 = -32000;
+
+/// An error response, `code` per the spec.
+#[derive(Debug)]
+pub struct Error {
+	pub code: i64,
+	pub message: String,
+}
+
+impl<E: std::fmt::Display> From<E> for Error {
+	fn from(e: E) -> Error {
+		Error {
+			code: FAILED,
+			message: e.to_string(),
+		}
+	}
+}
+
+#[derive(Deserialize)]
+struct Request {
+	jsonrpc: String,
+	/// Absent for notifications, which get no response.
+	id: Option<Value>,
+	method: String,
+	#[serde(default)]
+	params: Value,
+}
+
+#[derive(Deserialize)]
+struct EncodeParams {
+	file: PathBuf,
+	#[serde(flatten)]
+	chunk: ChunkParams,
+	output_file: Option<PathBuf>,
+	/// Encrypts the payload, see `encode --encrypt`.
+	passphrase: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DecodeParams {
+	file: PathBuf,
+	chunk_type: String,
+	passphrase: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FileParams {
+	file: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct ApplyEditParams {
+	file: PathBuf,
+	edits: Vec<Edit>,
+	output_file: Option<PathBuf>,
+}
+
+/// A change to the chunks of a png, applied in order. Indices are those of
+/// `inspect`, as left by the edits before.
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Edit {
+	/// At `index`, or right before IEND without one.
+	Insert {
+		index: Option<usize>,
+		#[serde(flatten)]
+		chunk: ChunkParams,
+	},
+	Replace {
+		index: usize,
+		#[serde(flatten)]
+		chunk: ChunkParams,
+	},
+	Remove { index: usize },
+}
+
+#[derive(Deserialize)]
+struct ChunkParams {
+	chunk_type: String,
+	message: Option<String>,
+	/// Base64, for data that isn't text.
+	data: Option<String>,
+}
+
+impl ChunkParams {
+	fn data(self) -> Result<Vec<u8>, Error> {
+		match (self.message, self.data) {
+			(Some(message), None) => Ok(message.into_bytes()),
+			(None, Some(data)) => Ok(base64::engine::general_purpose::STANDARD.decode(data)?),
+			_ => Err(error(INVALID_PARAMS, "Pass either message or data")),
+		}
+	}
+
+	fn chunk(self) -> Result<Chunk, Error> {
+		let chunk_type: ChunkType = self.chunk_type.parse()?;
+		chunk_type.is_valid()?;
+		Ok(Chunk::new(chunk_type, self.data()?))
+	}
+}
+
+/// What the server was told on the command line.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+	pub parse: ParseOptions,
+	pub timeout: Option<Duration>,
+	pub preserve_metadata: bool,
+}
+
+/// Serves requests, shared by every connection. Parsed pngs are kept
+/// until their file changes.
+#[derive(Debug, Default)]
+pub struct Server {
+	options: Options,
+	pngs: Mutex<HashMap<PathBuf, (Stamp, Png)>>,
+}
+
+impl Server {
+	pub fn new(options: Options) -> Server {
+		Server {
+			options,
+			pngs: Mutex::default(),
+		}
+	}
+
+	/// Response to a request line, `None` for notifications. The second
+	/// value is true once `shutdown` was asked for.
+	pub fn handle_line(&self, line: &str) -> (Option<String>, bool) {
+		let request: Request = match serde_json::from_str(line) {
+			Ok(request) => request,
+			Err(e) => {
+				let code = match serde_json::from_str::<Value>(line) {
+					Ok(_) => INVALID_REQUEST,
+					Err(_) => PARSE_ERROR,
+				};
+				return (Some(response(Value::Null, Err(error(code, e)))), false);
+			},
+		};
+		if request.jsonrpc != "2.0" {
+			let e = error(INVALID_REQUEST, "jsonrpc must be \"2.0\"");
+			return (Some(response(request.id.unwrap_or_default(), Err(e))), false);
+		}
+
+		let shutdown = request.method == "shutdown";
+		let result = self.call(&request.method, request.params);
+		(request.id.map(|id| response(id, result)), shutdown)
+	}
+
+	/// Answers the requests read from `reader` on `writer` until the end of
+	/// the input, true if it stopped at a `shutdown` request.
+	pub fn serve<R: BufRead, W: Write>(&self, reader: R, mut writer: W) -> std::io::Result<bool> {
+		for line in reader.lines() {
+			let line = line?;
+			if line.trim().is_empty() {
+				continue;
+			}
+			let (response, shutdown) = self.handle_line(&line);
+			if let Some(response) = response {
+				writeln!(writer, "{response}")?;
+				writer.flush()?;
+			}
+			if shutdown {
+				return Ok(true);
+			}
+		}
+		Ok(false)
+	}
+
+	fn call(&self, method: &str, params: Value) -> Result<Value, Error> {
+		match method {
+			"encode" => self.encode(parse_params(params)?),
+			"decode" => self.decode(parse_params(params)?),
+			"inspect" => self.inspect(parse_params::<FileParams>(params)?.file),
+			"applyEdit" => self.apply_edit(parse_params(params)?),
+			"shutdown" => Ok(Value::Null),
+			_ => Err(error(METHOD_NOT_FOUND, format!("No method `{method}`"))),
+		}
+	}
+
+	/// The png at `path`, parsed again only if it changed since.
+	fn load(&self, path: &Path) -> Result<Png, Error> {
+		let stamp = Stamp::of(path, self.options.timeout)?;
+		if let Some((cached, png)) = self.pngs.lock().expect("not poisoned").get(path) {
+			if *cached == stamp {
+				return Ok(png.clone());
+			}
+		}
+
+		let bytes = Bytes::from(io::read(path, self.options.timeout)?);
+		let png = Png::parse_bytes(&bytes, &self.options.parse)?;
+		self.pngs
+			.lock()
+			.expect("not poisoned")
+			.insert(path.to_owned(), (stamp, png.clone()));
+		Ok(png)
+	}
+
+	fn encode(&self, params: EncodeParams) -> Result<Value, Error> {
+		let mut chunk = params.chunk.chunk()?;
+		if let Some(passphrase) = params.passphrase {
+			let data = crypto::encrypt(passphrase.as_bytes(), chunk.data())?;
+			chunk = Chunk::new(*chunk.chunk_type(), data);
+		}
+		let payload_size = chunk.data().len();
+
+		let mut png = self.load(&params.file)?;
+		png.append_chunk(chunk);
+		let output = params.output_file.unwrap_or(params.file);
+		self.write(&output, png)?;
+
+		Ok(json!({ "output_file": output, "payload_size": payload_size }))
+	}
+
+	fn apply_edit(&self, params: ApplyEditParams) -> Result<Value, Error> {
+		let mut png = self.load(&params.file)?;
+		for (i, edit) in params.edits.into_iter().enumerate() {
+			let len = png.chunks().len();
+			let out_of_range = |index| error(INVALID_PARAMS, format!("Edit {i}: no chunk {index}, {len} chunks"));
+			match edit {
+				Edit::Insert { index: None, chunk } => match png.chunks().last() {
+					Some(last) if last.chunk_type().bytes() == *b"IEND" => png.append_chunk(chunk.chunk()?),
+					_ => {
+						let message = format!("Edit {i}: no IEND to insert before, pass an index");
+						return Err(error(INVALID_PARAMS, message));
+					},
+				},
+				Edit::Insert {
+					index: Some(index),
+					chunk,
+				} => png
+					.insert_chunk(index, chunk.chunk()?)
+					.map_err(|_| out_of_range(index))?,
+				Edit::Replace { index, chunk } => {
+					png.replace_chunk(index, chunk.chunk()?).ok_or_else(|| out_of_range(index))?;
+				},
+				Edit::Remove { index } => {
+					png.remove_chunk(index).ok_or_else(|| out_of_range(index))?;
+				},
+			}
+		}
+
+		let output = params.output_file.unwrap_or(params.file);
+		self.write(&output, png)?;
+		self.inspect(output)
+	}
+
+	/// Writes `png` to `path`, forgetting what was parsed of it before.
+	fn write(&self, path: &Path, png: Png) -> Result<(), Error> {
+		io::write_png(path, png, self.options.timeout, self.options.preserve_metadata)?;
+		self.pngs.lock().expect("not poisoned").remove(path);
+		Ok(())
+	}
+
+	fn decode(&self, params: DecodeParams) -> Result<Value, Error> {
+		let png = self.load(&params.file)?;
+		let chunk = png
+			.chunk_by_type(&params.chunk_type)
+			.ok_or(format!("No `{}` chunk in {}", params.chunk_type, params.file.display()))?;
+		let data = match params.passphrase {
+			Some(passphrase) => crypto::decrypt(passphrase.as_bytes(), chunk.data())?,
+			None => chunk.data().to_vec(),
+		};
+
+		Ok(json!({
+			"message": String::from_utf8_lossy(&data),
+			"data": base64::engine::general_purpose::STANDARD.encode(&data),
+		}))
+	}
+
+	fn inspect(&self, file: PathBuf) -> Result<Value, Error> {
+		let png = self.load(&file)?;
+		let chunks: Vec<Value> = png
+			.chunks()
+			.iter()
+			.enumerate()
+			.map(|(index, c)| {
+				json!({
+					"index": index,
+					"chunk_type": c.chunk_type().to_string(),
+					"length": c.length(),
+					"crc": c.crc(),
+					"critical": c.chunk_type().is_critical(),
+					"safe_to_copy": c.chunk_type().is_safe_to_copy(),
+				})
+			})
+			.collect();
+
+		let path = file.display().to_string();
+		let issues: Vec<Issue> = detect::scan(&png.as_bytes())?
+			.iter()
+			.map(|f| Issue::from_finding(&path, Severity::Warning, f))
+			.collect();
+
+		Ok(json!({ "chunks": chunks, "issues": issues }))
+	}
+}
+
+fn error(code: i64, message: impl std::fmt::Display) -> Error {
+	Error {
+		code,
+		message: message.to_string(),
+	}
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, Error> {
+	serde_json::from_value(params).map_err(|e| error(INVALID_PARAMS, e))
+}
+
+fn response(id: Value, result: Result<Value, Error>) -> String {
+	let response = match result {
+		Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+		Err(e) => json!({
+			"jsonrpc": "2.0",
+			"id": id,
+			"error": { "code": e.code, "message": e.message },
+		}),
+	};
+	response.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Error code of the response to `line`, `None` for a result.
+	fn code(server: &Server, line: &str) -> Option<i64> {
+		let (response, _) = server.handle_line(line);
+		let response: Value = serde_json::from_str(&response.expect("not a notification")).unwrap();
+		response["error"]["code"].as_i64()
+	}
+
+	#[test]
+	fn test_error_codes() {
+		let server = Server::default();
+		assert_eq!(code(&server, "{"), Some(PARSE_ERROR));
+		assert_eq!(code(&server, "[1, 2]"), Some(INVALID_REQUEST));
+		assert_eq!(
+			code(&server, r#"{"jsonrpc": "1.0", "id": 1, "method": "shutdown"}"#),
+			Some(INVALID_REQUEST)
+		);
+		assert_eq!(
+			code(&server, r#"{"jsonrpc": "2.0", "id": 1, "method": "nope"}"#),
+			Some(METHOD_NOT_FOUND)
+		);
+		assert_eq!(
+			code(&server, r#"{"jsonrpc": "2.0", "id": 1, "method": "decode", "params": {}}"#),
+			Some(INVALID_PARAMS)
+		);
+		assert_eq!(
+			code(&server, r#"{"jsonrpc": "2.0", "id": 1, "method": "inspect", "params": {"file": "/nonexistent.png"}}"#),
+			Some(FAILED)
+		);
+		assert_eq!(code(&server, r#"{"jsonrpc": "2.0", "id": 1, "method": "shutdown"}"#), None);
+	}
+
+	#[test]
+	fn test_notification_and_shutdown() {
+		let server = Server::default();
+		assert_eq!(
+			server.handle_line(r#"{"jsonrpc": "2.0", "method": "shutdown"}"#),
+			(None, true)
+		);
+		let (response, shutdown) = server.handle_line(r#"{"jsonrpc": "2.0", "id": 7, "method": "shutdown"}"#);
+		assert!(shutdown);
+		assert!(response.unwrap().contains(r#""id":7"#));
+	}
+
+	#[test]
+	fn test_insert_without_iend() {
+		let path = std::env::temp_dir().join(format!("vanish-rpc-{}.png", std::process::id()));
+		let chunks = ["IHDR", "IEND"].map(|t| Chunk::new(t.parse().unwrap(), Vec::new()));
+		std::fs::write(&path, Png::from_chunks(chunks.to_vec()).as_bytes()).unwrap();
+
+		let request = json!({
+			"jsonrpc": "2.0",
+			"id": 1,
+			"method": "applyEdit",
+			"params": {
+				"file": path,
+				"edits": [
+					{ "op": "remove", "index": 0 },
+					{ "op": "remove", "index": 0 },
+					{ "op": "insert", "chunk_type": "ruSt", "message": "hi" },
+				],
+			},
+		});
+		assert_eq!(code(&Server::default(), &request.to_string()), Some(INVALID_PARAMS));
+		// all the edits or none
+		assert_eq!(Png::try_from(&std::fs::read(&path).unwrap()[..]).unwrap().chunks().len(), 2);
+		std::fs::remove_file(path).unwrap();
+	}
+}
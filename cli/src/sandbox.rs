@@ -0,0 +1,243 @@
+// Parsing in a child process that can do nothing but read its input and
+// write its answer, see `check --sandbox`. A parser bug a hostile file
+// triggers takes down the child rather than vanish, and can't reach files,
+// processes or the network from there.
+//
+// The child is vanish again, started with `WORKER_ARG` before any other
+// argument is looked at. It reads the job and the file on stdin, locks
+// itself down, checks the file and answers with JSON on stdout. The lock
+// down is a seccomp filter letting through reading, writing, memory
+// management and exiting, any other system call kills the process. With a
+// `--timeout` the child also gets that much cpu time at most.
+//
+// Only Linux on x86_64 and aarch64 has the filter. Elsewhere `--sandbox` is
+// refused, a child with the same rights as vanish would isolate nothing.
+
+use std::io::{Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::Duration;
+
+use edpg::png::ParseOptions;
+use edpg::report::Report;
+use edpg::signature::Signature;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::cache;
+use crate::check::{self, Options};
+
+/// First argument vanish is started with to be the child.
+pub const WORKER_ARG: &str = "__sandbox-worker";
+
+/// Whether this platform can lock the child down.
+pub const SUPPORTED: bool = cfg!(all(
+	target_os = "linux",
+	any(target_arch = "x86_64", target_arch = "aarch64")
+));
+
+#[derive(Debug, Error)]
+pub enum Error {
+	#[error("Unable to run the sandboxed parser. {0}")]
+	Io(#[from] std::io::Error),
+	#[error("The sandboxed parser died ({0}), the file may be crafted to break parsers.")]
+	Crashed(ExitStatus),
+	#[error("Unreadable answer from the sandboxed parser. {0}")]
+	Answer(#[from] serde_json::Error),
+	#[error("Unable to lock the sandboxed parser down. {0}")]
+	LockDown(std::io::Error),
+}
+
+/// What the child is asked to do, the first line of its input. The file
+/// follows.
+#[derive(Debug, Serialize, Deserialize)]
+struct Job {
+	name: String,
+	verify_crc: bool,
+	max_prefix: usize,
+	signatures: Vec<Signature>,
+	/// Seconds of cpu time the child gets.
+	cpu_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Answer {
+	report: Report,
+	image: Option<cache::Image>,
+}
+
+/// `check::inspect` of `bytes` in a locked down child.
+pub fn inspect(
+	name: &str,
+	bytes: &[u8],
+	options: &Options,
+	timeout: Option<Duration>,
+) -> Result<(Report, Option<cache::Image>), Error> {
+	let job = Job {
+		name: name.to_owned(),
+		verify_crc: options.parse.verify_crc,
+		max_prefix: options.parse.max_prefix,
+		signatures: options.signatures.clone(),
+		cpu_secs: timeout.map(|t| t.as_secs().max(1)),
+	};
+	let mut child = Command::new(std::env::current_exe()?)
+		.arg(WORKER_ARG)
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::null())
+		.spawn()?;
+
+	// written while the answer is read, neither pipe fills up
+	let mut stdin = child.stdin.take().expect("piped");
+	let mut stdout = child.stdout.take().expect("piped");
+	let mut answer = Vec::new();
+	let written = std::thread::scope(|scope| {
+		let writer = scope.spawn(move || -> std::io::Result<()> {
+			serde_json::to_writer(&mut stdin, &job)?;
+			stdin.write_all(b"\n")?;
+			stdin.write_all(bytes)
+		});
+		stdout.read_to_end(&mut answer)?;
+		writer.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+	});
+
+	let status = child.wait()?;
+	if !status.success() {
+		return Err(Error::Crashed(status));
+	}
+	written?;
+	let answer: Answer = serde_json::from_slice(&answer)?;
+	Ok((answer.report, answer.image))
+}
+
+/// The child's side, never returns.
+pub fn worker() -> ! {
+	let code = match serve() {
+		Ok(()) => 0,
+		Err(e) => {
+			eprintln!("{e}");
+			1
+		},
+	};
+	std::process::exit(code)
+}
+
+fn serve() -> Result<(), Error> {
+	let mut input = Vec::new();
+	std::io::stdin().lock().read_to_end(&mut input)?;
+	let Some(newline) = input.iter().position(|&b| b == b'\n') else {
+		return Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
+	};
+	let job: Job = serde_json::from_slice(&input[..newline])?;
+	let bytes = &input[newline + 1..];
+	let options = Options {
+		parse: ParseOptions {
+			verify_crc: job.verify_crc,
+			max_prefix: job.max_prefix,
+		},
+		signatures: job.signatures,
+		sandbox: false,
+	};
+	// nothing past here gets to open, spawn or connect
+	let mut stdout = std::io::stdout().lock();
+	lock_down(job.cpu_secs).map_err(Error::LockDown)?;
+
+	let (report, image) = check::inspect(&job.name, bytes, &options);
+	serde_json::to_writer(&mut stdout, &Answer { report, image })?;
+	stdout.flush()?;
+	Ok(())
+}
+
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn lock_down(cpu_secs: Option<u64>) -> std::io::Result<()> {
+	use libc::{sock_filter, sock_fprog};
+
+	#[cfg(target_arch = "x86_64")]
+	const AUDIT_ARCH: u32 = 0xC000_003E;
+	#[cfg(target_arch = "aarch64")]
+	const AUDIT_ARCH: u32 = 0xC000_00B7;
+	const ALLOWED: [libc::c_long; 14] = [
+		libc::SYS_read,
+		libc::SYS_write,
+		libc::SYS_close,
+		libc::SYS_brk,
+		libc::SYS_mmap,
+		libc::SYS_munmap,
+		libc::SYS_mremap,
+		libc::SYS_madvise,
+		libc::SYS_futex,
+		libc::SYS_getrandom,
+		libc::SYS_sigaltstack,
+		libc::SYS_rt_sigreturn,
+		libc::SYS_exit,
+		libc::SYS_exit_group,
+	];
+
+	let load = |offset: usize| sock_filter {
+		code: (libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16,
+		jt: 0,
+		jf: 0,
+		k: offset as u32,
+	};
+	// skips the next instruction if equal
+	let skip_if = |k: u32| sock_filter {
+		code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16,
+		jt: 1,
+		jf: 0,
+		k,
+	};
+	let ret = |k: u32| sock_filter {
+		code: (libc::BPF_RET | libc::BPF_K) as u16,
+		jt: 0,
+		jf: 0,
+		k,
+	};
+
+	// system call numbers only mean something for the arch they're for
+	let mut filter = vec![
+		load(std::mem::offset_of!(libc::seccomp_data, arch)),
+		skip_if(AUDIT_ARCH),
+		ret(libc::SECCOMP_RET_KILL_PROCESS),
+		load(std::mem::offset_of!(libc::seccomp_data, nr)),
+	];
+	for nr in ALLOWED {
+		filter.push(sock_filter {
+			code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16,
+			jt: 0,
+			jf: 1,
+			k: nr as u32,
+		});
+		filter.push(ret(libc::SECCOMP_RET_ALLOW));
+	}
+	filter.push(ret(libc::SECCOMP_RET_KILL_PROCESS));
+	let program = sock_fprog {
+		len: filter.len() as u16,
+		filter: filter.as_mut_ptr(),
+	};
+
+	// SAFETY: plain calls with valid arguments, `program` outlives them and
+	// the kernel copies the filter
+	unsafe {
+		if let Some(secs) = cpu_secs {
+			let limit = libc::rlimit {
+				rlim_cur: secs,
+				rlim_max: secs,
+			};
+			if libc::setrlimit(libc::RLIMIT_CPU, &limit) != 0 {
+				return Err(std::io::Error::last_os_error());
+			}
+		}
+		if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+			return Err(std::io::Error::last_os_error());
+		}
+		let program: *const sock_fprog = &program;
+		if libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, program) != 0 {
+			return Err(std::io::Error::last_os_error());
+		}
+	}
+	Ok(())
+}
+
+#[cfg(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+fn lock_down(_: Option<u64>) -> std::io::Result<()> {
+	Err(std::io::ErrorKind::Unsupported.into())
+}
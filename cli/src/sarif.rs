@@ -0,0 +1,64 @@
+// Reports as SARIF 2.1.0, the format code scanning dashboards (GitHub,
+// GitLab, Azure DevOps...) take findings in.
+
+use edpg::report::{Location, Report, Severity};
+use serde_json::{json, Value};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// `report` as a SARIF log with a single run.
+pub fn to_sarif(report: &Report) -> Value {
+	let mut rules: Vec<&str> = report.issues.iter().map(|i| i.rule.as_str()).collect();
+	rules.sort_unstable();
+	rules.dedup();
+
+	let results: Vec<Value> = report
+		.issues
+		.iter()
+		.map(|issue| {
+			let mut location = json!({
+				"physicalLocation": {
+					"artifactLocation": { "uri": issue.path.replace('\\', "/") },
+				},
+			});
+			match issue.location {
+				Location::File => {},
+				Location::Offset(offset) => {
+					location["physicalLocation"]["region"] = json!({ "byteOffset": offset });
+				},
+				Location::Chunk(index) => {
+					location["logicalLocations"] = json!([{ "name": format!("chunk {index}") }]);
+				},
+			}
+
+			let mut result = json!({
+				"ruleId": issue.rule,
+				"level": match issue.severity {
+					Severity::Error => "error",
+					Severity::Warning => "warning",
+				},
+				"message": { "text": issue.message },
+				"locations": [location],
+			});
+			if let Some(remediation) = &issue.remediation {
+				result["properties"] = json!({ "remediation": remediation });
+			}
+			result
+		})
+		.collect();
+
+	json!({
+		"$schema": SCHEMA,
+		"version": "2.1.0",
+		"runs": [{
+			"tool": {
+				"driver": {
+					"name": "vanish",
+					"version": env!("CARGO_PKG_VERSION"),
+					"rules": rules.iter().map(|id| json!({ "id": id })).collect::<Vec<_>>(),
+				},
+			},
+			"results": results,
+		}],
+	})
+}
@@ -0,0 +1,110 @@
+// Rhai scripts over the chunks of a png.
+//
+// The script sees the loaded png as `png`, whatever it leaves there is what
+// gets written back. Scripts have no file or process access, the only way
+// out is `print`.
+
+use edpg::chunk::Chunk;
+use edpg::chunk_type::ChunkType;
+use edpg::png::Png;
+use rhai::{Array, Blob, Dynamic, Engine, EvalAltResult, Scope};
+
+/// Bound on the work a script may do, so a runaway loop ends with an error.
+pub const MAX_OPERATIONS: u64 = 50_000_000;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+fn index(png: &Png, i: i64, allow_end: bool) -> ScriptResult<usize> {
+	let len = png.chunks().len();
+	match usize::try_from(i) {
+		Ok(i) if i < len || (allow_end && i == len) => Ok(i),
+		_ => Err(format!("Chunk index {i} is out of range, the png has {len} chunks.").into()),
+	}
+}
+
+fn new_chunk(chunk_type: &str, data: Vec<u8>) -> ScriptResult<Chunk> {
+	let chunk_type: ChunkType = chunk_type.parse().map_err(|e| format!("{e}"))?;
+	Ok(Chunk::new(chunk_type, data))
+}
+
+fn engine() -> Engine {
+	let mut engine = Engine::new();
+	engine.set_max_operations(MAX_OPERATIONS);
+
+	engine
+		.register_type_with_name::<Chunk>("Chunk")
+		.register_fn("chunk", |t: &str, data: Blob| new_chunk(t, data))
+		.register_fn("chunk", |t: &str, text: &str| new_chunk(t, text.into()))
+		.register_get("type", |c: &mut Chunk| c.chunk_type().to_string())
+		.register_get("data", |c: &mut Chunk| -> Blob { c.data().to_vec() })
+		.register_get("text", |c: &mut Chunk| {
+			String::from_utf8_lossy(c.data()).into_owned()
+		})
+		.register_get("len", |c: &mut Chunk| c.length() as i64)
+		.register_get("crc", |c: &mut Chunk| c.crc() as i64)
+		.register_get("is_critical", |c: &mut Chunk| c.chunk_type().is_critical())
+		.register_get("is_public", |c: &mut Chunk| c.chunk_type().is_public())
+		.register_get("is_safe_to_copy", |c: &mut Chunk| {
+			c.chunk_type().is_safe_to_copy()
+		})
+		.register_fn("to_string", |c: &mut Chunk| c.to_string());
+
+	engine
+		.register_type_with_name::<Png>("Png")
+		.register_get("len", |png: &mut Png| png.chunks().len() as i64)
+		.register_fn("chunks", |png: &mut Png| -> Array {
+			png.chunks().iter().cloned().map(Dynamic::from).collect()
+		})
+		.register_fn("chunk", |png: &mut Png, i: i64| -> ScriptResult<Chunk> {
+			Ok(png.chunks()[index(png, i, false)?].clone())
+		})
+		.register_fn("find", |png: &mut Png, t: &str| {
+			png.find_nth_by_type(t, 0).map_or(-1, |i| i as i64)
+		})
+		.register_fn("find", |png: &mut Png, t: &str, n: i64| {
+			usize::try_from(n)
+				.ok()
+				.and_then(|n| png.find_nth_by_type(t, n))
+				.map_or(-1, |i| i as i64)
+		})
+		.register_fn("positions", |png: &mut Png, t: &str| -> Array {
+			png.positions_of(t).into_iter().map(|i| Dynamic::from(i as i64)).collect()
+		})
+		.register_fn("remove", |png: &mut Png, i: i64| -> ScriptResult<Chunk> {
+			let i = index(png, i, false)?;
+			Ok(png.remove_chunk(i).expect("index checked above"))
+		})
+		.register_fn("insert", |png: &mut Png, i: i64, chunk: Chunk| -> ScriptResult<()> {
+			let i = index(png, i, true)?;
+			png.insert_chunk(i, chunk).map_err(|e| e.to_string().into())
+		})
+		.register_fn("replace", |png: &mut Png, i: i64, chunk: Chunk| -> ScriptResult<Chunk> {
+			let i = index(png, i, false)?;
+			Ok(png.replace_chunk(i, chunk).expect("index checked above"))
+		})
+		.register_fn("push", |png: &mut Png, chunk: Chunk| -> ScriptResult<()> {
+			// goes in before IEND, there has to be one
+			match png.chunks().last() {
+				Some(last) if last.chunk_type().bytes() == *b"IEND" => {
+					png.append_chunk(chunk);
+					Ok(())
+				},
+				_ => Err("The png doesn't end in IEND, `insert` the chunk instead.".into()),
+			}
+		});
+
+	engine
+}
+
+/// Runs `script` with `png` in scope and returns the png as the script left
+/// it.
+pub fn run(script: &str, png: Png) -> ScriptResult<Png> {
+	let mut scope = Scope::new();
+	scope.push("png", png);
+
+	engine().run_with_scope(&mut scope, script)?;
+
+	scope
+		.get_value::<Png>("png")
+		.ok_or_else(|| "The script replaced `png` with something that is not a Png.".into())
+}
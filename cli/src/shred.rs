@@ -0,0 +1,75 @@
+// Best effort secure deletion: overwrite in place, sync, then unlink.
+//
+// Only as good as the filesystem lets it be. Copy on write filesystems and
+// SSD wear leveling put the overwrites somewhere else, the old blocks stay
+// until reused.
+
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+pub const DEFAULT_PASSES: usize = 3;
+
+/// Byte pattern of every pass, cycled.
+const PATTERNS: [u8; 3] = [0x00, 0xFF, 0x55];
+
+/// Overwrites `path` `passes` times, syncing after each, then truncates and
+/// removes it.
+pub fn shred(path: &Path, passes: usize) -> std::io::Result<()> {
+	crate::io::writable(path)?;
+	if let Some(fs) = copy_on_write_fs(path) {
+		eprintln!(
+			"{} is on {fs}, a copy on write filesystem, the old data likely survives shredding",
+			path.display()
+		);
+	}
+
+	let mut file = OpenOptions::new().write(true).open(path)?;
+	let len = file.metadata()?.len();
+	let mut block = vec![0; 64 * 1024];
+
+	for pass in 0..passes {
+		block.fill(PATTERNS[pass % PATTERNS.len()]);
+		file.seek(SeekFrom::Start(0))?;
+
+		let mut left = len;
+		while left > 0 {
+			let n = left.min(block.len() as u64) as usize;
+			file.write_all(&block[..n])?;
+			left -= n as u64;
+		}
+		file.sync_all()?;
+	}
+
+	file.set_len(0)?;
+	file.sync_all()?;
+	drop(file);
+	std::fs::remove_file(path)
+}
+
+/// Filesystem type of `path` if it is one known to never overwrite in place.
+#[cfg(target_os = "linux")]
+fn copy_on_write_fs(path: &Path) -> Option<String> {
+	let path = path.canonicalize().ok()?;
+	let mounts = std::fs::read_to_string("/proc/self/mounts").ok()?;
+
+	// the longest mount point containing the file is the one it lives on
+	let (_, fs) = mounts
+		.lines()
+		.filter_map(|line| {
+			let mut fields = line.split_whitespace();
+			let mount_point = fields.nth(1)?;
+			let fs = fields.next()?;
+			path.starts_with(mount_point).then_some((mount_point.len(), fs))
+		})
+		.max_by_key(|(len, _)| *len)?;
+
+	["btrfs", "zfs", "bcachefs"]
+		.contains(&fs)
+		.then(|| fs.to_owned())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_on_write_fs(_path: &Path) -> Option<String> {
+	None
+}
@@ -0,0 +1,165 @@
+// Files a png sits inside of: gzip (`.png.gz`), a zip holding a single png,
+// an icon. Commands see the png, `Ctx::load` takes the layers off and
+// `Ctx::save` puts them back on in reverse.
+
+use std::io::{Read, Write};
+
+use edpg::analysis::MAX_INFLATED_BYTES;
+use edpg::icon::{self, Icon, IconError};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum WrapperError {
+	#[error("{0}")]
+	Io(#[from] std::io::Error),
+	#[error("{0}")]
+	Zip(#[from] zip::result::ZipError),
+	#[error("{0}")]
+	Icon(#[from] IconError),
+	#[error("Zip archive holds {0} files, only a single png can be worked on in place.")]
+	NotSinglePng(usize),
+	#[error("Unpacks to more than {MAX_INFLATED_BYTES} bytes.")]
+	TooLarge,
+	#[error("More than {MAX_DEPTH} layers around the png.")]
+	TooDeep,
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const ZIP_MAGIC: &[u8; 4] = b"PK\x03\x04";
+/// Layers `unwrap_all` takes off, a gzip of a gzip of... stops here.
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Clone)]
+pub enum Wrapper {
+	Gzip,
+	/// Zip archive holding a single file, `name` is its path inside.
+	Zip { name: String },
+	/// Icon with the png at `index` taken out.
+	Icon { icon: Icon, index: usize },
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+	/// Leave gzip and zip archives alone.
+	pub keep_archives: bool,
+	/// Icon image to take out, the first png if not set.
+	pub inner_index: Option<usize>,
+}
+
+impl Wrapper {
+	/// The wrapper around `bytes` and what is inside, `None` if `bytes` isn't
+	/// wrapped in anything.
+	pub fn unwrap(
+		bytes: &[u8],
+		options: &Options,
+	) -> Result<Option<(Wrapper, Vec<u8>)>, WrapperError> {
+		if !options.keep_archives && bytes.starts_with(&GZIP_MAGIC) {
+			let inner = read_bounded(flate2::read::GzDecoder::new(bytes))?;
+			return Ok(Some((Wrapper::Gzip, inner)));
+		}
+
+		if !options.keep_archives && bytes.starts_with(ZIP_MAGIC) {
+			let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))?;
+			let files: Vec<usize> = (0..archive.len())
+				.filter(|&i| archive.by_index_raw(i).is_ok_and(|f| f.is_file()))
+				.collect();
+			let [index] = files[..] else {
+				return Err(WrapperError::NotSinglePng(files.len()));
+			};
+
+			let file = archive.by_index(index)?;
+			let name = file.name().to_owned();
+			let inner = read_bounded(file)?;
+			return Ok(Some((Wrapper::Zip { name }, inner)));
+		}
+
+		if icon::is_icon(bytes) {
+			let icon = Icon::parse(bytes)?;
+			let index = match options.inner_index {
+				Some(index) => index,
+				None => icon.first_png().ok_or(IconError::NoPng)?,
+			};
+			let inner = icon.png(index)?.to_vec();
+			return Ok(Some((Wrapper::Icon { icon, index }, inner)));
+		}
+
+		Ok(None)
+	}
+
+	/// `inner` wrapped back up the way it was found.
+	pub fn wrap(self, inner: Vec<u8>) -> Result<Vec<u8>, WrapperError> {
+		match self {
+			Wrapper::Gzip => {
+				let mut encoder =
+					flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+				encoder.write_all(&inner)?;
+				Ok(encoder.finish()?)
+			},
+			Wrapper::Zip { name } => {
+				let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+				let options = zip::write::SimpleFileOptions::default()
+					.compression_method(zip::CompressionMethod::Deflated);
+				archive.start_file(name, options)?;
+				archive.write_all(&inner)?;
+				Ok(archive.finish()?.into_inner())
+			},
+			Wrapper::Icon { mut icon, index } => {
+				icon.replace(index, inner)?;
+				Ok(icon.to_bytes())
+			},
+		}
+	}
+}
+
+/// All of `reader`, at most `MAX_INFLATED_BYTES` of it. A few kilobytes of
+/// gzip or zip can unpack to gigabytes.
+fn read_bounded(reader: impl Read) -> Result<Vec<u8>, WrapperError> {
+	let mut inner = Vec::new();
+	reader.take(MAX_INFLATED_BYTES + 1).read_to_end(&mut inner)?;
+	match inner.len() as u64 > MAX_INFLATED_BYTES {
+		true => Err(WrapperError::TooLarge),
+		false => Ok(inner),
+	}
+}
+
+/// Takes off every layer around `bytes`, outermost first.
+pub fn unwrap_all(
+	mut bytes: Vec<u8>,
+	options: &Options,
+) -> Result<(Vec<Wrapper>, Vec<u8>), WrapperError> {
+	let mut wrappers = Vec::new();
+	while let Some((wrapper, inner)) = Wrapper::unwrap(&bytes, options)? {
+		if wrappers.len() == MAX_DEPTH {
+			return Err(WrapperError::TooDeep);
+		}
+		wrappers.push(wrapper);
+		bytes = inner;
+	}
+	Ok((wrappers, bytes))
+}
+
+/// Puts `wrappers`, as returned by `unwrap_all`, back around `bytes`.
+pub fn wrap_all(wrappers: Vec<Wrapper>, bytes: Vec<u8>) -> Result<Vec<u8>, WrapperError> {
+	wrappers
+		.into_iter()
+		.rev()
+		.try_fold(bytes, |inner, wrapper| wrapper.wrap(inner))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_unwrap_all() {
+		let gzipped = |layers| (0..layers).try_fold(b"png".to_vec(), |inner, _| Wrapper::Gzip.wrap(inner));
+
+		let (wrappers, inner) = unwrap_all(gzipped(MAX_DEPTH).unwrap(), &Options::default()).unwrap();
+		assert_eq!(wrappers.len(), MAX_DEPTH);
+		assert_eq!(inner, b"png");
+		assert!(matches!(
+			unwrap_all(gzipped(MAX_DEPTH + 1).unwrap(), &Options::default()),
+			Err(WrapperError::TooDeep)
+		));
+	}
+}
@@ -0,0 +1,50 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn vanish(args: &[&str], dir: &Path) -> bool {
+	Command::new(env!("CARGO_BIN_EXE_cli"))
+		.args(args)
+		.current_dir(dir)
+		.output()
+		.unwrap()
+		.status
+		.success()
+}
+
+/// A fresh directory holding `in.png`, dice.png with a `ruSt` chunk.
+fn dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("vanish-remove-{name}-{}", std::process::id()));
+	let _ = std::fs::remove_dir_all(&dir);
+	std::fs::create_dir_all(&dir).unwrap();
+	std::fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/../dice.png"), dir.join("in.png")).unwrap();
+	assert!(vanish(&["encode", "in.png", "ruSt", "hidden", "--yes"], &dir));
+	dir
+}
+
+#[test]
+fn test_failed_save_removed_keeps_chunk() {
+	let dir = dir("failed");
+	let before = std::fs::read(dir.join("in.png")).unwrap();
+
+	// can't be written
+	assert!(!vanish(&["remove", "in.png", "ruSt", "--save-removed", "missing/chunk", "--yes"], &dir));
+	assert_eq!(std::fs::read(dir.join("in.png")).unwrap(), before);
+
+	// declined
+	std::fs::write(dir.join("chunk"), "taken").unwrap();
+	assert!(!vanish(&["remove", "in.png", "ruSt", "--save-removed", "chunk", "--no-input"], &dir));
+	assert_eq!(std::fs::read(dir.join("in.png")).unwrap(), before);
+
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_remove_saves_chunk_first() {
+	let dir = dir("saved");
+	assert!(vanish(&["remove", "in.png", "ruSt", "--save-removed", "chunk", "--yes"], &dir));
+	let saved = std::fs::read(dir.join("chunk")).unwrap();
+	assert!(saved.windows(10).any(|w| w == b"ruSthidden"));
+	assert!(!vanish(&["decode", "in.png", "ruSt"], &dir));
+
+	std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,23 @@
+#![cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+
+use std::process::{Command, Output};
+
+fn check(args: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_cli"))
+		.arg("check")
+		.args(args)
+		.output()
+		.unwrap()
+}
+
+#[test]
+fn test_sandboxed_check_matches_in_process() {
+	let dice = concat!(env!("CARGO_MANIFEST_DIR"), "/../dice.png");
+	let sandboxed = check(&[dice, "--sandbox", "--timeout", "30"]);
+	assert!(sandboxed.status.success());
+	assert_eq!(sandboxed.stdout, check(&[dice]).stdout);
+
+	let junk = check(&["--sandbox", concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")]);
+	assert!(!junk.status.success());
+	assert!(String::from_utf8_lossy(&junk.stdout).contains("Invalid Header"));
+}
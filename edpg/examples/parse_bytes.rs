@@ -0,0 +1,63 @@
+// Parsing a big png by copying every chunk out of the input against taking
+// slices of it with `Png::parse_bytes`. Run each way on its own, peak memory
+// is per process:
+//
+//   cargo run --release -p edpg --example parse_bytes -- copy
+//   cargo run --release -p edpg --example parse_bytes -- bytes
+//   cargo run --release -p edpg --example parse_bytes -- bytes --no-verify-crc
+
+use std::time::Instant;
+
+use bytes::Bytes;
+use edpg::chunk::Chunk;
+use edpg::png::{ParseOptions, Png};
+
+/// 24 IDAT chunks of 8 MB, about 200 MB.
+const IDAT_CHUNKS: usize = 24;
+const IDAT_BYTES: usize = 8 * 1024 * 1024;
+
+/// Written a chunk at a time, only the input itself is in memory.
+fn input() -> Vec<u8> {
+	let chunk = |t: &str, data: Vec<u8>| Chunk::new(t.parse().expect("valid"), data).as_bytes();
+	let mut input = Png::STANDARD_HEADER.to_vec();
+	input.reserve(IDAT_CHUNKS * (IDAT_BYTES + Chunk::METADATA_BYTES) + 64);
+	input.extend(chunk("IHDR", vec![0, 0, 16, 0, 0, 0, 16, 0, 8, 6, 0, 0, 0]));
+	for i in 0..IDAT_CHUNKS {
+		input.extend(chunk("IDAT", vec![i as u8; IDAT_BYTES]));
+	}
+	input.extend(chunk("IEND", Vec::new()));
+	input
+}
+
+/// Peak resident memory of this process, where the system tells.
+fn peak_rss() -> Option<String> {
+	let status = std::fs::read_to_string("/proc/self/status").ok()?;
+	let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+	Some(line["VmHWM:".len()..].trim().to_owned())
+}
+
+fn main() {
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	let options = ParseOptions {
+		verify_crc: !args.iter().any(|a| a == "--no-verify-crc"),
+		..Default::default()
+	};
+	let input = input();
+
+	let start = Instant::now();
+	let png = match args.first().map(String::as_str) {
+		Some("copy") => Png::parse_with(&input, &options),
+		Some("bytes") => Png::parse_bytes(&Bytes::from(input), &options),
+		_ => {
+			eprintln!("usage: parse_bytes copy|bytes [--no-verify-crc]");
+			std::process::exit(2);
+		},
+	}
+	.expect("parses");
+	let elapsed = start.elapsed();
+
+	println!("{} chunks in {elapsed:?}", png.chunks().len());
+	if let Some(rss) = peak_rss() {
+		println!("peak RSS {rss}");
+	}
+}
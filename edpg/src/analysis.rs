@@ -0,0 +1,388 @@
+// How well the zlib compressed chunks (zTXt, iCCP, IDAT) compress. Text and
+// color profiles shrink a lot and image data inflates to exactly what IHDR
+// calls for. Encrypted bytes dressed up as compressed text don't shrink at
+// all, and bytes tacked onto a zlib stream are never looked at by decoders.
+
+use std::fmt::Display;
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use crate::chunk::Chunk;
+use crate::iter::ChunkIter;
+use crate::png::Png;
+
+/// Inflating stops after this many bytes, whatever claims more is cut off.
+pub const MAX_INFLATED_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Text and profiles inflating to less than this many times their
+/// compressed size look random. zlib over random bytes even grows them a
+/// little.
+pub const MIN_RATIO: f64 = 1.1;
+
+/// Compressed data shorter than this is too short to judge by its ratio.
+pub const MIN_RATIO_BYTES: usize = 64;
+
+/// Compression of a single chunk, or of all IDAT chunks together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ratio {
+	/// Index of the chunk, of the first IDAT for image data.
+	pub index: usize,
+	pub chunk_type: String,
+	pub compressed: usize,
+	/// `None` if the data isn't a zlib stream.
+	pub inflated: Option<u64>,
+	/// Compressed bytes after the end of the zlib stream.
+	pub unused: usize,
+}
+
+impl Ratio {
+	/// Inflated size over compressed size.
+	pub fn ratio(&self) -> Option<f64> {
+		self.inflated
+			.map(|inflated| inflated as f64 / self.compressed.max(1) as f64)
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Anomaly {
+	/// Supposed to be zlib compressed, isn't.
+	NotZlib { index: usize, chunk_type: String },
+	/// Text or profile that barely compresses, see `MIN_RATIO`.
+	Incompressible {
+		index: usize,
+		chunk_type: String,
+		compressed: usize,
+		inflated: u64,
+	},
+	/// Bytes after the end of the zlib stream.
+	UnusedBytes {
+		index: usize,
+		chunk_type: String,
+		len: usize,
+	},
+	/// Image data inflating to another size than IHDR calls for.
+	ImageSize {
+		index: usize,
+		expected: u64,
+		actual: u64,
+	},
+}
+
+impl Anomaly {
+	/// Index of the chunk it is about.
+	pub fn index(&self) -> usize {
+		match self {
+			Self::NotZlib { index, .. }
+			| Self::Incompressible { index, .. }
+			| Self::UnusedBytes { index, .. }
+			| Self::ImageSize { index, .. } => *index,
+		}
+	}
+}
+
+impl Display for Anomaly {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::NotZlib { index, chunk_type } => {
+				write!(f, "chunk {index}: `{chunk_type}` data isn't zlib compressed")
+			},
+			Self::Incompressible {
+				index,
+				chunk_type,
+				compressed,
+				inflated,
+			} => write!(
+				f,
+				"chunk {index}: `{chunk_type}` inflates from {compressed} to only {inflated} \
+				 bytes, looks like random or encrypted data"
+			),
+			Self::UnusedBytes {
+				index,
+				chunk_type,
+				len,
+			} => write!(
+				f,
+				"chunk {index}: {len} bytes after the end of the compressed `{chunk_type}` data"
+			),
+			Self::ImageSize {
+				index,
+				expected,
+				actual,
+			} => write!(
+				f,
+				"chunk {index}: image data inflates to {actual} bytes, IHDR calls for {expected}"
+			),
+		}
+	}
+}
+
+/// Inflated size of `data` and how many of its bytes the zlib stream took,
+/// `None` if it isn't one.
+fn inflate(data: &[u8]) -> Option<(u64, usize)> {
+	let mut decoder = ZlibDecoder::new(data);
+	let inflated = std::io::copy(
+		&mut (&mut decoder).take(MAX_INFLATED_BYTES),
+		&mut std::io::sink(),
+	)
+	.ok()?;
+	Some((inflated, decoder.total_in() as usize))
+}
+
+fn ratio(index: usize, chunk_type: &str, compressed: &[u8]) -> Ratio {
+	let inflated = inflate(compressed);
+	Ratio {
+		index,
+		chunk_type: chunk_type.to_owned(),
+		compressed: compressed.len(),
+		inflated: inflated.map(|(len, _)| len),
+		unused: inflated.map_or(0, |(_, used)| compressed.len() - used),
+	}
+}
+
+/// Compressed part of a zTXt or iCCP chunk: after the keyword, its nul and
+/// the compression method.
+fn compressed_text(chunk: &Chunk) -> &[u8] {
+	let data = chunk.data();
+	match data.iter().position(|&b| b == 0) {
+		Some(nul) => data.get(nul + 2..).unwrap_or_default(),
+		None => data,
+	}
+}
+
+/// Bytes the image data should inflate to per IHDR, a filter byte and the
+/// pixels of every row, of every Adam7 pass if interlaced. `None` without a
+/// valid IHDR.
+pub fn expected_image_len(ihdr: &[u8]) -> Option<u64> {
+	let [w0, w1, w2, w3, h0, h1, h2, h3, bit_depth, color_type, _, _, interlace] = *ihdr else {
+		return None;
+	};
+	let width = u32::from_be_bytes([w0, w1, w2, w3]) as u64;
+	let height = u32::from_be_bytes([h0, h1, h2, h3]) as u64;
+	let channels = match color_type {
+		0 | 3 => 1,
+		2 => 3,
+		4 => 2,
+		6 => 4,
+		_ => return None,
+	};
+	let bits = channels * bit_depth as u64;
+	let rows = |width: u64, height: u64| match width * height {
+		0 => 0,
+		_ => height * (1 + (width * bits).div_ceil(8)),
+	};
+
+	match interlace {
+		0 => Some(rows(width, height)),
+		1 => {
+			// (x, y) of the first pixel and the step between pixels per pass
+			const ADAM7: [(u64, u64, u64, u64); 7] = [
+				(0, 0, 8, 8),
+				(4, 0, 8, 8),
+				(0, 4, 4, 8),
+				(2, 0, 4, 4),
+				(0, 2, 2, 4),
+				(1, 0, 2, 2),
+				(0, 1, 1, 2),
+			];
+			let pass = |start: u64, step: u64, len: u64| (len + step - 1).saturating_sub(start) / step;
+			Some(ADAM7
+				.iter()
+				.map(|&(x, y, dx, dy)| rows(pass(x, dx, width), pass(y, dy, height)))
+				.sum())
+		},
+		_ => None,
+	}
+}
+
+/// Compression of every zTXt and iCCP chunk, and of the image data, in file
+/// order.
+pub fn ratios(png: &Png) -> Vec<Ratio> {
+	let chunks = png.chunks();
+	let mut ratios: Vec<Ratio> = chunks
+		.iter()
+		.enumerate()
+		.filter(|(_, c)| matches!(&c.chunk_type().bytes(), b"zTXt" | b"iCCP"))
+		.map(|(i, c)| ratio(i, &c.chunk_type().to_string(), compressed_text(c)))
+		.collect();
+
+	// IDAT chunks split a single zlib stream anywhere
+	if let Some(first) = png.find_nth_by_type("IDAT", 0) {
+		let data: Vec<u8> = chunks
+			.of_type("IDAT")
+			.flat_map(|c| c.data().iter().copied())
+			.collect();
+		ratios.push(ratio(first, "IDAT", &data));
+		ratios.sort_by_key(|r| r.index);
+	}
+
+	ratios
+}
+
+/// Compressed chunks that don't compress like what they claim to hold, in
+/// file order.
+pub fn anomalies(png: &Png) -> Vec<Anomaly> {
+	let expected = png
+		.chunks()
+		.of_type("IHDR")
+		.next()
+		.and_then(|ihdr| expected_image_len(ihdr.data()));
+
+	let mut anomalies = Vec::new();
+	for r in ratios(png) {
+		let Some(inflated) = r.inflated else {
+			anomalies.push(Anomaly::NotZlib {
+				index: r.index,
+				chunk_type: r.chunk_type,
+			});
+			continue;
+		};
+
+		if r.chunk_type == "IDAT" {
+			match expected {
+				Some(expected) if expected != inflated && inflated < MAX_INFLATED_BYTES => {
+					anomalies.push(Anomaly::ImageSize {
+						index: r.index,
+						expected,
+						actual: inflated,
+					})
+				},
+				_ => {},
+			}
+		} else if r.compressed >= MIN_RATIO_BYTES
+			&& r.ratio().is_some_and(|ratio| ratio < MIN_RATIO)
+		{
+			anomalies.push(Anomaly::Incompressible {
+				index: r.index,
+				chunk_type: r.chunk_type.clone(),
+				compressed: r.compressed,
+				inflated,
+			});
+		}
+
+		if r.unused > 0 {
+			anomalies.push(Anomaly::UnusedBytes {
+				index: r.index,
+				chunk_type: r.chunk_type,
+				len: r.unused,
+			});
+		}
+	}
+
+	anomalies
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Write;
+	use std::str::FromStr;
+
+	use flate2::write::ZlibEncoder;
+	use flate2::Compression;
+
+	use super::*;
+	use crate::chunk_type::ChunkType;
+
+	fn deflate(data: &[u8]) -> Vec<u8> {
+		let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+		encoder.write_all(data).unwrap();
+		encoder.finish().unwrap()
+	}
+
+	/// Bytes that don't compress, from a xorshift.
+	fn noise(len: usize) -> Vec<u8> {
+		let mut state = 0x2545_f491_u32;
+		(0..len)
+			.map(|_| {
+				state ^= state << 13;
+				state ^= state >> 17;
+				state ^= state << 5;
+				state as u8
+			})
+			.collect()
+	}
+
+	fn testing_png(chunks: Vec<(&str, Vec<u8>)>) -> Png {
+		// 2x1 grayscale, 8 bits: a filter byte and 2 pixels
+		let ihdr = [0, 0, 0, 2, 0, 0, 0, 1, 8, 0, 0, 0, 0].to_vec();
+		Png::from_chunks(
+			std::iter::once(("IHDR", ihdr))
+				.chain(chunks)
+				.chain(std::iter::once(("IEND", Vec::new())))
+				.map(|(t, d)| Chunk::new(ChunkType::from_str(t).unwrap(), d))
+				.collect(),
+		)
+	}
+
+	fn ztxt(compressed: Vec<u8>) -> (&'static str, Vec<u8>) {
+		let mut data = b"Comment\0\0".to_vec();
+		data.extend(compressed);
+		("zTXt", data)
+	}
+
+	#[test]
+	fn test_expected_image_len() {
+		let ihdr = |w: u32, h: u32, interlace| {
+			let mut ihdr = [w.to_be_bytes(), h.to_be_bytes()].concat();
+			ihdr.extend_from_slice(&[8, 2, 0, 0, interlace]);
+			ihdr
+		};
+		assert_eq!(expected_image_len(&ihdr(3, 2, 0)), Some(2 * (1 + 9)));
+		// 1x1 only has a pixel in the first pass
+		assert_eq!(expected_image_len(&ihdr(1, 1, 1)), Some(1 + 3));
+		// 8x8 interlaced: passes of 1x1, 1x1, 2x1, 2x2, 4x2, 4x4, 8x4
+		assert_eq!(expected_image_len(&ihdr(8, 8, 1)), Some(4 + 4 + 7 + 14 + 26 + 52 + 100));
+		assert_eq!(expected_image_len(b"header"), None);
+	}
+
+	#[test]
+	fn test_clean() {
+		let text = deflate(&b"a comment that compresses well ".repeat(8));
+		let png = testing_png(vec![ztxt(text), ("IDAT", deflate(&[0, 1, 2]))]);
+		assert_eq!(ratios(&png).len(), 2);
+		assert!(anomalies(&png).is_empty());
+	}
+
+	#[test]
+	fn test_anomalies() {
+		let mut padded = deflate(&[0, 1, 2]);
+		padded.extend_from_slice(b"hidden");
+		let random = deflate(&noise(256));
+		let png = testing_png(vec![
+			ztxt(random.clone()),
+			ztxt(noise(256)),
+			("IDAT", padded),
+		]);
+
+		assert_eq!(anomalies(&png), [
+			Anomaly::Incompressible {
+				index: 1,
+				chunk_type: "zTXt".into(),
+				compressed: random.len(),
+				inflated: 256,
+			},
+			Anomaly::NotZlib {
+				index: 2,
+				chunk_type: "zTXt".into(),
+			},
+			Anomaly::UnusedBytes {
+				index: 3,
+				chunk_type: "IDAT".into(),
+				len: 6,
+			},
+		]);
+	}
+
+	#[test]
+	fn test_image_size() {
+		let png = testing_png(vec![
+			("IDAT", deflate(&[0, 1])[..4].to_vec()),
+			("IDAT", deflate(&[0, 1])[4..].to_vec()),
+		]);
+		assert_eq!(anomalies(&png), [Anomaly::ImageSize {
+			index: 1,
+			expected: 3,
+			actual: 2,
+		}]);
+	}
+}
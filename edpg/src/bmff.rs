@@ -0,0 +1,209 @@
+// Payloads in ISO base media files (MP4, MOV, M4A...), for short videos as
+// carriers.
+//
+// The file is a list of boxes, a big endian u32 size (1: a u64 size follows
+// the type, 0: up to the end of the file) and a 4 byte type. Players skip
+// `uuid` boxes they don't know, so a payload goes into one at the tail:
+// `VANISH_UUID`, the chunk type it is labelled with, then the data.
+
+use thiserror::Error;
+
+use crate::chunk_type::ChunkType;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum BmffError {
+	#[error("Not an ISO base media file, no `ftyp` box.")]
+	NotBmff,
+	#[error("Box at offset {0} runs past the end of the file.")]
+	Truncated(usize),
+	#[error("Box at offset {0} runs to the end of the file and is too large to be given a size.")]
+	OpenEnded(usize),
+}
+
+/// User type of the `uuid` boxes vanish writes.
+pub const VANISH_UUID: [u8; 16] = [
+	0x3c, 0x5e, 0x0b, 0x8f, 0x6a, 0x1d, 0x4e, 0x27, 0x9b, 0x61, 0xd4, 0x0e, 0x7a, 0x52, 0xc3, 0x18,
+];
+
+const BOX_HEADER_BYTES: usize = 8;
+const LARGE_SIZE_BYTES: usize = 8;
+
+/// A top level box, `offset` and `len` cover its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoxRef {
+	pub offset: usize,
+	pub len: usize,
+	/// Header bytes before the content, the u64 size included if present.
+	pub header_len: usize,
+	pub box_type: [u8; 4],
+	/// The size field was 0, the box runs to the end of the file.
+	pub open_ended: bool,
+}
+
+/// Whether `bytes` starts with an `ftyp` box.
+pub fn is_bmff(bytes: &[u8]) -> bool {
+	bytes.get(4..8) == Some(b"ftyp")
+}
+
+/// The top level boxes of `bytes`, in file order.
+pub fn boxes(bytes: &[u8]) -> Result<Vec<BoxRef>, BmffError> {
+	if !is_bmff(bytes) {
+		return Err(BmffError::NotBmff);
+	}
+
+	let mut boxes = Vec::new();
+	let mut offset = 0;
+	while offset < bytes.len() {
+		let header = bytes
+			.get(offset..offset + BOX_HEADER_BYTES)
+			.ok_or(BmffError::Truncated(offset))?;
+		let size = u32::from_be_bytes(header[..4].try_into().expect("slice is 4 bytes"));
+		let (len, header_len) = match size {
+			0 => (bytes.len() - offset, BOX_HEADER_BYTES),
+			1 => {
+				let large = bytes
+					.get(offset + BOX_HEADER_BYTES..offset + BOX_HEADER_BYTES + LARGE_SIZE_BYTES)
+					.ok_or(BmffError::Truncated(offset))?;
+				let large = u64::from_be_bytes(large.try_into().expect("slice is 8 bytes"));
+				(large as usize, BOX_HEADER_BYTES + LARGE_SIZE_BYTES)
+			},
+			size => (size as usize, BOX_HEADER_BYTES),
+		};
+		if len < header_len || offset + len > bytes.len() {
+			return Err(BmffError::Truncated(offset));
+		}
+
+		boxes.push(BoxRef {
+			offset,
+			len,
+			header_len,
+			box_type: header[4..8].try_into().expect("slice is 4 bytes"),
+			open_ended: size == 0,
+		});
+		offset += len;
+	}
+
+	Ok(boxes)
+}
+
+/// Content of a vanish `uuid` box: chunk type and data.
+fn vanish_content<'a>(bytes: &'a [u8], b: &BoxRef) -> Option<(&'a [u8], &'a [u8])> {
+	if &b.box_type != b"uuid" {
+		return None;
+	}
+	let content = &bytes[b.offset + b.header_len..b.offset + b.len];
+	let rest = content.strip_prefix(&VANISH_UUID)?;
+	(rest.len() >= 4).then(|| rest.split_at(4))
+}
+
+/// `bytes` with `data` appended in a vanish `uuid` box labelled
+/// `chunk_type`.
+pub fn append(bytes: &[u8], chunk_type: &ChunkType, data: &[u8]) -> Result<Vec<u8>, BmffError> {
+	let last = boxes(bytes)?.pop();
+	let content_len = VANISH_UUID.len() + 4 + data.len();
+	let mut out = Vec::with_capacity(bytes.len() + LARGE_SIZE_BYTES * 2 + content_len);
+	out.extend_from_slice(bytes);
+
+	// a box running to the end of the file wouldn't end anymore, give it its
+	// real size
+	if let Some(last) = last.filter(|b| b.open_ended) {
+		// a u64 size doesn't fit in place, and moving the content would break
+		// the sample offsets pointing into it
+		let len = u32::try_from(last.len).map_err(|_| BmffError::OpenEnded(last.offset))?;
+		out[last.offset..last.offset + 4].copy_from_slice(&len.to_be_bytes());
+	}
+
+	let size = BOX_HEADER_BYTES + content_len;
+	match u32::try_from(size) {
+		Ok(size) => {
+			out.extend_from_slice(&size.to_be_bytes());
+			out.extend_from_slice(b"uuid");
+		},
+		Err(_) => {
+			out.extend_from_slice(&1u32.to_be_bytes());
+			out.extend_from_slice(b"uuid");
+			out.extend_from_slice(&((size + LARGE_SIZE_BYTES) as u64).to_be_bytes());
+		},
+	}
+	out.extend_from_slice(&VANISH_UUID);
+	out.extend_from_slice(&chunk_type.bytes());
+	out.extend_from_slice(data);
+
+	Ok(out)
+}
+
+/// Data of the first vanish box labelled `chunk_type`.
+pub fn find<'a>(bytes: &'a [u8], chunk_type: &str) -> Result<Option<&'a [u8]>, BmffError> {
+	Ok(boxes(bytes)?
+		.iter()
+		.filter_map(|b| vanish_content(bytes, b))
+		.find(|(t, _)| *t == chunk_type.as_bytes())
+		.map(|(_, data)| data))
+}
+
+/// Chunk types of all vanish boxes, in file order.
+pub fn chunk_types(bytes: &[u8]) -> Result<Vec<String>, BmffError> {
+	Ok(boxes(bytes)?
+		.iter()
+		.filter_map(|b| vanish_content(bytes, b))
+		.map(|(t, _)| String::from_utf8_lossy(t).into_owned())
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+
+	fn testing_mp4() -> Vec<u8> {
+		let mut bytes = Vec::new();
+		bytes.extend_from_slice(b"\0\0\0\x10ftypisom\0\0\x02\0");
+		bytes.extend_from_slice(b"\0\0\0\x0cmoov\xde\xad\xbe\xef");
+		bytes.extend_from_slice(b"\0\0\0\x0cmdat\x01\x02\x03\x04");
+		bytes
+	}
+
+	#[test]
+	fn test_boxes() {
+		let types: Vec<_> = boxes(&testing_mp4())
+			.unwrap()
+			.iter()
+			.map(|b| b.box_type)
+			.collect();
+		assert_eq!(types, [*b"ftyp", *b"moov", *b"mdat"]);
+		assert_eq!(boxes(b"\x89PNG\r\n\x1a\n"), Err(BmffError::NotBmff));
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let mp4 = testing_mp4();
+		let out = append(&mp4, &ChunkType::from_str("ruSt").unwrap(), b"hidden").unwrap();
+
+		assert!(out.starts_with(&mp4));
+		assert_eq!(boxes(&out).unwrap().len(), 4);
+		assert_eq!(find(&out, "ruSt").unwrap(), Some(&b"hidden"[..]));
+		assert_eq!(find(&out, "nOpe").unwrap(), None);
+		assert_eq!(chunk_types(&out).unwrap(), ["ruSt"]);
+	}
+
+	#[test]
+	fn test_open_ended_box_gets_a_size() {
+		let mut mp4 = testing_mp4();
+		let mdat = mp4.len() - 12;
+		mp4[mdat..mdat + 4].copy_from_slice(&[0; 4]);
+		assert!(boxes(&mp4).unwrap()[2].open_ended);
+
+		let out = append(&mp4, &ChunkType::from_str("ruSt").unwrap(), b"x").unwrap();
+		let mdat = boxes(&out).unwrap()[2];
+		assert_eq!((mdat.len, mdat.open_ended), (12, false));
+		assert_eq!(find(&out, "ruSt").unwrap(), Some(&b"x"[..]));
+	}
+
+	#[test]
+	fn test_truncated() {
+		let mut mp4 = testing_mp4();
+		mp4.pop();
+		assert_eq!(boxes(&mp4), Err(BmffError::Truncated(28)));
+	}
+}
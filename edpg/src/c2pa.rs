@@ -0,0 +1,291 @@
+// C2PA manifest stores in pngs, the `caBX` chunk content authenticity tools
+// (c2patool, Adobe Content Credentials...) write and read. The store is a
+// JUMBF box made elsewhere, this embeds it, takes it out and checks it is
+// laid out as the spec wants: a `c2pa` superbox of manifests, each with its
+// assertions, claim and claim signature. The signatures themselves and the
+// hash bindings are left to a C2PA validator.
+//
+// JUMBF box: length (4 bytes, 1 for an 8 byte one after the type, 0 for up
+// to the end) | type (4 bytes) | payload. A `jumb` superbox starts with a
+// `jumd` description box: uuid (16 bytes) | toggles (1 byte) | label, nul
+// terminated if toggles has 0x02 | ...
+
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::png::Png;
+
+pub const C2PA_CHUNK_TYPE: &str = "caBX";
+
+/// The 12 bytes after the 4 letter type in every C2PA uuid.
+const UUID_SUFFIX: [u8; 12] = [0x00, 0x11, 0x00, 0x10, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71];
+const LABEL_TOGGLE: u8 = 0x02;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum C2paError {
+	#[error("No C2PA manifest store, no `caBX` chunk.")]
+	NoManifestStore,
+	#[error("Malformed JUMBF. {0}")]
+	Malformed(String),
+	#[error("Not a C2PA manifest store. {0}")]
+	NotAStore(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Manifest {
+	/// eg: `urn:uuid:...`.
+	pub label: String,
+	/// An update manifest rather than a standard one.
+	pub update: bool,
+	/// Labels of its assertions, eg: `c2pa.actions`.
+	pub assertions: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ManifestStore {
+	/// In the order they were added, the active one last.
+	pub manifests: Vec<Manifest>,
+}
+
+impl ManifestStore {
+	/// The manifest of the asset as it is now.
+	pub fn active(&self) -> Option<&Manifest> {
+		self.manifests.last()
+	}
+}
+
+/// A JUMBF superbox: the uuid and label of its description, then the boxes
+/// it holds.
+struct Superbox<'a> {
+	uuid: [u8; 16],
+	label: String,
+	children: Vec<JumbfBox<'a>>,
+}
+
+/// Type and payload of a box.
+type JumbfBox<'a> = ([u8; 4], &'a [u8]);
+
+impl Superbox<'_> {
+	/// The 4 letter C2PA type of the uuid, `None` for other uuids.
+	fn c2pa_type(&self) -> Option<&[u8]> {
+		(self.uuid[4..] == UUID_SUFFIX).then(|| &self.uuid[..4])
+	}
+
+	fn superboxes(&self) -> Result<Vec<Superbox<'_>>, C2paError> {
+		self.children
+			.iter()
+			.filter(|(t, _)| t == b"jumb")
+			.map(|(_, payload)| Superbox::parse(payload))
+			.collect()
+	}
+
+	fn parse(payload: &[u8]) -> Result<Superbox<'_>, C2paError> {
+		let mut children = boxes(payload)?.into_iter();
+		let description = match children.next() {
+			Some((t, description)) if &t == b"jumd" => description,
+			_ => return Err(malformed("superbox doesn't start with a description box")),
+		};
+		if description.len() < 17 {
+			return Err(malformed("description box is too short"));
+		}
+		let uuid = description[..16].try_into().expect("16 bytes");
+		let label = match description[16] & LABEL_TOGGLE != 0 {
+			true => {
+				let label = &description[17..];
+				let end = label.iter().position(|&b| b == 0).ok_or(malformed("label isn't terminated"))?;
+				String::from_utf8(label[..end].to_vec()).map_err(|_| malformed("label isn't UTF-8"))?
+			},
+			false => String::new(),
+		};
+		Ok(Superbox {
+			uuid,
+			label,
+			children: children.collect(),
+		})
+	}
+}
+
+fn malformed(message: &str) -> C2paError {
+	C2paError::Malformed(message.to_owned())
+}
+
+/// Type and payload of the boxes laid one after the other in `bytes`.
+fn boxes(mut bytes: &[u8]) -> Result<Vec<JumbfBox<'_>>, C2paError> {
+	let mut boxes = Vec::new();
+	while !bytes.is_empty() {
+		let header = bytes.get(..8).ok_or(malformed("box header cut short"))?;
+		let tbox: [u8; 4] = header[4..8].try_into().expect("4 bytes");
+		let (start, len) = match u32::from_be_bytes(header[..4].try_into().expect("4 bytes")) {
+			0 => (8, bytes.len()),
+			1 => {
+				let xl = bytes.get(8..16).ok_or(malformed("box header cut short"))?;
+				(16, usize::try_from(u64::from_be_bytes(xl.try_into().expect("8 bytes"))).unwrap_or(usize::MAX))
+			},
+			len => (8, len as usize),
+		};
+		if len < start || len > bytes.len() {
+			return Err(malformed(&format!("`{}` box is {len} bytes, {} left", tbox.escape_ascii(), bytes.len())));
+		}
+		boxes.push((tbox, &bytes[start..len]));
+		bytes = &bytes[len..];
+	}
+	Ok(boxes)
+}
+
+/// Checks `bytes` is a C2PA manifest store and lists what it holds.
+pub fn parse(bytes: &[u8]) -> Result<ManifestStore, C2paError> {
+	let store = match boxes(bytes)?.as_slice() {
+		[([b'j', b'u', b'm', b'b'], payload)] => Superbox::parse(payload)?,
+		_ => return Err(C2paError::NotAStore("expected a single `jumb` superbox".to_owned())),
+	};
+	if store.c2pa_type() != Some(b"c2pa") || store.label != "c2pa" {
+		return Err(C2paError::NotAStore(format!("superbox is labelled `{}`", store.label)));
+	}
+
+	let mut manifests = Vec::new();
+	for manifest in store.superboxes()? {
+		let update = match manifest.c2pa_type() {
+			Some(b"c2ma") => false,
+			Some(b"c2um") => true,
+			_ => return Err(C2paError::NotAStore(format!("`{}` isn't a manifest", manifest.label))),
+		};
+		let parts = manifest.superboxes()?;
+		let part = |kind: &[u8]| parts.iter().find(|p| p.c2pa_type() == Some(kind));
+		for (kind, what) in [(b"c2cl", "claim"), (b"c2cs", "claim signature")] {
+			if part(kind).is_none() {
+				return Err(C2paError::NotAStore(format!("manifest `{}` has no {what}", manifest.label)));
+			}
+		}
+		let assertions = match part(b"c2as") {
+			Some(store) => store.superboxes()?.into_iter().map(|a| a.label).collect(),
+			None => {
+				return Err(C2paError::NotAStore(format!(
+					"manifest `{}` has no assertion store",
+					manifest.label
+				)))
+			},
+		};
+		manifests.push(Manifest {
+			label: manifest.label,
+			update,
+			assertions,
+		});
+	}
+	if manifests.is_empty() {
+		return Err(C2paError::NotAStore("no manifests".to_owned()));
+	}
+	Ok(ManifestStore { manifests })
+}
+
+/// Puts the manifest store `bytes` right after IHDR, where C2PA readers look
+/// first, in place of any there was.
+pub fn embed(png: &mut Png, bytes: Vec<u8>) -> Result<ManifestStore, C2paError> {
+	let store = parse(&bytes)?;
+	remove(png);
+	let chunk = Chunk::new(C2PA_CHUNK_TYPE.parse().expect("valid"), bytes);
+	let at = png.find_nth_by_type("IHDR", 0).map_or(0, |i| i + 1);
+	png.insert_chunk(at, chunk).expect("within the chunks");
+	Ok(store)
+}
+
+/// The manifest store of `png`, raw.
+pub fn manifest_store(png: &Png) -> Result<&[u8], C2paError> {
+	png.chunk_by_type(C2PA_CHUNK_TYPE)
+		.map(Chunk::data)
+		.ok_or(C2paError::NoManifestStore)
+}
+
+/// Takes out every `caBX` chunk, true if there was one.
+pub fn remove(png: &mut Png) -> bool {
+	let positions = png.positions_of(C2PA_CHUNK_TYPE);
+	for &i in positions.iter().rev() {
+		png.remove_chunk(i);
+	}
+	!positions.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::chunk_type::ChunkType;
+
+	fn jumbf(tbox: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+		let mut bytes = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+		bytes.extend_from_slice(tbox);
+		bytes.extend_from_slice(payload);
+		bytes
+	}
+
+	fn superbox(kind: &[u8; 4], label: &str, children: &[Vec<u8>]) -> Vec<u8> {
+		let mut description = kind.to_vec();
+		description.extend_from_slice(&UUID_SUFFIX);
+		description.push(LABEL_TOGGLE | 0x01);
+		description.extend_from_slice(label.as_bytes());
+		description.push(0);
+		let mut payload = jumbf(b"jumd", &description);
+		children.iter().for_each(|c| payload.extend_from_slice(c));
+		jumbf(b"jumb", &payload)
+	}
+
+	fn store() -> Vec<u8> {
+		let assertions = superbox(b"c2as", "c2pa.assertions", &[
+			superbox(b"cbor", "c2pa.actions", &[jumbf(b"cbor", b"\xa0")]),
+			superbox(b"cbor", "c2pa.hash.data", &[jumbf(b"cbor", b"\xa0")]),
+		]);
+		let manifest = superbox(b"c2ma", "urn:uuid:1234", &[
+			assertions,
+			superbox(b"c2cl", "c2pa.claim", &[jumbf(b"cbor", b"\xa0")]),
+			superbox(b"c2cs", "c2pa.signature", &[jumbf(b"cbor", b"\xa0")]),
+		]);
+		superbox(b"c2pa", "c2pa", &[manifest])
+	}
+
+	fn png() -> Png {
+		let chunk = |t: &str| Chunk::new(ChunkType::from_str(t).unwrap(), b"data".to_vec());
+		Png::from_chunks(vec![chunk("IHDR"), chunk("IDAT"), chunk("IEND")])
+	}
+
+	#[test]
+	fn test_parse() {
+		let store = parse(&store()).unwrap();
+		let active = store.active().unwrap();
+		assert_eq!(active.label, "urn:uuid:1234");
+		assert!(!active.update);
+		assert_eq!(active.assertions, ["c2pa.actions", "c2pa.hash.data"]);
+	}
+
+	#[test]
+	fn test_not_a_store() {
+		let mut truncated = store();
+		truncated.pop();
+		assert!(matches!(parse(&truncated), Err(C2paError::Malformed(_))));
+
+		let no_signature = superbox(b"c2pa", "c2pa", &[superbox(b"c2ma", "urn:uuid:1234", &[
+			superbox(b"c2as", "c2pa.assertions", &[]),
+			superbox(b"c2cl", "c2pa.claim", &[]),
+		])]);
+		assert!(matches!(parse(&no_signature), Err(C2paError::NotAStore(_))));
+		assert!(matches!(
+			parse(&superbox(b"c2pa", "other", &[])),
+			Err(C2paError::NotAStore(_))
+		));
+	}
+
+	#[test]
+	fn test_embed() {
+		let mut png = png();
+		assert_eq!(manifest_store(&png), Err(C2paError::NoManifestStore));
+		embed(&mut png, store()).unwrap();
+		embed(&mut png, store()).unwrap();
+		assert_eq!(png.positions_of(C2PA_CHUNK_TYPE), [1]);
+		assert_eq!(manifest_store(&png).unwrap(), store());
+
+		assert!(remove(&mut png));
+		assert!(!remove(&mut png));
+		assert!(embed(&mut png, b"junk".to_vec()).is_err());
+	}
+}
@@ -1,8 +1,13 @@
 // Getters are for API stability
 
 use std::fmt::Display;
+use std::io::{IoSlice, Write};
+use std::ops::{Deref, DerefMut};
 use std::string::FromUtf8Error;
+use std::sync::Arc;
 
+use bytes::{Bytes, BytesMut};
+use smallvec::SmallVec;
 use thiserror::Error;
 
 use crate::chunk_type::{ChunkType, ChunkTypeError};
@@ -24,7 +29,92 @@ pub enum ChunkError {
 #[derive(Debug, Clone)]
 pub struct Chunk {
 	chunk_type: ChunkType,
-	data: Vec<u8>,
+	data: Data,
+}
+
+/// Chunk data. Small data is stored inline, the rest is shared between
+/// clones so copying a png doesn't copy its pixels. Parsed from `Bytes`, it
+/// is a slice of the input buffer, no copy at all.
+#[derive(Debug, Clone)]
+enum Data {
+	Inline(SmallVec<[u8; Chunk::INLINE_DATA_BYTES]>),
+	Shared(Bytes),
+}
+
+impl Data {
+	fn from_slice(data: &[u8]) -> Data {
+		match data.len() <= Chunk::INLINE_DATA_BYTES {
+			true => Data::Inline(SmallVec::from_slice(data)),
+			false => Data::Shared(Bytes::copy_from_slice(data)),
+		}
+	}
+
+	fn from_vec(data: Vec<u8>) -> Data {
+		match data.len() <= Chunk::INLINE_DATA_BYTES {
+			true => Data::Inline(SmallVec::from_vec(data)),
+			false => Data::Shared(data.into()),
+		}
+	}
+
+	fn from_bytes(data: Bytes) -> Data {
+		match data.len() <= Chunk::INLINE_DATA_BYTES {
+			true => Data::Inline(SmallVec::from_slice(&data)),
+			false => Data::Shared(data),
+		}
+	}
+
+	fn as_slice(&self) -> &[u8] {
+		match self {
+			Data::Inline(data) => data,
+			Data::Shared(data) => data,
+		}
+	}
+
+	/// Owned copy of the data, only copied if it is still shared.
+	fn into_vec(self) -> Vec<u8> {
+		match self {
+			Data::Inline(data) => data.into_vec(),
+			Data::Shared(data) => data.into(),
+		}
+	}
+}
+
+/// `Chunk::data_mut`, the data held by the chunk alone for as long as it
+/// lives.
+#[derive(Debug)]
+pub struct DataMut<'a> {
+	data: &'a mut Data,
+	/// Shared data taken out of `data`, put back on drop.
+	unique: Option<BytesMut>,
+}
+
+impl Deref for DataMut<'_> {
+	type Target = [u8];
+
+	fn deref(&self) -> &[u8] {
+		match &self.unique {
+			Some(unique) => unique,
+			None => self.data.as_slice(),
+		}
+	}
+}
+
+impl DerefMut for DataMut<'_> {
+	fn deref_mut(&mut self) -> &mut [u8] {
+		match (&mut self.unique, &mut *self.data) {
+			(Some(unique), _) => unique,
+			(None, Data::Inline(data)) => data,
+			(None, Data::Shared(_)) => unreachable!("shared data is taken out"),
+		}
+	}
+}
+
+impl Drop for DataMut<'_> {
+	fn drop(&mut self) {
+		if let Some(unique) = self.unique.take() {
+			*self.data = Data::Shared(unique.freeze());
+		}
+	}
 }
 
 impl Chunk {
@@ -35,16 +125,43 @@ impl Chunk {
 	pub const METADATA_BYTES: usize =
 		Self::LENGTH_BYTES + Self::CHUNK_TYPE_BYTES + Self::CRC_LENGTH_BYTES;
 
+	/// Data up to this size is stored inside the chunk, no heap allocation.
+	/// Covers IHDR, gAMA, pHYs, tIME and the like. Bigger data is shared
+	/// between clones of the chunk.
+	pub const INLINE_DATA_BYTES: usize = 16;
+
 	pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
 		// CRC is calculated over everything except length
 
 		Self {
 			chunk_type,
-			data,
+			data: Data::from_vec(data),
 			// crc,
 		}
 	}
 
+	/// Like `new` but copies `data`, small data never touches the heap.
+	pub fn from_slice(chunk_type: ChunkType, data: &[u8]) -> Chunk {
+		Self {
+			chunk_type,
+			data: Data::from_slice(data),
+		}
+	}
+
+	/// Chunk holding `data` as it is, shared with whoever else holds it.
+	pub fn from_shared(chunk_type: ChunkType, data: Arc<[u8]>) -> Chunk {
+		Self::from_bytes(chunk_type, Bytes::from_owner(data))
+	}
+
+	/// Like `from_shared`, for data that already is `Bytes`, eg: a slice of a
+	/// network frame.
+	pub fn from_bytes(chunk_type: ChunkType, data: Bytes) -> Chunk {
+		Self {
+			chunk_type,
+			data: Data::from_bytes(data),
+		}
+	}
+
 	pub fn length(&self) -> u32 {
 		self.data().len() as u32
 	}
@@ -54,7 +171,66 @@ impl Chunk {
 	}
 
 	pub fn data(&self) -> &[u8] {
-		&self.data
+		self.data.as_slice()
+	}
+
+	/// The data as something to hold on to past the chunk, a handle rather
+	/// than a copy unless it is stored inline.
+	pub fn shared_data(&self) -> Bytes {
+		match &self.data {
+			Data::Inline(data) => Bytes::copy_from_slice(data),
+			Data::Shared(data) => data.clone(),
+		}
+	}
+
+	/// Heap bytes held by the data, 0 while it is stored inline. Shared data
+	/// counts in full for every chunk holding it.
+	pub(crate) fn data_capacity(&self) -> usize {
+		match &self.data {
+			Data::Inline(_) => 0,
+			Data::Shared(data) => data.len(),
+		}
+	}
+
+	/// Whether this chunk and `other` hold the very same data, not a copy.
+	pub fn shares_data(&self, other: &Chunk) -> bool {
+		match (&self.data, &other.data) {
+			(Data::Shared(a), Data::Shared(b)) => a.as_ptr() == b.as_ptr() && a.len() == b.len(),
+			_ => false,
+		}
+	}
+
+	/// The data to edit in place, same length. Data shared with other chunks
+	/// is copied first, they keep the old one, data held by this chunk alone
+	/// is edited without a copy. Shared again once the guard is dropped.
+	pub fn data_mut(&mut self) -> DataMut<'_> {
+		let unique = match &mut self.data {
+			Data::Inline(_) => None,
+			Data::Shared(data) => Some(
+				std::mem::take(data)
+					.try_into_mut()
+					.unwrap_or_else(|shared| BytesMut::from(&shared[..])),
+			),
+		};
+		DataMut {
+			data: &mut self.data,
+			unique,
+		}
+	}
+
+	/// Replaces the data, returning the old one. The crc is always computed
+	/// from the current data so it follows along.
+	pub fn set_data(&mut self, data: Vec<u8>) -> Vec<u8> {
+		std::mem::replace(&mut self.data, Data::from_vec(data)).into_vec()
+	}
+
+	/// Edits the data in place, eg: appending to an existing payload. Data
+	/// shared with other chunks is copied first, they keep the old one, data
+	/// held by this chunk alone is edited without a copy.
+	pub fn map_data(&mut self, f: impl FnOnce(&mut Vec<u8>)) {
+		let mut data = std::mem::replace(&mut self.data, Data::Inline(SmallVec::new())).into_vec();
+		f(&mut data);
+		self.data = Data::from_vec(data);
 	}
 
 	/// Calculating the crc
@@ -62,16 +238,16 @@ impl Chunk {
 		use crc::{Crc, CRC_32_ISO_HDLC};
 		const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
 
-		// this is different from self.as_bytes as it doesn't include crc, length
-		let bytes: Vec<u8> = self
-			.chunk_type()
-			.bytes()
-			.iter()
-			.chain(self.data.iter())
-			.copied()
-			.collect();
+		// covers type and data, not the length
+		let mut digest = CRC.digest();
+		digest.update(&self.chunk_type().bytes());
+		digest.update(self.data());
+		digest.finalize()
+	}
 
-		CRC.checksum(&bytes)
+	/// Size of the serialized chunk, computed without serializing it.
+	pub fn byte_len(&self) -> usize {
+		Self::METADATA_BYTES + self.data().len()
 	}
 
 	/// Returns the data stored in this chunk as a `String`. This function will
@@ -90,22 +266,78 @@ impl Chunk {
 	// I spent hours debugging because i was operating on usize and not u32.
 	// Damm, systems programming aint direct
 	pub fn as_bytes(&self) -> Vec<u8> {
-		let data_len = self.data().len() as u32;
-		data_len
-			.to_be_bytes()
-			.iter()
-			.chain(self.chunk_type.bytes().iter())
-			.chain(self.data().iter())
-			.chain(self.crc().to_be_bytes().iter())
-			.copied()
-			.collect()
+		let mut bytes = Vec::with_capacity(self.byte_len());
+		self.write_into(&mut bytes);
+		bytes
+	}
+
+	/// Streams the serialized chunk into `w`, the data is written straight
+	/// from the chunk without being copied into a buffer first.
+	pub fn write_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+		let mut head = [0; Self::LENGTH_BYTES + Self::CHUNK_TYPE_BYTES];
+		head[..4].copy_from_slice(&self.length().to_be_bytes());
+		head[4..].copy_from_slice(&self.chunk_type.bytes());
+		let crc = self.crc().to_be_bytes();
+
+		let mut bufs = [IoSlice::new(&head), IoSlice::new(self.data()), IoSlice::new(&crc)];
+		let mut bufs = &mut bufs[..];
+		// `Write::write_all_vectored` is still unstable
+		while !bufs.is_empty() {
+			match w.write_vectored(bufs) {
+				Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+				Ok(n) => IoSlice::advance_slices(&mut bufs, n),
+				Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {},
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(())
+	}
+
+	/// Appends the serialized chunk to `out`, see `as_bytes`.
+	pub fn write_into(&self, out: &mut Vec<u8>) {
+		out.reserve(self.byte_len());
+		out.extend_from_slice(&self.length().to_be_bytes());
+		out.extend_from_slice(&self.chunk_type.bytes());
+		out.extend_from_slice(self.data());
+		out.extend_from_slice(&self.crc().to_be_bytes());
 	}
 }
 
-impl TryFrom<&[u8]> for Chunk {
-	type Error = ChunkError;
+/// How strict parsing is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+	/// Check every chunk's crc against its data. Turning it off skips the
+	/// checksum entirely, for bulk scans that don't care about integrity.
+	pub verify_crc: bool,
+	/// How many bytes of junk (a BOM, HTTP headers...) may come before the
+	/// png signature. They are skipped and kept aside, see `Png::prefix`.
+	pub max_prefix: usize,
+}
 
-	fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+impl Default for ParseOptions {
+	fn default() -> Self {
+		Self {
+			verify_crc: true,
+			max_prefix: 0,
+		}
+	}
+}
+
+impl Chunk {
+	pub fn parse_with(value: &[u8], options: &ParseOptions) -> Result<Self, ChunkError> {
+		Self::parse_data(value, options, Data::from_slice)
+	}
+
+	/// `parse_with` keeping the data as a slice of `value` rather than a copy.
+	pub fn parse_bytes(value: &Bytes, options: &ParseOptions) -> Result<Self, ChunkError> {
+		Self::parse_data(value, options, |data| Data::from_bytes(value.slice_ref(data)))
+	}
+
+	fn parse_data(
+		value: &[u8],
+		options: &ParseOptions,
+		to_data: impl FnOnce(&[u8]) -> Data,
+	) -> Result<Self, ChunkError> {
 		if value.len() < Self::METADATA_BYTES {
 			return Err(ChunkError::ShortInput(Self::METADATA_BYTES));
 		}
@@ -125,10 +357,17 @@ impl TryFrom<&[u8]> for Chunk {
 		let (data, value) = value.split_at(data_length as usize);
 		let (crc_bytes, _) = value.split_at(Self::CRC_LENGTH_BYTES);
 
-		let tmp = Self::new(chunk_type, data.into());
+		let chunk = Self {
+			chunk_type,
+			data: to_data(data),
+		};
+
+		if !options.verify_crc {
+			return Ok(chunk);
+		}
 
 		let found_crc = u32::from_be_bytes(crc_bytes.try_into()?);
-		let expected_crc = tmp.crc();
+		let expected_crc = chunk.crc();
 
 		if found_crc != expected_crc {
 			return Err(ChunkError::IncorrectCrc {
@@ -137,22 +376,52 @@ impl TryFrom<&[u8]> for Chunk {
 			});
 		}
 
-		Ok(Self::new(chunk_type, data.into()))
+		Ok(chunk)
+	}
+}
+
+impl TryFrom<&[u8]> for Chunk {
+	type Error = ChunkError;
+
+	fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+		Self::parse_with(value, &ParseOptions::default())
 	}
 }
 
+impl Chunk {
+	/// Bytes of data shown by `Display`, the rest is elided.
+	pub const PREVIEW_BYTES: usize = 32;
+
+	/// Printable start of the data, everything outside ascii graphic/space
+	/// shown as `.`.
+	pub fn preview(&self) -> String {
+		let mut preview: String = self
+			.data()
+			.iter()
+			.take(Self::PREVIEW_BYTES)
+			.map(|&b| match b {
+				b' ' => ' ',
+				b if b.is_ascii_graphic() => b as char,
+				_ => '.',
+			})
+			.collect();
+		if self.data().len() > Self::PREVIEW_BYTES {
+			preview.push_str("...");
+		}
+		preview
+	}
+}
+
+// `{:?}` keeps the raw bytes
 impl Display for Chunk {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		writeln!(
+		write!(
 			f,
-			"Chunk: Data_len={}, type={}, crc={}\n{}\n",
-			self.length(),
+			"{} len={} crc={:#010x} \"{}\"",
 			self.chunk_type(),
+			self.length(),
 			self.crc(),
-			match String::from_utf8(self.data().into()) {
-				Ok(s) => s,
-				Err(_) => "Only Your hidden message can be decoded.".to_owned(),
-			}
+			self.preview()
 		)
 	}
 }
@@ -212,6 +481,121 @@ mod tests {
 		assert_eq!(chunk_string, expected_chunk_string);
 	}
 
+	#[test]
+	fn test_set_data() {
+		let mut chunk = testing_chunk();
+		let old = chunk.set_data(b"new".to_vec());
+		assert_eq!(old.len(), 42);
+		assert_eq!(chunk.length(), 3);
+		assert_eq!(chunk.crc(), Chunk::new(*chunk.chunk_type(), b"new".to_vec()).crc());
+	}
+
+	#[test]
+	fn test_map_data() {
+		let mut chunk = testing_chunk();
+		chunk.map_data(|d| d.extend_from_slice(b" more"));
+		assert!(chunk.data_as_string().unwrap().ends_with("be! more"));
+		assert_eq!(chunk.length(), 47);
+		// the serialized form round trips, so length and crc agree with the data
+		let parsed = Chunk::try_from(chunk.as_bytes().as_ref()).unwrap();
+		assert_eq!(parsed.data(), chunk.data());
+	}
+
+	#[test]
+	fn test_chunk_display() {
+		let chunk = testing_chunk();
+		assert_eq!(
+			chunk.to_string(),
+			"RuSt len=42 crc=0xabd1d84e \"This is where your secret messag...\""
+		);
+
+		let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0, b'a', 0xff]);
+		assert_eq!(chunk.preview(), ".a.");
+	}
+
+	#[test]
+	fn test_byte_len() {
+		let chunk = testing_chunk();
+		let bytes = chunk.as_bytes();
+		assert_eq!(chunk.byte_len(), bytes.len());
+		assert_eq!(bytes.capacity(), bytes.len());
+	}
+
+	#[test]
+	fn test_small_data_inline() {
+		let chunk_type = ChunkType::from_str("RuSt").unwrap();
+		let small = Chunk::from_slice(chunk_type, b"tiny");
+		assert_eq!(small.data_capacity(), 0);
+		assert!(testing_chunk().data_capacity() >= 42);
+
+		let mut chunk = small.clone();
+		chunk.map_data(|d| d.extend_from_slice(&[0; 64]));
+		assert_eq!(chunk.length(), 68);
+		assert_eq!(&chunk.data()[..4], b"tiny");
+	}
+
+	#[test]
+	fn test_shared_data() {
+		let chunk = testing_chunk();
+		let mut copy = chunk.clone();
+		assert!(copy.shares_data(&chunk));
+
+		// copy on write, the original keeps its data
+		copy.data_mut()[0] = b't';
+		assert!(!copy.shares_data(&chunk));
+		assert!(chunk.data().starts_with(b"This"));
+		assert!(copy.data().starts_with(b"this"));
+		assert_eq!(copy.crc(), Chunk::new(*chunk.chunk_type(), copy.data().to_vec()).crc());
+
+		// held alone, edited where it is and cheap to clone again
+		let at = copy.data().as_ptr();
+		copy.data_mut()[1] = b'H';
+		assert_eq!(copy.data().as_ptr(), at);
+		assert!(copy.clone().shares_data(&copy));
+
+		let mut small = Chunk::from_slice(*chunk.chunk_type(), b"tiny");
+		small.data_mut().copy_from_slice(b"TINY");
+		assert_eq!(small.data(), b"TINY");
+
+		copy.map_data(|d| d[0] = b'T');
+		assert!(copy.data().starts_with(b"THis"));
+
+		let shared = Chunk::from_bytes(*chunk.chunk_type(), chunk.shared_data());
+		assert!(shared.shares_data(&chunk));
+	}
+
+	#[test]
+	fn test_parse_bytes() {
+		let bytes = Bytes::from(testing_chunk().as_bytes());
+		let chunk = Chunk::parse_bytes(&bytes, &ParseOptions::default()).unwrap();
+		assert_eq!(chunk.data(), testing_chunk().data());
+		// the data is the input buffer itself
+		assert_eq!(chunk.data().as_ptr(), bytes[Chunk::LENGTH_BYTES + 4..].as_ptr());
+	}
+
+	#[test]
+	fn test_skip_crc() {
+		let mut bytes = testing_chunk().as_bytes();
+		let last = bytes.len() - 1;
+		bytes[last] ^= 1;
+		assert!(Chunk::try_from(bytes.as_ref()).is_err());
+
+		let options = ParseOptions {
+			verify_crc: false,
+			..Default::default()
+		};
+		let chunk = Chunk::parse_with(&bytes, &options).unwrap();
+		assert_eq!(chunk.length(), 42);
+	}
+
+	#[test]
+	fn test_write_to() {
+		let chunk = testing_chunk();
+		let mut out = Vec::new();
+		chunk.write_to(&mut out).unwrap();
+		assert_eq!(out, chunk.as_bytes());
+	}
+
 	#[test]
 	fn test_chunk_crc() {
 		let chunk = testing_chunk();
@@ -13,6 +13,8 @@ pub enum ChunkTypeError {
 	TryFromSliceError,
 	#[error("Length and reserved bit should be valid. This shouldn't occur in the first place")]
 	InvalidChunkType,
+	#[error("The third letter of `{}` must be uppercase, the png spec reserves it, eg: `{}`.", .0, .0.corrected())]
+	ReservedBit(ChunkType),
 }
 
 // DAMM: A Rust String is just a Vec<u8> whose bytes have been validated as
@@ -25,7 +27,7 @@ pub struct ChunkType {
 }
 
 impl ChunkType {
-	pub fn bytes(&self) -> [u8; 4] {
+	pub const fn bytes(&self) -> [u8; 4] {
 		self.bytes
 	}
 
@@ -48,40 +50,115 @@ impl ChunkType {
 	}
 
 	/// Checks if `this` chunk is necessary to display the PNG
-	pub fn is_critical(&self) -> bool {
-		self.bytes()
-			.first()
-			.expect("This should not have happened. Report the bug.")
-			.is_ascii_uppercase()
+	pub const fn is_critical(&self) -> bool {
+		self.bytes[0].is_ascii_uppercase()
 	}
 
 	/// ## Not part of public API.
 	/// Not even sure what this is for.
-	pub fn is_public(&self) -> bool {
-		self.bytes()
-			.get(1)
-			.expect("This should not have happened. Report the bug.")
-			.is_ascii_uppercase()
+	pub const fn is_public(&self) -> bool {
+		self.bytes[1].is_ascii_uppercase()
 	}
 
 	/// Mandate by PNG spec, it should be true otherwise chunk is wrong
 	pub fn is_reserved_bit_valid(&self) -> Result<bool, ChunkTypeError> {
-		match self.bytes().get(2) {
-			Some(byte) if byte.is_ascii_uppercase() => Ok(true),
-			_ => Err(ChunkTypeError::InvalidChunkType),
+		match self.bytes[2].is_ascii_uppercase() {
+			true => Ok(true),
+			false => Err(ChunkTypeError::ReservedBit(*self)),
 		}
 	}
 
+	/// This type with the third letter uppercased, so the reserved bit is
+	/// valid. eg: `rust` becomes `ruSt`.
+	pub fn corrected(&self) -> ChunkType {
+		let mut bytes = self.bytes;
+		bytes[2] = bytes[2].to_ascii_uppercase();
+		ChunkType { bytes }
+	}
+
 	/// Irrelevant for decoders but useful in img editors tells whether
 	/// the chunk is okay to be copied for the modified version of the img
-	pub fn is_safe_to_copy(&self) -> bool {
-		self.bytes()
-			.get(3)
-			.expect("This should not have happened. Report the bug.")
-			.is_ascii_lowercase()
+	pub const fn is_safe_to_copy(&self) -> bool {
+		self.bytes[3].is_ascii_lowercase()
+	}
+
+	/// This type with the fourth letter lowercased if `safe` and uppercased
+	/// otherwise. eg: `ruST` becomes `ruSt` when safe to copy.
+	pub fn with_safe_to_copy(&self, safe: bool) -> ChunkType {
+		self.with_case(3, !safe)
+	}
+
+	/// This type with the first letter uppercased if `critical`, lowercased
+	/// otherwise.
+	pub fn set_critical(&self, critical: bool) -> Result<ChunkType, ChunkTypeError> {
+		self.with_case(0, critical).checked()
+	}
+
+	/// This type with the second letter lowercased if `private`, uppercased
+	/// otherwise.
+	pub fn set_private(&self, private: bool) -> Result<ChunkType, ChunkTypeError> {
+		self.with_case(1, !private).checked()
+	}
+
+	/// `with_safe_to_copy`, checked with `is_valid`.
+	pub fn set_safe_to_copy(&self, safe: bool) -> Result<ChunkType, ChunkTypeError> {
+		self.with_safe_to_copy(safe).checked()
+	}
+
+	/// Never fails to show `bytes`, for files that may be malformed: a
+	/// chunk type if they are all letters, reserved bit as it is (see
+	/// `corrected`), or a wrapper that displays them escaped otherwise.
+	pub fn from_bytes_lossy(bytes: [u8; 4]) -> Result<ChunkType, DisplayableInvalid> {
+		match bytes.iter().all(u8::is_ascii_alphabetic) {
+			true => Ok(ChunkType { bytes }),
+			false => Err(DisplayableInvalid { bytes }),
+		}
+	}
+
+	fn with_case(&self, idx: usize, upper: bool) -> ChunkType {
+		let mut bytes = self.bytes;
+		bytes[idx] = match upper {
+			true => bytes[idx].to_ascii_uppercase(),
+			false => bytes[idx].to_ascii_lowercase(),
+		};
+		ChunkType { bytes }
+	}
+
+	fn checked(self) -> Result<ChunkType, ChunkTypeError> {
+		self.is_valid()?;
+		Ok(self)
 	}
 }
 
+/// Bytes where a chunk type should be that aren't one, displayed with
+/// anything but printable ascii escaped, eg: `ru\x00t`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayableInvalid {
+	bytes: [u8; 4],
+}
+
+impl DisplayableInvalid {
+	pub const fn bytes(&self) -> [u8; 4] {
+		self.bytes
+	}
+}
+
+impl Display for DisplayableInvalid {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write_escaped(f, &self.bytes)
+	}
+}
+
+fn write_escaped(f: &mut std::fmt::Formatter<'_>, bytes: &[u8]) -> std::fmt::Result {
+	for &b in bytes {
+		match b.is_ascii_graphic() {
+			true => write!(f, "{}", b as char)?,
+			false => write!(f, "\\x{b:02x}")?,
+		}
+	}
+	Ok(())
+}
+
 impl TryFrom<[u8; 4]> for ChunkType {
 	type Error = ChunkTypeError;
 
@@ -112,9 +189,10 @@ impl FromStr for ChunkType {
 
 impl Display for ChunkType {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		match String::from_utf8(self.bytes.into()) {
-			Ok(s) => write!(f, "{}", s),
-			Err(e) => write!(f, "{}", e),
+		// `TryFrom` takes any bytes
+		match std::str::from_utf8(&self.bytes) {
+			Ok(s) if !s.contains(|c: char| c.is_control()) => write!(f, "{}", s),
+			_ => write_escaped(f, &self.bytes),
 		}
 	}
 }
@@ -200,7 +278,9 @@ mod tests {
 	#[test]
 	pub fn test_chunk_type_is_reserved_bit_invalid() -> Result<(), ChunkTypeError> {
 		let chunk = ChunkType::from_str("Rust").unwrap();
-		assert!(chunk.is_reserved_bit_valid().is_err());
+		assert_eq!(chunk.is_reserved_bit_valid(), Err(ChunkTypeError::ReservedBit(chunk)));
+		assert_eq!(chunk.corrected().to_string(), "RuSt");
+		assert!(chunk.corrected().is_reserved_bit_valid().is_ok());
 		Ok(())
 	}
 
@@ -216,6 +296,33 @@ mod tests {
 		assert!(!chunk.is_safe_to_copy());
 	}
 
+	#[test]
+	pub fn test_chunk_type_with_safe_to_copy() {
+		let chunk = ChunkType::from_str("ruST").unwrap();
+		assert_eq!(chunk.with_safe_to_copy(true).to_string(), "ruSt");
+		assert_eq!(chunk.with_safe_to_copy(false), chunk);
+		assert!(chunk.with_safe_to_copy(true).is_safe_to_copy());
+	}
+
+	#[test]
+	pub fn test_chunk_type_setters() {
+		let chunk = ChunkType::from_str("ruSt").unwrap();
+		let set = chunk
+			.set_critical(true)
+			.and_then(|c| c.set_private(false))
+			.and_then(|c| c.set_safe_to_copy(false))
+			.unwrap();
+		assert_eq!(set.to_string(), "RUST");
+		assert!(set.is_critical() && set.is_public() && !set.is_safe_to_copy());
+		assert_eq!(set.set_critical(false).unwrap().to_string(), "rUST");
+
+		let reserved = ChunkType::from_str("rust").unwrap();
+		assert_eq!(
+			reserved.set_critical(true),
+			Err(ChunkTypeError::ReservedBit(ChunkType::from_str("Rust").unwrap()))
+		);
+	}
+
 	#[test]
 	pub fn test_valid_chunk_is_valid() {
 		let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -247,4 +354,29 @@ mod tests {
 		let _chunk_string = format!("{}", chunk_type_1);
 		let _are_chunks_equal = chunk_type_1 == chunk_type_2;
 	}
+
+	#[test]
+	fn test_from_bytes_lossy() {
+		assert_eq!(ChunkType::from_bytes_lossy(*b"ruSt").unwrap().to_string(), "ruSt");
+		// shown as it is, not corrected
+		assert_eq!(ChunkType::from_bytes_lossy(*b"rust").unwrap().to_string(), "rust");
+
+		let invalid = ChunkType::from_bytes_lossy([b'r', b'u', 0, 0xff]).unwrap_err();
+		assert_eq!(invalid.to_string(), "ru\\x00\\xff");
+		assert_eq!(invalid.bytes(), [b'r', b'u', 0, 0xff]);
+		assert_eq!(ChunkType::from_bytes_lossy(*b"a b1").unwrap_err().to_string(), "a\\x20b1");
+
+		let from_any = ChunkType::try_from([0xc3, 0xa9, b'\n', b'x']).unwrap();
+		assert_eq!(from_any.to_string(), "\\xc3\\xa9\\x0ax");
+		assert_eq!(ChunkType::try_from([0xc3, 0xa9, b'a', b'x']).unwrap().to_string(), "éax");
+	}
+
+	#[test]
+	fn test_const_properties() {
+		const IHDR: ChunkType = ChunkType { bytes: *b"IHDR" };
+		const _: () = assert!(IHDR.is_critical() && IHDR.is_public() && !IHDR.is_safe_to_copy());
+		const RUST: ChunkType = ChunkType { bytes: *b"ruSt" };
+		const _: () = assert!(!RUST.is_critical() && !RUST.is_public() && RUST.is_safe_to_copy());
+		assert_eq!(IHDR.bytes(), *b"IHDR");
+	}
 }
@@ -0,0 +1,170 @@
+// zstd for payloads hidden with `payload::embed`, optionally with a
+// dictionary trained on samples like them. Small similar payloads (JSON
+// telemetry, logs...) barely compress on their own, what they have in common
+// goes in the dictionary once instead of in every image.
+//
+// Dictionary: magic (0xEC30A437, little endian) | id (4 bytes, little
+// endian) | ... The id ends up in the manifest, see `payload::Encoding`.
+
+use std::io::{Read, Write};
+
+use thiserror::Error;
+
+/// Slow to compress, but payloads are small and written once.
+const LEVEL: i32 = 19;
+const DICTIONARY_MAGIC: [u8; 4] = 0xEC30_A437_u32.to_le_bytes();
+/// Largest file `decompress` puts back, a few bytes of zstd can claim
+/// gigabytes.
+pub const MAX_DECOMPRESSED_BYTES: u64 = 1024 * 1024 * 1024;
+/// zstd's own default, plenty for payloads of a few kilobytes.
+pub const DEFAULT_DICTIONARY_BYTES: usize = 110 * 1024;
+
+#[derive(Debug, Error)]
+pub enum CompressError {
+	#[error("Failed to train a dictionary. {0}")]
+	Train(std::io::Error),
+	#[error("Not a zstd dictionary.")]
+	NotADictionary,
+	#[error("Compressed with dictionary {0}, pass it to decompress.")]
+	MissingDictionary(u32),
+	#[error("Compressed with dictionary {expected}, not {found}.")]
+	WrongDictionary { expected: u32, found: u32 },
+	#[error("Decompresses to more than {MAX_DECOMPRESSED_BYTES} bytes.")]
+	TooLarge,
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+}
+
+/// A zstd dictionary, as `train` and `zstd --train` write it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dictionary {
+	bytes: Vec<u8>,
+	id: u32,
+}
+
+impl Dictionary {
+	pub fn parse(bytes: Vec<u8>) -> Result<Dictionary, CompressError> {
+		match bytes.get(..8) {
+			Some(header) if header[..4] == DICTIONARY_MAGIC => {
+				let id = u32::from_le_bytes(header[4..8].try_into().expect("4 bytes"));
+				Ok(Dictionary { bytes, id })
+			},
+			_ => Err(CompressError::NotADictionary),
+		}
+	}
+
+	/// What the manifest records, to tell the dictionary needed apart.
+	pub fn id(&self) -> u32 {
+		self.id
+	}
+
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.bytes
+	}
+}
+
+/// A dictionary of at most `max_bytes` for payloads like `samples`. zstd
+/// wants a good many of them, a few dozen at least.
+pub fn train<S: AsRef<[u8]>>(samples: &[S], max_bytes: usize) -> Result<Dictionary, CompressError> {
+	let bytes = zstd::dict::from_samples(samples, max_bytes).map_err(CompressError::Train)?;
+	Dictionary::parse(bytes)
+}
+
+/// `data` as a zstd frame with a checksum, compressed against `dictionary`
+/// if given.
+pub fn compress(data: &[u8], dictionary: Option<&Dictionary>) -> Result<Vec<u8>, CompressError> {
+	let mut encoder = match dictionary {
+		Some(dictionary) => zstd::Encoder::with_dictionary(Vec::new(), LEVEL, dictionary.as_bytes())?,
+		None => zstd::Encoder::new(Vec::new(), LEVEL)?,
+	};
+	encoder.include_checksum(true)?;
+	encoder.write_all(data)?;
+	Ok(encoder.finish()?)
+}
+
+/// Undoes `compress`. `id` is the dictionary the data was compressed with,
+/// from the manifest, `dictionary` has to be that one.
+pub fn decompress(
+	data: &[u8],
+	id: Option<u32>,
+	dictionary: Option<&Dictionary>,
+) -> Result<Vec<u8>, CompressError> {
+	let decoder = match (id, dictionary) {
+		(None, _) => zstd::Decoder::with_buffer(data)?,
+		(Some(id), None) => return Err(CompressError::MissingDictionary(id)),
+		(Some(expected), Some(dictionary)) if dictionary.id != expected => {
+			return Err(CompressError::WrongDictionary {
+				expected,
+				found: dictionary.id,
+			})
+		},
+		(Some(_), Some(dictionary)) => zstd::Decoder::with_dictionary(data, dictionary.as_bytes())?,
+	};
+
+	let mut decompressed = Vec::new();
+	decoder
+		.take(MAX_DECOMPRESSED_BYTES + 1)
+		.read_to_end(&mut decompressed)?;
+	if decompressed.len() as u64 > MAX_DECOMPRESSED_BYTES {
+		return Err(CompressError::TooLarge);
+	}
+	Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Telemetry records that share most of their keys and values.
+	fn samples() -> Vec<Vec<u8>> {
+		(0..400)
+			.map(|i| {
+				format!(
+					r#"{{"device":"sensor-{:03}","firmware":"2.4.{}","status":"ok","temperature":{}.{},"humidity":{},"battery":"{}%"}}"#,
+					i % 50,
+					i % 7,
+					18 + i % 9,
+					i % 10,
+					40 + i % 31,
+					100 - i % 60
+				)
+				.into_bytes()
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let data = b"hello hello hello hello hello".repeat(10);
+		let compressed = compress(&data, None).unwrap();
+		assert!(compressed.len() < data.len());
+		assert_eq!(decompress(&compressed, None, None).unwrap(), data);
+	}
+
+	#[test]
+	fn test_dictionary() {
+		let samples = samples();
+		let dictionary = train(&samples, 4096).unwrap();
+		assert_eq!(Dictionary::parse(dictionary.as_bytes().to_vec()).unwrap(), dictionary);
+
+		let record = br#"{"device":"sensor-007","firmware":"2.4.3","status":"ok","temperature":21.5,"humidity":52,"battery":"77%"}"#;
+		let plain = compress(record, None).unwrap();
+		let trained = compress(record, Some(&dictionary)).unwrap();
+		assert!(trained.len() * 2 < plain.len(), "{} vs {}", trained.len(), plain.len());
+
+		let id = Some(dictionary.id());
+		assert_eq!(decompress(&trained, id, Some(&dictionary)).unwrap(), record);
+		assert!(matches!(
+			decompress(&trained, id, None),
+			Err(CompressError::MissingDictionary(_))
+		));
+		assert!(matches!(
+			decompress(&trained, id.map(|id| id ^ 1), Some(&dictionary)),
+			Err(CompressError::WrongDictionary { .. })
+		));
+		assert!(matches!(
+			Dictionary::parse(b"not a dictionary".to_vec()),
+			Err(CompressError::NotADictionary)
+		));
+	}
+}
@@ -0,0 +1,214 @@
+// Golden file checks over a directory of real world pngs: every file has to
+// come back byte for byte out of a parse and serialize, and hold up to what
+// the rest of the crate takes for granted. Meant for test suites, eg:
+//
+//     #[test]
+//     fn assets() {
+//         edpg::corpus::assert_dir("assets/".as_ref());
+//     }
+
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use crate::png::{ParseOptions, Png};
+use crate::roundtrip::{self, Roundtrip};
+
+/// What is wrong with a file of the corpus.
+#[derive(Debug)]
+pub enum Problem {
+	Unreadable(std::io::Error),
+	/// Doesn't parse at all.
+	Invalid(String),
+	/// Parses, but isn't written back as it was.
+	NotIdentical(Roundtrip),
+	/// Parses, but breaks an assumption of the crate.
+	Invariant(String),
+}
+
+impl Display for Problem {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Unreadable(e) => write!(f, "unreadable, {e}"),
+			Self::Invalid(e) => write!(f, "doesn't parse, {e}"),
+			Self::NotIdentical(roundtrip) => write!(f, "{roundtrip}"),
+			Self::Invariant(e) => write!(f, "{e}"),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct Failure {
+	pub path: PathBuf,
+	pub problem: Problem,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+	/// Pngs looked at.
+	pub checked: usize,
+	pub failures: Vec<Failure>,
+}
+
+impl Report {
+	pub fn is_ok(&self) -> bool {
+		self.failures.is_empty()
+	}
+}
+
+impl Display for Report {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} of {} pngs failed", self.failures.len(), self.checked)?;
+		for failure in &self.failures {
+			write!(f, "\n{}: {}", failure.path.display(), failure.problem)?;
+		}
+		Ok(())
+	}
+}
+
+/// Every `.png` under `dir`, subdirectories included, in path order.
+pub fn files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+	let mut files = Vec::new();
+	let mut dirs = vec![dir.to_path_buf()];
+	while let Some(dir) = dirs.pop() {
+		for entry in std::fs::read_dir(dir)? {
+			let path = entry?.path();
+			if path.is_dir() {
+				dirs.push(path);
+			} else if path
+				.extension()
+				.is_some_and(|e| e.eq_ignore_ascii_case("png"))
+			{
+				files.push(path);
+			}
+		}
+	}
+	files.sort();
+	Ok(files)
+}
+
+/// Checks the png in `bytes`, `None` if it passes.
+pub fn check(bytes: &[u8], options: &ParseOptions) -> Option<Problem> {
+	let roundtrip = match roundtrip::compare(bytes, options) {
+		Ok(roundtrip) => roundtrip,
+		Err(e) => return Some(Problem::Invalid(e.to_string())),
+	};
+	if !roundtrip.is_identical() {
+		return Some(Problem::NotIdentical(roundtrip));
+	}
+
+	let png = Png::parse_with(bytes, options).expect("parsed by compare");
+	invariant(png.byte_len() == bytes.len(), || {
+		format!("byte_len is {}, the file {} bytes", png.byte_len(), bytes.len())
+	})
+	.or_else(|| {
+		invariant(png.chunks().first().is_some_and(|c| &c.chunk_type().bytes() == b"IHDR"), || {
+			"the first chunk isn't IHDR".to_owned()
+		})
+	})
+	.or_else(|| {
+		invariant(png.find_nth_by_type("IEND", 0).is_some(), || "no IEND chunk".to_owned())
+	})
+	.or_else(|| {
+		// read lazily, the chunks up to IEND come out the same
+		let streamed: Result<Vec<_>, _> = match Png::chunks_from_reader(bytes, options) {
+			Ok(reader) => reader.map(|c| c.map(|c| c.as_bytes())).collect(),
+			Err(e) => Err(e),
+		};
+		let iend = png.find_nth_by_type("IEND", 0).map_or(0, |i| i + 1);
+		let parsed: Vec<_> = png.chunks()[..iend].iter().map(|c| c.as_bytes()).collect();
+		invariant(streamed.is_ok_and(|s| s == parsed), || {
+			"chunks_from_reader doesn't read the same chunks".to_owned()
+		})
+	})
+}
+
+fn invariant(holds: bool, message: impl FnOnce() -> String) -> Option<Problem> {
+	(!holds).then(|| Problem::Invariant(message()))
+}
+
+/// Checks every png under `dir`.
+pub fn check_dir(dir: &Path, options: &ParseOptions) -> std::io::Result<Report> {
+	let mut report = Report::default();
+	for path in files(dir)? {
+		report.checked += 1;
+		let problem = match std::fs::read(&path) {
+			Ok(bytes) => check(&bytes, options),
+			Err(e) => Some(Problem::Unreadable(e)),
+		};
+		if let Some(problem) = problem {
+			report.failures.push(Failure { path, problem });
+		}
+	}
+	Ok(report)
+}
+
+/// `check_dir` with the default options, panicking with the report if any
+/// png fails or there are none at all.
+pub fn assert_dir(dir: &Path) {
+	let report = check_dir(dir, &ParseOptions::default())
+		.unwrap_or_else(|e| panic!("can't read {}: {e}", dir.display()));
+	assert!(report.checked > 0, "no pngs under {}", dir.display());
+	assert!(report.is_ok(), "{report}");
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::chunk::Chunk;
+	use crate::chunk_type::ChunkType;
+
+	fn chunk(t: &str, d: &str) -> Chunk {
+		Chunk::new(ChunkType::from_str(t).unwrap(), d.into())
+	}
+
+	fn png() -> Vec<u8> {
+		Png::from_chunks(vec![chunk("IHDR", "header"), chunk("IEND", "")]).as_bytes()
+	}
+
+	/// A fresh, empty directory for `name`.
+	fn dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("edpg-corpus-{name}-{}", std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		std::fs::create_dir_all(dir.join("sub")).unwrap();
+		dir
+	}
+
+	#[test]
+	fn test_check() {
+		assert!(check(&png(), &ParseOptions::default()).is_none());
+
+		let no_ihdr = Png::from_chunks(vec![chunk("ruSt", ""), chunk("IEND", "")]).as_bytes();
+		assert!(matches!(
+			check(&no_ihdr, &ParseOptions::default()),
+			Some(Problem::Invariant(_))
+		));
+
+		let mut trailer = png();
+		trailer.extend_from_slice(b"ab");
+		assert!(matches!(
+			check(&trailer, &ParseOptions::default()),
+			Some(Problem::NotIdentical(_))
+		));
+	}
+
+	#[test]
+	fn test_check_dir() {
+		let dir = dir("check");
+		std::fs::write(dir.join("a.png"), png()).unwrap();
+		std::fs::write(dir.join("sub/B.PNG"), png()).unwrap();
+		std::fs::write(dir.join("sub/broken.png"), b"not a png").unwrap();
+		std::fs::write(dir.join("notes.txt"), b"skipped").unwrap();
+
+		let report = check_dir(&dir, &ParseOptions::default()).unwrap();
+		assert_eq!(report.checked, 3);
+		assert_eq!(report.failures.len(), 1);
+		assert!(report.failures[0].path.ends_with("sub/broken.png"));
+		assert!(matches!(report.failures[0].problem, Problem::Invalid(_)));
+
+		std::fs::remove_file(dir.join("sub/broken.png")).unwrap();
+		assert_dir(&dir);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+}
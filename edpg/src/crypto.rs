@@ -0,0 +1,310 @@
+// Passphrase based encryption for chunk data.
+// Sealed layout: salt (16 bytes) | nonce (12 bytes) | ciphertext + tag
+// `encrypt_with_totp` puts `TOTP_MAGIC` in front, authenticated along with
+// the ciphertext.
+//
+// The one time passwords of `encrypt_with_totp` and `lock_chunk` add no
+// protection. The TOTP secret is sealed with the passphrase like the data,
+// so the passphrase alone decrypts everything, `decrypt` or any
+// ChaCha20-Poly1305 tool included. Decrypting can't need the secret either,
+// whoever decodes only has a code. The code check only keeps honest
+// frontends from decrypting without one.
+
+pub mod totp;
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::{ChunkType, ChunkTypeError};
+use crate::crypto::totp::Totp;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum CryptoError {
+	#[error("Wrong passphrase or the data has been tampered with.")]
+	DecryptionFailed,
+	#[error("Encrypted data must be at least {0} bytes long.")]
+	ShortInput(usize),
+	#[error("Unable to derive a key from the passphrase. {0}")]
+	KeyDerivation(String),
+	#[error("This chunk is guarded by a one time password, provide the current code.")]
+	TotpRequired,
+	#[error("One time password doesn't match.")]
+	TotpMismatch,
+	#[error("{0}")]
+	ChunkTypeError(#[from] ChunkTypeError),
+}
+
+pub const SALT_BYTES: usize = 16;
+pub const NONCE_BYTES: usize = 12;
+pub const TAG_BYTES: usize = 16;
+pub const OVERHEAD_BYTES: usize = SALT_BYTES + NONCE_BYTES + TAG_BYTES;
+/// Marks what `encrypt_with_totp` sealed. Plain `encrypt` output starts with
+/// a random salt, which may happen to match, so it only counts if the
+/// ciphertext checks out with it.
+pub const TOTP_MAGIC: [u8; 4] = *b"\x89OTP";
+
+pub(crate) fn derive_key(passphrase: &[u8], salt: &[u8]) -> Result<Key, CryptoError> {
+	let mut key = Key::default();
+	Argon2::default()
+		.hash_password_into(passphrase, salt, &mut key)
+		.map_err(|e| CryptoError::KeyDerivation(e.to_string()))?;
+
+	Ok(key)
+}
+
+/// Encrypts `plaintext` with a key derived from `passphrase`. Salt and nonce
+/// are generated fresh and stored in front of the ciphertext so `decrypt`
+/// only needs the passphrase.
+pub fn encrypt(passphrase: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+	seal(passphrase, plaintext, b"")
+}
+
+/// `encrypt` with `aad` authenticated but not stored.
+fn seal(passphrase: &[u8], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+	let mut salt = [0u8; SALT_BYTES];
+	let mut nonce = [0u8; NONCE_BYTES];
+	OsRng.fill_bytes(&mut salt);
+	OsRng.fill_bytes(&mut nonce);
+
+	let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+	let ciphertext = cipher
+		.encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+		.map_err(|_| CryptoError::DecryptionFailed)?;
+
+	Ok(salt
+		.iter()
+		.chain(nonce.iter())
+		.chain(ciphertext.iter())
+		.copied()
+		.collect())
+}
+
+/// Reverses `encrypt`. A wrong passphrase and tampered bytes are
+/// indistinguishable, both end up as `CryptoError::DecryptionFailed`.
+pub fn decrypt(passphrase: &[u8], sealed: &[u8]) -> Result<Vec<u8>, CryptoError> {
+	open(passphrase, sealed, b"")
+}
+
+/// Reverses `seal`.
+pub(crate) fn open(passphrase: &[u8], sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+	if sealed.len() < OVERHEAD_BYTES {
+		return Err(CryptoError::ShortInput(OVERHEAD_BYTES));
+	}
+
+	let (salt, rest) = sealed.split_at(SALT_BYTES);
+	let (nonce, ciphertext) = rest.split_at(NONCE_BYTES);
+
+	let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+	cipher
+		.decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+		.map_err(|_| CryptoError::DecryptionFailed)
+}
+
+/// `encrypt`, with `decrypt_with_code` also asking for the current one time
+/// password of `totp`. Not any safer than `encrypt`, see the top of this
+/// module. The secret is sealed in front of the data as `lock_chunk` does.
+// Plaintext layout: secret len (1 byte) | secret | data
+pub fn encrypt_with_totp(passphrase: &[u8], plaintext: &[u8], totp: &Totp) -> Result<Vec<u8>, CryptoError> {
+	let sealed = seal(passphrase, &with_secret(Some(totp), plaintext), &TOTP_MAGIC)?;
+	Ok([&TOTP_MAGIC[..], &sealed].concat())
+}
+
+/// Reverses `encrypt` and `encrypt_with_totp`, `code` is only looked at for
+/// the latter.
+pub fn decrypt_with_code(passphrase: &[u8], sealed: &[u8], code: Option<u32>) -> Result<Vec<u8>, CryptoError> {
+	if let Some(rest) = sealed.strip_prefix(&TOTP_MAGIC) {
+		match open(passphrase, rest, &TOTP_MAGIC) {
+			Ok(plaintext) => return open_secret(&plaintext, code).map(<[u8]>::to_vec),
+			// a salt that starts like the magic, or the wrong passphrase
+			Err(CryptoError::DecryptionFailed | CryptoError::ShortInput(_)) => {},
+			Err(e) => return Err(e),
+		}
+	}
+	decrypt(passphrase, sealed)
+}
+
+/// `data` with the secret of `totp`, if any, in front.
+fn with_secret(totp: Option<&Totp>, data: &[u8]) -> Vec<u8> {
+	let secret = totp.map(Totp::secret).unwrap_or_default();
+	let secret_len = u8::try_from(secret.len()).expect("TOTP secrets are 20 bytes");
+
+	std::iter::once(&secret_len)
+		.chain(secret.iter())
+		.chain(data.iter())
+		.copied()
+		.collect()
+}
+
+/// Undoes `with_secret`, checking `code` if there is a secret.
+fn open_secret(plaintext: &[u8], code: Option<u32>) -> Result<&[u8], CryptoError> {
+	let (secret, data) = split_secret(plaintext)?;
+	if !secret.is_empty() {
+		let code = code.ok_or(CryptoError::TotpRequired)?;
+		if !Totp::new(secret.to_vec()).verify(code) {
+			return Err(CryptoError::TotpMismatch);
+		}
+	}
+	Ok(data)
+}
+
+/// Secret and data `with_secret` put together, the secret empty if there was
+/// none.
+pub(crate) fn split_secret(plaintext: &[u8]) -> Result<(&[u8], &[u8]), CryptoError> {
+	let short = || CryptoError::ShortInput(OVERHEAD_BYTES + 1);
+
+	let (&secret_len, rest) = plaintext.split_first().ok_or_else(short)?;
+	rest.split_at_checked(secret_len as usize).ok_or_else(short)
+}
+
+/// Encrypts the data of `chunk` and relabels it as `label`. The original chunk
+/// type is sealed together with the data so `unlock_chunk` can restore it.
+///
+/// With a `totp`, its secret is sealed alongside and `unlock_chunk`
+/// additionally asks for the current one time password. That is a prompt,
+/// not protection, see the top of this module.
+// Plaintext layout: type (4 bytes) | secret len (1 byte) | secret | data
+pub fn lock_chunk(
+	chunk: &Chunk,
+	label: ChunkType,
+	passphrase: &[u8],
+	totp: Option<&Totp>,
+) -> Result<Chunk, CryptoError> {
+	let plaintext = [&chunk.chunk_type().bytes()[..], &with_secret(totp, chunk.data())].concat();
+	Ok(Chunk::new(label, encrypt(passphrase, &plaintext)?))
+}
+
+/// Reverses `lock_chunk`, giving back the chunk with its original type and
+/// data. `code` is only looked at if the chunk was locked with a TOTP.
+pub fn unlock_chunk(chunk: &Chunk, passphrase: &[u8], code: Option<u32>) -> Result<Chunk, CryptoError> {
+	let plaintext = decrypt(passphrase, chunk.data())?;
+	let (chunk_type, rest) = plaintext
+		.split_first_chunk::<{ Chunk::CHUNK_TYPE_BYTES }>()
+		.ok_or(CryptoError::ShortInput(OVERHEAD_BYTES + Chunk::CHUNK_TYPE_BYTES + 1))?;
+	let data = open_secret(rest, code)?;
+
+	let chunk_type = ChunkType::try_from(*chunk_type)?;
+	chunk_type.is_valid()?;
+
+	Ok(Chunk::new(chunk_type, data.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+
+	#[test]
+	fn test_encrypt_decrypt_roundtrip() {
+		let sealed = encrypt(b"hunter2", b"secret message").unwrap();
+		assert_eq!(sealed.len(), OVERHEAD_BYTES + b"secret message".len());
+		assert_eq!(decrypt(b"hunter2", &sealed).unwrap(), b"secret message");
+	}
+
+	#[test]
+	fn test_wrong_passphrase() {
+		let sealed = encrypt(b"hunter2", b"secret message").unwrap();
+		assert_eq!(
+			decrypt(b"hunter3", &sealed).unwrap_err(),
+			CryptoError::DecryptionFailed
+		);
+	}
+
+	#[test]
+	fn test_tampered_data() {
+		let mut sealed = encrypt(b"hunter2", b"secret message").unwrap();
+		*sealed.last_mut().unwrap() ^= 1;
+		assert_eq!(
+			decrypt(b"hunter2", &sealed).unwrap_err(),
+			CryptoError::DecryptionFailed
+		);
+	}
+
+	#[test]
+	fn test_short_input() {
+		assert_eq!(
+			decrypt(b"hunter2", &[0; 10]).unwrap_err(),
+			CryptoError::ShortInput(OVERHEAD_BYTES)
+		);
+	}
+
+	#[test]
+	fn test_lock_unlock_chunk() {
+		let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Author\0me".to_vec());
+		let label = ChunkType::from_str("lcKd").unwrap();
+
+		let locked = lock_chunk(&chunk, label, b"pw", None).unwrap();
+		assert_eq!(locked.chunk_type(), &label);
+		assert_ne!(locked.data(), chunk.data());
+
+		let unlocked = unlock_chunk(&locked, b"pw", None).unwrap();
+		assert_eq!(unlocked.chunk_type().to_string(), "tEXt");
+		assert_eq!(unlocked.data(), chunk.data());
+	}
+
+	#[test]
+	fn test_lock_unlock_chunk_with_totp() {
+		let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Author\0me".to_vec());
+		let label = ChunkType::from_str("lcKd").unwrap();
+		let totp = Totp::generate();
+
+		let locked = lock_chunk(&chunk, label, b"pw", Some(&totp)).unwrap();
+		assert_eq!(
+			unlock_chunk(&locked, b"pw", None).unwrap_err(),
+			CryptoError::TotpRequired
+		);
+
+		let code = totp.code_at(
+			std::time::SystemTime::now()
+				.duration_since(std::time::UNIX_EPOCH)
+				.unwrap()
+				.as_secs(),
+		);
+		assert_eq!(
+			unlock_chunk(&locked, b"pw", Some((code + 1) % 1_000_000)).unwrap_err(),
+			CryptoError::TotpMismatch
+		);
+
+		let unlocked = unlock_chunk(&locked, b"pw", Some(code)).unwrap();
+		assert_eq!(unlocked.data(), chunk.data());
+	}
+
+	#[test]
+	fn test_encrypt_with_totp() {
+		let totp = Totp::generate();
+		let code = totp.code_at(
+			std::time::SystemTime::now()
+				.duration_since(std::time::UNIX_EPOCH)
+				.unwrap()
+				.as_secs(),
+		);
+
+		let sealed = encrypt_with_totp(b"pw", b"secret message", &totp).unwrap();
+		assert_eq!(
+			decrypt_with_code(b"pw", &sealed, None).unwrap_err(),
+			CryptoError::TotpRequired
+		);
+		assert_eq!(
+			decrypt_with_code(b"pw", &sealed, Some((code + 1) % 1_000_000)).unwrap_err(),
+			CryptoError::TotpMismatch
+		);
+		assert_eq!(decrypt_with_code(b"pw", &sealed, Some(code)).unwrap(), b"secret message");
+
+		// plain `encrypt` doesn't need a code, whatever the message
+		let sealed = encrypt(b"pw", b"secret message").unwrap();
+		assert_eq!(decrypt_with_code(b"pw", &sealed, None).unwrap(), b"secret message");
+		let sealed = encrypt(b"pw", b"\x89OTP\x14 looks guarded").unwrap();
+		assert_eq!(decrypt_with_code(b"pw", &sealed, None).unwrap(), b"\x89OTP\x14 looks guarded");
+		// nor a salt that starts like the magic
+		let mut sealed = encrypt(b"pw", b"secret message").unwrap();
+		sealed[..4].copy_from_slice(&TOTP_MAGIC);
+		assert_eq!(
+			decrypt_with_code(b"pw", &sealed, None).unwrap_err(),
+			CryptoError::DecryptionFailed
+		);
+	}
+}
@@ -0,0 +1,139 @@
+// RFC 6238 time based one time passwords, the same flavour authenticator apps
+// speak: HMAC-SHA1, 30 second steps, 6 digits.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+pub const SECRET_BYTES: usize = 20;
+pub const STEP_SECS: u64 = 30;
+pub const DIGITS: u32 = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Totp {
+	secret: Vec<u8>,
+}
+
+impl Totp {
+	pub fn new(secret: Vec<u8>) -> Self {
+		Self { secret }
+	}
+
+	/// Fresh random secret.
+	pub fn generate() -> Self {
+		let mut secret = vec![0u8; SECRET_BYTES];
+		OsRng.fill_bytes(&mut secret);
+		Self { secret }
+	}
+
+	pub fn secret(&self) -> &[u8] {
+		&self.secret
+	}
+
+	/// The code valid at `unix_secs`.
+	pub fn code_at(&self, unix_secs: u64) -> u32 {
+		let counter = unix_secs / STEP_SECS;
+
+		let mut mac = Hmac::<Sha1>::new_from_slice(&self.secret)
+			.expect("HMAC accepts keys of any length");
+		mac.update(&counter.to_be_bytes());
+		let digest = mac.finalize().into_bytes();
+
+		// dynamic truncation, see RFC 4226 section 5.3
+		let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+		let bin = u32::from_be_bytes([
+			digest[offset] & 0x7f,
+			digest[offset + 1],
+			digest[offset + 2],
+			digest[offset + 3],
+		]);
+
+		bin % 10u32.pow(DIGITS)
+	}
+
+	/// Accepts the code of the current step or the one right before/after it,
+	/// to forgive small clock drift.
+	pub fn verify_at(&self, code: u32, unix_secs: u64) -> bool {
+		[
+			unix_secs.saturating_sub(STEP_SECS),
+			unix_secs,
+			unix_secs + STEP_SECS,
+		]
+		.iter()
+		.any(|&t| self.code_at(t) == code)
+	}
+
+	pub fn verify(&self, code: u32) -> bool {
+		self.verify_at(code, now())
+	}
+
+	/// Secret in the unpadded base32 form authenticator apps expect.
+	pub fn to_base32(&self) -> String {
+		const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+		let mut out = String::new();
+		for group in self.secret.chunks(5) {
+			let mut buf = [0u8; 5];
+			buf[..group.len()].copy_from_slice(group);
+			let bits = u64::from_be_bytes([0, 0, 0, buf[0], buf[1], buf[2], buf[3], buf[4]]);
+
+			let chars = (group.len() * 8).div_ceil(5);
+			for i in 0..chars {
+				let idx = (bits >> (35 - i * 5)) & 0x1f;
+				out.push(ALPHABET[idx as usize] as char);
+			}
+		}
+
+		out
+	}
+
+	/// `otpauth://` uri, most authenticator apps can import it from a QR code.
+	pub fn otpauth_uri(&self, label: &str) -> String {
+		format!(
+			"otpauth://totp/Vanish:{label}?secret={}&issuer=Vanish&digits={DIGITS}&period={STEP_SECS}",
+			self.to_base32()
+		)
+	}
+}
+
+fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// RFC 6238 appendix B, SHA1 vectors truncated to 6 digits
+	fn rfc_totp() -> Totp {
+		Totp::new(b"12345678901234567890".to_vec())
+	}
+
+	#[test]
+	fn test_rfc_vectors() {
+		let totp = rfc_totp();
+		assert_eq!(totp.code_at(59), 287082);
+		assert_eq!(totp.code_at(1111111109), 81804);
+		assert_eq!(totp.code_at(1234567890), 5924);
+		assert_eq!(totp.code_at(20000000000), 353130);
+	}
+
+	#[test]
+	fn test_verify_allows_one_step_drift() {
+		let totp = rfc_totp();
+		assert!(totp.verify_at(287082, 59 + STEP_SECS));
+		assert!(!totp.verify_at(287082, 59 + 2 * STEP_SECS));
+	}
+
+	#[test]
+	fn test_base32() {
+		assert_eq!(rfc_totp().to_base32(), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+		assert_eq!(Totp::new(b"f".to_vec()).to_base32(), "MY");
+	}
+}
@@ -0,0 +1,326 @@
+// Signs that a png carries more than an image.
+
+use std::fmt::Display;
+
+use crate::analysis::{self, Anomaly};
+use crate::chunk::Chunk;
+use crate::png::{ParseOptions, Png, PngError};
+use crate::polyglot;
+use crate::report::Location;
+use crate::signature::{self, Signature};
+
+/// Chunk types from the PNG spec and its registered extensions (APNG, the
+/// GIF conversion chunks...). Anything else is private to some tool.
+pub const KNOWN_CHUNK_TYPES: [&str; 34] = [
+	"IHDR", "PLTE", "IDAT", "IEND", "cHRM", "cICP", "gAMA", "iCCP", "mDCV", "cLLI", "sBIT",
+	"sRGB", "bKGD", "hIST", "tRNS", "eXIf", "pHYs", "sPLT", "tIME", "iTXt", "tEXt", "zTXt",
+	"acTL", "fcTL", "fdAT", "oFFs", "pCAL", "sCAL", "sTER", "gIFg", "gIFx", "gIFt", "fRAc",
+	"dSIG",
+];
+
+/// Type `lock-chunk` stores encrypted chunks under unless told otherwise.
+pub const LOCKED_CHUNK_TYPE: &str = "lcKd";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+	/// Ancillary chunk of a type no standard defines.
+	UnknownChunk { index: usize, chunk_type: String },
+	/// Chunk encrypted by `lock-chunk`.
+	LockedChunk { index: usize },
+	/// Bytes after IEND, viewers never look at them.
+	TrailingData { offset: usize, len: usize },
+	/// A zip archive after IEND, the file opens as either.
+	ZipArchive { offset: usize },
+	/// Compressed chunk that doesn't compress like what it claims to hold.
+	Compression(Anomaly),
+	/// Chunk carrying the signature of a known tool, see `signature`.
+	Signature { index: usize, tool: String },
+	/// Data after IEND carrying the signature of a known tool.
+	TrailerSignature { offset: usize, tool: String },
+	/// Bytes before the png signature, see `ParseOptions::max_prefix`.
+	ForeignPrefix { len: usize },
+}
+
+impl Display for Finding {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::UnknownChunk { index, chunk_type } => {
+				write!(f, "chunk {index}: unknown ancillary chunk `{chunk_type}`")
+			},
+			Self::LockedChunk { index } => write!(f, "chunk {index}: encrypted vanish chunk"),
+			Self::TrailingData { offset, len } => {
+				write!(f, "offset {offset}: {len} bytes after IEND")
+			},
+			Self::ZipArchive { offset } => {
+				write!(f, "offset {offset}: zip archive after IEND, png/zip polyglot")
+			},
+			Self::Compression(anomaly) => write!(f, "{anomaly}"),
+			Self::Signature { index, tool } => write!(f, "chunk {index}: looks like {tool} output"),
+			Self::TrailerSignature { offset, tool } => {
+				write!(f, "offset {offset}: {tool} data after IEND")
+			},
+			Self::ForeignPrefix { len } => write!(f, "offset 0: {len} bytes before the png signature"),
+		}
+	}
+}
+
+pub fn is_known(chunk_type: &str) -> bool {
+	KNOWN_CHUNK_TYPES.contains(&chunk_type)
+}
+
+/// Everything in `bytes` that looks like a hidden payload, in file order.
+pub fn scan(bytes: &[u8]) -> Result<Vec<Finding>, PngError> {
+	scan_with(bytes, &ParseOptions::default())
+}
+
+pub fn scan_with(bytes: &[u8], options: &ParseOptions) -> Result<Vec<Finding>, PngError> {
+	scan_matching(bytes, options, &signature::builtin())
+}
+
+/// `scan_with`, looking for `signatures` instead of the builtin ones.
+pub fn scan_matching(
+	bytes: &[u8],
+	options: &ParseOptions,
+	signatures: &[Signature],
+) -> Result<Vec<Finding>, PngError> {
+	let start = Png::signature(bytes, options.max_prefix).ok_or_else(|| Png::header_error(bytes))?;
+	// the trailer doesn't have to be chunks, only parse up to IEND
+	let end = start + Png::image_len(&bytes[start..]).unwrap_or(bytes.len() - start);
+	let png = Png::parse_with(&bytes[start..end], options)?;
+	let mut findings = Vec::new();
+	if start > 0 {
+		findings.push(Finding::ForeignPrefix { len: start });
+	}
+	let mut offset = start + Png::STANDARD_HEADER.len();
+	let mut anomalies = analysis::anomalies(&png).into_iter().peekable();
+	let mut matched = signature::match_chunks(signatures, &png).into_iter();
+
+	for (index, chunk) in png.chunks().iter().enumerate() {
+		let chunk_type = chunk.chunk_type().to_string();
+		offset += Chunk::METADATA_BYTES + chunk.data().len();
+
+		if chunk_type == LOCKED_CHUNK_TYPE {
+			findings.push(Finding::LockedChunk { index });
+		} else if !chunk.chunk_type().is_critical() && !is_known(&chunk_type) {
+			findings.push(Finding::UnknownChunk {
+				index,
+				chunk_type: chunk_type.clone(),
+			});
+		}
+		while let Some(anomaly) = anomalies.next_if(|a| a.index() == index) {
+			findings.push(Finding::Compression(anomaly));
+		}
+		for s in matched.next().unwrap_or_default() {
+			findings.push(Finding::Signature {
+				index,
+				tool: s.tool.clone(),
+			});
+		}
+
+		if chunk_type == "IEND" {
+			// whatever follows, chunks or not, is trailer
+			findings.extend(scan_trailer(offset, &bytes[offset..], signatures));
+			break;
+		}
+	}
+
+	Ok(findings)
+}
+
+/// Findings about `trailer`, the bytes after IEND of a png whose image ends
+/// at `offset`. Lets a png that only grew be checked without its image.
+pub fn scan_trailer(offset: usize, trailer: &[u8], signatures: &[Signature]) -> Vec<Finding> {
+	let mut findings = Vec::new();
+	if !trailer.is_empty() {
+		findings.push(Finding::TrailingData {
+			offset,
+			len: trailer.len(),
+		});
+	}
+	if polyglot::is_zip(trailer) {
+		findings.push(Finding::ZipArchive { offset });
+	}
+	for s in signatures.iter().filter(|s| s.matches_trailer(trailer)) {
+		findings.push(Finding::TrailerSignature {
+			offset,
+			tool: s.tool.clone(),
+		});
+	}
+	findings
+}
+
+impl Finding {
+	/// Stable name of the kind of finding, for tools keyed on it.
+	pub fn rule(&self) -> &'static str {
+		match self {
+			Self::UnknownChunk { .. } => "unknown-chunk",
+			Self::LockedChunk { .. } => "locked-chunk",
+			Self::TrailingData { .. } => "trailing-data",
+			Self::ZipArchive { .. } => "zip-archive",
+			Self::Compression(_) => "compression-anomaly",
+			Self::Signature { .. } => "tool-signature",
+			Self::TrailerSignature { .. } => "trailer-signature",
+			Self::ForeignPrefix { .. } => "foreign-prefix",
+		}
+	}
+
+	pub fn location(&self) -> Location {
+		match self {
+			Self::UnknownChunk { index, .. }
+			| Self::LockedChunk { index }
+			| Self::Signature { index, .. } => Location::Chunk(*index),
+			Self::Compression(anomaly) => Location::Chunk(anomaly.index()),
+			Self::TrailingData { offset, .. }
+			| Self::ZipArchive { offset }
+			| Self::TrailerSignature { offset, .. } => Location::Offset(*offset),
+			Self::ForeignPrefix { .. } => Location::Offset(0),
+		}
+	}
+
+	/// What gets rid of it.
+	pub fn remediation(&self) -> Option<&'static str> {
+		match self {
+			Self::UnknownChunk { .. } | Self::LockedChunk { .. } | Self::Signature { .. } => {
+				Some("remove the chunk, eg: `vanish remove`")
+			},
+			Self::TrailingData { .. } | Self::ZipArchive { .. } | Self::TrailerSignature { .. } => {
+				Some("cut the file off after IEND")
+			},
+			// can't be cut out, only re-encoding the image gets rid of it
+			Self::Compression(_) => Some("re-encode the image"),
+			Self::ForeignPrefix { .. } => Some("cut the bytes before the png signature"),
+		}
+	}
+
+	/// Whether this is about the bytes after IEND, see `scan_trailer`.
+	pub fn is_trailer(&self) -> bool {
+		matches!(
+			self,
+			Self::TrailingData { .. } | Self::ZipArchive { .. } | Self::TrailerSignature { .. }
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::chunk_type::ChunkType;
+
+	fn chunk(t: &str, d: &str) -> Chunk {
+		Chunk::new(ChunkType::from_str(t).unwrap(), d.into())
+	}
+
+	fn testing_png() -> Png {
+		Png::from_chunks(vec![
+			chunk("IHDR", "header"),
+			chunk("tEXt", "Title\0cat"),
+			chunk("IEND", ""),
+		])
+	}
+
+	#[test]
+	fn test_clean() {
+		assert!(scan(&testing_png().as_bytes()).unwrap().is_empty());
+	}
+
+	#[test]
+	fn test_unknown_and_locked() {
+		let mut png = testing_png();
+		png.append_chunk(chunk("ruSt", "secret"));
+		png.append_chunk(chunk("lcKd", "sealed"));
+		assert_eq!(scan(&png.as_bytes()).unwrap(), [
+			Finding::UnknownChunk {
+				index: 2,
+				chunk_type: "ruSt".to_owned()
+			},
+			Finding::LockedChunk { index: 3 },
+		]);
+	}
+
+	#[test]
+	fn test_trailing_data() {
+		let mut bytes = testing_png().as_bytes();
+		let end = bytes.len();
+		bytes.extend_from_slice(b"xy");
+		assert_eq!(scan(&bytes).unwrap(), [Finding::TrailingData {
+			offset: end,
+			len: 2
+		}]);
+
+		// chunks after IEND are trailer too
+		let mut png = testing_png();
+		png.insert_chunk(3, chunk("tEXt", "a\0b")).unwrap();
+		assert_eq!(scan(&png.as_bytes()).unwrap(), [Finding::TrailingData {
+			offset: end,
+			len: 15
+		}]);
+	}
+
+	#[test]
+	fn test_zip_archive() {
+		let mut bytes = testing_png().as_bytes();
+		let end = bytes.len();
+		bytes.extend_from_slice(b"PK\x03\x04 rest of a zip");
+		assert_eq!(scan(&bytes).unwrap(), [
+			Finding::TrailingData { offset: end, len: 18 },
+			Finding::ZipArchive { offset: end },
+		]);
+	}
+
+	#[test]
+	fn test_foreign_prefix() {
+		let mut bytes = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+		let start = bytes.len();
+		bytes.extend(testing_png().as_bytes());
+		bytes.extend_from_slice(b"xy");
+		assert!(scan(&bytes).is_err());
+
+		let options = ParseOptions {
+			max_prefix: 64,
+			..Default::default()
+		};
+		assert_eq!(scan_with(&bytes, &options).unwrap(), [
+			Finding::ForeignPrefix { len: start },
+			Finding::TrailingData {
+				offset: bytes.len() - 2,
+				len: 2
+			},
+		]);
+	}
+
+	#[test]
+	fn test_scan_trailer() {
+		let mut bytes = testing_png().as_bytes();
+		let end = bytes.len();
+		assert!(scan_trailer(end, &[], &signature::builtin()).is_empty());
+
+		bytes.extend_from_slice(b"PK\x03\x04 rest of a zip");
+		let findings = scan(&bytes).unwrap();
+		assert!(findings.iter().all(Finding::is_trailer));
+		assert_eq!(scan_trailer(end, &bytes[end..], &signature::builtin()), findings);
+	}
+
+	#[test]
+	fn test_signatures() {
+		let mut png = testing_png();
+		png.append_chunk(chunk("ruSt", "Salted__x"));
+		let mut bytes = png.as_bytes();
+		let end = bytes.len();
+		bytes.extend_from_slice(b"7z\xbc\xaf\x27\x1c");
+
+		let findings = scan(&bytes).unwrap();
+		assert!(findings.contains(&Finding::TrailerSignature {
+			offset: end,
+			tool: "7-Zip".into(),
+		}));
+		assert!(findings
+			.iter()
+			.any(|f| matches!(f, Finding::Signature { tool, .. } if tool == "openssl enc")));
+		assert!(!scan_matching(&bytes, &ParseOptions::default(), &[])
+			.unwrap()
+			.iter()
+			.any(|f| matches!(f, Finding::Signature { .. } | Finding::TrailerSignature { .. })));
+	}
+}
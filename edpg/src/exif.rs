@@ -0,0 +1,192 @@
+// The text fields of an eXIf chunk, and their tEXt equivalents.
+// eXIf holds a bare TIFF structure: byte order, 42, offset of the first IFD.
+// Only the ascii entries of that first IFD are looked at.
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ExifError {
+	#[error("Exif data must start with `II` or `MM` followed by 42.")]
+	InvalidHeader,
+	#[error("Exif data ends before offset {0}.")]
+	Truncated(usize),
+}
+
+/// Exif tags that have a tEXt counterpart, with the keyword they map to.
+pub const FIELDS: [(u16, &str); 7] = [
+	(0x010E, "Description"),
+	(0x010F, "Make"),
+	(0x0110, "Model"),
+	(0x0131, "Software"),
+	(0x0132, "Creation Time"),
+	(0x013B, "Author"),
+	(0x8298, "Copyright"),
+];
+
+const ASCII: u16 = 2;
+const ENTRY_BYTES: usize = 12;
+
+pub fn keyword(tag: u16) -> Option<&'static str> {
+	FIELDS.iter().find(|(t, _)| *t == tag).map(|(_, k)| *k)
+}
+
+pub fn tag(keyword: &str) -> Option<u16> {
+	FIELDS.iter().find(|(_, k)| *k == keyword).map(|(t, _)| *t)
+}
+
+struct Reader<'a> {
+	bytes: &'a [u8],
+	little_endian: bool,
+}
+
+impl Reader<'_> {
+	fn slice(&self, offset: usize, len: usize) -> Result<&[u8], ExifError> {
+		offset
+			.checked_add(len)
+			.and_then(|end| self.bytes.get(offset..end))
+			.ok_or(ExifError::Truncated(offset.saturating_add(len)))
+	}
+
+	fn u16(&self, offset: usize) -> Result<u16, ExifError> {
+		let b: [u8; 2] = self.slice(offset, 2)?.try_into().expect("slice is 2 bytes");
+		Ok(match self.little_endian {
+			true => u16::from_le_bytes(b),
+			false => u16::from_be_bytes(b),
+		})
+	}
+
+	fn u32(&self, offset: usize) -> Result<u32, ExifError> {
+		let b: [u8; 4] = self.slice(offset, 4)?.try_into().expect("slice is 4 bytes");
+		Ok(match self.little_endian {
+			true => u32::from_le_bytes(b),
+			false => u32::from_be_bytes(b),
+		})
+	}
+}
+
+/// The known text fields of `exif`, in IFD order, as `(tag, value)`.
+pub fn read(exif: &[u8]) -> Result<Vec<(u16, String)>, ExifError> {
+	let little_endian = match exif.get(..2) {
+		Some(b"II") => true,
+		Some(b"MM") => false,
+		_ => return Err(ExifError::InvalidHeader),
+	};
+	let r = Reader {
+		bytes: exif,
+		little_endian,
+	};
+	if r.u16(2)? != 42 {
+		return Err(ExifError::InvalidHeader);
+	}
+
+	let ifd = r.u32(4)? as usize;
+	let count = r.u16(ifd)? as usize;
+	let mut fields = Vec::new();
+	for i in 0..count {
+		let entry = ifd + 2 + i * ENTRY_BYTES;
+		let tag = r.u16(entry)?;
+		if r.u16(entry + 2)? != ASCII || keyword(tag).is_none() {
+			continue;
+		}
+
+		// values that fit in 4 bytes are stored in place of the offset
+		let len = r.u32(entry + 4)? as usize;
+		let value = match len <= 4 {
+			true => r.slice(entry + 8, len)?,
+			false => r.slice(r.u32(entry + 8)? as usize, len)?,
+		};
+		let value = value.split(|&b| b == 0).next().unwrap_or_default();
+		fields.push((tag, String::from_utf8_lossy(value).into_owned()));
+	}
+
+	Ok(fields)
+}
+
+/// A big endian TIFF structure holding `fields` as ascii entries of a single
+/// IFD, ready to be the data of an eXIf chunk.
+pub fn write(fields: &[(u16, String)]) -> Vec<u8> {
+	let mut fields: Vec<&(u16, String)> = fields.iter().collect();
+	// IFD entries must be sorted by tag
+	fields.sort_by_key(|(tag, _)| *tag);
+
+	let ifd_bytes = 2 + fields.len() * ENTRY_BYTES + 4;
+	let mut out = Vec::with_capacity(8 + ifd_bytes);
+	out.extend_from_slice(b"MM");
+	out.extend_from_slice(&42u16.to_be_bytes());
+	out.extend_from_slice(&8u32.to_be_bytes());
+	out.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+
+	let mut data = Vec::new();
+	let data_offset = 8 + ifd_bytes;
+	for (tag, value) in fields {
+		let mut value = value.as_bytes().to_vec();
+		value.push(0);
+
+		out.extend_from_slice(&tag.to_be_bytes());
+		out.extend_from_slice(&ASCII.to_be_bytes());
+		out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+		if value.len() <= 4 {
+			value.resize(4, 0);
+			out.extend_from_slice(&value);
+		} else {
+			out.extend_from_slice(&((data_offset + data.len()) as u32).to_be_bytes());
+			data.extend_from_slice(&value);
+			// values start on a word boundary
+			if data.len() % 2 == 1 {
+				data.push(0);
+			}
+		}
+	}
+	// no next IFD
+	out.extend_from_slice(&0u32.to_be_bytes());
+	out.extend_from_slice(&data);
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_round_trip() {
+		let fields = vec![
+			(0x013B, "Someone".to_owned()),
+			(0x010F, "Cam".to_owned()),
+			(0x0131, "vanish".to_owned()),
+		];
+		let exif = write(&fields);
+		assert_eq!(read(&exif).unwrap(), [
+			(0x010F, "Cam".to_owned()),
+			(0x0131, "vanish".to_owned()),
+			(0x013B, "Someone".to_owned()),
+		]);
+	}
+
+	#[test]
+	fn test_little_endian() {
+		#[rustfmt::skip]
+		let exif = [
+			b'I', b'I', 42, 0, 8, 0, 0, 0,
+			2, 0,
+			// Make, ascii, 3 bytes in place
+			0x0F, 0x01, 2, 0, 3, 0, 0, 0, b'A', b'B', 0, 0,
+			// unknown tag, skipped
+			0x00, 0x90, 2, 0, 3, 0, 0, 0, b'x', b'y', 0, 0,
+			0, 0, 0, 0,
+		];
+		assert_eq!(read(&exif).unwrap(), [(0x010F, "AB".to_owned())]);
+	}
+
+	#[test]
+	fn test_invalid() {
+		assert_eq!(read(b"XX*\0"), Err(ExifError::InvalidHeader));
+		assert_eq!(read(b"MM\0\x2a\0\0\0\x40"), Err(ExifError::Truncated(0x42)));
+	}
+
+	#[test]
+	fn test_keywords() {
+		assert_eq!(keyword(0x8298), Some("Copyright"));
+		assert_eq!(tag("Author"), Some(0x013B));
+		assert_eq!(tag("Nope"), None);
+	}
+}
@@ -0,0 +1,207 @@
+// Remediations of `detect` findings that are safe to apply without a human
+// looking at the file: only what viewers never look at goes (data after
+// IEND, ancillary chunks), or wrong crcs get rewritten. Pixels are never
+// touched, compression anomalies have no fix here.
+//
+// Fixes work on the raw bytes, whatever isn't fixed is written back as it
+// was, a broken crc included.
+
+use std::fmt::Display;
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::detect::Finding;
+use crate::png::{Png, PngError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fix {
+	/// Cut the file off after IEND.
+	StripTrailer { offset: usize, len: usize },
+	/// Drop an ancillary chunk.
+	RemoveChunk { index: usize, chunk_type: String },
+	/// Write the right crc into chunks whose crc doesn't match.
+	FixCrcs { indices: Vec<usize> },
+}
+
+impl Display for Fix {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::StripTrailer { offset, len } => {
+				write!(f, "offset {offset}: strip the {len} bytes after IEND")
+			},
+			Self::RemoveChunk { index, chunk_type } => {
+				write!(f, "chunk {index}: remove the `{chunk_type}` chunk")
+			},
+			Self::FixCrcs { indices } => {
+				let indices: Vec<String> = indices.iter().map(|i| i.to_string()).collect();
+				write!(f, "rewrite the crc of chunks {}", indices.join(", "))
+			},
+		}
+	}
+}
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Right crc of the chunk at `range` in `bytes`, over its type and data.
+fn crc(bytes: &[u8], range: &std::ops::Range<usize>) -> u32 {
+	CRC.checksum(&bytes[range.start + Chunk::LENGTH_BYTES..range.end - Chunk::CRC_LENGTH_BYTES])
+}
+
+/// Indices of the chunks whose stored crc is wrong.
+pub fn bad_crcs(bytes: &[u8]) -> Result<Vec<usize>, PngError> {
+	Ok(Png::chunk_ranges(bytes)?
+		.iter()
+		.enumerate()
+		.filter(|(_, r)| bytes[r.end - Chunk::CRC_LENGTH_BYTES..r.end] != crc(bytes, r).to_be_bytes())
+		.map(|(i, _)| i)
+		.collect())
+}
+
+/// Fixes for `findings` of `bytes`, and for its wrong crcs. Each fix comes
+/// once, however many findings it takes care of.
+pub fn plan(bytes: &[u8], findings: &[Finding]) -> Result<Vec<Fix>, PngError> {
+	let ranges = Png::chunk_ranges(bytes)?;
+	let chunk_type = |index: usize| {
+		let start = ranges[index].start + Chunk::LENGTH_BYTES;
+		let chunk_type = bytes[start..start + Chunk::CHUNK_TYPE_BYTES].try_into().expect("4 bytes");
+		ChunkType::from_bytes_lossy(chunk_type).map_or_else(|t| t.to_string(), |t| t.to_string())
+	};
+
+	let mut fixes = Vec::new();
+	for finding in findings {
+		let fix = match finding {
+			Finding::TrailingData { offset, len } => Fix::StripTrailer {
+				offset: *offset,
+				len: *len,
+			},
+			// a signature can name a critical chunk type, those stay
+			Finding::UnknownChunk { index, .. }
+			| Finding::LockedChunk { index }
+			| Finding::Signature { index, .. }
+				if chunk_type(*index).starts_with(|c: char| c.is_ascii_lowercase()) =>
+			{
+				Fix::RemoveChunk {
+					index: *index,
+					chunk_type: chunk_type(*index),
+				}
+			},
+			// the trailer goes with `TrailingData`
+			_ => continue,
+		};
+		if !fixes.contains(&fix) {
+			fixes.push(fix);
+		}
+	}
+
+	let indices = bad_crcs(bytes)?;
+	if !indices.is_empty() {
+		fixes.push(Fix::FixCrcs { indices });
+	}
+	Ok(fixes)
+}
+
+/// `bytes` with `fixes` applied.
+pub fn apply(bytes: &[u8], fixes: &[Fix]) -> Result<Vec<u8>, PngError> {
+	let ranges = Png::chunk_ranges(bytes)?;
+	let image_len = ranges.last().map_or(Png::STANDARD_HEADER.len(), |r| r.end);
+
+	let mut out = Vec::with_capacity(bytes.len());
+	out.extend_from_slice(&Png::STANDARD_HEADER);
+	for (index, range) in ranges.iter().enumerate() {
+		let removed = fixes.iter().any(|f| {
+			matches!(f, Fix::RemoveChunk { index: i, .. } if *i == index)
+		});
+		if removed {
+			continue;
+		}
+
+		out.extend_from_slice(&bytes[range.clone()]);
+		let crc_fixed = fixes
+			.iter()
+			.any(|f| matches!(f, Fix::FixCrcs { indices } if indices.contains(&index)));
+		if crc_fixed {
+			let at = out.len() - Chunk::CRC_LENGTH_BYTES;
+			out[at..].copy_from_slice(&crc(bytes, range).to_be_bytes());
+		}
+	}
+
+	if !fixes.iter().any(|f| matches!(f, Fix::StripTrailer { .. })) {
+		out.extend_from_slice(&bytes[image_len..]);
+	}
+	Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::detect;
+	use crate::png::ParseOptions;
+
+	fn chunk(t: &str, d: &str) -> Chunk {
+		Chunk::new(ChunkType::from_str(t).unwrap(), d.into())
+	}
+
+	fn clean_png() -> Vec<u8> {
+		Png::from_chunks(vec![chunk("IHDR", "header"), chunk("IEND", "")]).as_bytes()
+	}
+
+	fn dirty_png() -> Vec<u8> {
+		let mut bytes = Png::from_chunks(vec![
+			chunk("IHDR", "header"),
+			chunk("ruSt", "Salted__secret"),
+			chunk("IEND", ""),
+		])
+		.as_bytes();
+		bytes.extend_from_slice(b"trailer");
+		bytes
+	}
+
+	#[test]
+	fn test_plan() {
+		let bytes = dirty_png();
+		let fixes = plan(&bytes, &detect::scan(&bytes).unwrap()).unwrap();
+		assert_eq!(fixes, [
+			Fix::RemoveChunk {
+				index: 1,
+				chunk_type: "ruSt".into(),
+			},
+			Fix::StripTrailer {
+				offset: bytes.len() - 7,
+				len: 7,
+			},
+		]);
+	}
+
+	#[test]
+	fn test_apply() {
+		let bytes = dirty_png();
+		let fixes = plan(&bytes, &detect::scan(&bytes).unwrap()).unwrap();
+		assert_eq!(apply(&bytes, &fixes).unwrap(), clean_png());
+
+		// only what was picked is fixed
+		let kept = apply(&bytes, &fixes[..1]).unwrap();
+		assert!(kept.ends_with(b"trailer"));
+		assert!(apply(&bytes, &[]).unwrap() == bytes);
+	}
+
+	#[test]
+	fn test_fix_crcs() {
+		let mut bytes = clean_png();
+		let ihdr_crc = 8 + 8 + 6;
+		bytes[ihdr_crc] ^= 0xFF;
+		assert!(Png::try_from(&bytes[..]).is_err());
+
+		let options = ParseOptions {
+			verify_crc: false,
+			..Default::default()
+		};
+		let findings = detect::scan_with(&bytes, &options).unwrap();
+		let fixes = plan(&bytes, &findings).unwrap();
+		assert_eq!(fixes, [Fix::FixCrcs { indices: vec![0] }]);
+		assert_eq!(apply(&bytes, &fixes).unwrap(), clean_png());
+	}
+}
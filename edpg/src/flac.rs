@@ -0,0 +1,179 @@
+// Payloads in FLAC files, for lossless audio as carriers.
+//
+// After `fLaC` come metadata blocks, each a header byte (the top bit set on
+// the last block, the rest the block type), a big endian u24 length and the
+// data, then the audio frames. Decoders skip `APPLICATION` blocks of ids they
+// don't know, so a payload goes into one after the last metadata block:
+// `VANISH_ID`, the chunk type it is labelled with, then the data.
+
+use thiserror::Error;
+
+use crate::chunk_type::ChunkType;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum FlacError {
+	#[error("Not a FLAC file, no `fLaC` marker.")]
+	NotFlac,
+	#[error("Metadata block at offset {0} runs past the end of the file.")]
+	Truncated(usize),
+	#[error("{0} bytes don't fit in a metadata block, the most is {MAX_BLOCK_BYTES}.")]
+	TooLarge(usize),
+}
+
+/// Application id of the blocks vanish writes.
+pub const VANISH_ID: [u8; 4] = *b"vnSH";
+
+/// Largest metadata block, the length is 24 bits.
+pub const MAX_BLOCK_BYTES: usize = (1 << 24) - 1;
+
+const MARKER: &[u8; 4] = b"fLaC";
+const BLOCK_HEADER_BYTES: usize = 4;
+const LAST_BLOCK: u8 = 0x80;
+const APPLICATION: u8 = 2;
+
+/// A metadata block, `offset` is where its header starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRef {
+	pub offset: usize,
+	pub block_type: u8,
+	pub len: usize,
+	pub last: bool,
+}
+
+/// Whether `bytes` starts with the FLAC marker.
+pub fn is_flac(bytes: &[u8]) -> bool {
+	bytes.starts_with(MARKER)
+}
+
+/// The metadata blocks of `bytes`, in file order.
+pub fn blocks(bytes: &[u8]) -> Result<Vec<BlockRef>, FlacError> {
+	if !is_flac(bytes) {
+		return Err(FlacError::NotFlac);
+	}
+
+	let mut blocks = Vec::new();
+	let mut offset = MARKER.len();
+	loop {
+		let header = bytes
+			.get(offset..offset + BLOCK_HEADER_BYTES)
+			.ok_or(FlacError::Truncated(offset))?;
+		let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+		if offset + BLOCK_HEADER_BYTES + len > bytes.len() {
+			return Err(FlacError::Truncated(offset));
+		}
+		let block = BlockRef {
+			offset,
+			block_type: header[0] & !LAST_BLOCK,
+			len,
+			last: header[0] & LAST_BLOCK != 0,
+		};
+		blocks.push(block);
+		if block.last {
+			return Ok(blocks);
+		}
+		offset += BLOCK_HEADER_BYTES + len;
+	}
+}
+
+/// Content of a vanish `APPLICATION` block: chunk type and data.
+fn vanish_content<'a>(bytes: &'a [u8], b: &BlockRef) -> Option<(&'a [u8], &'a [u8])> {
+	if b.block_type != APPLICATION {
+		return None;
+	}
+	let start = b.offset + BLOCK_HEADER_BYTES;
+	let rest = bytes[start..start + b.len].strip_prefix(&VANISH_ID)?;
+	(rest.len() >= 4).then(|| rest.split_at(4))
+}
+
+/// `bytes` with `data` in a vanish `APPLICATION` block labelled
+/// `chunk_type`, after the other metadata blocks.
+pub fn append(bytes: &[u8], chunk_type: &ChunkType, data: &[u8]) -> Result<Vec<u8>, FlacError> {
+	let last = *blocks(bytes)?.last().expect("blocks stop at the last one");
+	let len = VANISH_ID.len() + 4 + data.len();
+	if len > MAX_BLOCK_BYTES {
+		return Err(FlacError::TooLarge(len));
+	}
+	let frames = last.offset + BLOCK_HEADER_BYTES + last.len;
+
+	let mut out = Vec::with_capacity(bytes.len() + BLOCK_HEADER_BYTES + len);
+	out.extend_from_slice(&bytes[..frames]);
+	// the new block is the last one now
+	out[last.offset] &= !LAST_BLOCK;
+	out.push(LAST_BLOCK | APPLICATION);
+	out.extend_from_slice(&(len as u32).to_be_bytes()[1..]);
+	out.extend_from_slice(&VANISH_ID);
+	out.extend_from_slice(&chunk_type.bytes());
+	out.extend_from_slice(data);
+	out.extend_from_slice(&bytes[frames..]);
+
+	Ok(out)
+}
+
+/// Data of the first vanish block labelled `chunk_type`.
+pub fn find<'a>(bytes: &'a [u8], chunk_type: &str) -> Result<Option<&'a [u8]>, FlacError> {
+	Ok(blocks(bytes)?
+		.iter()
+		.filter_map(|b| vanish_content(bytes, b))
+		.find(|(t, _)| *t == chunk_type.as_bytes())
+		.map(|(_, data)| data))
+}
+
+/// Chunk types of all vanish blocks, in file order.
+pub fn chunk_types(bytes: &[u8]) -> Result<Vec<String>, FlacError> {
+	Ok(blocks(bytes)?
+		.iter()
+		.filter_map(|b| vanish_content(bytes, b))
+		.map(|(t, _)| String::from_utf8_lossy(t).into_owned())
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+
+	fn testing_flac() -> Vec<u8> {
+		let mut bytes = b"fLaC".to_vec();
+		// STREAMINFO, contents don't matter here
+		bytes.push(0);
+		bytes.extend_from_slice(&[0, 0, 34]);
+		bytes.extend_from_slice(&[0x11; 34]);
+		// PADDING, last
+		bytes.push(LAST_BLOCK | 1);
+		bytes.extend_from_slice(&[0, 0, 4]);
+		bytes.extend_from_slice(&[0; 4]);
+		// a frame
+		bytes.extend_from_slice(&[0xff, 0xf8, 0x69, 0x08]);
+		bytes
+	}
+
+	#[test]
+	fn test_blocks() {
+		let blocks = blocks(&testing_flac()).unwrap();
+		assert_eq!(blocks.iter().map(|b| b.block_type).collect::<Vec<_>>(), [0, 1]);
+		assert!(blocks[1].last);
+		assert_eq!(super::blocks(b"\x89PNG\r\n\x1a\n"), Err(FlacError::NotFlac));
+		assert_eq!(super::blocks(&testing_flac()[..20]), Err(FlacError::Truncated(4)));
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let flac = testing_flac();
+		let out = append(&flac, &ChunkType::from_str("ruSt").unwrap(), b"hidden").unwrap();
+
+		// frames untouched, after the new block
+		assert!(out.ends_with(&[0xff, 0xf8, 0x69, 0x08]));
+		let blocks = blocks(&out).unwrap();
+		assert_eq!(blocks.iter().map(|b| b.last).collect::<Vec<_>>(), [false, false, true]);
+		assert_eq!(find(&out, "ruSt").unwrap(), Some(&b"hidden"[..]));
+		assert_eq!(find(&out, "nOpe").unwrap(), None);
+
+		let twice = append(&out, &ChunkType::from_str("tWoo").unwrap(), b"").unwrap();
+		assert_eq!(chunk_types(&twice).unwrap(), ["ruSt", "tWoo"]);
+		assert_eq!(
+			append(&flac, &ChunkType::from_str("ruSt").unwrap(), &vec![0; MAX_BLOCK_BYTES]),
+			Err(FlacError::TooLarge(MAX_BLOCK_BYTES + 8))
+		);
+	}
+}
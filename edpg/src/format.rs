@@ -0,0 +1,162 @@
+// Every envelope `encode --encrypt` has written messages in, so what an
+// older vanish hid can still be opened and sealed again the current way, see
+// `upgrade`. Oldest first:
+//
+// - `Encrypted`: salt | nonce | ciphertext, see `crypto::encrypt`. Unchanged
+//   since the start.
+// - `TotpInPlaintext`: `encrypt_with_totp` as first released. `\0OTP` |
+//   secret len | secret | data sealed with `encrypt`, the marker inside the
+//   ciphertext where a plain message could start with it too.
+// - `Totp`: `encrypt_with_totp` now, `crypto::TOTP_MAGIC` in front.
+//
+// The manifests of embedded files (`payload`) have versions too, both of
+// them still written, so there is nothing to upgrade there.
+
+use std::fmt::Display;
+
+use crate::crypto::{self, totp::Totp, CryptoError};
+
+/// Marker `encrypt_with_totp` used to put in front of the plaintext.
+const PLAINTEXT_TOTP_MAGIC: [u8; 4] = *b"\0OTP";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+	Encrypted,
+	TotpInPlaintext,
+	Totp,
+}
+
+impl Version {
+	/// Whether `encode` still writes it.
+	pub fn is_current(self) -> bool {
+		self != Self::TotpInPlaintext
+	}
+}
+
+impl Display for Version {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Encrypted => write!(f, "encrypted"),
+			Self::TotpInPlaintext => write!(f, "encrypted with a one time password (old format)"),
+			Self::Totp => write!(f, "encrypted with a one time password"),
+		}
+	}
+}
+
+/// A message as `open` found it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Opened {
+	pub version: Version,
+	/// The one time password it was sealed with, if any.
+	pub totp: Option<Totp>,
+	pub data: Vec<u8>,
+}
+
+/// Opens `sealed`, whichever version wrote it. No one time password is asked
+/// for, the secret comes back instead so it can be sealed again.
+pub fn open(passphrase: &[u8], sealed: &[u8]) -> Result<Opened, CryptoError> {
+	if let Some(rest) = sealed.strip_prefix(&crypto::TOTP_MAGIC) {
+		match crypto::open(passphrase, rest, &crypto::TOTP_MAGIC) {
+			Ok(plaintext) => return opened(Version::Totp, &plaintext),
+			// a salt that starts like the magic, or the wrong passphrase
+			Err(CryptoError::DecryptionFailed | CryptoError::ShortInput(_)) => {},
+			Err(e) => return Err(e),
+		}
+	}
+
+	let plaintext = crypto::decrypt(passphrase, sealed)?;
+	match plaintext.strip_prefix(&PLAINTEXT_TOTP_MAGIC) {
+		// the old decoder read a plain message starting like this the same way
+		Some(rest) => opened(Version::TotpInPlaintext, rest),
+		None => Ok(Opened {
+			version: Version::Encrypted,
+			totp: None,
+			data: plaintext,
+		}),
+	}
+}
+
+fn opened(version: Version, plaintext: &[u8]) -> Result<Opened, CryptoError> {
+	let (secret, data) = crypto::split_secret(plaintext)?;
+	Ok(Opened {
+		version,
+		totp: (!secret.is_empty()).then(|| Totp::new(secret.to_vec())),
+		data: data.to_vec(),
+	})
+}
+
+/// `sealed` in the current envelope, under `new_passphrase` if given. The
+/// version it was in comes along, `None` if it is current already and keeps
+/// its passphrase.
+pub fn upgrade(
+	passphrase: &[u8],
+	sealed: &[u8],
+	new_passphrase: Option<&[u8]>,
+) -> Result<Option<(Version, Vec<u8>)>, CryptoError> {
+	let opened = open(passphrase, sealed)?;
+	if opened.version.is_current() && new_passphrase.is_none() {
+		return Ok(None);
+	}
+
+	let passphrase = new_passphrase.unwrap_or(passphrase);
+	let sealed = match &opened.totp {
+		Some(totp) => crypto::encrypt_with_totp(passphrase, &opened.data, totp)?,
+		None => crypto::encrypt(passphrase, &opened.data)?,
+	};
+	Ok(Some((opened.version, sealed)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// What `encrypt_with_totp` wrote before the marker moved out.
+	fn plaintext_totp(passphrase: &[u8], data: &[u8], totp: &Totp) -> Vec<u8> {
+		let secret = totp.secret();
+		let plaintext = [&PLAINTEXT_TOTP_MAGIC[..], &[secret.len() as u8], secret, data].concat();
+		crypto::encrypt(passphrase, &plaintext).unwrap()
+	}
+
+	#[test]
+	fn test_open_versions() {
+		let totp = Totp::generate();
+		let old = open(b"pass", &plaintext_totp(b"pass", b"hidden", &totp)).unwrap();
+		assert_eq!(old, Opened {
+			version: Version::TotpInPlaintext,
+			totp: Some(totp.clone()),
+			data: b"hidden".to_vec(),
+		});
+
+		let current = crypto::encrypt_with_totp(b"pass", b"hidden", &totp).unwrap();
+		assert_eq!(open(b"pass", &current).unwrap().version, Version::Totp);
+		let plain = crypto::encrypt(b"pass", b"hidden").unwrap();
+		assert_eq!(open(b"pass", &plain).unwrap().totp, None);
+		assert_eq!(open(b"nope", &plain), Err(CryptoError::DecryptionFailed));
+	}
+
+	#[test]
+	fn test_upgrade() {
+		let totp = Totp::generate();
+		let (version, sealed) = upgrade(b"pass", &plaintext_totp(b"pass", b"hidden", &totp), None)
+			.unwrap()
+			.unwrap();
+		assert_eq!(version, Version::TotpInPlaintext);
+		assert_eq!(sealed[..4], crypto::TOTP_MAGIC);
+		assert_eq!(
+			crypto::decrypt_with_code(b"pass", &sealed, None),
+			Err(CryptoError::TotpRequired)
+		);
+		let now = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)
+			.unwrap()
+			.as_secs();
+		let code = totp.code_at(now);
+		assert_eq!(crypto::decrypt_with_code(b"pass", &sealed, Some(code)).unwrap(), b"hidden");
+
+		// current ones are only sealed again for a new passphrase
+		assert_eq!(upgrade(b"pass", &sealed, None), Ok(None));
+		let (version, sealed) = upgrade(b"pass", &sealed, Some(b"new")).unwrap().unwrap();
+		assert_eq!(version, Version::Totp);
+		assert_eq!(open(b"new", &sealed).unwrap().totp, Some(totp));
+	}
+}
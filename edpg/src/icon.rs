@@ -0,0 +1,350 @@
+// Icon files, ICO (Windows) and ICNS (macOS). Both are a list of images and
+// modern ones store the larger sizes as complete pngs, which is where chunks
+// can go.
+//
+// ICO: a 6 byte header, a 16 byte little endian directory entry per image,
+// then the image data. ICNS: `icns` and a big endian length, then elements of
+// a 4 byte type, a big endian length (header included) and the data.
+
+use thiserror::Error;
+
+use crate::png::Png;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum IconError {
+	#[error("Not an ICO or ICNS file.")]
+	InvalidHeader,
+	#[error("Icon is cut short, image {0} runs past the end of the file.")]
+	Truncated(usize),
+	#[error("Icon has no image {0}.")]
+	NoImage(usize),
+	#[error("Image {0} of the icon is not a png.")]
+	NotPng(usize),
+	#[error("Icon has no png image.")]
+	NoPng,
+	#[error("Image {0} is too large for an icon.")]
+	TooLarge(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+	Ico,
+	Icns,
+}
+
+const ICO_HEADER_BYTES: usize = 6;
+const ICO_ENTRY_BYTES: usize = 16;
+const ICNS_HEADER_BYTES: usize = 8;
+const ICNS_TOC: &[u8; 4] = b"TOC ";
+
+#[derive(Debug, Clone)]
+pub struct Image {
+	/// ICO: the directory entry minus size and offset (dimensions, colors,
+	/// planes, bit depth). ICNS: the element type, padded with zeros.
+	tag: [u8; 8],
+	data: Vec<u8>,
+}
+
+impl Image {
+	pub fn data(&self) -> &[u8] {
+		&self.data
+	}
+
+	pub fn is_png(&self) -> bool {
+		self.data.starts_with(&Png::STANDARD_HEADER)
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Icon {
+	kind: Kind,
+	/// ICO: reserved and type fields of the header, the type tells icons
+	/// from cursors.
+	header: [u8; 4],
+	images: Vec<Image>,
+}
+
+/// Whether `bytes` starts like an ICO or ICNS file.
+pub fn is_icon(bytes: &[u8]) -> bool {
+	matches!(bytes.get(..4), Some(b"icns" | [0, 0, 1 | 2, 0]))
+}
+
+fn u32_le(bytes: &[u8]) -> usize {
+	u32::from_le_bytes(bytes[..4].try_into().expect("slice is 4 bytes")) as usize
+}
+
+fn u32_be(bytes: &[u8]) -> usize {
+	u32::from_be_bytes(bytes[..4].try_into().expect("slice is 4 bytes")) as usize
+}
+
+impl Icon {
+	pub fn parse(bytes: &[u8]) -> Result<Icon, IconError> {
+		match bytes.get(..4) {
+			Some(b"icns") => Self::parse_icns(bytes),
+			Some(header @ [0, 0, 1 | 2, 0]) => Self::parse_ico(bytes, header),
+			_ => Err(IconError::InvalidHeader),
+		}
+	}
+
+	fn parse_ico(bytes: &[u8], header: &[u8]) -> Result<Icon, IconError> {
+		let count = bytes
+			.get(4..ICO_HEADER_BYTES)
+			.map(|c| u16::from_le_bytes([c[0], c[1]]) as usize)
+			.ok_or(IconError::InvalidHeader)?;
+
+		let images = (0..count)
+			.map(|i| {
+				let entry = bytes
+					.get(ICO_HEADER_BYTES + i * ICO_ENTRY_BYTES..)
+					.and_then(|rest| rest.get(..ICO_ENTRY_BYTES))
+					.ok_or(IconError::Truncated(i))?;
+				let (size, offset) = (u32_le(&entry[8..]), u32_le(&entry[12..]));
+				let data = bytes
+					.get(offset..)
+					.and_then(|rest| rest.get(..size))
+					.ok_or(IconError::Truncated(i))?;
+
+				Ok(Image {
+					tag: entry[..8].try_into().expect("slice is 8 bytes"),
+					data: data.to_vec(),
+				})
+			})
+			.collect::<Result<_, _>>()?;
+
+		Ok(Icon {
+			kind: Kind::Ico,
+			header: header.try_into().expect("slice is 4 bytes"),
+			images,
+		})
+	}
+
+	fn parse_icns(bytes: &[u8]) -> Result<Icon, IconError> {
+		let len = bytes
+			.get(4..ICNS_HEADER_BYTES)
+			.map(u32_be)
+			.ok_or(IconError::InvalidHeader)?;
+		let bytes = bytes.get(..len).unwrap_or(bytes);
+
+		let mut images = Vec::new();
+		let mut rest = &bytes[ICNS_HEADER_BYTES..];
+		while !rest.is_empty() {
+			let index = images.len();
+			let element = rest
+				.get(..ICNS_HEADER_BYTES)
+				.ok_or(IconError::Truncated(index))?;
+			let len = u32_be(&element[4..]);
+			let data = rest
+				.get(ICNS_HEADER_BYTES..len.max(ICNS_HEADER_BYTES))
+				.ok_or(IconError::Truncated(index))?;
+
+			let mut tag = [0; 8];
+			tag[..4].copy_from_slice(&element[..4]);
+			images.push(Image {
+				tag,
+				data: data.to_vec(),
+			});
+			rest = &rest[ICNS_HEADER_BYTES + data.len()..];
+		}
+
+		Ok(Icon {
+			kind: Kind::Icns,
+			header: *b"icns",
+			images,
+		})
+	}
+
+	pub fn kind(&self) -> Kind {
+		self.kind
+	}
+
+	pub fn images(&self) -> &[Image] {
+		&self.images
+	}
+
+	/// Index of the first image stored as a png.
+	pub fn first_png(&self) -> Option<usize> {
+		self.images.iter().position(Image::is_png)
+	}
+
+	/// Bytes of image `index`, which has to be a png.
+	pub fn png(&self, index: usize) -> Result<&[u8], IconError> {
+		let image = self.images.get(index).ok_or(IconError::NoImage(index))?;
+		match image.is_png() {
+			true => Ok(image.data()),
+			false => Err(IconError::NotPng(index)),
+		}
+	}
+
+	/// Swaps the data of image `index` for `data`, returning the old one.
+	/// Offsets and lengths are worked out again by `to_bytes`.
+	pub fn replace(&mut self, index: usize, data: Vec<u8>) -> Result<Vec<u8>, IconError> {
+		if u32::try_from(data.len()).is_err() {
+			return Err(IconError::TooLarge(index));
+		}
+		let image = self.images.get_mut(index).ok_or(IconError::NoImage(index))?;
+		Ok(std::mem::replace(&mut image.data, data))
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		match self.kind {
+			Kind::Ico => self.ico_bytes(),
+			Kind::Icns => self.icns_bytes(),
+		}
+	}
+
+	fn ico_bytes(&self) -> Vec<u8> {
+		let directory = ICO_HEADER_BYTES + self.images.len() * ICO_ENTRY_BYTES;
+		let data_len: usize = self.images.iter().map(|i| i.data.len()).sum();
+		let mut out = Vec::with_capacity(directory + data_len);
+
+		out.extend_from_slice(&self.header);
+		out.extend_from_slice(&(self.images.len() as u16).to_le_bytes());
+		let mut offset = directory;
+		for image in &self.images {
+			out.extend_from_slice(&image.tag);
+			out.extend_from_slice(&(image.data.len() as u32).to_le_bytes());
+			out.extend_from_slice(&(offset as u32).to_le_bytes());
+			offset += image.data.len();
+		}
+		for image in &self.images {
+			out.extend_from_slice(&image.data);
+		}
+
+		out
+	}
+
+	fn icns_bytes(&self) -> Vec<u8> {
+		let element_len = |image: &Image| ICNS_HEADER_BYTES + image.data.len();
+		// the table of contents lists every other element with its length,
+		// it has to follow any size change
+		let toc: Vec<u8> = self
+			.images
+			.iter()
+			.filter(|i| &i.tag[..4] != ICNS_TOC)
+			.flat_map(|i| {
+				let len = element_len(i) as u32;
+				i.tag[..4].iter().copied().chain(len.to_be_bytes())
+			})
+			.collect();
+
+		let total: usize = ICNS_HEADER_BYTES
+			+ self
+				.images
+				.iter()
+				.map(|i| match &i.tag[..4] == ICNS_TOC {
+					true => ICNS_HEADER_BYTES + toc.len(),
+					false => element_len(i),
+				})
+				.sum::<usize>();
+
+		let mut out = Vec::with_capacity(total);
+		out.extend_from_slice(&self.header);
+		out.extend_from_slice(&(total as u32).to_be_bytes());
+		for image in &self.images {
+			let data = match &image.tag[..4] == ICNS_TOC {
+				true => &toc,
+				false => &image.data,
+			};
+			out.extend_from_slice(&image.tag[..4]);
+			out.extend_from_slice(&((ICNS_HEADER_BYTES + data.len()) as u32).to_be_bytes());
+			out.extend_from_slice(data);
+		}
+
+		out
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::chunk::Chunk;
+	use crate::chunk_type::ChunkType;
+
+	fn png_bytes(text: &str) -> Vec<u8> {
+		let chunk = |t: &str, d: &str| Chunk::new(ChunkType::from_str(t).unwrap(), d.into());
+		Png::from_chunks(vec![chunk("IHDR", "header"), chunk("tEXt", text), chunk("IEND", "")])
+			.as_bytes()
+	}
+
+	fn testing_ico() -> Vec<u8> {
+		let (bmp, png) = (b"BMPDATA".to_vec(), png_bytes("a\0b"));
+		let mut bytes = vec![0, 0, 1, 0, 2, 0];
+		let mut offset = 6 + 2 * 16;
+		for (side, data) in [(16, &bmp), (0, &png)] {
+			bytes.extend_from_slice(&[side, side, 0, 0, 1, 0, 32, 0]);
+			bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+			bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+			offset += data.len();
+		}
+		bytes.extend_from_slice(&bmp);
+		bytes.extend_from_slice(&png);
+		bytes
+	}
+
+	fn testing_icns() -> Vec<u8> {
+		let png = png_bytes("a\0b");
+		let mut elements = Vec::new();
+		elements.extend_from_slice(b"TOC \0\0\0\x10ic10");
+		elements.extend_from_slice(&(8 + png.len() as u32).to_be_bytes());
+		elements.extend_from_slice(b"ic10");
+		elements.extend_from_slice(&(8 + png.len() as u32).to_be_bytes());
+		elements.extend_from_slice(&png);
+
+		let mut bytes = b"icns".to_vec();
+		bytes.extend_from_slice(&(8 + elements.len() as u32).to_be_bytes());
+		bytes.extend_from_slice(&elements);
+		bytes
+	}
+
+	#[test]
+	fn test_ico_round_trip() {
+		let bytes = testing_ico();
+		assert!(is_icon(&bytes));
+		let icon = Icon::parse(&bytes).unwrap();
+		assert_eq!(icon.kind(), Kind::Ico);
+		assert_eq!(icon.images().len(), 2);
+		assert_eq!(icon.first_png(), Some(1));
+		assert_eq!(icon.png(0), Err(IconError::NotPng(0)));
+		assert_eq!(icon.png(2), Err(IconError::NoImage(2)));
+		assert_eq!(icon.to_bytes(), bytes);
+	}
+
+	#[test]
+	fn test_ico_replace() {
+		let mut icon = Icon::parse(&testing_ico()).unwrap();
+		let bigger = png_bytes("longer\0text");
+		icon.replace(1, bigger.clone()).unwrap();
+
+		let icon = Icon::parse(&icon.to_bytes()).unwrap();
+		assert_eq!(icon.png(1).unwrap(), bigger);
+		assert_eq!(icon.images()[0].data(), b"BMPDATA");
+	}
+
+	#[test]
+	fn test_icns_replace_updates_toc() {
+		let bytes = testing_icns();
+		let mut icon = Icon::parse(&bytes).unwrap();
+		assert_eq!(icon.kind(), Kind::Icns);
+		assert_eq!(icon.to_bytes(), bytes);
+
+		let bigger = png_bytes("longer\0text");
+		icon.replace(1, bigger.clone()).unwrap();
+		let bytes = icon.to_bytes();
+		let icon = Icon::parse(&bytes).unwrap();
+		assert_eq!(icon.png(1).unwrap(), bigger);
+		assert_eq!(u32_be(&bytes[4..]), bytes.len());
+		assert_eq!(u32_be(&icon.images()[0].data()[4..]), 8 + bigger.len());
+	}
+
+	#[test]
+	fn test_invalid() {
+		assert!(!is_icon(&png_bytes("a\0b")));
+		assert!(matches!(Icon::parse(b"nope"), Err(IconError::InvalidHeader)));
+
+		let mut cut = testing_ico();
+		cut.truncate(cut.len() - 1);
+		assert!(matches!(Icon::parse(&cut), Err(IconError::Truncated(1))));
+	}
+}
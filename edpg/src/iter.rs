@@ -0,0 +1,86 @@
+// Lazy filters over chunks, eg: `png.chunks().of_type("tEXt").larger_than(64)`
+
+use crate::chunk::Chunk;
+
+/// Combinators for anything yielding `&Chunk`, slices included. Nothing is
+/// evaluated until the result is iterated.
+pub trait ChunkIter<'a>: IntoIterator<Item = &'a Chunk> + Sized {
+	/// Chunks whose type is exactly `chunk_type`.
+	fn of_type(self, chunk_type: &'a str) -> impl Iterator<Item = &'a Chunk> {
+		let chunk_type = chunk_type.as_bytes();
+		self.into_iter()
+			.filter(move |c| c.chunk_type().bytes() == chunk_type)
+	}
+
+	/// Chunks that are not needed to display the image.
+	fn ancillary(self) -> impl Iterator<Item = &'a Chunk> {
+		self.into_iter().filter(|c| !c.chunk_type().is_critical())
+	}
+
+	/// Chunks that are needed to display the image.
+	fn critical(self) -> impl Iterator<Item = &'a Chunk> {
+		self.into_iter().filter(|c| c.chunk_type().is_critical())
+	}
+
+	/// Chunks holding more than `len` bytes of data.
+	fn larger_than(self, len: usize) -> impl Iterator<Item = &'a Chunk> {
+		self.into_iter().filter(move |c| c.data().len() > len)
+	}
+
+	/// The data of every chunk run through `f`.
+	fn map_data<T, F>(self, mut f: F) -> impl Iterator<Item = T>
+	where
+		F: FnMut(&'a [u8]) -> T,
+	{
+		self.into_iter().map(move |c| f(c.data()))
+	}
+}
+
+impl<'a, I> ChunkIter<'a> for I where I: IntoIterator<Item = &'a Chunk> {}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::chunk_type::ChunkType;
+	use crate::png::Png;
+
+	fn testing_png() -> Png {
+		let chunk = |t: &str, d: &str| Chunk::new(ChunkType::from_str(t).unwrap(), d.into());
+		Png::from_chunks(vec![
+			chunk("IHDR", "header"),
+			chunk("tEXt", "short"),
+			chunk("tEXt", "a longer text"),
+			chunk("ruSt", "a longer secret"),
+			chunk("IEND", ""),
+		])
+	}
+
+	#[test]
+	fn test_of_type() {
+		let png = testing_png();
+		assert_eq!(png.chunks().of_type("tEXt").count(), 2);
+		assert_eq!(png.chunks().of_type("zTXt").count(), 0);
+	}
+
+	#[test]
+	fn test_ancillary_and_critical() {
+		let png = testing_png();
+		assert_eq!(png.chunks().ancillary().count(), 3);
+		assert_eq!(png.chunks().critical().count(), 2);
+	}
+
+	#[test]
+	fn test_chained() {
+		let png = testing_png();
+		let texts: Vec<String> = png
+			.chunks()
+			.ancillary()
+			.larger_than(5)
+			.of_type("tEXt")
+			.map_data(|d| String::from_utf8_lossy(d).into_owned())
+			.collect();
+		assert_eq!(texts, ["a longer text"]);
+	}
+}
@@ -1,3 +1,35 @@
+pub mod analysis;
+pub mod bmff;
+#[cfg(feature = "c2pa")]
+pub mod c2pa;
 pub mod chunk;
 pub mod chunk_type;
+#[cfg(feature = "zstd")]
+pub mod compress;
+#[cfg(feature = "testing")]
+pub mod corpus;
+pub mod crypto;
+pub mod detect;
+pub mod exif;
+pub mod fix;
+pub mod flac;
+pub mod format;
+pub mod icon;
+pub mod iter;
+pub mod mutate;
+pub mod payload;
+pub mod pdf;
 pub mod png;
+pub mod policy;
+pub mod polyglot;
+pub mod provenance;
+pub mod repro;
+pub mod roundtrip;
+pub mod report;
+pub mod search;
+pub mod signature;
+pub mod steg;
+pub mod svg;
+pub mod text;
+pub mod textstego;
+pub mod wav;
@@ -0,0 +1,171 @@
+// Deliberately broken pngs, for exercising other decoders.
+
+use std::fmt::Display;
+
+use crate::chunk::Chunk;
+use crate::png::Png;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MutationKind {
+	/// CRC of the target chunk no longer matches.
+	BadCrc,
+	/// File ends halfway through the target chunk.
+	TruncatedChunk,
+	/// Length field of the target chunk points past the end of the file.
+	OversizeLength,
+	/// Every IEND chunk is dropped.
+	MissingIend,
+	/// Target chunk swaps places with the one after it.
+	ReorderedChunks,
+}
+
+impl MutationKind {
+	pub const ALL: [MutationKind; 5] = [
+		Self::BadCrc,
+		Self::TruncatedChunk,
+		Self::OversizeLength,
+		Self::MissingIend,
+		Self::ReorderedChunks,
+	];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::BadCrc => "bad-crc",
+			Self::TruncatedChunk => "truncated-chunk",
+			Self::OversizeLength => "oversize-length",
+			Self::MissingIend => "missing-iend",
+			Self::ReorderedChunks => "reordered-chunks",
+		}
+	}
+}
+
+impl Display for MutationKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mutation {
+	pub kind: MutationKind,
+	/// Index of the chunk the mutation targets.
+	pub chunk: usize,
+}
+
+/// Lists `count` mutations, cycling through every kind and walking the
+/// target over all chunks. Same input, same plan.
+pub fn plan(png: &Png, count: usize) -> Vec<Mutation> {
+	let chunks = png.chunks().len().max(1);
+	let kinds = MutationKind::ALL.len();
+
+	(0..count)
+		.map(|i| Mutation {
+			kind: MutationKind::ALL[i % kinds],
+			chunk: (i / kinds) % chunks,
+		})
+		.collect()
+}
+
+/// Serializes `png` with `mutation` applied.
+pub fn apply(png: &Png, mutation: Mutation) -> Vec<u8> {
+	let mut chunks: Vec<Vec<u8>> = png.chunks().iter().map(Chunk::as_bytes).collect();
+	let target = mutation.chunk.min(chunks.len().saturating_sub(1));
+
+	match mutation.kind {
+		MutationKind::BadCrc => {
+			if let Some(crc) = chunks.get_mut(target).and_then(|c| c.last_mut()) {
+				*crc ^= 0xff;
+			}
+		},
+		MutationKind::TruncatedChunk => {
+			chunks.truncate(target + 1);
+			if let Some(chunk) = chunks.last_mut() {
+				chunk.truncate(chunk.len() / 2);
+			}
+		},
+		MutationKind::OversizeLength => {
+			let rest: usize = chunks.iter().skip(target).map(Vec::len).sum();
+			let length = u32::try_from(rest + 1).unwrap_or(u32::MAX);
+			if let Some(chunk) = chunks.get_mut(target) {
+				chunk[..Chunk::LENGTH_BYTES].copy_from_slice(&length.to_be_bytes());
+			}
+		},
+		MutationKind::MissingIend => {
+			chunks.retain(|c| c[Chunk::LENGTH_BYTES..][..Chunk::CHUNK_TYPE_BYTES] != *b"IEND");
+		},
+		MutationKind::ReorderedChunks => {
+			if chunks.len() > 1 {
+				let other = (target + 1) % chunks.len();
+				chunks.swap(target, other);
+			}
+		},
+	}
+
+	Png::STANDARD_HEADER
+		.iter()
+		.copied()
+		.chain(chunks.into_iter().flatten())
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::chunk_type::ChunkType;
+
+	fn testing_png() -> Png {
+		let chunk = |t: &str, d: &str| Chunk::new(ChunkType::from_str(t).unwrap(), d.into());
+		Png::from_chunks(vec![
+			chunk("FrSt", "I am the first chunk"),
+			chunk("miDl", "I am another chunk"),
+			chunk("IEND", ""),
+		])
+	}
+
+	#[test]
+	fn test_plan_is_systematic() {
+		let png = testing_png();
+		let plan = plan(&png, 12);
+
+		assert_eq!(plan.len(), 12);
+		assert_eq!(plan[0], Mutation { kind: MutationKind::BadCrc, chunk: 0 });
+		assert_eq!(plan[5], Mutation { kind: MutationKind::BadCrc, chunk: 1 });
+		assert_eq!(plan[11], Mutation { kind: MutationKind::TruncatedChunk, chunk: 2 });
+	}
+
+	#[test]
+	fn test_broken_mutations_fail_to_parse() {
+		let png = testing_png();
+		for kind in [
+			MutationKind::BadCrc,
+			MutationKind::TruncatedChunk,
+			MutationKind::OversizeLength,
+		] {
+			let bytes = apply(&png, Mutation { kind, chunk: 1 });
+			assert!(Png::try_from(bytes.as_ref()).is_err(), "{kind} parsed");
+		}
+	}
+
+	#[test]
+	fn test_missing_iend() {
+		let bytes = apply(&testing_png(), Mutation {
+			kind: MutationKind::MissingIend,
+			chunk: 0,
+		});
+		let png = Png::try_from(bytes.as_ref()).unwrap();
+		assert!(png.chunk_by_type("IEND").is_none());
+	}
+
+	#[test]
+	fn test_reordered_chunks() {
+		let bytes = apply(&testing_png(), Mutation {
+			kind: MutationKind::ReorderedChunks,
+			chunk: 0,
+		});
+		let png = Png::try_from(bytes.as_ref()).unwrap();
+		assert_eq!(png.find_nth_by_type("miDl", 0), Some(0));
+		assert_eq!(png.find_nth_by_type("FrSt", 0), Some(1));
+	}
+}
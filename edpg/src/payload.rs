@@ -0,0 +1,589 @@
+// Payloads spread over as many chunks as they need.
+
+use std::convert::Infallible;
+use std::io::Write;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+/// Default amount of payload data per chunk. Keeps chunks well clear of the
+/// sizes some viewers choke on.
+pub const DEFAULT_CHUNK_DATA_BYTES: usize = 64 * 1024;
+
+/// Incremental encoder, payload bytes go in piece by piece and every chunk is
+/// written to the inner writer as soon as it is full. Only one chunk worth of
+/// data is ever buffered; a slow inner writer blocks `write`, which is the
+/// backpressure.
+#[derive(Debug)]
+pub struct PayloadWriter<W: Write> {
+	inner: W,
+	chunk_type: ChunkType,
+	chunk_data_bytes: usize,
+	buf: Vec<u8>,
+	chunks_written: usize,
+}
+
+impl<W: Write> PayloadWriter<W> {
+	pub fn new(inner: W, chunk_type: ChunkType) -> Self {
+		Self::with_chunk_size(inner, chunk_type, DEFAULT_CHUNK_DATA_BYTES)
+	}
+
+	/// `chunk_data_bytes` is clamped to at least 1.
+	pub fn with_chunk_size(inner: W, chunk_type: ChunkType, chunk_data_bytes: usize) -> Self {
+		let chunk_data_bytes = chunk_data_bytes.max(1);
+		Self {
+			inner,
+			chunk_type,
+			chunk_data_bytes,
+			buf: Vec::with_capacity(chunk_data_bytes),
+			chunks_written: 0,
+		}
+	}
+
+	pub fn chunks_written(&self) -> usize {
+		self.chunks_written
+	}
+
+	fn emit(&mut self) -> std::io::Result<()> {
+		let chunk = Chunk::new(self.chunk_type, std::mem::take(&mut self.buf));
+		self.inner.write_all(&chunk.as_bytes())?;
+		self.buf = Vec::with_capacity(self.chunk_data_bytes);
+		self.chunks_written += 1;
+		Ok(())
+	}
+
+	/// Writes out whatever is still buffered as a last, shorter chunk and hands
+	/// back the inner writer.
+	pub fn finish(mut self) -> std::io::Result<W> {
+		if !self.buf.is_empty() {
+			self.emit()?;
+		}
+		self.inner.flush()?;
+		Ok(self.inner)
+	}
+}
+
+impl<W: Write> Write for PayloadWriter<W> {
+	fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+		let room = self.chunk_data_bytes - self.buf.len();
+		let taken = room.min(bytes.len());
+		self.buf.extend_from_slice(&bytes[..taken]);
+
+		if self.buf.len() == self.chunk_data_bytes {
+			self.emit()?;
+		}
+
+		Ok(taken)
+	}
+
+	/// Flushes the inner writer. A partially filled chunk stays buffered, it
+	/// only goes out on `finish`.
+	fn flush(&mut self) -> std::io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+/// Data of every `chunk_type` chunk of `png` joined in file order, the
+/// payload `PayloadWriter` split up.
+pub fn assemble(png: &Png, chunk_type: &str) -> Vec<u8> {
+	fragments(png, chunk_type).concat()
+}
+
+fn fragments<'a>(png: &'a Png, chunk_type: &str) -> Vec<&'a [u8]> {
+	png.chunks()
+		.iter()
+		.filter(|c| c.chunk_type().to_string() == chunk_type)
+		.map(Chunk::data)
+		.collect()
+}
+
+/// Like `assemble`, with every fragment run through `f` first (decryption,
+/// verification...). The fragments are handed out in contiguous runs to up to
+/// `threads` scoped threads, the results are joined in file order whichever
+/// thread finishes first. On failure the error of the earliest failing
+/// fragment is returned.
+pub fn assemble_with<F, E>(
+	png: &Png,
+	chunk_type: &str,
+	threads: NonZeroUsize,
+	f: F,
+) -> Result<Vec<u8>, E>
+where
+	F: Fn(&[u8]) -> Result<Vec<u8>, E> + Sync,
+	E: Send,
+{
+	Ok(map_fragments(&fragments(png, chunk_type), threads, f)?.concat())
+}
+
+/// `f` of every fragment on up to `threads` scoped threads, in file order.
+fn map_fragments<F, T, E>(fragments: &[&[u8]], threads: NonZeroUsize, f: F) -> Result<Vec<T>, E>
+where
+	F: Fn(&[u8]) -> Result<T, E> + Sync,
+	T: Send,
+	E: Send,
+{
+	if fragments.is_empty() {
+		return Ok(Vec::new());
+	}
+	let per_thread = fragments.len().div_ceil(threads.get());
+
+	let runs: Vec<Result<Vec<T>, E>> = std::thread::scope(|scope| {
+		let handles: Vec<_> = fragments
+			.chunks(per_thread)
+			.map(|run| scope.spawn(|| run.iter().map(|fragment| f(fragment)).collect()))
+			.collect();
+		handles
+			.into_iter()
+			.map(|h| h.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+			.collect()
+	});
+
+	let mut mapped = Vec::with_capacity(fragments.len());
+	for run in runs {
+		mapped.extend(run?);
+	}
+	Ok(mapped)
+}
+
+/// crc32 of `a` followed by `b` from the crc32 of each, zlib's
+/// `crc32_combine`. Feeds `len_b` zero bits through the crc one power of two
+/// at a time, so it's O(log len_b) whatever the length.
+fn crc32_combine(mut crc_a: u32, crc_b: u32, mut len_b: u64) -> u32 {
+	fn times(matrix: &[u32; 32], mut vec: u32) -> u32 {
+		let mut sum = 0;
+		for row in matrix {
+			if vec == 0 {
+				break;
+			}
+			if vec & 1 != 0 {
+				sum ^= row;
+			}
+			vec >>= 1;
+		}
+		sum
+	}
+	fn square(matrix: &[u32; 32]) -> [u32; 32] {
+		matrix.map(|row| times(matrix, row))
+	}
+
+	// one zero bit, then squared up to a zero byte
+	let mut odd = [0; 32];
+	odd[0] = 0xedb8_8320;
+	for (n, row) in odd.iter_mut().enumerate().skip(1) {
+		*row = 1 << (n - 1);
+	}
+	let even = square(&odd);
+	let mut odd = square(&even);
+	let mut even;
+
+	while len_b != 0 {
+		even = square(&odd);
+		if len_b & 1 != 0 {
+			crc_a = times(&even, crc_a);
+		}
+		len_b >>= 1;
+		if len_b == 0 {
+			break;
+		}
+		odd = square(&even);
+		if len_b & 1 != 0 {
+			crc_a = times(&odd, crc_a);
+		}
+		len_b >>= 1;
+	}
+	crc_a ^ crc_b
+}
+
+/// Type of the chunk a `Manifest` is stored in, one per embedded file.
+pub const MANIFEST_CHUNK_TYPE: &str = "vnMf";
+
+const MANIFEST_VERSION: u8 = 1;
+/// Adds the encoding after the crc, only written for encoded files so
+/// plain ones still read with older versions.
+const ENCODED_MANIFEST_VERSION: u8 = 2;
+/// version, chunk type, size, crc, then the name.
+const MANIFEST_HEADER_BYTES: usize = 1 + 4 + 8 + 4;
+/// encoding, dictionary id.
+const ENCODING_BYTES: usize = 1 + 4;
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PayloadError {
+	#[error("No file is hidden in `{0}` chunks.")]
+	NoManifest(String),
+	#[error("Manifest of the `{0}` chunks is corrupt.")]
+	BadManifest(String),
+	#[error("Expected {expected} bytes, the chunks hold {found}.")]
+	SizeMismatch { expected: u64, found: u64 },
+	#[error("Checksum mismatch, expected {expected:08x} got {found:08x}.")]
+	ChecksumMismatch { expected: u32, found: u32 },
+}
+
+/// How the file is stored in the chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+	/// As it is.
+	#[default]
+	Plain,
+	/// Compressed, see `compress`, with the id of the dictionary if one was
+	/// used. The same dictionary is needed to get the file back.
+	Zstd { dictionary: Option<u32> },
+}
+
+/// What `embed` hid: the original file name, the size and crc32 of what is
+/// in the chunks, so `extract` can tell a complete file from a truncated or
+/// reordered one, and how to turn that back into the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+	pub chunk_type: ChunkType,
+	pub name: String,
+	pub size: u64,
+	pub checksum: u32,
+	pub encoding: Encoding,
+}
+
+impl Manifest {
+	/// `payload` as it goes into the chunks, already encoded.
+	pub fn new(chunk_type: ChunkType, name: &str, payload: &[u8], encoding: Encoding) -> Manifest {
+		Manifest {
+			chunk_type,
+			name: name.to_owned(),
+			size: payload.len() as u64,
+			checksum: CRC.checksum(payload),
+			encoding,
+		}
+	}
+
+	pub fn as_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(MANIFEST_HEADER_BYTES + ENCODING_BYTES + self.name.len());
+		bytes.push(match self.encoding {
+			Encoding::Plain => MANIFEST_VERSION,
+			Encoding::Zstd { .. } => ENCODED_MANIFEST_VERSION,
+		});
+		bytes.extend_from_slice(&self.chunk_type.bytes());
+		bytes.extend_from_slice(&self.size.to_be_bytes());
+		bytes.extend_from_slice(&self.checksum.to_be_bytes());
+		if let Encoding::Zstd { dictionary } = self.encoding {
+			bytes.push(1);
+			// zstd never gives a dictionary id 0
+			bytes.extend_from_slice(&dictionary.unwrap_or(0).to_be_bytes());
+		}
+		bytes.extend_from_slice(self.name.as_bytes());
+		bytes
+	}
+
+	/// None for anything that isn't a manifest this version wrote.
+	pub fn parse(bytes: &[u8]) -> Option<Manifest> {
+		if bytes.len() < MANIFEST_HEADER_BYTES {
+			return None;
+		}
+		let (header, rest) = bytes.split_at(MANIFEST_HEADER_BYTES);
+		let (encoding, name) = match header[0] {
+			MANIFEST_VERSION => (Encoding::Plain, rest),
+			ENCODED_MANIFEST_VERSION if rest.len() >= ENCODING_BYTES => {
+				let (encoding, name) = rest.split_at(ENCODING_BYTES);
+				let dictionary = u32::from_be_bytes(encoding[1..5].try_into().ok()?);
+				match encoding[0] {
+					1 => (Encoding::Zstd {
+						dictionary: (dictionary != 0).then_some(dictionary),
+					}, name),
+					_ => return None,
+				}
+			},
+			_ => return None,
+		};
+		Some(Manifest {
+			chunk_type: ChunkType::try_from(<[u8; 4]>::try_from(&header[1..5]).ok()?).ok()?,
+			size: u64::from_be_bytes(header[5..13].try_into().ok()?),
+			checksum: u32::from_be_bytes(header[13..17].try_into().ok()?),
+			name: String::from_utf8(name.to_vec()).ok()?,
+			encoding,
+		})
+	}
+
+	/// Checks `payload` is the file this manifest describes.
+	pub fn verify(&self, payload: &[u8]) -> Result<(), PayloadError> {
+		self.verify_checksum(payload.len() as u64, CRC.checksum(payload))
+	}
+
+	fn verify_checksum(&self, size: u64, checksum: u32) -> Result<(), PayloadError> {
+		if size != self.size {
+			return Err(PayloadError::SizeMismatch {
+				expected: self.size,
+				found: size,
+			});
+		}
+		if checksum != self.checksum {
+			return Err(PayloadError::ChecksumMismatch {
+				expected: self.checksum,
+				found: checksum,
+			});
+		}
+		Ok(())
+	}
+}
+
+/// Hides `payload` in `chunk_type` chunks of at most `chunk_data_bytes`
+/// each, behind a manifest recording `name` and how `payload` is encoded.
+/// Returns the number of data chunks added.
+pub fn embed(
+	png: &mut Png,
+	chunk_type: ChunkType,
+	name: &str,
+	payload: &[u8],
+	encoding: Encoding,
+	chunk_data_bytes: usize,
+) -> usize {
+	let chunks = file_chunks(chunk_type, name, payload, encoding, chunk_data_bytes);
+	let added = chunks.len() - 1;
+	for chunk in chunks {
+		png.append_chunk(chunk);
+	}
+	added
+}
+
+/// The chunks `embed` adds, manifest first, for writers that don't hold a
+/// `Png`.
+pub fn file_chunks(
+	chunk_type: ChunkType,
+	name: &str,
+	payload: &[u8],
+	encoding: Encoding,
+	chunk_data_bytes: usize,
+) -> Vec<Chunk> {
+	let manifest = Manifest::new(chunk_type, name, payload, encoding);
+	let manifest_type = ChunkType::from_str(MANIFEST_CHUNK_TYPE).expect("valid chunk type");
+	std::iter::once(Chunk::new(manifest_type, manifest.as_bytes()))
+		.chain(payload.chunks(chunk_data_bytes.max(1)).map(|f| Chunk::from_slice(chunk_type, f)))
+		.collect()
+}
+
+/// Manifest `embed` left for the `chunk_type` chunks, the last one if the
+/// file was hidden more than once.
+pub fn manifest(png: &Png, chunk_type: &str) -> Result<Manifest, PayloadError> {
+	let mut found = Err(PayloadError::NoManifest(chunk_type.to_owned()));
+	for chunk in png.chunks() {
+		if chunk.chunk_type().to_string() != MANIFEST_CHUNK_TYPE {
+			continue;
+		}
+		match Manifest::parse(chunk.data()) {
+			Some(m) if m.chunk_type.to_string() == chunk_type => found = Ok(m),
+			Some(_) => {},
+			None if chunk.data().get(1..5) == Some(chunk_type.as_bytes()) => {
+				found = Err(PayloadError::BadManifest(chunk_type.to_owned()))
+			},
+			None => {},
+		}
+	}
+	found
+}
+
+/// Reassembles the file `embed` hid in the `chunk_type` chunks, checked
+/// against its manifest. Still encoded as the manifest says.
+pub fn extract(png: &Png, chunk_type: &str) -> Result<(Manifest, Vec<u8>), PayloadError> {
+	let threads = std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+	extract_with(png, chunk_type, threads)
+}
+
+/// `extract` with the fragments checksummed on up to `threads` threads. Only
+/// the per fragment crcs are folded in order, the payload is copied out once
+/// it checks out.
+pub fn extract_with(
+	png: &Png,
+	chunk_type: &str,
+	threads: NonZeroUsize,
+) -> Result<(Manifest, Vec<u8>), PayloadError> {
+	let manifest = manifest(png, chunk_type)?;
+	let fragments = fragments(png, chunk_type);
+	let checksums = map_fragments(&fragments, threads, |fragment| {
+		Ok::<_, Infallible>((fragment.len() as u64, CRC.checksum(fragment)))
+	})
+	.unwrap_or_else(|never| match never {});
+
+	let (size, checksum) = checksums
+		.into_iter()
+		.fold((0, 0), |(size, crc), (len, fragment)| (size + len, crc32_combine(crc, fragment, len)));
+	manifest.verify_checksum(size, checksum)?;
+	Ok((manifest, fragments.concat()))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+
+	fn split_chunks(mut bytes: &[u8]) -> Vec<Chunk> {
+		let mut chunks = Vec::new();
+		while !bytes.is_empty() {
+			let len = u32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize;
+			let (chunk, rest) = bytes.split_at(Chunk::METADATA_BYTES + len);
+			chunks.push(Chunk::try_from(chunk).unwrap());
+			bytes = rest;
+		}
+		chunks
+	}
+
+	#[test]
+	fn test_payload_writer_splits_into_chunks() {
+		let chunk_type = ChunkType::from_str("ruSt").unwrap();
+		let mut writer = PayloadWriter::with_chunk_size(Vec::new(), chunk_type, 4);
+
+		writer.write_all(b"hello").unwrap();
+		assert_eq!(writer.chunks_written(), 1);
+		writer.write_all(b" world").unwrap();
+		let bytes = writer.finish().unwrap();
+
+		let chunks = split_chunks(&bytes);
+		let data: Vec<&[u8]> = chunks.iter().map(Chunk::data).collect();
+		assert_eq!(data, [&b"hell"[..], b"o wo", b"rld"]);
+		assert!(chunks.iter().all(|c| c.chunk_type() == &chunk_type));
+	}
+
+	#[test]
+	fn test_payload_writer_exact_fit() {
+		let chunk_type = ChunkType::from_str("ruSt").unwrap();
+		let mut writer = PayloadWriter::with_chunk_size(Vec::new(), chunk_type, 4);
+
+		writer.write_all(b"12345678").unwrap();
+		let bytes = writer.finish().unwrap();
+
+		assert_eq!(split_chunks(&bytes).len(), 2);
+	}
+
+	#[test]
+	fn test_payload_writer_empty() {
+		let chunk_type = ChunkType::from_str("ruSt").unwrap();
+		let writer = PayloadWriter::new(Vec::new(), chunk_type);
+		assert!(writer.finish().unwrap().is_empty());
+	}
+
+	fn split_png(payload: &[u8], chunk_data_bytes: usize) -> Png {
+		let chunk_type = ChunkType::from_str("ruSt").unwrap();
+		let mut writer = PayloadWriter::with_chunk_size(Vec::new(), chunk_type, chunk_data_bytes);
+		writer.write_all(payload).unwrap();
+		let mut chunks = split_chunks(&writer.finish().unwrap());
+		chunks.insert(0, Chunk::new(ChunkType::from_str("IHDR").unwrap(), b"header".to_vec()));
+		chunks.push(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()));
+		Png::from_chunks(chunks)
+	}
+
+	#[test]
+	fn test_assemble() {
+		let payload: Vec<u8> = (0..=255).cycle().take(1000).collect();
+		let png = split_png(&payload, 7);
+		assert_eq!(assemble(&png, "ruSt"), payload);
+		assert!(assemble(&png, "nOne").is_empty());
+
+		for threads in [1, 3, 64, 1000] {
+			let threads = NonZeroUsize::new(threads).unwrap();
+			let upper = assemble_with(&png, "ruSt", threads, |f| {
+				Ok::<_, ()>(f.to_ascii_uppercase())
+			});
+			assert_eq!(upper.unwrap(), payload.to_ascii_uppercase());
+		}
+	}
+
+	#[test]
+	fn test_embed_and_extract() {
+		let payload: Vec<u8> = (0..=255).cycle().take(1000).collect();
+		let mut png = split_png(b"", 1);
+		let chunk_type = ChunkType::from_str("fiLe").unwrap();
+
+		assert_eq!(embed(&mut png, chunk_type, "notes.pdf", &payload, Encoding::Plain, 300), 4);
+		assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+
+		let (manifest, extracted) = extract(&png, "fiLe").unwrap();
+		assert_eq!(extracted, payload);
+		assert_eq!(manifest.name, "notes.pdf");
+		assert_eq!(manifest.size, 1000);
+		assert_eq!(extract(&png, "ruSt"), Err(PayloadError::NoManifest("ruSt".into())));
+	}
+
+	#[test]
+	fn test_manifest_encoding() {
+		let chunk_type = ChunkType::from_str("fiLe").unwrap();
+		let plain = Manifest::new(chunk_type, "a.json", b"{}", Encoding::Plain);
+		assert_eq!(plain.as_bytes()[0], MANIFEST_VERSION);
+		assert_eq!(Manifest::parse(&plain.as_bytes()), Some(plain));
+
+		for dictionary in [None, Some(0xdead_beef)] {
+			let manifest = Manifest::new(chunk_type, "a.json", b"{}", Encoding::Zstd { dictionary });
+			let bytes = manifest.as_bytes();
+			assert_eq!(bytes[0], ENCODED_MANIFEST_VERSION);
+			assert_eq!(Manifest::parse(&bytes), Some(manifest));
+			assert_eq!(Manifest::parse(&bytes[..MANIFEST_HEADER_BYTES + 2]), None);
+		}
+	}
+
+	#[test]
+	fn test_extract_detects_missing_chunk() {
+		let mut png = split_png(b"", 1);
+		let chunk_type = ChunkType::from_str("fiLe").unwrap();
+		embed(&mut png, chunk_type, "a.bin", b"aaaabbbbcccc", Encoding::Plain, 4);
+
+		let idx = png.find_nth_by_type("fiLe", 1).unwrap();
+		png.remove_chunk(idx);
+		assert_eq!(
+			extract(&png, "fiLe").unwrap_err(),
+			PayloadError::SizeMismatch { expected: 12, found: 8 }
+		);
+
+		let idx = png.find_nth_by_type("fiLe", 0).unwrap();
+		png.insert_chunk(idx + 1, Chunk::from_slice(chunk_type, b"xxxx")).unwrap();
+		assert!(matches!(
+			extract(&png, "fiLe"),
+			Err(PayloadError::ChecksumMismatch { .. })
+		));
+	}
+
+	#[test]
+	fn test_crc32_combine() {
+		let payload: Vec<u8> = (0..=255).cycle().take(1000).collect();
+		for at in [0, 1, 7, 500, 999, 1000] {
+			let (a, b) = payload.split_at(at);
+			let combined = crc32_combine(CRC.checksum(a), CRC.checksum(b), b.len() as u64);
+			assert_eq!(combined, CRC.checksum(&payload), "{at}");
+		}
+	}
+
+	#[test]
+	fn test_extract_with_threads() {
+		let payload: Vec<u8> = (0..=255).cycle().take(1000).collect();
+		let mut png = split_png(b"", 1);
+		let chunk_type = ChunkType::from_str("fiLe").unwrap();
+		embed(&mut png, chunk_type, "a.bin", &payload, Encoding::Plain, 7);
+
+		for threads in [1, 3, 64, 1000] {
+			let threads = NonZeroUsize::new(threads).unwrap();
+			assert_eq!(extract_with(&png, "fiLe", threads).unwrap().1, payload);
+		}
+
+		// swapped fragments have the right size and crcs, not the right order
+		let first = png.find_nth_by_type("fiLe", 0).unwrap();
+		let moved = png.remove_chunk(first).unwrap();
+		png.insert_chunk(first + 1, moved).unwrap();
+		assert!(matches!(
+			extract_with(&png, "fiLe", NonZeroUsize::new(4).unwrap()),
+			Err(PayloadError::ChecksumMismatch { .. })
+		));
+	}
+
+	#[test]
+	fn test_assemble_with_reports_first_error() {
+		let png = split_png(b"aaaabbbbccccdddd", 4);
+		let threads = NonZeroUsize::new(4).unwrap();
+		let res = assemble_with(&png, "ruSt", threads, |f| match f[0] {
+			b'b' | b'd' => Err(f[0]),
+			_ => Ok(f.to_vec()),
+		});
+		assert_eq!(res, Err(b'b'));
+	}
+}
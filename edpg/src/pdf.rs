@@ -0,0 +1,380 @@
+// Payloads in PDF documents, added as an incremental update.
+//
+// A PDF can be changed by appending to it: new objects, an xref section
+// listing where they are and a trailer pointing back (`/Prev`) at the one
+// before. Everything already in the file stays as it was, signatures
+// included. A payload goes into a stream object of its own nothing refers
+// to, readers never look at it:
+//
+//     n 0 obj
+//     << /VanishType /ruSt /Length 6 >>
+//     stream
+//     hidden
+//     endstream
+//     endobj
+//
+// The trailer before says what the new one has to carry over: the object
+// count (`/Size`), the document catalog (`/Root`), `/Info`, `/ID` and
+// `/Encrypt`. It is either a classic `trailer` dictionary or, since PDF 1.5,
+// the dictionary of an xref stream, and the update is written the same way.
+
+use thiserror::Error;
+
+use crate::chunk_type::ChunkType;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PdfError {
+	#[error("Not a PDF, no `%PDF-` header.")]
+	NotPdf,
+	#[error("No `startxref` at the end of the document.")]
+	NoStartxref,
+	#[error("No trailer at offset {0}, the one `startxref` points to.")]
+	NoTrailer(usize),
+	#[error("The trailer has no `/{0}`.")]
+	MissingKey(&'static str),
+}
+
+const HEADER: &[u8] = b"%PDF-";
+const VANISH_TYPE: &[u8] = b"<< /VanishType /";
+
+/// Whether `bytes` starts with a PDF header.
+pub fn is_pdf(bytes: &[u8]) -> bool {
+	bytes.starts_with(HEADER)
+}
+
+fn position(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+	haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn is_whitespace(b: u8) -> bool {
+	matches!(b, b' ' | b'\t' | b'\r' | b'\n' | b'\x0c' | b'\0')
+}
+
+fn skip_whitespace(bytes: &[u8], mut at: usize) -> usize {
+	while bytes.get(at).is_some_and(|&b| is_whitespace(b)) {
+		at += 1;
+	}
+	at
+}
+
+/// Digits at `at`, and where they end.
+fn number(bytes: &[u8], at: usize) -> Option<(usize, usize)> {
+	let at = at.min(bytes.len());
+	let len = bytes[at..].iter().take_while(|b| b.is_ascii_digit()).count();
+	let digits = std::str::from_utf8(&bytes[at..at + len]).ok()?;
+	Some((digits.parse().ok()?, at + len))
+}
+
+/// End of the dictionary, array or string starting at `start`, past its
+/// closing delimiter.
+fn object_end(bytes: &[u8], start: usize) -> Option<usize> {
+	let mut depth = 0usize;
+	let mut at = start;
+	loop {
+		match bytes.get(at..)? {
+			[b'<', b'<', ..] => {
+				depth += 1;
+				at += 2;
+			},
+			[b'>', b'>', ..] => {
+				depth = depth.checked_sub(1)?;
+				at += 2;
+			},
+			[b'[', ..] => {
+				depth += 1;
+				at += 1;
+			},
+			[b']', ..] => {
+				depth = depth.checked_sub(1)?;
+				at += 1;
+			},
+			// hex string, can't nest
+			[b'<', ..] => at += bytes[at..].iter().position(|&b| b == b'>')? + 1,
+			[b'(', ..] => {
+				let mut open = 0;
+				loop {
+					match bytes.get(at)? {
+						b'\\' => at += 1,
+						b'(' => open += 1,
+						b')' => open -= 1,
+						_ => {},
+					}
+					at += 1;
+					if open == 0 {
+						break;
+					}
+				}
+			},
+			_ => at += 1,
+		}
+		if depth == 0 {
+			return Some(at);
+		}
+	}
+}
+
+/// Raw value of `/key` in `dict`: a reference, a number or whatever
+/// `object_end` can tell the end of.
+fn value<'a>(dict: &'a [u8], key: &str) -> Option<&'a [u8]> {
+	let name = format!("/{key}");
+	let mut from = 0;
+	let at = loop {
+		let at = from + position(&dict[from..], name.as_bytes())? + name.len();
+		// not a longer name starting the same
+		match dict.get(at) {
+			Some(&b) if b.is_ascii_alphanumeric() => from = at,
+			_ => break skip_whitespace(dict, at),
+		}
+	};
+
+	match dict.get(at)? {
+		b'[' | b'<' | b'(' => Some(&dict[at..object_end(dict, at)?]),
+		_ => {
+			let (_, end) = number(dict, at)?;
+			// `n g R`
+			let reference = number(dict, skip_whitespace(dict, end))
+				.map(|(_, end)| skip_whitespace(dict, end))
+				.filter(|&r| dict.get(r) == Some(&b'R'));
+			Some(&dict[at..reference.map_or(end, |r| r + 1)])
+		},
+	}
+}
+
+/// The trailer dictionary of the newest xref section, and where that section
+/// starts.
+pub fn trailer(bytes: &[u8]) -> Result<(&[u8], usize), PdfError> {
+	// the last one, in the tail
+	let tail = bytes.len().saturating_sub(1024);
+	let keyword = bytes[tail..]
+		.windows(9)
+		.rposition(|w| w == b"startxref")
+		.ok_or(PdfError::NoStartxref)?;
+	let (offset, _) = number(bytes, skip_whitespace(bytes, tail + keyword + 9)).ok_or(PdfError::NoStartxref)?;
+
+	let section = bytes.get(offset..).ok_or(PdfError::NoTrailer(offset))?;
+	// classic: the table then `trailer`, xref stream: `n g obj` then the dict
+	let dict = match section.starts_with(b"xref") {
+		true => position(section, b"trailer").and_then(|t| position(&section[t..], b"<<").map(|d| t + d)),
+		false => position(&section[..section.len().min(64)], b"<<"),
+	}
+	.ok_or(PdfError::NoTrailer(offset))?;
+	let end = object_end(section, dict).ok_or(PdfError::NoTrailer(offset))?;
+	Ok((&section[dict..end], offset))
+}
+
+/// `bytes` with `data` appended in an incremental update, in a stream
+/// labelled `chunk_type`.
+pub fn append(bytes: &[u8], chunk_type: &ChunkType, data: &[u8]) -> Result<Vec<u8>, PdfError> {
+	if !is_pdf(bytes) {
+		return Err(PdfError::NotPdf);
+	}
+	let (dict, prev) = trailer(bytes)?;
+	let (size, _) = value(dict, "Size")
+		.and_then(|size| number(size, 0))
+		.ok_or(PdfError::MissingKey("Size"))?;
+	let root = value(dict, "Root").ok_or(PdfError::MissingKey("Root"))?;
+
+	let mut out = Vec::with_capacity(bytes.len() + data.len() + 256);
+	out.extend_from_slice(bytes);
+	if !out.ends_with(b"\n") && !out.ends_with(b"\r") {
+		out.push(b'\n');
+	}
+
+	let object = out.len();
+	out.extend_from_slice(format!("{size} 0 obj\n").as_bytes());
+	out.extend_from_slice(VANISH_TYPE);
+	out.extend_from_slice(&chunk_type.bytes());
+	out.extend_from_slice(format!(" /Length {} >>\nstream\n", data.len()).as_bytes());
+	out.extend_from_slice(data);
+	out.extend_from_slice(b"\nendstream\nendobj\n");
+
+	// what every trailer has to repeat
+	let mut carried = b"/Root ".to_vec();
+	carried.extend_from_slice(root);
+	for key in ["Info", "ID", "Encrypt"] {
+		if let Some(v) = value(dict, key) {
+			carried.extend_from_slice(format!(" /{key} ").as_bytes());
+			carried.extend_from_slice(v);
+		}
+	}
+
+	let xref = out.len();
+	match bytes[prev..].starts_with(b"xref") {
+		true => {
+			// entries are 20 bytes each, the line end included
+			out.extend_from_slice(format!("xref\n{size} 1\n{object:010} 00000 n\r\n").as_bytes());
+			out.extend_from_slice(format!("trailer\n<< /Size {} ", size + 1).as_bytes());
+			out.extend_from_slice(&carried);
+			out.extend_from_slice(format!(" /Prev {prev} >>\n").as_bytes());
+		},
+		false => {
+			// readers of xref streams get one too, listing the payload and
+			// itself: type 1, offset, generation 0
+			let mut entries = Vec::new();
+			for offset in [object, xref] {
+				entries.push(1);
+				entries.extend_from_slice(&(offset as u64).to_be_bytes());
+				entries.push(0);
+			}
+			out.extend_from_slice(
+				format!(
+					"{} 0 obj\n<< /Type /XRef /Size {} /Index [{size} 2] /W [1 8 1] /Length {} ",
+					size + 1,
+					size + 2,
+					entries.len()
+				)
+				.as_bytes(),
+			);
+			out.extend_from_slice(&carried);
+			out.extend_from_slice(format!(" /Prev {prev} >>\nstream\n").as_bytes());
+			out.extend_from_slice(&entries);
+			out.extend_from_slice(b"\nendstream\nendobj\n");
+		},
+	}
+	out.extend_from_slice(format!("startxref\n{xref}\n%%EOF\n").as_bytes());
+
+	Ok(out)
+}
+
+/// A stream `append` wrote.
+struct Stream<'a> {
+	chunk_type: &'a [u8],
+	data: &'a [u8],
+}
+
+/// The vanish streams, in file order.
+fn streams(bytes: &[u8]) -> Result<Vec<Stream<'_>>, PdfError> {
+	if !is_pdf(bytes) {
+		return Err(PdfError::NotPdf);
+	}
+
+	let mut streams = Vec::new();
+	let mut from = 0;
+	while let Some(at) = position(&bytes[from..], VANISH_TYPE) {
+		let start = from + at + VANISH_TYPE.len();
+		from = start;
+		// only what `append` writes
+		let Some(chunk_type) = bytes.get(start..start + 4) else {
+			break;
+		};
+		let Some(rest) = bytes[start + 4..].strip_prefix(b" /Length ") else {
+			continue;
+		};
+		let length_at = bytes.len() - rest.len();
+		let Some((len, end)) = number(bytes, length_at) else {
+			continue;
+		};
+		let data = end + b" >>\nstream\n".len();
+		if bytes.get(end..data) == Some(b" >>\nstream\n") && data + len <= bytes.len() {
+			streams.push(Stream {
+				chunk_type,
+				data: &bytes[data..data + len],
+			});
+			from = data + len;
+		}
+	}
+	Ok(streams)
+}
+
+/// Data of the first vanish stream labelled `chunk_type`.
+pub fn find<'a>(bytes: &'a [u8], chunk_type: &str) -> Result<Option<&'a [u8]>, PdfError> {
+	Ok(streams(bytes)?
+		.into_iter()
+		.find(|s| s.chunk_type == chunk_type.as_bytes())
+		.map(|s| s.data))
+}
+
+/// Chunk types of all vanish streams, in file order.
+pub fn chunk_types(bytes: &[u8]) -> Result<Vec<String>, PdfError> {
+	Ok(streams(bytes)?
+		.into_iter()
+		.map(|s| String::from_utf8_lossy(s.chunk_type).into_owned())
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+
+	/// A one page document with a classic xref table.
+	fn testing_pdf() -> Vec<u8> {
+		let objects = [
+			"<< /Type /Catalog /Pages 2 0 R >>",
+			"<< /Type /Pages /Kids [3 0 R] /Count 1 >>",
+			"<< /Type /Page /Parent 2 0 R /MediaBox [0 0 10 10] >>",
+		];
+		let mut pdf = b"%PDF-1.4\n".to_vec();
+		let mut offsets = Vec::new();
+		for (i, object) in objects.iter().enumerate() {
+			offsets.push(pdf.len());
+			pdf.extend_from_slice(format!("{} 0 obj\n{object}\nendobj\n", i + 1).as_bytes());
+		}
+		let xref = pdf.len();
+		pdf.extend_from_slice(b"xref\n0 4\n0000000000 65535 f\r\n");
+		for offset in offsets {
+			pdf.extend_from_slice(format!("{offset:010} 00000 n\r\n").as_bytes());
+		}
+		pdf.extend_from_slice(
+			format!("trailer\n<< /Size 4 /Root 1 0 R /ID [<ab><cd>] >>\nstartxref\n{xref}\n%%EOF").as_bytes(),
+		);
+		pdf
+	}
+
+	#[test]
+	fn test_value() {
+		let dict = b"<< /Size 12 /RootX 9 /Root 1 0 R /ID [<a>(b\\))] /Info<</Deep<<>>>> >>";
+		assert_eq!(value(dict, "Size"), Some(&b"12"[..]));
+		assert_eq!(value(dict, "Root"), Some(&b"1 0 R"[..]));
+		assert_eq!(value(dict, "ID"), Some(&b"[<a>(b\\))]"[..]));
+		assert_eq!(value(dict, "Info"), Some(&b"<</Deep<<>>>>"[..]));
+		assert_eq!(value(dict, "Encrypt"), None);
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let pdf = testing_pdf();
+		let out = append(&pdf, &ChunkType::from_str("ruSt").unwrap(), b"hidden >>\nstream\n").unwrap();
+
+		assert!(out.starts_with(&pdf));
+		assert_eq!(find(&out, "ruSt").unwrap(), Some(&b"hidden >>\nstream\n"[..]));
+		assert_eq!(find(&out, "nOpe").unwrap(), None);
+
+		// the new section points back and at the new object
+		let (dict, xref) = trailer(&out).unwrap();
+		assert_eq!(value(dict, "Size"), Some(&b"5"[..]));
+		assert_eq!(value(dict, "Root"), Some(&b"1 0 R"[..]));
+		assert_eq!(value(dict, "ID"), Some(&b"[<ab><cd>]"[..]));
+		let (prev, _) = number(value(dict, "Prev").unwrap(), 0).unwrap();
+		assert_eq!(prev, trailer(&pdf).unwrap().1);
+		let entry = &out[xref + b"xref\n4 1\n".len()..][..20];
+		let (object, _) = number(entry, 0).unwrap();
+		assert!(out[object..].starts_with(b"4 0 obj\n"));
+
+		let twice = append(&out, &ChunkType::from_str("tWoo").unwrap(), b"").unwrap();
+		assert_eq!(chunk_types(&twice).unwrap(), ["ruSt", "tWoo"]);
+		assert_eq!(value(trailer(&twice).unwrap().0, "Size"), Some(&b"6"[..]));
+	}
+
+	#[test]
+	fn test_xref_stream_trailer() {
+		let pdf = b"%PDF-1.5\n7 0 obj\n<< /Type /XRef /Size 8 /Root 1 0 R /W [1 2 1] /Length 0 >>\nstream\n\nendstream\nendobj\nstartxref\n9\n%%EOF\n";
+		let out = append(pdf, &ChunkType::from_str("ruSt").unwrap(), b"x").unwrap();
+		assert!(out[pdf.len()..].starts_with(b"8 0 obj\n"));
+		assert_eq!(find(&out, "ruSt").unwrap(), Some(&b"x"[..]));
+
+		let (dict, xref) = trailer(&out).unwrap();
+		assert!(out[xref..].starts_with(b"9 0 obj\n<< /Type /XRef "));
+		assert_eq!(value(dict, "Size"), Some(&b"10"[..]));
+		assert_eq!(value(dict, "Index"), Some(&b"[8 2]"[..]));
+		assert_eq!(value(dict, "Prev"), Some(&b"9"[..]));
+		// the first entry, type 1 at the payload's offset
+		let entries = &out[xref + position(&out[xref..], b"stream\n").unwrap() + 7..];
+		assert_eq!(entries[0], 1);
+		assert_eq!(u64::from_be_bytes(entries[1..9].try_into().unwrap()) as usize, pdf.len());
+
+		assert_eq!(append(b"%PDF-1.4\n", &ChunkType::from_str("ruSt").unwrap(), b""), Err(PdfError::NoStartxref));
+		assert_eq!(append(b"GIF89a", &ChunkType::from_str("ruSt").unwrap(), b""), Err(PdfError::NotPdf));
+	}
+}
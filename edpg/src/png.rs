@@ -1,38 +1,103 @@
-use std::{fmt::Display, path::PathBuf};
+use std::{
+	fmt::Display,
+	io::{Read, Seek, SeekFrom, Write},
+	path::PathBuf,
+};
+
+use bytes::Bytes;
 
+pub use crate::chunk::ParseOptions;
 use crate::{
 	chunk::{Chunk, ChunkError},
 	chunk_type::ChunkTypeError,
 };
 use thiserror::Error;
 
+#[cfg(feature = "image-interop")]
+mod interop;
+
 #[derive(Debug, Error)]
 pub enum PngError {
 	#[error("Empty Chunk List.")]
 	ChunkListEmpty,
 	#[error("Invalid Header. This is not a PNG file.")]
 	InvalidHeader,
+	#[error("Not a PNG but {0}, made of the same chunks. Only PNGs are supported.")]
+	MultiImage(&'static str),
 	#[error("Unknown Chunk")]
 	InvalidChunk(#[from] ChunkError),
 	#[error("Unknown Chunk Type")]
 	InvalidChunkType(#[from] ChunkTypeError),
 	#[error("Invalid length. Must contain the std header for PNG")]
 	InvalidLength,
+	#[cfg(feature = "image-interop")]
+	#[error("Image conversion failed. {0}")]
+	Image(#[from] image::ImageError),
 	#[error("Unable to convert slice. {0}")]
 	SliceToSized(#[from] std::array::TryFromSliceError),
 	#[error("Failed to read file. {0}")]
 	FailedToRead(#[from] std::io::Error),
+	#[error("Index {0} is out of range, the png has {1} chunks.")]
+	IndexOutOfRange(usize, usize),
 }
 
+/// Clones are cheap, chunk data is shared between them until edited.
 #[derive(Debug, Clone)]
 pub struct Png {
 	chunk_list: Vec<Chunk>,
+	/// Junk the signature came after, see `ParseOptions::max_prefix`.
+	prefix: Vec<u8>,
+}
+
+/// State of a `Png` to go back to, see `Png::snapshot`.
+#[derive(Debug, Clone)]
+pub struct PngSnapshot {
+	chunks: Vec<Chunk>,
 }
 
 impl Png {
 	pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+	/// Signature of MNG, animations made of png and jng images.
+	pub const MNG_HEADER: [u8; 8] = [138, 77, 78, 71, 13, 10, 26, 10];
+	/// Signature of JNG, a jpeg with png style chunks around it.
+	pub const JNG_HEADER: [u8; 8] = [139, 74, 78, 71, 13, 10, 26, 10];
+
 	pub fn new(chunk_list: Vec<Chunk>) -> Self {
-		Self { chunk_list }
+		Self {
+			chunk_list,
+			prefix: Vec::new(),
+		}
+	}
+
+	/// Offset of the png signature in `bytes`, looked for in the first
+	/// `max_prefix` bytes past the start.
+	pub fn signature(bytes: &[u8], max_prefix: usize) -> Option<usize> {
+		let head = &bytes[..bytes.len().min(max_prefix.saturating_add(Self::STANDARD_HEADER.len()))];
+		memchr::memmem::find(head, &Self::STANDARD_HEADER)
+	}
+
+	/// "MNG" or "JNG" if `bytes` starts with the signature of either, the
+	/// png siblings sharing its chunk layout.
+	pub fn multi_image_format(bytes: &[u8]) -> Option<&'static str> {
+		match bytes.get(..8)?.try_into().ok()? {
+			Self::MNG_HEADER => Some("MNG"),
+			Self::JNG_HEADER => Some("JNG"),
+			_ => None,
+		}
+	}
+
+	/// Why `bytes` has no png signature where it should be.
+	pub(crate) fn header_error(bytes: &[u8]) -> PngError {
+		match Self::multi_image_format(bytes) {
+			Some(format) => PngError::MultiImage(format),
+			None => PngError::InvalidHeader,
+		}
+	}
+
+	/// Bytes that came before the signature when parsed, empty for a clean
+	/// png. They are never written back.
+	pub fn prefix(&self) -> &[u8] {
+		&self.prefix
 	}
 
 	pub fn data_string_by_type(&self, chunk_type: &str) -> Option<String> {
@@ -52,20 +117,194 @@ impl Png {
 		self.chunk_list.insert(self.chunks().len() - 1, chunk)
 	}
 
+	#[deprecated(note = "use `find_nth_by_type(chunk_type, 0)` or `positions_of`")]
 	pub fn find_by_chunk(&self, chunk_type: &str) -> Option<usize> {
+		self.find_nth_by_type(chunk_type, 0)
+	}
+
+	/// Index of the `n`th (from 0) chunk of `chunk_type`.
+	pub fn find_nth_by_type(&self, chunk_type: &str, n: usize) -> Option<usize> {
+		self.positions(chunk_type).nth(n)
+	}
+
+	/// Indices of every chunk of `chunk_type`, in file order.
+	pub fn positions_of(&self, chunk_type: &str) -> Vec<usize> {
+		self.positions(chunk_type).collect()
+	}
+
+	fn positions<'a>(&'a self, chunk_type: &'a str) -> impl Iterator<Item = usize> + 'a {
 		self.chunks()
 			.iter()
-			.position(|x| x.chunk_type().to_string() == chunk_type)
+			.enumerate()
+			.filter(move |(_, x)| x.chunk_type().bytes() == chunk_type.as_bytes())
+			.map(|(i, _)| i)
 	}
 
 	pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<Chunk, PngError> {
-		if let Some(idx) = self.find_by_chunk(chunk_type) {
+		if let Some(idx) = self.find_nth_by_type(chunk_type, 0) {
 			Ok(self.chunk_list.remove(idx))
 		} else {
 			Err(PngError::ChunkListEmpty)
 		}
 	}
 
+	/// Offset right after IEND, where the image ends for every viewer. Only
+	/// walks the chunk lengths, whatever follows IEND doesn't have to parse.
+	pub fn image_len(bytes: &[u8]) -> Result<usize, PngError> {
+		let ranges = Self::chunk_ranges(bytes)?;
+		Ok(ranges.last().map_or(Self::STANDARD_HEADER.len(), |r| r.end))
+	}
+
+	/// Byte range of every chunk up to IEND, found by walking the chunk
+	/// lengths only, nothing is parsed or crc checked.
+	pub fn chunk_ranges(bytes: &[u8]) -> Result<Vec<std::ops::Range<usize>>, PngError> {
+		if !bytes.starts_with(&Self::STANDARD_HEADER) {
+			return Err(Self::header_error(bytes));
+		}
+
+		let mut ranges = Vec::new();
+		let mut offset = Self::STANDARD_HEADER.len();
+		loop {
+			let meta = bytes
+				.get(offset..offset + Chunk::LENGTH_BYTES + Chunk::CHUNK_TYPE_BYTES)
+				.ok_or(PngError::InvalidLength)?;
+			let len = u32::from_be_bytes(meta[..4].try_into()?) as usize;
+			let end = offset + Chunk::METADATA_BYTES + len;
+			if end > bytes.len() {
+				return Err(PngError::InvalidLength);
+			}
+			ranges.push(offset..end);
+			offset = end;
+			if &meta[4..] == b"IEND" {
+				return Ok(ranges);
+			}
+		}
+	}
+
+	/// Reads `reader` only up to the first chunk of `chunk_type`, the chunks
+	/// before it are skipped without being buffered or crc checked. `None` if
+	/// IEND or the end of the input comes first. `options` apply the same as
+	/// in [`Png::parse_with`], to the signature and the chunk found.
+	pub fn find_chunk_streaming<R: Read>(
+		mut reader: R,
+		chunk_type: &str,
+		options: &ParseOptions,
+	) -> Result<Option<Chunk>, PngError> {
+		// the signature may sit up to max_prefix bytes in
+		let mut head = Vec::new();
+		reader
+			.by_ref()
+			.take((options.max_prefix as u64).saturating_add(Self::STANDARD_HEADER.len() as u64))
+			.read_to_end(&mut head)?;
+		let start = Self::signature(&head, options.max_prefix).ok_or_else(|| Self::header_error(&head))?;
+		let mut reader = head[start + Self::STANDARD_HEADER.len()..].chain(reader);
+
+		let mut meta = [0; Chunk::LENGTH_BYTES + Chunk::CHUNK_TYPE_BYTES];
+		loop {
+			match reader.read_exact(&mut meta) {
+				Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+				res => res?,
+			}
+			let len = u32::from_be_bytes(meta[..4].try_into()?) as u64;
+			let found = &meta[4..];
+
+			if found == chunk_type.as_bytes() {
+				let mut bytes = meta.to_vec();
+				reader
+					.by_ref()
+					.take(len + Chunk::CRC_LENGTH_BYTES as u64)
+					.read_to_end(&mut bytes)?;
+				if bytes.len() < Chunk::METADATA_BYTES + len as usize {
+					return Err(PngError::InvalidLength);
+				}
+				return Ok(Some(Chunk::parse_with(&bytes, options)?));
+			}
+			if found == b"IEND" {
+				return Ok(None);
+			}
+
+			let skipped = std::io::copy(
+				&mut reader.by_ref().take(len + Chunk::CRC_LENGTH_BYTES as u64),
+				&mut std::io::sink(),
+			)?;
+			if skipped < len + Chunk::CRC_LENGTH_BYTES as u64 {
+				return Err(PngError::InvalidLength);
+			}
+		}
+	}
+
+	/// Chunks of the png `reader` holds, read and parsed one at a time as the
+	/// iterator is advanced, so only one chunk is ever in memory. Ends after
+	/// IEND; the signature has to come first, `max_prefix` isn't looked at.
+	pub fn chunks_from_reader<R: Read>(
+		mut reader: R,
+		options: &ParseOptions,
+	) -> Result<ChunkReader<R>, PngError> {
+		let mut header = [0; 8];
+		reader.read_exact(&mut header)?;
+		if header != Self::STANDARD_HEADER {
+			return Err(Self::header_error(&header));
+		}
+		Ok(ChunkReader {
+			reader,
+			options: *options,
+			done: false,
+		})
+	}
+
+	/// Offset of the IEND chunk, found by seeking from one chunk header to the
+	/// next without reading any chunk data. `None` if the input ends first.
+	pub fn iend_offset<R: Read + Seek>(mut reader: R) -> Result<Option<u64>, PngError> {
+		let mut header = [0; 8];
+		reader.read_exact(&mut header)?;
+		if header != Self::STANDARD_HEADER {
+			return Err(PngError::InvalidHeader);
+		}
+
+		let mut offset = header.len() as u64;
+		let mut meta = [0; Chunk::LENGTH_BYTES + Chunk::CHUNK_TYPE_BYTES];
+		loop {
+			match reader.read_exact(&mut meta) {
+				Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+				res => res?,
+			}
+			if &meta[4..] == b"IEND" {
+				return Ok(Some(offset));
+			}
+
+			let len = u32::from_be_bytes(meta[..4].try_into()?) as u64;
+			offset += Chunk::METADATA_BYTES as u64 + len;
+			reader.seek(SeekFrom::Start(offset))?;
+		}
+	}
+
+	/// Mutable access to the chunk at `idx`, for editing it in place.
+	pub fn chunk_mut(&mut self, idx: usize) -> Option<&mut Chunk> {
+		self.chunk_list.get_mut(idx)
+	}
+
+	/// Inserts `chunk` at `idx`, shifting everything after it. `idx` may be the
+	/// number of chunks, which appends after IEND.
+	pub fn insert_chunk(&mut self, idx: usize, chunk: Chunk) -> Result<(), PngError> {
+		if idx > self.chunk_list.len() {
+			return Err(PngError::IndexOutOfRange(idx, self.chunk_list.len()));
+		}
+
+		self.chunk_list.insert(idx, chunk);
+		Ok(())
+	}
+
+	/// Takes out the chunk at `idx`, `None` if there is none.
+	pub fn remove_chunk(&mut self, idx: usize) -> Option<Chunk> {
+		(idx < self.chunk_list.len()).then(|| self.chunk_list.remove(idx))
+	}
+
+	/// Puts `chunk` at `idx`, handing back the chunk that was there.
+	pub fn replace_chunk(&mut self, idx: usize, chunk: Chunk) -> Option<Chunk> {
+		let slot = self.chunk_list.get_mut(idx)?;
+		Some(std::mem::replace(slot, chunk))
+	}
+
 	pub fn header(&self) -> &[u8; 8] {
 		&Self::STANDARD_HEADER
 	}
@@ -80,27 +319,81 @@ impl Png {
 	}
 
 	pub fn as_bytes(&self) -> Vec<u8> {
-		// help me understand why this wouldn't work
-		// &Self::STANDARD_HEADER
-		// 	.iter()
-		// 	.chain(self.chunks().into_iter().flat_map(|&x| x.as_bytes()))
-		// 	.collect();
+		let mut bytes = Vec::with_capacity(self.byte_len());
+		self.write_into(&mut bytes);
+		bytes
+	}
+
+	/// Streams the serialized png into `w`, chunk by chunk, without building
+	/// it in memory. `w` should be buffered, every chunk is a few small
+	/// writes.
+	pub fn write_to<W: Write>(&self, mut w: W) -> std::io::Result<()> {
+		w.write_all(&Self::STANDARD_HEADER)?;
+		for chunk in self.chunks() {
+			chunk.write_to(&mut w)?;
+		}
+		w.flush()
+	}
+
+	/// Appends the serialized png to `out`, reserving the room once.
+	pub fn write_into(&self, out: &mut Vec<u8>) {
+		out.reserve(self.byte_len());
+		out.extend_from_slice(&Self::STANDARD_HEADER);
+		for chunk in self.chunks() {
+			chunk.write_into(out);
+		}
+	}
+
+	/// The current state, to `restore` later, eg: for undo in an editor.
+	/// Cheap, chunk data is shared with the png rather than copied.
+	pub fn snapshot(&self) -> PngSnapshot {
+		PngSnapshot {
+			chunks: self.chunk_list.clone(),
+		}
+	}
 
-		let mut bytes = Vec::new();
-		bytes.extend(Self::STANDARD_HEADER); // Add the 8-byte PNG header
+	/// Goes back to the state of `snapshot`, whatever changed since is lost.
+	pub fn restore(&mut self, snapshot: PngSnapshot) {
+		self.chunk_list = snapshot.chunks;
+	}
 
-		bytes.extend(self.chunks().iter().flat_map(|x| x.as_bytes()));
+	/// Size of the serialized png, computed without serializing it.
+	pub fn byte_len(&self) -> usize {
+		Self::STANDARD_HEADER.len() + self.chunks().iter().map(Chunk::byte_len).sum::<usize>()
+	}
 
-		bytes
+	/// Rough heap plus inline footprint of this `Png`, allocations included
+	/// at their capacity.
+	pub fn estimated_memory(&self) -> usize {
+		std::mem::size_of::<Self>()
+			+ self.chunk_list.capacity() * std::mem::size_of::<Chunk>()
+			+ self.chunks().iter().map(|c| c.data_capacity()).sum::<usize>()
 	}
 }
 
-impl TryFrom<&[u8]> for Png {
-	type Error = PngError;
+impl Png {
+	pub fn parse_with(value: &[u8], options: &ParseOptions) -> Result<Self, PngError> {
+		Self::parse_chunks(value, options, |chunk| Chunk::parse_with(chunk, options))
+	}
 
-	fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+	/// `parse_with` where chunk data stays a slice of `value`, nothing is
+	/// copied. The whole buffer lives as long as any of its chunks.
+	pub fn parse_bytes(value: &Bytes, options: &ParseOptions) -> Result<Self, PngError> {
+		Self::parse_chunks(value, options, |chunk| {
+			Chunk::parse_bytes(&value.slice_ref(chunk), options)
+		})
+	}
+
+	fn parse_chunks(
+		value: &[u8],
+		options: &ParseOptions,
+		parse: impl Fn(&[u8]) -> Result<Chunk, ChunkError>,
+	) -> Result<Self, PngError> {
 		/// Returns a `(Chunk, bytes)` `Chunk` is extracted from `bytes` meaning it doesn't consume `bytes`.
-		fn get_chunk(bytes: &[u8]) -> Result<(Chunk, &[u8]), PngError> {
+		fn get_chunk<'a>(
+			bytes: &'a [u8],
+			parse: &impl Fn(&[u8]) -> Result<Chunk, ChunkError>,
+		) -> Result<(Chunk, &'a [u8]), PngError> {
 			let len_bytes: [u8; 4] = bytes[..4].try_into()?;
 			let chunk_data_len = u32::from_be_bytes(len_bytes);
 
@@ -111,7 +404,7 @@ impl TryFrom<&[u8]> for Png {
 			}
 
 			let (chunk, rem) = bytes.split_at(total_chunk_len);
-			let chunk = Chunk::try_from(chunk)?;
+			let chunk = parse(chunk)?;
 
 			Ok((chunk, rem))
 		}
@@ -120,22 +413,77 @@ impl TryFrom<&[u8]> for Png {
 			return Err(PngError::InvalidLength);
 		}
 
-		let (header, mut chunk_bytes) = value.split_at(8);
-
-		if header != Self::STANDARD_HEADER {
-			return Err(PngError::InvalidHeader);
-		}
+		let start = Self::signature(value, options.max_prefix).ok_or_else(|| Self::header_error(value))?;
+		let (prefix, value) = value.split_at(start);
+		let (_, mut chunk_bytes) = value.split_at(8);
 
 		let mut chunk_list: Vec<Chunk> = Vec::new();
 
 		while chunk_bytes.len() >= 4 {
-			let (chunk, rem) = get_chunk(chunk_bytes)?;
+			let (chunk, rem) = get_chunk(chunk_bytes, &parse)?;
 
 			chunk_list.push(chunk);
 			chunk_bytes = rem;
 		}
 
-		Ok(Self { chunk_list })
+		Ok(Self {
+			chunk_list,
+			prefix: prefix.to_vec(),
+		})
+	}
+}
+
+/// Lazy chunks of a png, see `Png::chunks_from_reader`. Stops at the first
+/// error.
+#[derive(Debug)]
+pub struct ChunkReader<R: Read> {
+	reader: R,
+	options: ParseOptions,
+	done: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+	fn read_chunk(&mut self) -> Result<Option<Chunk>, PngError> {
+		let mut meta = [0; Chunk::LENGTH_BYTES + Chunk::CHUNK_TYPE_BYTES];
+		match self.reader.read_exact(&mut meta) {
+			Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+			res => res?,
+		}
+		let len = u32::from_be_bytes(meta[..4].try_into()?) as u64;
+
+		let mut bytes = meta.to_vec();
+		self.reader
+			.by_ref()
+			.take(len + Chunk::CRC_LENGTH_BYTES as u64)
+			.read_to_end(&mut bytes)?;
+		if bytes.len() < Chunk::METADATA_BYTES + len as usize {
+			return Err(PngError::InvalidLength);
+		}
+		self.done = &meta[4..] == b"IEND";
+		Ok(Some(Chunk::parse_with(&bytes, &self.options)?))
+	}
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+	type Item = Result<Chunk, PngError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.done {
+			return None;
+		}
+		let chunk = self.read_chunk();
+		if !matches!(chunk, Ok(Some(_))) {
+			self.done = true;
+		}
+		chunk.transpose()
+	}
+}
+
+impl TryFrom<&[u8]> for Png {
+	type Error = PngError;
+
+	fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+		Self::parse_with(value, &ParseOptions::default())
 	}
 }
 
@@ -150,14 +498,21 @@ impl TryFrom<PathBuf> for Png {
 	}
 }
 
+// one row per chunk, `{:?}` keeps the raw bytes
 impl Display for Png {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		let s: Vec<String> = self
-			.chunks()
-			.iter()
-			.map(|chunk| chunk.to_string())
-			.collect();
-		write!(f, "{}", s.join(""))
+		writeln!(f, "{:>4}  {:<4}  {:>10}  {:<10}  data", "#", "type", "length", "crc")?;
+		for (i, chunk) in self.chunks().iter().enumerate() {
+			writeln!(
+				f,
+				"{i:>4}  {}  {:>10}  {:#010x}  {}",
+				chunk.chunk_type(),
+				chunk.length(),
+				chunk.crc(),
+				chunk.preview()
+			)?;
+		}
+		Ok(())
 	}
 }
 
@@ -304,6 +659,101 @@ mod tests {
 		assert_eq!(&chunk.data_as_string().unwrap(), "Message");
 	}
 
+	#[test]
+	fn test_snapshot_restore() {
+		let mut png = testing_png();
+		let snapshot = png.snapshot();
+		assert!(png.chunks()[0].shares_data(&snapshot.chunks[0]));
+
+		png.remove_first_chunk("FrSt").unwrap();
+		png.chunk_list[0].map_data(|d| d.push(b'!'));
+		assert_eq!(snapshot.chunks[1].data(), b"I am another chunk");
+
+		png.restore(snapshot);
+		assert_eq!(png.as_bytes(), testing_png().as_bytes());
+	}
+
+	#[test]
+	fn test_clone_copy_on_write() {
+		let png = testing_png();
+		let mut copy = png.clone();
+		for (a, b) in png.chunks().iter().zip(copy.chunks()) {
+			assert!(a.shares_data(b));
+		}
+
+		copy.chunk_mut(0).unwrap().data_mut()[0] = b'i';
+		assert!(!copy.chunks()[0].shares_data(&png.chunks()[0]));
+		assert!(copy.chunks()[1].shares_data(&png.chunks()[1]));
+		assert_eq!(png.chunks()[0].data(), b"I am the first chunk");
+		assert_eq!(copy.chunks()[0].data(), b"i am the first chunk");
+	}
+
+	#[test]
+	fn test_parse_bytes() {
+		let bytes = Bytes::from(testing_png().as_bytes());
+		let png = Png::parse_bytes(&bytes, &ParseOptions::default()).unwrap();
+		assert_eq!(png.as_bytes(), bytes);
+		assert!(bytes.as_ptr_range().contains(&png.chunks()[0].data().as_ptr()));
+	}
+
+	#[test]
+	fn test_prefix() {
+		let mut bytes = b"\xEF\xBB\xBF".to_vec();
+		bytes.extend(testing_png().as_bytes());
+		assert!(Png::try_from(&bytes[..]).is_err());
+		assert_eq!(Png::signature(&bytes, 2), None);
+		assert_eq!(Png::signature(&bytes, 3), Some(3));
+		assert_eq!(Png::signature(&bytes, usize::MAX), Some(3));
+
+		let options = ParseOptions {
+			max_prefix: 16,
+			..Default::default()
+		};
+		let png = Png::parse_with(&bytes, &options).unwrap();
+		assert_eq!(png.prefix(), b"\xEF\xBB\xBF");
+		assert_eq!(png.as_bytes(), testing_png().as_bytes());
+	}
+
+	#[test]
+	fn test_chunks_from_reader() {
+		let bytes = testing_png().as_bytes();
+		let read: Vec<Vec<u8>> = Png::chunks_from_reader(&bytes[..], &ParseOptions::default())
+			.unwrap()
+			.map(|c| c.unwrap().as_bytes())
+			.collect();
+		let expected: Vec<Vec<u8>> = testing_chunks().iter().map(Chunk::as_bytes).collect();
+		assert_eq!(read, expected);
+
+		// stops at IEND, whatever follows
+		let mut png = testing_png();
+		png.chunk_list.push(chunk_from_strings("IEND", "").unwrap());
+		let mut bytes = png.as_bytes();
+		bytes.extend_from_slice(b"trailer");
+		let reader = Png::chunks_from_reader(&bytes[..], &ParseOptions::default()).unwrap();
+		assert_eq!(reader.count(), 4);
+
+		// a chunk cut short is an error, and the last item
+		let bytes = testing_png().as_bytes();
+		let mut reader = Png::chunks_from_reader(&bytes[..bytes.len() - 2], &ParseOptions::default())
+			.unwrap()
+			.skip(2);
+		assert!(matches!(reader.next(), Some(Err(PngError::InvalidLength))));
+		assert!(reader.next().is_none());
+
+		assert!(Png::chunks_from_reader(&b"not a png"[..], &ParseOptions::default()).is_err());
+	}
+
+	#[test]
+	fn test_multi_image() {
+		let mut bytes = testing_png().as_bytes();
+		bytes[..8].copy_from_slice(&Png::MNG_HEADER);
+		assert!(matches!(Png::try_from(&bytes[..]), Err(PngError::MultiImage("MNG"))));
+		bytes[..8].copy_from_slice(&Png::JNG_HEADER);
+		assert!(matches!(Png::image_len(&bytes), Err(PngError::MultiImage("JNG"))));
+		bytes[0] = 0;
+		assert!(matches!(Png::try_from(&bytes[..]), Err(PngError::InvalidHeader)));
+	}
+
 	#[test]
 	fn test_remove_first_chunk() {
 		let mut png = testing_png();
@@ -313,6 +763,51 @@ mod tests {
 		assert!(chunk.is_none());
 	}
 
+	#[test]
+	fn test_duplicate_types() {
+		let mut png = testing_png();
+		png.append_chunk(chunk_from_strings("TeSt", "one").unwrap());
+		png.append_chunk(chunk_from_strings("TeSt", "two").unwrap());
+		assert_eq!(png.positions_of("TeSt"), [2, 3]);
+		assert_eq!(png.find_nth_by_type("TeSt", 1), Some(3));
+		assert_eq!(png.find_nth_by_type("TeSt", 2), None);
+		assert!(png.positions_of("nOne").is_empty());
+	}
+
+	#[test]
+	fn test_insert_chunk() {
+		let mut png = testing_png();
+		png.insert_chunk(0, chunk_from_strings("TeSt", "Message").unwrap())
+			.unwrap();
+		assert_eq!(png.find_nth_by_type("TeSt", 0), Some(0));
+		assert!(matches!(
+			png.insert_chunk(5, chunk_from_strings("TeSt", "Message").unwrap()),
+			Err(PngError::IndexOutOfRange(5, 4))
+		));
+	}
+
+	#[test]
+	fn test_remove_chunk() {
+		let mut png = testing_png();
+		let removed = png.remove_chunk(1).unwrap();
+		assert_eq!(&removed.chunk_type().to_string(), "miDl");
+		assert_eq!(png.chunks().len(), 2);
+		assert!(png.remove_chunk(2).is_none());
+	}
+
+	#[test]
+	fn test_replace_chunk() {
+		let mut png = testing_png();
+		let old = png
+			.replace_chunk(1, chunk_from_strings("TeSt", "Message").unwrap())
+			.unwrap();
+		assert_eq!(&old.chunk_type().to_string(), "miDl");
+		assert_eq!(png.find_nth_by_type("TeSt", 0), Some(1));
+		assert!(png
+			.replace_chunk(3, chunk_from_strings("TeSt", "Message").unwrap())
+			.is_none());
+	}
+
 	#[test]
 	fn test_png_from_image_file() {
 		let png = Png::try_from(&PNG_FILE[..]);
@@ -348,6 +843,91 @@ mod tests {
 		let _png_string = format!("{}", png);
 	}
 
+	#[test]
+	fn test_png_is_send_and_sync() {
+		// parsing is shared out over threads by `check`
+		fn assert_send_sync<T: Send + Sync>() {}
+		assert_send_sync::<Png>();
+		assert_send_sync::<PngError>();
+	}
+
+	#[test]
+	fn test_find_chunk_streaming() {
+		let found = Png::find_chunk_streaming(&PNG_FILE[..], "IEND", &ParseOptions::default())
+			.unwrap()
+			.unwrap();
+		assert_eq!(found.chunk_type().to_string(), "IEND");
+
+		let mut png = testing_png();
+		png.insert_chunk(3, chunk_from_strings("TeSt", "Message").unwrap())
+			.unwrap();
+		let bytes = png.as_bytes();
+		let found = Png::find_chunk_streaming(bytes.as_slice(), "TeSt", &ParseOptions::default()).unwrap();
+		assert_eq!(found.unwrap().data(), b"Message");
+		assert!(Png::find_chunk_streaming(bytes.as_slice(), "nOne", &ParseOptions::default())
+			.unwrap()
+			.is_none());
+
+		// the chunk itself is still verified
+		let mut bytes = bytes;
+		let last = bytes.len() - 1;
+		bytes[last] ^= 1;
+		assert!(Png::find_chunk_streaming(bytes.as_slice(), "TeSt", &ParseOptions::default()).is_err());
+		let options = ParseOptions {
+			verify_crc: false,
+			..Default::default()
+		};
+		let found = Png::find_chunk_streaming(bytes.as_slice(), "TeSt", &options).unwrap();
+		assert_eq!(found.unwrap().data(), b"Message");
+
+		// bytes before the signature
+		let mut prefixed = b"junk".to_vec();
+		prefixed.extend_from_slice(&PNG_FILE);
+		assert!(Png::find_chunk_streaming(prefixed.as_slice(), "IEND", &ParseOptions::default()).is_err());
+		let options = ParseOptions {
+			max_prefix: 4,
+			..Default::default()
+		};
+		let found = Png::find_chunk_streaming(prefixed.as_slice(), "IEND", &options).unwrap();
+		assert_eq!(found.unwrap().chunk_type().to_string(), "IEND");
+	}
+
+	#[test]
+	fn test_iend_offset() {
+		let bytes = &PNG_FILE[..];
+		let offset = Png::iend_offset(std::io::Cursor::new(bytes)).unwrap().unwrap();
+		assert_eq!(offset as usize, bytes.len() - Chunk::METADATA_BYTES);
+
+		let cut = &bytes[..offset as usize];
+		assert_eq!(Png::iend_offset(std::io::Cursor::new(cut)).unwrap(), None);
+	}
+
+	#[test]
+	fn test_write_to() {
+		let png = Png::try_from(&PNG_FILE[..]).unwrap();
+		let mut out = Vec::new();
+		png.write_to(&mut out).unwrap();
+		assert_eq!(out, PNG_FILE);
+	}
+
+	#[test]
+	fn test_byte_len() {
+		let png = Png::try_from(&PNG_FILE[..]).unwrap();
+		let bytes = png.as_bytes();
+		assert_eq!(png.byte_len(), PNG_FILE.len());
+		assert_eq!(bytes.capacity(), bytes.len());
+		assert!(png.estimated_memory() > png.byte_len());
+	}
+
+	#[test]
+	fn test_png_display_table() {
+		let table = testing_png().to_string();
+		let rows: Vec<&str> = table.lines().collect();
+		assert_eq!(rows.len(), 4);
+		assert!(rows[0].contains("type"));
+		assert!(rows[2].contains("miDl") && rows[2].contains("I am another chunk"));
+	}
+
 	// This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
 	const PNG_FILE: [u8; 4803] = [
 		137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 50, 0, 0, 0, 50, 8,
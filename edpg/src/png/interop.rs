@@ -0,0 +1,46 @@
+// Conversions to and from the `image` crate, so decoded images can be handed
+// over without a trip through the disk.
+
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat};
+
+use super::{Png, PngError};
+
+impl Png {
+	/// Encodes `image` as a png. Only the chunks the encoder writes end up in
+	/// it, nothing of a previous file survives the decode.
+	pub fn from_image(image: &DynamicImage) -> Result<Png, PngError> {
+		let mut bytes = Vec::new();
+		image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+		Png::try_from(bytes.as_ref())
+	}
+}
+
+/// Decodes the pixels, ancillary chunks are dropped on the way.
+impl TryFrom<&Png> for DynamicImage {
+	type Error = PngError;
+
+	fn try_from(png: &Png) -> Result<Self, Self::Error> {
+		Ok(image::load_from_memory_with_format(
+			&png.as_bytes(),
+			ImageFormat::Png,
+		)?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_image_round_trip() {
+		let image = DynamicImage::new_rgba8(3, 2);
+		let png = Png::from_image(&image).unwrap();
+		assert_eq!(&png.chunks()[0].chunk_type().to_string(), "IHDR");
+
+		let decoded = DynamicImage::try_from(&png).unwrap();
+		assert_eq!((decoded.width(), decoded.height()), (3, 2));
+		assert_eq!(decoded.to_rgba8(), image.to_rgba8());
+	}
+}
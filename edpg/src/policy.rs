@@ -0,0 +1,229 @@
+// Image hygiene rules, written down once and checked against every file.
+//
+// With the `serde` feature a policy can be read from a file, eg: as TOML
+//
+//   allowed_chunk_types = ["IHDR", "PLTE", "IDAT", "IEND", "tEXt"]
+//   required_chunk_types = ["IHDR", "IDAT", "IEND"]
+//   max_ancillary_bytes = 4096
+//   forbidden_patterns = ["password"]
+//   deny_trailing_data = true
+
+use std::fmt::Display;
+
+use crate::detect::{self, Finding};
+use crate::png::{Png, PngError};
+use crate::report::Location;
+use crate::search::Searcher;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct Policy {
+	/// Only these chunk types may appear, any type if not set.
+	pub allowed_chunk_types: Option<Vec<String>>,
+	/// Chunk types that may never appear.
+	pub denied_chunk_types: Vec<String>,
+	/// Chunk types that must appear at least once.
+	pub required_chunk_types: Vec<String>,
+	/// Upper bound on the data of all ancillary chunks together.
+	pub max_ancillary_bytes: Option<usize>,
+	/// Byte strings that may not appear in ancillary chunk data.
+	pub forbidden_patterns: Vec<String>,
+	/// Whether data after IEND is a violation.
+	pub deny_trailing_data: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+	NotAllowed { index: usize, chunk_type: String },
+	Denied { index: usize, chunk_type: String },
+	Missing { chunk_type: String },
+	AncillaryBytes { bytes: usize, max: usize },
+	ForbiddenPattern { index: usize, pattern: String },
+	TrailingData { offset: usize, len: usize },
+}
+
+impl Display for Violation {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::NotAllowed { index, chunk_type } => {
+				write!(f, "chunk {index}: `{chunk_type}` is not an allowed chunk type")
+			},
+			Self::Denied { index, chunk_type } => {
+				write!(f, "chunk {index}: `{chunk_type}` is a denied chunk type")
+			},
+			Self::Missing { chunk_type } => write!(f, "required `{chunk_type}` chunk is missing"),
+			Self::AncillaryBytes { bytes, max } => {
+				write!(f, "{bytes} bytes of ancillary data, at most {max} allowed")
+			},
+			Self::ForbiddenPattern { index, pattern } => {
+				write!(f, "chunk {index}: contains forbidden pattern `{pattern}`")
+			},
+			Self::TrailingData { offset, len } => {
+				write!(f, "offset {offset}: {len} bytes after IEND")
+			},
+		}
+	}
+}
+
+impl Violation {
+	/// Stable name of the rule broken, for tools keyed on it.
+	pub fn rule(&self) -> &'static str {
+		match self {
+			Self::NotAllowed { .. } => "policy-not-allowed",
+			Self::Denied { .. } => "policy-denied",
+			Self::Missing { .. } => "policy-missing",
+			Self::AncillaryBytes { .. } => "policy-ancillary-bytes",
+			Self::ForbiddenPattern { .. } => "policy-forbidden-pattern",
+			Self::TrailingData { .. } => "policy-trailing-data",
+		}
+	}
+
+	pub fn location(&self) -> Location {
+		match self {
+			Self::NotAllowed { index, .. }
+			| Self::Denied { index, .. }
+			| Self::ForbiddenPattern { index, .. } => Location::Chunk(*index),
+			Self::TrailingData { offset, .. } => Location::Offset(*offset),
+			Self::Missing { .. } | Self::AncillaryBytes { .. } => Location::File,
+		}
+	}
+}
+
+impl Policy {
+	/// Every rule `bytes` breaks, chunk type rules first.
+	pub fn check(&self, bytes: &[u8]) -> Result<Vec<Violation>, PngError> {
+		let png = Png::try_from(bytes)?;
+		let mut violations = Vec::new();
+		let types: Vec<String> = png
+			.chunks()
+			.iter()
+			.map(|c| c.chunk_type().to_string())
+			.collect();
+
+		for (index, chunk_type) in types.iter().enumerate() {
+			if let Some(allowed) = &self.allowed_chunk_types {
+				if !allowed.contains(chunk_type) {
+					violations.push(Violation::NotAllowed {
+						index,
+						chunk_type: chunk_type.clone(),
+					});
+				}
+			}
+			if self.denied_chunk_types.contains(chunk_type) {
+				violations.push(Violation::Denied {
+					index,
+					chunk_type: chunk_type.clone(),
+				});
+			}
+		}
+
+		for chunk_type in &self.required_chunk_types {
+			if !types.contains(chunk_type) {
+				violations.push(Violation::Missing {
+					chunk_type: chunk_type.clone(),
+				});
+			}
+		}
+
+		let ancillary = png
+			.chunks()
+			.iter()
+			.enumerate()
+			.filter(|(_, c)| !c.chunk_type().is_critical());
+
+		if let Some(max) = self.max_ancillary_bytes {
+			let bytes = ancillary.clone().map(|(_, c)| c.data().len()).sum();
+			if bytes > max {
+				violations.push(Violation::AncillaryBytes { bytes, max });
+			}
+		}
+
+		let searcher = Searcher::new(&self.forbidden_patterns);
+		for (index, chunk) in ancillary {
+			for pattern in searcher.matching(chunk.data()) {
+				violations.push(Violation::ForbiddenPattern {
+					index,
+					pattern: self.forbidden_patterns[pattern].clone(),
+				});
+			}
+		}
+
+		if self.deny_trailing_data {
+			for finding in detect::scan(bytes)? {
+				if let Finding::TrailingData { offset, len } = finding {
+					violations.push(Violation::TrailingData { offset, len });
+				}
+			}
+		}
+
+		Ok(violations)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::chunk::Chunk;
+	use crate::chunk_type::ChunkType;
+
+	fn chunk(t: &str, d: &str) -> Chunk {
+		Chunk::new(ChunkType::from_str(t).unwrap(), d.into())
+	}
+
+	fn testing_bytes() -> Vec<u8> {
+		Png::from_chunks(vec![
+			chunk("IHDR", "header"),
+			chunk("tEXt", "Comment\0my password"),
+			chunk("ruSt", "secret"),
+			chunk("IEND", ""),
+		])
+		.as_bytes()
+	}
+
+	#[test]
+	fn test_default_allows_everything() {
+		assert!(Policy::default().check(&testing_bytes()).unwrap().is_empty());
+	}
+
+	#[test]
+	fn test_chunk_types() {
+		let policy = Policy {
+			allowed_chunk_types: Some(vec!["IHDR".into(), "tEXt".into(), "IEND".into()]),
+			denied_chunk_types: vec!["tEXt".into()],
+			required_chunk_types: vec!["IDAT".into()],
+			..Default::default()
+		};
+		assert_eq!(policy.check(&testing_bytes()).unwrap(), [
+			Violation::Denied {
+				index: 1,
+				chunk_type: "tEXt".into()
+			},
+			Violation::NotAllowed {
+				index: 2,
+				chunk_type: "ruSt".into()
+			},
+			Violation::Missing {
+				chunk_type: "IDAT".into()
+			},
+		]);
+	}
+
+	#[test]
+	fn test_budget_and_patterns() {
+		let policy = Policy {
+			max_ancillary_bytes: Some(10),
+			forbidden_patterns: vec!["password".into(), "nothing".into()],
+			..Default::default()
+		};
+		assert_eq!(policy.check(&testing_bytes()).unwrap(), [
+			Violation::AncillaryBytes { bytes: 25, max: 10 },
+			Violation::ForbiddenPattern {
+				index: 1,
+				pattern: "password".into()
+			},
+		]);
+	}
+}
@@ -0,0 +1,210 @@
+// Files that are a png and a zip at once. Image readers stop at IEND, unzip
+// tools look for the end of central directory record from the back, so a zip
+// appended after IEND is seen by both, as long as its offsets account for the
+// png in front of it.
+
+use thiserror::Error;
+
+use crate::png::{Png, PngError};
+
+#[derive(Debug, Error)]
+pub enum PolyglotError {
+	#[error("{0}")]
+	Png(#[from] PngError),
+	#[error("Not a zip archive.")]
+	NotZip,
+	#[error("Zip64 archives are not supported.")]
+	Zip64,
+	#[error("Zip archive is corrupt, {0}.")]
+	Corrupt(&'static str),
+}
+
+const LOCAL_HEADER: &[u8; 4] = b"PK\x03\x04";
+const CENTRAL_HEADER: &[u8; 4] = b"PK\x01\x02";
+const END_OF_CENTRAL_DIRECTORY: &[u8; 4] = b"PK\x05\x06";
+const CENTRAL_HEADER_BYTES: usize = 46;
+const END_OF_CENTRAL_DIRECTORY_BYTES: usize = 22;
+
+fn u16_at(bytes: &[u8], at: usize) -> Result<usize, PolyglotError> {
+	bytes
+		.get(at..at + 2)
+		.map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+		.ok_or(PolyglotError::Corrupt("record cut short"))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Result<u32, PolyglotError> {
+	bytes
+		.get(at..at + 4)
+		.map(|b| u32::from_le_bytes(b.try_into().expect("slice is 4 bytes")))
+		.ok_or(PolyglotError::Corrupt("record cut short"))
+}
+
+/// Rewrites the offsets of `zip`, whose offsets count from `from` bytes
+/// before it, to count from `to` bytes before it instead. For when bytes are
+/// put in front of it or taken away.
+fn shift(zip: &mut [u8], from: usize, to: usize) -> Result<(), PolyglotError> {
+	// the comment at the end can be up to 64k, the record is the last match
+	let eocd = zip
+		.windows(4)
+		.rposition(|w| w == END_OF_CENTRAL_DIRECTORY)
+		.ok_or(PolyglotError::NotZip)?;
+	if eocd + END_OF_CENTRAL_DIRECTORY_BYTES > zip.len() {
+		return Err(PolyglotError::Corrupt("end of central directory cut short"));
+	}
+
+	let entries = u16_at(zip, eocd + 10)?;
+	let directory = u32_at(zip, eocd + 16)?;
+	if entries == 0xFFFF || directory == u32::MAX {
+		return Err(PolyglotError::Zip64);
+	}
+
+	let out_of_range = || PolyglotError::Corrupt("offset out of range");
+	let moved = |offset: u32| {
+		(offset as usize)
+			.checked_sub(from)
+			.and_then(|o| u32::try_from(o + to).ok())
+			.ok_or_else(out_of_range)
+	};
+
+	let mut at = (directory as usize)
+		.checked_sub(from)
+		.ok_or_else(out_of_range)?;
+	for _ in 0..entries {
+		if zip.get(at..at + 4) != Some(CENTRAL_HEADER) {
+			return Err(PolyglotError::Corrupt("bad central directory entry"));
+		}
+		let local = u32_at(zip, at + 42)?;
+		if local == u32::MAX {
+			return Err(PolyglotError::Zip64);
+		}
+		zip[at + 42..at + 46].copy_from_slice(&moved(local)?.to_le_bytes());
+
+		at += CENTRAL_HEADER_BYTES
+			+ u16_at(zip, at + 28)?
+			+ u16_at(zip, at + 30)?
+			+ u16_at(zip, at + 32)?;
+	}
+
+	zip[eocd + 16..eocd + 20].copy_from_slice(&moved(directory)?.to_le_bytes());
+	Ok(())
+}
+
+/// `png` with `zip` appended, both readable. Anything that already followed
+/// IEND is dropped.
+pub fn create(png: &[u8], zip: &[u8]) -> Result<Vec<u8>, PolyglotError> {
+	if !zip.starts_with(LOCAL_HEADER) {
+		return Err(PolyglotError::NotZip);
+	}
+	let end = Png::image_len(png)?;
+
+	let mut out = Vec::with_capacity(end + zip.len());
+	out.extend_from_slice(&png[..end]);
+	out.extend_from_slice(zip);
+	shift(&mut out[end..], 0, end)?;
+
+	Ok(out)
+}
+
+/// Whether `bytes` starts like a zip archive.
+pub fn is_zip(bytes: &[u8]) -> bool {
+	bytes.starts_with(LOCAL_HEADER)
+}
+
+/// Offset of a zip archive after IEND, if there is one.
+pub fn find_zip(bytes: &[u8]) -> Result<Option<usize>, PngError> {
+	let end = Png::image_len(bytes)?;
+	Ok(is_zip(&bytes[end..]).then_some(end))
+}
+
+/// The zip archive hidden after IEND, as a standalone file.
+pub fn extract(bytes: &[u8]) -> Result<Vec<u8>, PolyglotError> {
+	let start = find_zip(bytes)?.ok_or(PolyglotError::NotZip)?;
+	let mut zip = bytes[start..].to_vec();
+	shift(&mut zip, start, 0)?;
+	Ok(zip)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::chunk::Chunk;
+	use crate::chunk_type::ChunkType;
+
+	fn testing_png() -> Vec<u8> {
+		let chunk = |t: &str, d: &str| Chunk::new(ChunkType::from_str(t).unwrap(), d.into());
+		Png::from_chunks(vec![chunk("IHDR", "header"), chunk("IEND", "")]).as_bytes()
+	}
+
+	/// A stored (uncompressed) zip holding `a.txt`.
+	fn testing_zip() -> Vec<u8> {
+		let (name, data) = (b"a.txt", b"hello");
+		let mut zip = Vec::new();
+		zip.extend_from_slice(LOCAL_HEADER);
+		zip.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+		zip.extend_from_slice(&0x3610a686u32.to_le_bytes());
+		zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+		zip.extend_from_slice(&[0, 0]);
+		zip.extend_from_slice(name);
+		zip.extend_from_slice(data);
+
+		let directory = zip.len();
+		zip.extend_from_slice(CENTRAL_HEADER);
+		zip.extend_from_slice(&[20, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+		zip.extend_from_slice(&0x3610a686u32.to_le_bytes());
+		zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+		zip.extend_from_slice(&[0; 12]);
+		zip.extend_from_slice(&0u32.to_le_bytes());
+		zip.extend_from_slice(name);
+
+		let size = zip.len() - directory;
+		zip.extend_from_slice(END_OF_CENTRAL_DIRECTORY);
+		zip.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0]);
+		zip.extend_from_slice(&(size as u32).to_le_bytes());
+		zip.extend_from_slice(&(directory as u32).to_le_bytes());
+		zip.extend_from_slice(&[0, 0]);
+		zip
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let (png, zip) = (testing_png(), testing_zip());
+		let both = create(&png, &zip).unwrap();
+
+		assert_eq!(&both[..png.len()], png);
+		assert_eq!(find_zip(&both).unwrap(), Some(png.len()));
+		assert_eq!(extract(&both).unwrap(), zip);
+		assert_eq!(find_zip(&png).unwrap(), None);
+	}
+
+	#[test]
+	fn test_offsets_point_into_the_file() {
+		let png = testing_png();
+		let both = create(&png, &testing_zip()).unwrap();
+
+		let eocd = both.len() - END_OF_CENTRAL_DIRECTORY_BYTES;
+		let directory = u32_at(&both, eocd + 16).unwrap() as usize;
+		assert_eq!(&both[directory..directory + 4], CENTRAL_HEADER);
+		let local = u32_at(&both, directory + 42).unwrap() as usize;
+		assert_eq!((local, &both[local..local + 4]), (png.len(), &LOCAL_HEADER[..]));
+	}
+
+	#[test]
+	fn test_replaces_trailing_data() {
+		let mut png = testing_png();
+		let end = png.len();
+		png.extend_from_slice(b"junk");
+		let both = create(&png, &testing_zip()).unwrap();
+		assert_eq!(find_zip(&both).unwrap(), Some(end));
+	}
+
+	#[test]
+	fn test_not_zip() {
+		assert!(matches!(create(&testing_png(), b"nope"), Err(PolyglotError::NotZip)));
+	}
+}
@@ -0,0 +1,312 @@
+// Provenance stamps: who made an image, under which terms and out of which
+// pixels, written as standard text chunks any viewer shows. An optional
+// `vnPv` chunk seals them with an HMAC-SHA256 keyed by a passphrase, so
+// `verify` can tell whether the stamp was edited since.
+//
+// vnPv layout: version (1 byte) | salt (16 bytes) | mac (32 bytes) | the
+// keywords it covers, nul separated.
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::crypto::{self, CryptoError, SALT_BYTES};
+use crate::png::Png;
+use crate::text::{Text, TextError};
+
+pub const PROVENANCE_CHUNK_TYPE: &str = "vnPv";
+pub const CREATOR_KEYWORD: &str = "Author";
+pub const COPYRIGHT_KEYWORD: &str = "Copyright";
+pub const SOFTWARE_KEYWORD: &str = "Software";
+/// `sha256:` and the hex of `source_hash`.
+pub const SOURCE_HASH_KEYWORD: &str = "Source Hash";
+
+const VERSION: u8 = 1;
+const MAC_BYTES: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum ProvenanceError {
+	#[error("{0}")]
+	Text(#[from] TextError),
+	#[error("{0}")]
+	Crypto(#[from] CryptoError),
+	#[error("`{0}` is written by the stamp itself.")]
+	ReservedKeyword(String),
+	#[error("Malformed `vnPv` chunk.")]
+	BadSeal,
+}
+
+/// What a stamp says beyond the hash of the pixels.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+	pub creator: Option<String>,
+	pub copyright: Option<String>,
+	/// Fields of any other keyword, eg: C2PA style assertions such as
+	/// `c2pa.training-mining`.
+	pub claims: Vec<(String, String)>,
+}
+
+/// Whether the fields of a stamp are as they were sealed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Seal {
+	/// No `vnPv` chunk.
+	Unsealed,
+	/// Sealed, but no passphrase to check it with.
+	Unchecked,
+	Valid,
+	/// Wrong passphrase, or a field changed since.
+	Invalid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Verification {
+	/// Keyword and text of every field of the stamp found.
+	pub fields: Vec<(String, String)>,
+	/// `source_hash` of the png as it is now.
+	pub source_hash: String,
+	/// Whether it is the one recorded, `None` without a recorded one.
+	pub source_hash_matches: Option<bool>,
+	pub seal: Seal,
+}
+
+impl Verification {
+	/// A recorded hash that matches, and a seal that isn't broken.
+	pub fn is_valid(&self) -> bool {
+		self.source_hash_matches == Some(true) && self.seal != Seal::Invalid
+	}
+}
+
+/// `sha256:` and the hex sha256 of the data of IHDR, PLTE and the IDAT
+/// chunks in order: what the pixels are made of, whatever metadata comes and
+/// goes.
+pub fn source_hash(png: &Png) -> String {
+	let mut hasher = Sha256::new();
+	for chunk in png.chunks() {
+		if matches!(&chunk.chunk_type().bytes(), b"IHDR" | b"PLTE" | b"IDAT") {
+			hasher.update(chunk.data());
+		}
+	}
+	let hex: String = hasher.finalize().iter().map(|b| format!("{b:02x}")).collect();
+	format!("sha256:{hex}")
+}
+
+impl Provenance {
+	/// Keywords and texts written, in order.
+	fn fields(&self, png: &Png) -> Result<Vec<(String, String)>, ProvenanceError> {
+		let mut fields = Vec::new();
+		if let Some(creator) = &self.creator {
+			fields.push((CREATOR_KEYWORD.to_owned(), creator.clone()));
+		}
+		if let Some(copyright) = &self.copyright {
+			fields.push((COPYRIGHT_KEYWORD.to_owned(), copyright.clone()));
+		}
+		for (keyword, text) in &self.claims {
+			if is_reserved(keyword) {
+				return Err(ProvenanceError::ReservedKeyword(keyword.clone()));
+			}
+			fields.push((keyword.clone(), text.clone()));
+		}
+		fields.push((SOFTWARE_KEYWORD.to_owned(), "vanish".to_owned()));
+		fields.push((SOURCE_HASH_KEYWORD.to_owned(), source_hash(png)));
+		Ok(fields)
+	}
+
+	/// Writes the stamp into `png` right before IEND, replacing the text
+	/// chunks of the same keywords and any earlier seal. Sealed with
+	/// `passphrase` if there is one.
+	pub fn stamp(&self, png: &mut Png, passphrase: Option<&[u8]>) -> Result<(), ProvenanceError> {
+		let fields = self.fields(png)?;
+		let chunks = fields
+			.iter()
+			.map(|(keyword, text)| text_chunk(keyword, text))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let stale: Vec<usize> = png
+			.chunks()
+			.iter()
+			.enumerate()
+			.filter(|(_, c)| {
+				c.chunk_type().bytes() == PROVENANCE_CHUNK_TYPE.as_bytes()
+					|| Text::try_from(*c).is_ok_and(|t| fields.iter().any(|(k, _)| k == t.keyword()))
+			})
+			.map(|(i, _)| i)
+			.collect();
+		for i in stale.into_iter().rev() {
+			png.remove_chunk(i);
+		}
+		for chunk in chunks {
+			png.append_chunk(chunk);
+		}
+
+		if let Some(passphrase) = passphrase {
+			let mut salt = [0u8; SALT_BYTES];
+			OsRng.fill_bytes(&mut salt);
+			let keywords: Vec<&str> = fields.iter().map(|(k, _)| k.as_str()).collect();
+			let mac = mac(passphrase, &salt, &fields)?.finalize().into_bytes();
+
+			let mut data = vec![VERSION];
+			data.extend_from_slice(&salt);
+			data.extend_from_slice(&mac);
+			data.extend_from_slice(keywords.join("\0").as_bytes());
+			png.append_chunk(Chunk::new(PROVENANCE_CHUNK_TYPE.parse().expect("valid"), data));
+		}
+		Ok(())
+	}
+}
+
+/// Checks the stamp of `png`: the pixels against the hash it recorded and,
+/// with `passphrase`, the seal.
+pub fn verify(png: &Png, passphrase: Option<&[u8]>) -> Result<Verification, ProvenanceError> {
+	let texts: Vec<Text> = png.chunks().iter().filter_map(|c| Text::try_from(c).ok()).collect();
+	let field = |keyword: &str| {
+		texts
+			.iter()
+			.find(|t| t.keyword() == keyword)
+			.map(|t| (t.keyword().to_owned(), t.text().to_owned()))
+	};
+
+	let sealed = png.chunk_by_type(PROVENANCE_CHUNK_TYPE);
+	let (fields, seal) = match sealed {
+		Some(chunk) => {
+			let data = chunk.data();
+			let header = 1 + SALT_BYTES + MAC_BYTES;
+			if data.len() < header || data[0] != VERSION {
+				return Err(ProvenanceError::BadSeal);
+			}
+			let keywords = std::str::from_utf8(&data[header..]).map_err(|_| ProvenanceError::BadSeal)?;
+			let fields: Vec<(String, String)> = keywords.split('\0').filter_map(field).collect();
+			let seal = match passphrase {
+				None => Seal::Unchecked,
+				Some(_) if fields.len() != keywords.split('\0').count() => Seal::Invalid,
+				Some(passphrase) => {
+					let salt = &data[1..1 + SALT_BYTES];
+					// constant time, unlike comparing the bytes
+					match mac(passphrase, salt, &fields)?.verify_slice(&data[1 + SALT_BYTES..header]) {
+						Ok(()) => Seal::Valid,
+						Err(_) => Seal::Invalid,
+					}
+				},
+			};
+			(fields, seal)
+		},
+		None => {
+			let standard = [CREATOR_KEYWORD, COPYRIGHT_KEYWORD, SOFTWARE_KEYWORD, SOURCE_HASH_KEYWORD];
+			(standard.into_iter().filter_map(field).collect(), Seal::Unsealed)
+		},
+	};
+
+	let source_hash = source_hash(png);
+	let source_hash_matches = fields
+		.iter()
+		.find(|(k, _)| k == SOURCE_HASH_KEYWORD)
+		.map(|(_, recorded)| *recorded == source_hash);
+	Ok(Verification {
+		fields,
+		source_hash,
+		source_hash_matches,
+		seal,
+	})
+}
+
+fn is_reserved(keyword: &str) -> bool {
+	[CREATOR_KEYWORD, COPYRIGHT_KEYWORD, SOFTWARE_KEYWORD, SOURCE_HASH_KEYWORD].contains(&keyword)
+}
+
+/// tEXt if `text` is Latin-1, iTXt otherwise.
+fn text_chunk(keyword: &str, text: &str) -> Result<Chunk, TextError> {
+	let text = match Text::new(&"tEXt".parse().expect("valid"), keyword, text) {
+		Err(TextError::NotLatin1(_)) => Text::new(&"iTXt".parse().expect("valid"), keyword, text)?,
+		text => text?,
+	};
+	Ok(text.to_chunk())
+}
+
+/// The seal of `fields`, left unfinalized so `verify` can check one.
+fn mac(passphrase: &[u8], salt: &[u8], fields: &[(String, String)]) -> Result<Hmac<Sha256>, CryptoError> {
+	let key = crypto::derive_key(passphrase, salt)?;
+	let mut mac = Hmac::<Sha256>::new_from_slice(&key).expect("any key length");
+	for (keyword, text) in fields {
+		mac.update(keyword.as_bytes());
+		mac.update(b"\0");
+		mac.update(text.as_bytes());
+		mac.update(b"\0");
+	}
+	Ok(mac)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::chunk_type::ChunkType;
+
+	fn chunk(t: &str, d: &[u8]) -> Chunk {
+		Chunk::new(ChunkType::from_str(t).unwrap(), d.to_vec())
+	}
+
+	fn png() -> Png {
+		Png::from_chunks(vec![chunk("IHDR", b"header"), chunk("IDAT", b"pixels"), chunk("IEND", b"")])
+	}
+
+	fn provenance() -> Provenance {
+		Provenance {
+			creator: Some("Ada 李".into()),
+			copyright: Some("© 2026 Ada, CC BY 4.0".into()),
+			claims: vec![("c2pa.training-mining".into(), "notAllowed".into())],
+		}
+	}
+
+	#[test]
+	fn test_stamp_and_verify() {
+		let mut png = png();
+		provenance().stamp(&mut png, None).unwrap();
+		let verification = verify(&png, None).unwrap();
+		assert!(verification.is_valid());
+		assert_eq!(verification.seal, Seal::Unsealed);
+		assert_eq!(verification.fields[0], ("Author".into(), "Ada 李".into()));
+
+		// stamped again, nothing doubles up
+		provenance().stamp(&mut png, None).unwrap();
+		assert_eq!(png.positions_of("tEXt").len(), 4);
+		assert_eq!(png.positions_of("iTXt").len(), 1);
+
+		png.chunk_mut(1).unwrap().set_data(b"pixelz".to_vec());
+		assert_eq!(verify(&png, None).unwrap().source_hash_matches, Some(false));
+	}
+
+	#[test]
+	fn test_seal() {
+		let mut png = png();
+		provenance().stamp(&mut png, Some(b"secret")).unwrap();
+		assert_eq!(verify(&png, None).unwrap().seal, Seal::Unchecked);
+		assert_eq!(verify(&png, Some(b"secret")).unwrap().seal, Seal::Valid);
+		assert_eq!(verify(&png, Some(b"wrong")).unwrap().seal, Seal::Invalid);
+		assert_eq!(verify(&png, Some(b"secret")).unwrap().fields.len(), 5);
+
+		// the creator edited after sealing
+		let at = png.positions_of("iTXt")[0];
+		png.replace_chunk(at, text_chunk("Author", "Mallory").unwrap());
+		let verification = verify(&png, Some(b"secret")).unwrap();
+		assert_eq!(verification.seal, Seal::Invalid);
+		assert!(!verification.is_valid());
+	}
+
+	#[test]
+	fn test_reserved_keyword() {
+		let provenance = Provenance {
+			claims: vec![("Source Hash".into(), "sha256:00".into())],
+			..Default::default()
+		};
+		assert!(matches!(
+			provenance.stamp(&mut png(), None),
+			Err(ProvenanceError::ReservedKeyword(_))
+		));
+	}
+}
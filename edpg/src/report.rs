@@ -0,0 +1,152 @@
+// What analyses found, in one shape whatever found it (detect findings,
+// policy violations, files that don't parse) so every output format only has
+// to render this.
+
+use std::fmt::Display;
+
+use crate::detect::Finding;
+use crate::policy::Violation;
+
+/// Most severe first, reports are sorted this way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Severity {
+	/// The file is unreadable, not a valid png, or breaks a rule it is held
+	/// to.
+	Error,
+	/// Valid, but carries something that looks hidden.
+	Warning,
+}
+
+/// Where in the file an issue is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Location {
+	/// The file as a whole.
+	#[default]
+	File,
+	/// The chunk at this index.
+	Chunk(usize),
+	/// This byte offset.
+	Offset(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Issue {
+	pub path: String,
+	pub severity: Severity,
+	/// Kind of issue, eg: `trailing-data`, see `Finding::rule`.
+	#[cfg_attr(feature = "serde", serde(default))]
+	pub rule: String,
+	#[cfg_attr(feature = "serde", serde(default))]
+	pub location: Location,
+	pub message: String,
+	/// How to get rid of it, if there is a way.
+	#[cfg_attr(feature = "serde", serde(default))]
+	pub remediation: Option<String>,
+}
+
+impl Issue {
+	/// A file that couldn't be analysed at all.
+	pub fn failed(path: &str, rule: &str, message: String) -> Issue {
+		Issue {
+			path: path.to_owned(),
+			severity: Severity::Error,
+			rule: rule.to_owned(),
+			location: Location::File,
+			message,
+			remediation: None,
+		}
+	}
+
+	pub fn from_finding(path: &str, severity: Severity, finding: &Finding) -> Issue {
+		Issue {
+			path: path.to_owned(),
+			severity,
+			rule: finding.rule().to_owned(),
+			location: finding.location(),
+			message: finding.to_string(),
+			remediation: finding.remediation().map(str::to_owned),
+		}
+	}
+
+	pub fn from_violation(path: &str, violation: &Violation) -> Issue {
+		Issue {
+			path: path.to_owned(),
+			severity: Severity::Error,
+			rule: violation.rule().to_owned(),
+			location: violation.location(),
+			message: violation.to_string(),
+			remediation: None,
+		}
+	}
+}
+
+impl Display for Issue {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}: {:?}: {}", self.path, self.severity, self.message)
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report {
+	pub files: usize,
+	pub issues: Vec<Issue>,
+}
+
+impl Report {
+	/// Report of a single file.
+	pub fn file(issues: Vec<Issue>) -> Report {
+		Report { files: 1, issues }
+	}
+
+	pub fn merge(mut self, other: Report) -> Report {
+		self.files += other.files;
+		self.issues.extend(other.issues);
+		self
+	}
+
+	pub fn count(&self, severity: Severity) -> usize {
+		self.issues.iter().filter(|i| i.severity == severity).count()
+	}
+
+	/// Sorts by severity, then path, issues of a file staying in file order.
+	pub fn sort(&mut self) {
+		self.issues
+			.sort_by(|a, b| (a.severity, &a.path).cmp(&(b.severity, &b.path)));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_from_finding() {
+		let finding = Finding::TrailingData { offset: 20, len: 4 };
+		let issue = Issue::from_finding("a.png", Severity::Warning, &finding);
+		assert_eq!(issue.rule, "trailing-data");
+		assert_eq!(issue.location, Location::Offset(20));
+		assert!(issue.remediation.is_some());
+		assert_eq!(issue.to_string(), "a.png: Warning: offset 20: 4 bytes after IEND");
+	}
+
+	#[test]
+	fn test_sort_and_count() {
+		let warning = |path: &str| Issue {
+			severity: Severity::Warning,
+			..Issue::failed(path, "x", String::new())
+		};
+		let mut report = Report::file(vec![warning("b"), warning("a")])
+			.merge(Report::file(vec![Issue::failed("c", "x", String::new())]));
+		report.sort();
+
+		let paths: Vec<&str> = report.issues.iter().map(|i| i.path.as_str()).collect();
+		assert_eq!(paths, ["c", "a", "b"]);
+		assert_eq!((report.files, report.count(Severity::Warning)), (2, 2));
+	}
+}
@@ -0,0 +1,162 @@
+// Minimal reproductions of parse failures, small and free of pixel data so
+// they can be attached to bug reports.
+
+use crate::chunk::Chunk;
+use crate::png::Png;
+
+/// Where a chunk sits in the input, as far as its length field can tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+	pub offset: usize,
+	/// Whole chunk, length field through CRC.
+	pub len: usize,
+	pub chunk_type: [u8; 4],
+}
+
+/// Bytes kept from the start of a file whose header is already broken.
+pub const HEADER_CONTEXT_BYTES: usize = 64;
+
+/// Walks the chunk layout of `bytes` using only the length fields, until the
+/// first chunk that fails to parse. Returns the spans walked and the index of
+/// the failing one, `None` if every chunk parses.
+pub fn find_failure(bytes: &[u8]) -> (Vec<Span>, Option<usize>) {
+	let mut spans = Vec::new();
+	let mut offset = Png::STANDARD_HEADER.len();
+
+	while offset + Chunk::LENGTH_BYTES <= bytes.len() {
+		let rest = &bytes[offset..];
+		let data_len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+		let mut chunk_type = [0u8; 4];
+		let type_bytes = &rest[Chunk::LENGTH_BYTES..][..Chunk::CHUNK_TYPE_BYTES.min(rest.len() - 4)];
+		chunk_type[..type_bytes.len()].copy_from_slice(type_bytes);
+
+		let len = Chunk::METADATA_BYTES.saturating_add(data_len);
+		let fits = len <= rest.len();
+		spans.push(Span {
+			offset,
+			len: len.min(rest.len()),
+			chunk_type,
+		});
+
+		if !fits || Chunk::try_from(&rest[..len]).is_err() {
+			let failing = spans.len() - 1;
+			return (spans, Some(failing));
+		}
+		offset += len;
+	}
+
+	(spans, None)
+}
+
+/// Cuts `bytes` down to what's needed to reproduce its parse failure: the png
+/// header, the first chunk (usually IHDR), the chunk right before the failing
+/// one and the failing chunk itself. Data of IDAT chunks is zeroed, CRCs of
+/// the context chunks are recomputed so only the failing chunk is broken.
+///
+/// Returns `None` if `bytes` parses fine.
+pub fn minimize(bytes: &[u8]) -> Option<Vec<u8>> {
+	if bytes.len() < Png::STANDARD_HEADER.len() || bytes[..8] != Png::STANDARD_HEADER {
+		return Some(bytes[..bytes.len().min(HEADER_CONTEXT_BYTES)].to_vec());
+	}
+
+	let (spans, failing) = find_failure(bytes);
+	let failing = failing?;
+
+	let mut context: Vec<usize> = vec![0, failing.saturating_sub(1)];
+	context.retain(|&i| i < failing);
+	context.dedup();
+
+	let mut out = Png::STANDARD_HEADER.to_vec();
+	for i in context {
+		let span = spans[i];
+		let mut chunk = Chunk::try_from(&bytes[span.offset..][..span.len])
+			.expect("chunks before the failing one parse");
+		if &span.chunk_type == b"IDAT" {
+			chunk = Chunk::new(*chunk.chunk_type(), vec![0; chunk.data().len()]);
+		}
+		out.extend(chunk.as_bytes());
+	}
+
+	let span = spans[failing];
+	let mut failed = bytes[span.offset..][..span.len].to_vec();
+	if &span.chunk_type == b"IDAT" {
+		let data_end = failed.len().saturating_sub(Chunk::CRC_LENGTH_BYTES);
+		let data_start = (Chunk::LENGTH_BYTES + Chunk::CHUNK_TYPE_BYTES).min(data_end);
+		failed[data_start..data_end].fill(0);
+	}
+	out.extend(failed);
+
+	Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::chunk_type::ChunkType;
+	use crate::mutate::{self, Mutation, MutationKind};
+
+	fn testing_png() -> Png {
+		let chunk = |t: &str, d: &[u8]| Chunk::new(ChunkType::from_str(t).unwrap(), d.into());
+		Png::from_chunks(vec![
+			chunk("IHDR", &[0; 13]),
+			chunk("IDAT", b"secret pixels"),
+			chunk("teXt", b"first"),
+			chunk("teXt", b"second"),
+			chunk("IEND", b""),
+		])
+	}
+
+	#[test]
+	fn test_valid_png_has_no_repro() {
+		assert!(minimize(&testing_png().as_bytes()).is_none());
+	}
+
+	#[test]
+	fn test_find_failure() {
+		let bytes = mutate::apply(&testing_png(), Mutation {
+			kind: MutationKind::BadCrc,
+			chunk: 3,
+		});
+		let (spans, failing) = find_failure(&bytes);
+		assert_eq!(failing, Some(3));
+		assert_eq!(spans[0].offset, 8);
+		assert_eq!(&spans[3].chunk_type, b"teXt");
+	}
+
+	#[test]
+	fn test_minimize_keeps_context_and_failure() {
+		let bytes = mutate::apply(&testing_png(), Mutation {
+			kind: MutationKind::BadCrc,
+			chunk: 3,
+		});
+		let repro = minimize(&bytes).unwrap();
+
+		// IHDR, the chunk before, then the failing one
+		let (spans, failing) = find_failure(&repro);
+		assert_eq!(failing, Some(2));
+		let types: Vec<_> = spans.iter().map(|s| s.chunk_type).collect();
+		assert_eq!(types, [*b"IHDR", *b"teXt", *b"teXt"]);
+		assert!(repro.len() < bytes.len());
+	}
+
+	#[test]
+	fn test_minimize_zeroes_pixels() {
+		let bytes = mutate::apply(&testing_png(), Mutation {
+			kind: MutationKind::BadCrc,
+			chunk: 2,
+		});
+		let repro = minimize(&bytes).unwrap();
+
+		assert!(!repro.windows(13).any(|w| w == b"secret pixels"));
+		let (spans, _) = find_failure(&repro);
+		assert_eq!(&spans[1].chunk_type, b"IDAT");
+	}
+
+	#[test]
+	fn test_minimize_bad_header() {
+		let repro = minimize(b"GIF89a").unwrap();
+		assert_eq!(repro, b"GIF89a");
+	}
+}
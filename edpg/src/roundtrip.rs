@@ -0,0 +1,205 @@
+// Whether a file comes out of a parse and serialize byte for byte the way it
+// went in, and where it doesn't. Whatever doesn't survive here (bytes around
+// the png, a wrong crc read with verification off) is lost by every in place
+// edit.
+
+use std::fmt::Display;
+
+use crate::chunk_type::ChunkType;
+use crate::png::{ParseOptions, Png, PngError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Difference {
+	/// Bytes before the png signature, see `ParseOptions::max_prefix`.
+	Prefix { len: usize },
+	/// A chunk written back differently, `at` is the offset of its first
+	/// byte that differs.
+	Chunk {
+		index: usize,
+		chunk_type: String,
+		offset: usize,
+		at: usize,
+	},
+	/// Bytes after IEND not written back as they were.
+	Trailer { offset: usize, len: usize },
+	/// Chunks written back that aren't in the input, or the other way round.
+	ChunkCount { input: usize, output: usize },
+}
+
+impl Display for Difference {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Prefix { len } => write!(f, "offset 0: the {len} bytes before the png are dropped"),
+			Self::Chunk {
+				index,
+				chunk_type,
+				offset,
+				at,
+			} => write!(
+				f,
+				"offset {at}: chunk {index} (`{chunk_type}` at {offset}) is written back differently"
+			),
+			Self::Trailer { offset, len } => {
+				write!(f, "offset {offset}: the {len} bytes after IEND are not kept as they were")
+			},
+			Self::ChunkCount { input, output } => {
+				write!(f, "{input} chunks read, {output} written back")
+			},
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Roundtrip {
+	pub input_len: usize,
+	pub output_len: usize,
+	pub differences: Vec<Difference>,
+}
+
+impl Roundtrip {
+	pub fn is_identical(&self) -> bool {
+		self.differences.is_empty()
+	}
+}
+
+impl Display for Roundtrip {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self.is_identical() {
+			true => write!(f, "identical, {} bytes", self.input_len)?,
+			false => write!(
+				f,
+				"differs, {} bytes in, {} bytes out",
+				self.input_len, self.output_len
+			)?,
+		}
+		for difference in &self.differences {
+			write!(f, "\n  {difference}")?;
+		}
+		Ok(())
+	}
+}
+
+/// Parses `bytes` with `options`, serializes the result and compares the two.
+pub fn compare(bytes: &[u8], options: &ParseOptions) -> Result<Roundtrip, PngError> {
+	let png = Png::parse_with(bytes, options)?;
+	let output = png.as_bytes();
+	let prefix = png.prefix().len();
+	let input = &bytes[prefix..];
+
+	let mut differences = Vec::new();
+	if prefix > 0 {
+		differences.push(Difference::Prefix { len: prefix });
+	}
+
+	let read = Png::chunk_ranges(input)?;
+	let written = Png::chunk_ranges(&output)?;
+	for (index, (r, w)) in read.iter().zip(&written).enumerate() {
+		let (before, after) = (&input[r.clone()], &output[w.clone()]);
+		if before == after {
+			continue;
+		}
+		let same = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+		differences.push(Difference::Chunk {
+			index,
+			chunk_type: ChunkType::from_bytes_lossy(after[4..8].try_into().expect("4 bytes"))
+				.map_or_else(|t| t.to_string(), |t| t.to_string()),
+			offset: prefix + r.start,
+			at: prefix + r.start + same,
+		});
+	}
+	if read.len() != written.len() {
+		differences.push(Difference::ChunkCount {
+			input: read.len(),
+			output: written.len(),
+		});
+	}
+
+	// chunks after IEND are parsed and written back too
+	let end = prefix + read.last().map_or(0, |r| r.end);
+	let written_end = written.last().map_or(0, |r| r.end);
+	if bytes[end..] != output[written_end..] {
+		differences.push(Difference::Trailer {
+			offset: end,
+			len: bytes.len() - end,
+		});
+	}
+
+	Ok(Roundtrip {
+		input_len: bytes.len(),
+		output_len: output.len(),
+		differences,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::chunk::Chunk;
+
+	fn chunk(t: &str, d: &str) -> Chunk {
+		Chunk::new(ChunkType::from_str(t).unwrap(), d.into())
+	}
+
+	fn png() -> Vec<u8> {
+		Png::from_chunks(vec![
+			chunk("IHDR", "header"),
+			chunk("ruSt", "hidden"),
+			chunk("IEND", ""),
+		])
+		.as_bytes()
+	}
+
+	#[test]
+	fn test_identical() {
+		let bytes = png();
+		let roundtrip = compare(&bytes, &ParseOptions::default()).unwrap();
+		assert!(roundtrip.is_identical());
+		assert_eq!(roundtrip.output_len, bytes.len());
+	}
+
+	#[test]
+	fn test_prefix() {
+		let mut bytes = b"junk".to_vec();
+		bytes.extend(png());
+		let options = ParseOptions {
+			max_prefix: 16,
+			..Default::default()
+		};
+
+		let roundtrip = compare(&bytes, &options).unwrap();
+		assert_eq!(roundtrip.differences, [Difference::Prefix { len: 4 }]);
+		assert_eq!(roundtrip.output_len, bytes.len() - 4);
+	}
+
+	#[test]
+	fn test_chunks_after_iend() {
+		let mut bytes = png();
+		bytes.extend(chunk("ruSt", "after").as_bytes());
+		assert!(compare(&bytes, &ParseOptions::default()).unwrap().is_identical());
+	}
+
+	#[test]
+	fn test_bad_crc() {
+		let mut bytes = png();
+		// last crc byte of the ruSt chunk: signature, IHDR, then ruSt
+		let crc_end = 8 + 18 + 18;
+		bytes[crc_end - 1] ^= 0xff;
+		let options = ParseOptions {
+			verify_crc: false,
+			..Default::default()
+		};
+
+		let roundtrip = compare(&bytes, &options).unwrap();
+		assert_eq!(roundtrip.differences, [Difference::Chunk {
+			index: 1,
+			chunk_type: "ruSt".into(),
+			offset: 8 + 18,
+			at: crc_end - 1,
+		}]);
+	}
+}
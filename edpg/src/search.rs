@@ -0,0 +1,113 @@
+// Looking for several byte patterns at once in chunk data, fast enough that
+// scanning is bound by memory bandwidth rather than by the search.
+//
+// Every pattern gets a SIMD accelerated `memchr::memmem::Finder`, built once
+// and reused over every haystack.
+
+use memchr::memmem::Finder;
+
+use crate::png::Png;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+	/// Index of the pattern in the list the `Searcher` was built from.
+	pub pattern: usize,
+	pub offset: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Searcher {
+	finders: Vec<Finder<'static>>,
+}
+
+impl Searcher {
+	/// Empty patterns are kept, so indices line up, but never match.
+	pub fn new<P: AsRef<[u8]>>(patterns: impl IntoIterator<Item = P>) -> Self {
+		Self {
+			finders: patterns
+				.into_iter()
+				.map(|p| Finder::new(p.as_ref()).into_owned())
+				.collect(),
+		}
+	}
+
+	fn finders(&self) -> impl Iterator<Item = (usize, &Finder<'static>)> {
+		self.finders
+			.iter()
+			.enumerate()
+			.filter(|(_, f)| !f.needle().is_empty())
+	}
+
+	/// Whether any pattern occurs in `haystack`.
+	pub fn is_match(&self, haystack: &[u8]) -> bool {
+		self.finders().any(|(_, f)| f.find(haystack).is_some())
+	}
+
+	/// Index of every pattern that occurs in `haystack`, in pattern order.
+	pub fn matching(&self, haystack: &[u8]) -> Vec<usize> {
+		self.finders()
+			.filter(|(_, f)| f.find(haystack).is_some())
+			.map(|(i, _)| i)
+			.collect()
+	}
+
+	/// Every occurrence of every pattern, sorted by offset. Occurrences of one
+	/// pattern don't overlap, those of different patterns may.
+	pub fn find_all(&self, haystack: &[u8]) -> Vec<Match> {
+		let mut matches: Vec<Match> = self
+			.finders()
+			.flat_map(|(pattern, f)| {
+				f.find_iter(haystack)
+					.map(move |offset| Match { pattern, offset })
+			})
+			.collect();
+		matches.sort_by_key(|m| (m.offset, m.pattern));
+		matches
+	}
+
+	/// `find_all` over the data of every chunk of `png`, with the chunk index.
+	pub fn find_in_chunks(&self, png: &Png) -> Vec<(usize, Match)> {
+		png.chunks()
+			.iter()
+			.enumerate()
+			.flat_map(|(index, chunk)| {
+				self.find_all(chunk.data())
+					.into_iter()
+					.map(move |m| (index, m))
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::chunk::Chunk;
+	use crate::chunk_type::ChunkType;
+
+	#[test]
+	fn test_find_all() {
+		let searcher = Searcher::new(["aba", "", "b"]);
+		assert_eq!(searcher.find_all(b"ababa"), [
+			Match { pattern: 0, offset: 0 },
+			Match { pattern: 2, offset: 1 },
+			Match { pattern: 2, offset: 3 },
+		]);
+		assert_eq!(searcher.matching(b"xbx"), [2]);
+		assert!(!searcher.is_match(b"xyz"));
+	}
+
+	#[test]
+	fn test_find_in_chunks() {
+		let chunk = |t: &str, d: &str| Chunk::new(ChunkType::from_str(t).unwrap(), d.into());
+		let png = Png::from_chunks(vec![
+			chunk("IHDR", "header"),
+			chunk("tEXt", "Comment\0secret"),
+			chunk("IEND", ""),
+		]);
+		let found = Searcher::new(["secret"]).find_in_chunks(&png);
+		assert_eq!(found, [(1, Match { pattern: 0, offset: 8 })]);
+	}
+}
@@ -0,0 +1,269 @@
+// Traces tools leave at the byte level of a png: chunk types they write and
+// magic bytes their output starts with, in a chunk or after IEND. Tools that
+// only touch pixel bits (what zsteg digs up, OpenStego's LSB modes) leave
+// nothing to match here.
+//
+// `builtin` covers the common encrypted and archive formats people append,
+// and the envelopes vanish itself writes.
+// With the `serde` feature more can be read from a file, eg: as TOML
+//
+//   [[signature]]
+//   tool = "sometool"
+//   chunk_type = "stGo"
+//
+//   [[signature]]
+//   tool = "othertool"
+//   magic_hex = "de ad be ef"
+
+use thiserror::Error;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::search::Searcher;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SignatureError {
+	#[error("Signature of `{0}` matches nothing, give it a chunk type or magic bytes.")]
+	Empty(String),
+	#[error("Signature of `{0}` has an invalid `magic_hex`.")]
+	InvalidHex(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct Signature {
+	/// Tool leaving the trace.
+	pub tool: String,
+	/// Chunk type the tool writes. Without magic bytes any chunk of this
+	/// type matches, without a chunk type every ancillary chunk and the
+	/// data after IEND are looked at.
+	pub chunk_type: Option<String>,
+	/// Text the data starts with.
+	pub magic: Option<String>,
+	/// Bytes the data starts with, as hex. Whitespace is ignored.
+	pub magic_hex: Option<String>,
+}
+
+/// A list of signatures as read from a file, under `[[signature]]`.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct SignatureFile {
+	#[cfg_attr(feature = "serde", serde(rename = "signature"))]
+	pub signatures: Vec<Signature>,
+}
+
+fn hex(s: &str) -> Option<Vec<u8>> {
+	let digits: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+	if !digits.len().is_multiple_of(2) {
+		return None;
+	}
+	digits
+		.chunks(2)
+		.map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+		.collect()
+}
+
+impl Signature {
+	fn text(tool: &str, magic: &str) -> Signature {
+		Signature {
+			tool: tool.to_owned(),
+			magic: Some(magic.to_owned()),
+			..Default::default()
+		}
+	}
+
+	fn binary(tool: &str, magic_hex: &str) -> Signature {
+		Signature {
+			tool: tool.to_owned(),
+			magic_hex: Some(magic_hex.to_owned()),
+			..Default::default()
+		}
+	}
+
+	/// The magic bytes, `None` if the signature has none.
+	pub fn magic_bytes(&self) -> Result<Option<Vec<u8>>, SignatureError> {
+		match (&self.magic, &self.magic_hex) {
+			(Some(magic), _) => Ok(Some(magic.as_bytes().to_vec())),
+			(None, Some(magic_hex)) => hex(magic_hex)
+				.map(Some)
+				.ok_or_else(|| SignatureError::InvalidHex(self.tool.clone())),
+			(None, None) => Ok(None),
+		}
+	}
+
+	/// Errors out on signatures that can't match anything.
+	pub fn validate(&self) -> Result<(), SignatureError> {
+		match (&self.chunk_type, self.magic_bytes()?) {
+			(None, None) => Err(SignatureError::Empty(self.tool.clone())),
+			(_, Some(magic)) if magic.is_empty() => Err(SignatureError::Empty(self.tool.clone())),
+			_ => Ok(()),
+		}
+	}
+
+	/// Whether chunks of `chunk_type` are looked at, whatever they hold.
+	fn covers(&self, chunk_type: &ChunkType) -> bool {
+		match &self.chunk_type {
+			Some(t) => chunk_type.bytes() == t.as_bytes(),
+			None => !chunk_type.is_critical(),
+		}
+	}
+
+	/// Whether `chunk` carries this signature.
+	pub fn matches_chunk(&self, chunk: &Chunk) -> bool {
+		self.covers(chunk.chunk_type()) && self.matches_data(|magic| chunk.data().starts_with(magic))
+	}
+
+	/// Whether data this signature covers matches, `starts_with` telling if
+	/// it starts with the magic bytes.
+	fn matches_data(&self, starts_with: impl FnOnce(&[u8]) -> bool) -> bool {
+		match self.magic_bytes() {
+			Ok(Some(magic)) => starts_with(&magic),
+			Ok(None) => self.chunk_type.is_some(),
+			Err(_) => false,
+		}
+	}
+
+	/// Whether the data after IEND carries this signature.
+	pub fn matches_trailer(&self, trailer: &[u8]) -> bool {
+		self.chunk_type.is_none()
+			&& self
+				.magic_bytes()
+				.is_ok_and(|magic| magic.is_some_and(|m| !m.is_empty() && trailer.starts_with(&m)))
+	}
+}
+
+/// Signatures every chunk of `png` carries, by chunk index, as
+/// `matches_chunk` would find them. The magic bytes of all of them are looked
+/// for in one pass over the chunk data, see `Searcher`.
+pub fn match_chunks<'a>(signatures: &'a [Signature], png: &Png) -> Vec<Vec<&'a Signature>> {
+	// invalid ones stay in as empty patterns, so the indices line up
+	let magics: Vec<Vec<u8>> = signatures
+		.iter()
+		.map(|s| s.magic_bytes().ok().flatten().unwrap_or_default())
+		.collect();
+	let mut at_start = vec![Vec::new(); png.chunks().len()];
+	for (index, found) in Searcher::new(&magics).find_in_chunks(png) {
+		if found.offset == 0 {
+			at_start[index].push(found.pattern);
+		}
+	}
+
+	png.chunks()
+		.iter()
+		.zip(at_start)
+		.map(|(chunk, at_start)| {
+			signatures
+				.iter()
+				.enumerate()
+				.filter(|(i, s)| s.covers(chunk.chunk_type()) && s.matches_data(|_| at_start.contains(i)))
+				.map(|(_, s)| s)
+				.collect()
+		})
+		.collect()
+}
+
+/// What ships with vanish: encrypted blobs and archives, formats with no
+/// business inside or after an image, and what vanish itself leaves. Zip
+/// archives are already reported as polyglots.
+pub fn builtin() -> Vec<Signature> {
+	vec![
+		// `encode --totp`, see `crypto::TOTP_MAGIC`
+		Signature::binary("vanish (totp)", "89 4f 54 50"),
+		// manifest of an `encode-file`, see `payload::MANIFEST_CHUNK_TYPE`
+		Signature {
+			tool: "vanish (embedded file)".to_owned(),
+			chunk_type: Some("vnMf".to_owned()),
+			..Default::default()
+		},
+		// `encode-file --compress`, the first chunk starts a zstd frame
+		Signature::binary("zstd", "28 b5 2f fd"),
+		// `openssl enc` with a salt, the default
+		Signature::text("openssl enc", "Salted__"),
+		Signature::text("age", "age-encryption.org/v1"),
+		Signature::text("gpg (armored)", "-----BEGIN PGP MESSAGE-----"),
+		Signature::binary("7-Zip", "37 7a bc af 27 1c"),
+		Signature::binary("RAR", "52 61 72 21 1a 07"),
+		Signature::binary("gzip", "1f 8b 08"),
+		Signature::binary("xz", "fd 37 7a 58 5a 00"),
+	]
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+	use crate::crypto::TOTP_MAGIC;
+	use crate::payload::MANIFEST_CHUNK_TYPE;
+
+	fn chunk(t: &str, d: &[u8]) -> Chunk {
+		Chunk::new(ChunkType::from_str(t).unwrap(), d.to_vec())
+	}
+
+	#[test]
+	fn test_magic_bytes() {
+		let sig = Signature::binary("x", "de ad\nbe ef");
+		assert_eq!(sig.magic_bytes(), Ok(Some(vec![0xde, 0xad, 0xbe, 0xef])));
+		assert_eq!(
+			Signature::binary("x", "abc").validate(),
+			Err(SignatureError::InvalidHex("x".into()))
+		);
+		assert_eq!(
+			Signature::text("x", "").validate(),
+			Err(SignatureError::Empty("x".into()))
+		);
+		assert!(builtin().iter().all(|s| s.validate().is_ok()));
+	}
+
+	#[test]
+	fn test_matches() {
+		let salted = Signature::text("openssl enc", "Salted__");
+		assert!(salted.matches_chunk(&chunk("ruSt", b"Salted__12345678")));
+		assert!(!salted.matches_chunk(&chunk("IDAT", b"Salted__12345678")));
+		assert!(salted.matches_trailer(b"Salted__12345678"));
+		assert!(!salted.matches_trailer(b"x"));
+
+		let typed = Signature {
+			tool: "stgo".into(),
+			chunk_type: Some("stGo".into()),
+			..Default::default()
+		};
+		assert!(typed.matches_chunk(&chunk("stGo", b"anything")));
+		assert!(!typed.matches_chunk(&chunk("ruSt", b"anything")));
+		assert!(!typed.matches_trailer(b"anything"));
+	}
+
+	#[test]
+	fn test_match_chunks() {
+		let mut signatures = builtin();
+		signatures.push(Signature::binary("broken", "zz"));
+		let png = Png::from_chunks(vec![
+			chunk("IHDR", b"header"),
+			chunk("IDAT", b"Salted__ in pixels"),
+			chunk("ruSt", b"x Salted__ not at the start"),
+			chunk("ruSt", &[&TOTP_MAGIC[..], b"sealed"].concat()),
+			chunk(MANIFEST_CHUNK_TYPE, b"\x01fiLe"),
+			chunk("IEND", b""),
+		]);
+		let found = match_chunks(&signatures, &png);
+		let tools: Vec<Vec<&str>> = found
+			.iter()
+			.map(|s| s.iter().map(|s| s.tool.as_str()).collect())
+			.collect();
+		assert_eq!(tools, [
+			vec![],
+			vec![],
+			vec![],
+			vec!["vanish (totp)"],
+			vec!["vanish (embedded file)"],
+			vec![],
+		]);
+		for (chunk, found) in png.chunks().iter().zip(&found) {
+			let each: Vec<&Signature> = signatures.iter().filter(|s| s.matches_chunk(chunk)).collect();
+			assert_eq!(&each, found);
+		}
+	}
+}
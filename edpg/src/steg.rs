@@ -0,0 +1,825 @@
+// Payloads hidden in the pixels instead of a chunk of their own: the least
+// significant bit of every sample carries a bit of a length prefixed
+// message. A chunk lister sees nothing but the image's own chunks.
+//
+// The image data is inflated and unfiltered, the bits written, then every
+// row filtered again with the filter it had and the lot deflated back into
+// IDAT chunks. Only 8 and 16 bit non-interlaced grayscale and truecolor
+// images, with or without alpha: a bit flipped in a palette index is a
+// different color altogether.
+//
+// `spread` splits a message over a set of images, eg: the frames of a
+// screen recording, each holding a share as large as its pixels allow
+// relative to the others, so none of them carries more than the rest. Every
+// piece has a crc of its own, and an optional parity piece lets
+// `gather_damaged` rebuild one that went missing.
+
+use std::io::{Read, Write};
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use thiserror::Error;
+
+use crate::analysis::MAX_INFLATED_BYTES;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::payload::DEFAULT_CHUNK_DATA_BYTES;
+use crate::png::Png;
+use crate::report::{Issue, Location, Severity};
+
+/// Bytes of the length written ahead of the message.
+const LENGTH_BYTES: usize = 4;
+/// Bytes `spread` writes ahead of every piece, see `Piece`.
+const PIECE_HEADER_BYTES: usize = 21;
+/// Most pieces a message is read back from, more is a damaged header.
+const MAX_PIECES: usize = 1 << 16;
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+#[derive(Debug, Error)]
+pub enum StegError {
+	#[error("No valid IHDR chunk.")]
+	NoHeader,
+	#[error("No IDAT chunk.")]
+	NoImageData,
+	#[error("{0} not supported, only 8 and 16 bit non-interlaced grayscale or truecolor images.")]
+	Unsupported(String),
+	#[error("Corrupt image data. {0}")]
+	Corrupt(String),
+	#[error("Message is {len} bytes, the pixels only have room for {capacity}.")]
+	TooLarge { len: usize, capacity: usize },
+	#[error("No message hidden in the pixels.")]
+	NoMessage,
+	#[error("No images to spread the message over.")]
+	NoImages,
+	#[error("Image {position} holds piece {index} of {count}, images are missing or out of order.")]
+	OutOfOrder {
+		position: usize,
+		index: usize,
+		count: usize,
+	},
+	#[error("The pieces don't add up to the message, checksum mismatch.")]
+	ChecksumMismatch,
+}
+
+/// What of IHDR matters here.
+#[derive(Debug, Clone, Copy)]
+struct Header {
+	width: usize,
+	height: usize,
+	bit_depth: u8,
+	channels: usize,
+}
+
+impl Header {
+	fn parse(png: &Png) -> Result<Header, StegError> {
+		let ihdr = png.chunk_by_type("IHDR").ok_or(StegError::NoHeader)?;
+		let [w0, w1, w2, w3, h0, h1, h2, h3, bit_depth, color_type, _, _, interlace] =
+			*ihdr.data()
+		else {
+			return Err(StegError::NoHeader);
+		};
+		let channels = match color_type {
+			0 => 1,
+			2 => 3,
+			4 => 2,
+			6 => 4,
+			3 => return Err(StegError::Unsupported("Palette image".to_owned())),
+			_ => return Err(StegError::NoHeader),
+		};
+		match (bit_depth, interlace) {
+			(8 | 16, 0) => {},
+			(8 | 16, _) => return Err(StegError::Unsupported("Interlaced image".to_owned())),
+			_ => return Err(StegError::Unsupported(format!("{bit_depth} bit image"))),
+		}
+
+		let header = Header {
+			width: u32::from_be_bytes([w0, w1, w2, w3]) as usize,
+			height: u32::from_be_bytes([h0, h1, h2, h3]) as usize,
+			bit_depth,
+			channels,
+		};
+		// every size below is at most this one, checked once here
+		let inflated = header
+			.width
+			.checked_mul(header.pixel_bytes())
+			.and_then(|row| row.checked_add(1))
+			.and_then(|row| row.checked_mul(header.height));
+		if inflated.is_none() {
+			return Err(StegError::Corrupt(format!(
+				"{}x{} image is too large.",
+				header.width, header.height
+			)));
+		}
+		Ok(header)
+	}
+
+	/// Bytes per complete pixel, the distance filters look back.
+	fn pixel_bytes(&self) -> usize {
+		self.channels * self.bit_depth as usize / 8
+	}
+
+	fn row_bytes(&self) -> usize {
+		self.width * self.pixel_bytes()
+	}
+
+	/// Filter bytes and all, what IDAT inflates to.
+	fn inflated_bytes(&self) -> usize {
+		self.height * (1 + self.row_bytes())
+	}
+
+	/// Bytes carrying a bit: every sample, the low byte of it at 16 bits.
+	fn carriers(&self) -> usize {
+		self.height * self.row_bytes() / (self.bit_depth as usize / 8)
+	}
+
+	/// Offset in the unfiltered pixels of the `n`th carrier.
+	fn carrier(&self, n: usize) -> usize {
+		match self.bit_depth {
+			16 => n * 2 + 1,
+			_ => n,
+		}
+	}
+
+	fn capacity(&self) -> usize {
+		(self.carriers() / 8).saturating_sub(LENGTH_BYTES)
+	}
+}
+
+/// The image data unfiltered, and the filter every row had.
+struct Pixels {
+	header: Header,
+	filters: Vec<u8>,
+	raw: Vec<u8>,
+}
+
+impl Pixels {
+	fn read(png: &Png) -> Result<Pixels, StegError> {
+		let header = Header::parse(png)?;
+		let compressed: Vec<u8> = png
+			.chunks()
+			.iter()
+			.filter(|c| &c.chunk_type().bytes() == b"IDAT")
+			.flat_map(|c| c.data().iter().copied())
+			.collect();
+		if compressed.is_empty() {
+			return Err(StegError::NoImageData);
+		}
+
+		let row_bytes = header.row_bytes();
+		let expected = header.inflated_bytes();
+		// IHDR can claim anything, the read is bounded by `take`
+		let mut inflated = Vec::with_capacity(expected.min(MAX_INFLATED_BYTES as usize));
+		ZlibDecoder::new(compressed.as_slice())
+			.take(expected as u64 + 1)
+			.read_to_end(&mut inflated)
+			.map_err(|e| StegError::Corrupt(e.to_string()))?;
+		if inflated.len() != expected {
+			return Err(StegError::Corrupt(format!(
+				"Inflates to {} bytes, IHDR calls for {expected}.",
+				inflated.len()
+			)));
+		}
+
+		let bpp = header.pixel_bytes();
+		let mut filters = Vec::with_capacity(header.height);
+		let mut raw = vec![0; header.height * row_bytes];
+		for (y, row) in inflated.chunks_exact(1 + row_bytes).enumerate() {
+			let filter = row[0];
+			if filter > 4 {
+				return Err(StegError::Corrupt(format!("Unknown filter {filter} on row {y}.")));
+			}
+			filters.push(filter);
+
+			let (done, rest) = raw.split_at_mut(y * row_bytes);
+			let prev = done.get(done.len().saturating_sub(row_bytes)..).filter(|_| y > 0);
+			let current = &mut rest[..row_bytes];
+			for x in 0..row_bytes {
+				let (a, b, c) = neighbours(current, prev, x, bpp);
+				current[x] = row[1 + x].wrapping_add(predict(filter, a, b, c));
+			}
+		}
+
+		Ok(Pixels {
+			header,
+			filters,
+			raw,
+		})
+	}
+
+	/// Filters, deflates and puts the pixels back in place of the IDAT
+	/// chunks of `png`.
+	fn write(&self, png: &mut Png) -> Result<(), StegError> {
+		let row_bytes = self.header.row_bytes();
+		let bpp = self.header.pixel_bytes();
+		let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+		let mut filtered = vec![0; 1 + row_bytes];
+		for (y, current) in self.raw.chunks_exact(row_bytes.max(1)).enumerate() {
+			let filter = self.filters[y];
+			let prev = y.checked_sub(1).map(|p| &self.raw[p * row_bytes..y * row_bytes]);
+			filtered[0] = filter;
+			for x in 0..row_bytes {
+				let (a, b, c) = neighbours(current, prev, x, bpp);
+				filtered[1 + x] = current[x].wrapping_sub(predict(filter, a, b, c));
+			}
+			encoder.write_all(&filtered).map_err(|e| StegError::Corrupt(e.to_string()))?;
+		}
+		let compressed = encoder.finish().map_err(|e| StegError::Corrupt(e.to_string()))?;
+
+		let idats = png.positions_of("IDAT");
+		let first = *idats.first().ok_or(StegError::NoImageData)?;
+		for &idx in idats.iter().rev() {
+			png.remove_chunk(idx);
+		}
+		let idat = ChunkType::try_from(*b"IDAT").expect("valid chunk type");
+		for (i, data) in compressed.chunks(DEFAULT_CHUNK_DATA_BYTES).enumerate() {
+			png.insert_chunk(first + i, Chunk::from_slice(idat, data))
+				.map_err(|e| StegError::Corrupt(e.to_string()))?;
+		}
+		Ok(())
+	}
+
+	fn bit(&self, n: usize) -> u8 {
+		self.raw[self.header.carrier(n)] & 1
+	}
+
+	fn set_bit(&mut self, n: usize, bit: u8) {
+		let byte = &mut self.raw[self.header.carrier(n)];
+		*byte = (*byte & !1) | bit;
+	}
+
+	/// `len` bytes from the bits starting at carrier `from`.
+	fn read_bytes(&self, from: usize, len: usize) -> Vec<u8> {
+		(0..len)
+			.map(|i| (0..8).fold(0, |byte, b| byte << 1 | self.bit(from + i * 8 + b)))
+			.collect()
+	}
+}
+
+/// Left, above and upper left of byte `x`, zero off the image.
+fn neighbours(current: &[u8], prev: Option<&[u8]>, x: usize, bpp: usize) -> (u8, u8, u8) {
+	let left = x.checked_sub(bpp);
+	let a = left.map_or(0, |l| current[l]);
+	let b = prev.map_or(0, |p| p[x]);
+	let c = prev.zip(left).map_or(0, |(p, l)| p[l]);
+	(a, b, c)
+}
+
+/// What `filter` predicts the byte to be, it stores the difference.
+fn predict(filter: u8, a: u8, b: u8, c: u8) -> u8 {
+	match filter {
+		1 => a,
+		2 => b,
+		3 => ((a as u16 + b as u16) / 2) as u8,
+		4 => {
+			let p = a as i16 + b as i16 - c as i16;
+			let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+			match (pa <= pb && pa <= pc, pb <= pc) {
+				(true, _) => a,
+				(false, true) => b,
+				(false, false) => c,
+			}
+		},
+		_ => 0,
+	}
+}
+
+/// Most message bytes the pixels of `png` can hold.
+pub fn capacity(png: &Png) -> Result<usize, StegError> {
+	Header::parse(png).map(|h| h.capacity())
+}
+
+/// Hides `message` in the least significant bits of the pixels of `png`.
+pub fn embed(png: &mut Png, message: &[u8]) -> Result<(), StegError> {
+	let mut pixels = Pixels::read(png)?;
+	let capacity = pixels.header.capacity();
+	if message.len() > capacity {
+		return Err(StegError::TooLarge {
+			len: message.len(),
+			capacity,
+		});
+	}
+
+	let length = (message.len() as u32).to_be_bytes();
+	for (i, byte) in length.iter().chain(message).enumerate() {
+		for b in 0..8 {
+			pixels.set_bit(i * 8 + b, byte >> (7 - b) & 1);
+		}
+	}
+	pixels.write(png)
+}
+
+/// The message `embed` hid in the pixels of `png`.
+pub fn extract(png: &Png) -> Result<Vec<u8>, StegError> {
+	let pixels = Pixels::read(png)?;
+	if pixels.header.carriers() < LENGTH_BYTES * 8 {
+		return Err(StegError::NoMessage);
+	}
+	let length = pixels.read_bytes(0, LENGTH_BYTES);
+	let len = u32::from_be_bytes(length.try_into().expect("4 bytes")) as usize;
+	if len > pixels.header.capacity() {
+		return Err(StegError::NoMessage);
+	}
+	Ok(pixels.read_bytes(LENGTH_BYTES * 8, len))
+}
+
+/// A piece of a message `spread` over several images, as written ahead of
+/// its data: its index, how many pieces there are, whether the last one is
+/// parity, the length and crc32 of the whole message, then the crc32 of
+/// the piece itself.
+#[derive(Debug, Clone)]
+struct Piece {
+	index: usize,
+	count: usize,
+	parity: bool,
+	len: usize,
+	checksum: u32,
+	data: Vec<u8>,
+}
+
+impl Piece {
+	fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(PIECE_HEADER_BYTES + self.data.len());
+		bytes.extend_from_slice(&(self.index as u32).to_be_bytes());
+		bytes.extend_from_slice(&(self.count as u32).to_be_bytes());
+		bytes.push(u8::from(self.parity));
+		bytes.extend_from_slice(&(self.len as u32).to_be_bytes());
+		bytes.extend_from_slice(&self.checksum.to_be_bytes());
+		bytes.extend_from_slice(&CRC.checksum(&self.data).to_be_bytes());
+		bytes.extend_from_slice(&self.data);
+		bytes
+	}
+
+	/// `None` unless `bytes` is a piece that came through intact.
+	fn parse(bytes: &[u8]) -> Option<Piece> {
+		let header = bytes.get(..PIECE_HEADER_BYTES)?;
+		let u32_at = |at: usize| u32::from_be_bytes(header[at..at + 4].try_into().expect("4 bytes"));
+		let piece = Piece {
+			index: u32_at(0) as usize,
+			count: u32_at(4) as usize,
+			parity: header[8] == 1,
+			len: u32_at(9) as usize,
+			checksum: u32_at(13),
+			data: bytes[PIECE_HEADER_BYTES..].to_vec(),
+		};
+		let intact = header[8] <= 1
+			&& piece.index < piece.count
+			&& piece.count <= MAX_PIECES
+			&& CRC.checksum(&piece.data) == u32_at(17);
+		intact.then_some(piece)
+	}
+
+	/// Whether `other` is a piece of the same message.
+	fn same_message(&self, other: &Piece) -> bool {
+		(self.count, self.parity, self.len, self.checksum)
+			== (other.count, other.parity, other.len, other.checksum)
+	}
+
+	fn is_parity(&self) -> bool {
+		self.parity && self.index == self.count - 1
+	}
+}
+
+/// Hides `message` over the pixels of `pngs`, every image a piece in
+/// proportion to its capacity. With `parity` the last image holds the xor
+/// of the others instead, every piece the same size, so that any one image
+/// can go missing, see `gather_damaged`. `gather` takes them back in the
+/// same order.
+pub fn spread(pngs: &mut [Png], message: &[u8], parity: bool) -> Result<(), StegError> {
+	if pngs.len() <= usize::from(parity) || pngs.len() > MAX_PIECES {
+		return Err(StegError::NoImages);
+	}
+	// room left once the header of a piece is written, checked for every
+	// image before any is touched
+	let capacities = pngs
+		.iter()
+		.map(|png| Ok(capacity(png)?.saturating_sub(PIECE_HEADER_BYTES)))
+		.collect::<Result<Vec<_>, StegError>>()?;
+
+	let count = pngs.len();
+	let data_pieces = count - usize::from(parity);
+	let ends: Vec<usize> = match parity {
+		true => {
+			let share = message.len().div_ceil(data_pieces);
+			let smallest = capacities.iter().copied().min().unwrap_or_default();
+			if share > smallest {
+				return Err(StegError::TooLarge {
+					len: message.len(),
+					capacity: smallest * data_pieces,
+				});
+			}
+			(1..=data_pieces).map(|i| (i * share).min(message.len())).collect()
+		},
+		false => {
+			let total: usize = capacities.iter().sum();
+			if message.len() > total {
+				return Err(StegError::TooLarge {
+					len: message.len(),
+					capacity: total,
+				});
+			}
+			let mut room = 0;
+			capacities
+				.iter()
+				.map(|capacity| {
+					room += capacity;
+					// rounded up, never past what the image has room for
+					(message.len() as u128 * room as u128).div_ceil(total.max(1) as u128) as usize
+				})
+				.collect()
+		},
+	};
+
+	let mut pieces = Vec::with_capacity(count);
+	let mut start = 0;
+	for (index, end) in ends.into_iter().enumerate() {
+		pieces.push(Piece {
+			index,
+			count,
+			parity,
+			len: message.len(),
+			checksum: CRC.checksum(message),
+			data: message[start..end].to_vec(),
+		});
+		start = end;
+	}
+	if parity {
+		let data = xor(pieces.iter().map(|p| p.data.as_slice()));
+		pieces.push(Piece {
+			index: count - 1,
+			data,
+			..pieces[0].clone()
+		});
+	}
+
+	for (png, piece) in pngs.iter_mut().zip(pieces) {
+		embed(png, &piece.to_bytes())?;
+	}
+	Ok(())
+}
+
+/// Byte by byte xor of `pieces`, as long as the longest.
+fn xor<'a>(pieces: impl Iterator<Item = &'a [u8]>) -> Vec<u8> {
+	let mut out = Vec::new();
+	for piece in pieces {
+		if piece.len() > out.len() {
+			out.resize(piece.len(), 0);
+		}
+		out.iter_mut().zip(piece).for_each(|(o, b)| *o ^= b);
+	}
+	out
+}
+
+/// The message `spread` hid over `pngs`, given in the order it had them,
+/// every one of them intact.
+pub fn gather(pngs: &[Png]) -> Result<Vec<u8>, StegError> {
+	if pngs.is_empty() {
+		return Err(StegError::NoImages);
+	}
+
+	let mut message = Vec::new();
+	let mut first: Option<Piece> = None;
+	for (position, png) in pngs.iter().enumerate() {
+		let piece = Piece::parse(&extract(png)?).ok_or(StegError::NoMessage)?;
+		if piece.index != position || piece.count != pngs.len() {
+			return Err(StegError::OutOfOrder {
+				position,
+				index: piece.index,
+				count: piece.count,
+			});
+		}
+		if !first.get_or_insert_with(|| piece.clone()).same_message(&piece) {
+			return Err(StegError::ChecksumMismatch);
+		}
+		if !piece.is_parity() {
+			message.extend_from_slice(&piece.data);
+		}
+	}
+
+	let first = first.expect("at least one png");
+	match first.len == message.len() && first.checksum == CRC.checksum(&message) {
+		true => Ok(message),
+		false => Err(StegError::ChecksumMismatch),
+	}
+}
+
+/// What became of a piece of the message, see `gather_damaged`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+	/// Read intact out of its image.
+	Recovered,
+	/// Lost, rebuilt out of the others and the parity piece.
+	Reconstructed,
+	/// Lost for good.
+	Missing,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+	pub index: usize,
+	/// Holds the xor of the others rather than part of the message.
+	pub parity: bool,
+	/// Where in the images given it was read from.
+	pub position: Option<usize>,
+	pub recovery: Recovery,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gathered {
+	/// `None` when too many pieces are missing.
+	pub message: Option<Vec<u8>>,
+	/// Every piece the message was spread over, in order.
+	pub fragments: Vec<Fragment>,
+	/// Images with no intact piece of the message, by position.
+	pub unusable: Vec<usize>,
+}
+
+impl Gathered {
+	/// Everything short of a fully recovered message, `paths` being those of
+	/// the images in the order they were given. Lost pieces go under the
+	/// first path, for the set as a whole.
+	pub fn issues(&self, paths: &[&str]) -> Vec<Issue> {
+		let set = paths.first().copied().unwrap_or_default();
+		let count = self.fragments.len();
+		let mut issues: Vec<Issue> = self
+			.unusable
+			.iter()
+			.map(|&position| Issue {
+				path: paths.get(position).copied().unwrap_or(set).to_owned(),
+				severity: Severity::Warning,
+				rule: "spread-unusable".to_owned(),
+				location: Location::File,
+				message: "No intact piece of the message, damaged or of another one.".to_owned(),
+				remediation: None,
+			})
+			.collect();
+		for fragment in &self.fragments {
+			let (severity, rule, what) = match fragment.recovery {
+				Recovery::Recovered => continue,
+				Recovery::Reconstructed => (Severity::Warning, "spread-reconstructed", "rebuilt from parity"),
+				Recovery::Missing => (Severity::Error, "spread-missing", "missing"),
+			};
+			issues.push(Issue {
+				path: set.to_owned(),
+				severity,
+				rule: rule.to_owned(),
+				location: Location::File,
+				message: format!("Piece {} of {count} {what}.", fragment.index + 1),
+				remediation: None,
+			});
+		}
+		issues
+	}
+}
+
+/// Like `gather`, for a set that lost images or had some damaged: `pngs` in
+/// any order, what is intact is put back in place and a single lost piece
+/// rebuilt if the message was spread with parity.
+pub fn gather_damaged(pngs: &[Png]) -> Result<Gathered, StegError> {
+	let mut unusable = Vec::new();
+	let mut found: Vec<(usize, Piece)> = Vec::new();
+	for (position, png) in pngs.iter().enumerate() {
+		match extract(png).ok().and_then(|bytes| Piece::parse(&bytes)) {
+			Some(piece) if found.first().is_none_or(|(_, first)| first.same_message(&piece)) => {
+				found.push((position, piece))
+			},
+			_ => unusable.push(position),
+		}
+	}
+	let first = found.first().map(|(_, piece)| piece.clone()).ok_or(StegError::NoMessage)?;
+
+	let mut pieces: Vec<Option<(usize, Piece)>> = vec![None; first.count];
+	for (position, piece) in found {
+		match &pieces[piece.index] {
+			// the same piece twice, eg: a copied image
+			Some(_) => unusable.push(position),
+			None => {
+				let index = piece.index;
+				pieces[index] = Some((position, piece));
+			},
+		}
+	}
+	unusable.sort();
+
+	let mut fragments: Vec<Fragment> = pieces
+		.iter()
+		.enumerate()
+		.map(|(index, piece)| Fragment {
+			index,
+			parity: first.parity && index == first.count - 1,
+			position: piece.as_ref().map(|(position, _)| *position),
+			recovery: match piece {
+				Some(_) => Recovery::Recovered,
+				None => Recovery::Missing,
+			},
+		})
+		.collect();
+
+	let data_pieces = first.count - usize::from(first.parity);
+	let mut data: Vec<Option<Vec<u8>>> = pieces[..data_pieces]
+		.iter()
+		.map(|p| p.as_ref().map(|(_, piece)| piece.data.clone()))
+		.collect();
+	let lost: Vec<usize> = (0..data_pieces).filter(|&i| data[i].is_none()).collect();
+	if let ([index], Some(Some((_, parity)))) = (lost.as_slice(), first.parity.then(|| &pieces[first.count - 1])) {
+		let share = first.len.div_ceil(data_pieces);
+		let len = ((index + 1) * share).min(first.len) - (index * share).min(first.len);
+		let others = data.iter().flatten().map(Vec::as_slice);
+		let mut rebuilt = xor(others.chain([parity.data.as_slice()]));
+		rebuilt.resize(len, 0);
+		data[*index] = Some(rebuilt);
+		fragments[*index].recovery = Recovery::Reconstructed;
+	}
+
+	let message = data
+		.into_iter()
+		.collect::<Option<Vec<_>>>()
+		.map(|pieces| pieces.concat())
+		.filter(|message| message.len() == first.len && CRC.checksum(message) == first.checksum);
+	Ok(Gathered {
+		message,
+		fragments,
+		unusable,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+
+	fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+		Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+	}
+
+	/// `width` x `height` image, every filter type used, split over two
+	/// IDAT chunks.
+	fn image(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Png {
+		let mut ihdr = Vec::new();
+		ihdr.extend_from_slice(&width.to_be_bytes());
+		ihdr.extend_from_slice(&height.to_be_bytes());
+		ihdr.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+
+		let channels = match color_type {
+			2 => 3,
+			6 => 4,
+			_ => 1,
+		};
+		let row_bytes = width as usize * channels * bit_depth.max(8) as usize / 8;
+		let mut inflated = Vec::new();
+		for y in 0..height as usize {
+			inflated.push((y % 5) as u8);
+			inflated.extend((0..row_bytes).map(|x| (x * 7 + y * 13) as u8));
+		}
+		let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+		encoder.write_all(&inflated).unwrap();
+		let compressed = encoder.finish().unwrap();
+		let (one, two) = compressed.split_at(compressed.len() / 2);
+
+		Png::from_chunks(vec![
+			chunk("IHDR", ihdr),
+			chunk("IDAT", one.to_vec()),
+			chunk("IDAT", two.to_vec()),
+			chunk("IEND", Vec::new()),
+		])
+	}
+
+	#[test]
+	fn test_embed_and_extract() {
+		for (bit_depth, color_type) in [(8, 2), (8, 6), (16, 0), (16, 2)] {
+			let mut png = image(16, 16, bit_depth, color_type);
+			let before = Pixels::read(&png).unwrap();
+
+			embed(&mut png, b"hidden in plain sight").unwrap();
+			assert_eq!(extract(&png).unwrap(), b"hidden in plain sight");
+
+			let after = Pixels::read(&png).unwrap();
+			assert_eq!(after.filters, before.filters);
+			assert!(before.raw.iter().zip(&after.raw).all(|(b, a)| (b ^ a) <= 1));
+			assert_eq!(png.positions_of("IDAT"), [1]);
+		}
+	}
+
+	#[test]
+	fn test_oversized_header() {
+		for (width, height) in [(1 << 31, 1 << 31), (u32::MAX, u32::MAX), (60_000, 60_000)] {
+			let mut png = image(4, 4, 16, 6);
+			let mut ihdr = png.chunks()[0].data().to_vec();
+			ihdr[..4].copy_from_slice(&u32::to_be_bytes(width));
+			ihdr[4..8].copy_from_slice(&u32::to_be_bytes(height));
+			png.replace_chunk(0, chunk("IHDR", ihdr)).unwrap();
+			assert!(matches!(extract(&png), Err(StegError::Corrupt(_))), "{width}x{height}");
+		}
+	}
+
+	#[test]
+	fn test_capacity() {
+		let png = image(16, 10, 8, 2);
+		// 16 * 10 * 3 samples, a bit each
+		assert_eq!(capacity(&png).unwrap(), 60 - LENGTH_BYTES);
+
+		let mut png = image(2, 2, 8, 0);
+		assert_eq!(capacity(&png).unwrap(), 0);
+		assert!(matches!(
+			embed(&mut png, b"x"),
+			Err(StegError::TooLarge { len: 1, capacity: 0 })
+		));
+		assert!(matches!(extract(&png), Err(StegError::NoMessage)));
+	}
+
+	#[test]
+	fn test_unsupported() {
+		for (bit_depth, color_type) in [(8, 3), (4, 0), (1, 0)] {
+			let mut png = image(8, 8, bit_depth, color_type);
+			assert!(matches!(
+				embed(&mut png, b"x"),
+				Err(StegError::Unsupported(_))
+			));
+		}
+	}
+
+	#[test]
+	fn test_spread_and_gather() {
+		let message: Vec<u8> = (0..150).collect();
+		let mut pngs = vec![image(16, 16, 8, 2), image(16, 16, 8, 0), image(16, 24, 16, 2)];
+		spread(&mut pngs, &message, false).unwrap();
+		assert_eq!(gather(&pngs).unwrap(), message);
+
+		// shares follow the capacity of each image
+		let lens: Vec<usize> = pngs.iter().map(|p| extract(p).unwrap().len() - PIECE_HEADER_BYTES).collect();
+		assert_eq!(lens.iter().sum::<usize>(), message.len());
+		assert!(lens[1] < lens[0] && lens[0] < lens[2]);
+
+		pngs.swap(0, 1);
+		assert!(matches!(
+			gather(&pngs),
+			Err(StegError::OutOfOrder {
+				position: 0,
+				index: 1,
+				count: 3
+			})
+		));
+		assert!(matches!(gather(&pngs[..0]), Err(StegError::NoImages)));
+	}
+
+	#[test]
+	fn test_spread_too_large() {
+		let mut pngs = vec![image(16, 16, 8, 2), image(16, 16, 8, 2)];
+		let room = 2 * (capacity(&pngs[0]).unwrap() - PIECE_HEADER_BYTES);
+		let before = pngs.clone();
+
+		assert!(matches!(
+			spread(&mut pngs, &vec![1; room + 1], false),
+			Err(StegError::TooLarge { capacity, .. }) if capacity == room
+		));
+		assert_eq!(pngs[0].as_bytes(), before[0].as_bytes());
+
+		spread(&mut pngs, &vec![1; room], false).unwrap();
+		assert_eq!(gather(&pngs).unwrap(), vec![1; room]);
+	}
+
+	#[test]
+	fn test_gather_damaged() {
+		let message: Vec<u8> = (0..100).collect();
+		let mut pngs: Vec<Png> = (0..4).map(|_| image(16, 16, 8, 2)).collect();
+		spread(&mut pngs, &message, true).unwrap();
+		assert_eq!(gather(&pngs).unwrap(), message);
+
+		// any order, nothing lost
+		pngs.reverse();
+		let gathered = gather_damaged(&pngs).unwrap();
+		assert_eq!(gathered.message.as_deref(), Some(message.as_slice()));
+		assert!(gathered.fragments.iter().all(|f| f.recovery == Recovery::Recovered));
+		assert_eq!(gathered.fragments[0].position, Some(3));
+		assert!(gathered.fragments[3].parity);
+		assert!(gathered.issues(&["a", "b", "c", "d"]).is_empty());
+
+		// piece 2 damaged, rebuilt from parity
+		pngs.reverse();
+		let (damaged, rest) = pngs.split_at_mut(2);
+		rest[0] = image(16, 16, 8, 2);
+		embed(&mut rest[0], b"not a piece").unwrap();
+		let mut set = damaged.to_vec();
+		set.extend_from_slice(rest);
+		let gathered = gather_damaged(&set).unwrap();
+		assert_eq!(gathered.message.as_deref(), Some(message.as_slice()));
+		assert_eq!(gathered.fragments[2].recovery, Recovery::Reconstructed);
+		assert_eq!(gathered.unusable, [2]);
+		let issues = gathered.issues(&["a", "b", "c", "d"]);
+		assert_eq!(issues.len(), 2);
+		assert_eq!((issues[0].path.as_str(), issues[0].rule.as_str()), ("c", "spread-unusable"));
+		assert_eq!(issues[1].rule, "spread-reconstructed");
+
+		// two pieces gone, no way back
+		let gathered = gather_damaged(&[set[0].clone(), set[3].clone()]).unwrap();
+		assert_eq!(gathered.message, None);
+		assert_eq!(
+			gathered.fragments.iter().map(|f| f.recovery).collect::<Vec<_>>(),
+			[Recovery::Recovered, Recovery::Missing, Recovery::Missing, Recovery::Recovered]
+		);
+		assert!(matches!(gather_damaged(&[image(16, 16, 8, 2)]), Err(StegError::NoMessage)));
+	}
+}
@@ -0,0 +1,349 @@
+// Payloads in SVG images, for vector assets as carriers.
+//
+// SVG is XML, and renderers ignore elements of namespaces they don't know.
+// A payload goes base64 encoded into one, in a `<metadata>` block right
+// before the root closes:
+//
+//     <metadata><vanish:payload xmlns:vanish="urn:vanish:payload"
+//       type="ruSt">aGlkZGVu</vanish:payload></metadata>
+//
+// The XML reader below knows just enough to find tags: comments, CDATA,
+// processing instructions and the doctype are skipped, quoted attribute
+// values are honoured. No entities, no validation.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use thiserror::Error;
+
+use crate::chunk_type::ChunkType;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SvgError {
+	#[error("Not an SVG, the root element isn't `<svg>`.")]
+	NotSvg,
+	#[error("Unterminated markup at offset {0}.")]
+	Unterminated(usize),
+	#[error("The `{0}` payload isn't valid base64.")]
+	Base64(String),
+}
+
+/// Namespace of the elements vanish writes.
+pub const NAMESPACE: &str = "urn:vanish:payload";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+	Start {
+		name: &'a str,
+		attributes: &'a str,
+		/// `<name/>`
+		empty: bool,
+		offset: usize,
+		/// Past the `>`.
+		end: usize,
+	},
+	End {
+		name: &'a str,
+		offset: usize,
+	},
+	Text(&'a str),
+}
+
+/// The tags and text of an XML document, in order.
+struct Tokens<'a> {
+	xml: &'a str,
+	at: usize,
+}
+
+impl<'a> Tokens<'a> {
+	fn new(xml: &'a str) -> Self {
+		// a BOM isn't text
+		Self {
+			xml,
+			at: xml.len() - xml.trim_start_matches('\u{feff}').len(),
+		}
+	}
+
+	/// Offset past `until`, looked for from `from`.
+	fn past(&self, from: usize, until: &str) -> Result<usize, SvgError> {
+		self.xml[from..]
+			.find(until)
+			.map(|i| from + i + until.len())
+			.ok_or(SvgError::Unterminated(from))
+	}
+
+	/// Offset past the `>` ending the tag at `from`, skipping quoted values.
+	fn tag_end(&self, from: usize) -> Result<usize, SvgError> {
+		let mut quote = None;
+		for (i, c) in self.xml[from..].char_indices() {
+			match (quote, c) {
+				(None, '"' | '\'') => quote = Some(c),
+				(Some(q), c) if c == q => quote = None,
+				(None, '>') => return Ok(from + i + 1),
+				_ => {},
+			}
+		}
+		Err(SvgError::Unterminated(from))
+	}
+}
+
+impl<'a> Iterator for Tokens<'a> {
+	type Item = Result<Token<'a>, SvgError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			let rest = &self.xml[self.at..];
+			if rest.is_empty() {
+				return None;
+			}
+			let start = self.at;
+			if !rest.starts_with('<') {
+				let len = rest.find('<').unwrap_or(rest.len());
+				self.at += len;
+				return Some(Ok(Token::Text(&rest[..len])));
+			}
+
+			let end = match rest {
+				_ if rest.starts_with("<!--") => self.past(start, "-->"),
+				_ if rest.starts_with("<![CDATA[") => match self.past(start, "]]>") {
+					Ok(end) => {
+						self.at = end;
+						return Some(Ok(Token::Text(&self.xml[start + 9..end - 3])));
+					},
+					Err(e) => Err(e),
+				},
+				_ if rest.starts_with("<?") => self.past(start, "?>"),
+				// a doctype, maybe with an internal subset
+				_ if rest.starts_with("<!") => match rest.find(['[', '>']) {
+					Some(i) if rest.as_bytes()[i] == b'[' => self.past(start, "]").and_then(|e| self.past(e, ">")),
+					_ => self.past(start, ">"),
+				},
+				_ if rest.starts_with("</") => match self.past(start, ">") {
+					Ok(end) => {
+						self.at = end;
+						let name = self.xml[start + 2..end - 1].trim();
+						return Some(Ok(Token::End { name, offset: start }));
+					},
+					Err(e) => Err(e),
+				},
+				_ => match self.tag_end(start) {
+					Ok(end) => {
+						self.at = end;
+						let inner = &self.xml[start + 1..end - 1];
+						let (inner, empty) = match inner.strip_suffix('/') {
+							Some(inner) => (inner, true),
+							None => (inner, false),
+						};
+						let name_len = inner.find(|c: char| c.is_whitespace()).unwrap_or(inner.len());
+						return Some(Ok(Token::Start {
+							name: &inner[..name_len],
+							attributes: &inner[name_len..],
+							empty,
+							offset: start,
+							end,
+						}));
+					},
+					Err(e) => Err(e),
+				},
+			};
+			match end {
+				Ok(end) => self.at = end,
+				Err(e) => {
+					// nothing past broken markup
+					self.at = self.xml.len();
+					return Some(Err(e));
+				},
+			}
+		}
+	}
+}
+
+/// Value of `name` in the attributes of a tag.
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+	let mut rest = attributes;
+	loop {
+		let (key, value) = rest.split_once('=')?;
+		let value = value.trim_start();
+		let quote = value.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+		let (value, after) = value[1..].split_once(quote)?;
+		if key.trim() == name {
+			return Some(value);
+		}
+		rest = after;
+	}
+}
+
+/// Part of `name` after the prefix.
+fn local_name(name: &str) -> &str {
+	name.rsplit_once(':').map_or(name, |(_, local)| local)
+}
+
+/// Whether `head`, the start of a file, is an SVG: whatever comes before
+/// the first tag isn't text, and that tag is `<svg>`.
+pub fn is_svg(head: &[u8]) -> bool {
+	// the head may end in the middle of a character
+	let text = match std::str::from_utf8(head) {
+		Ok(text) => text,
+		Err(e) => std::str::from_utf8(&head[..e.valid_up_to()]).expect("valid up to there"),
+	};
+	for token in Tokens::new(text) {
+		match token {
+			Ok(Token::Start { name, .. }) => return local_name(name) == "svg",
+			Ok(Token::Text(text)) if text.trim().is_empty() => {},
+			_ => return false,
+		}
+	}
+	false
+}
+
+/// The document and its root element.
+fn root(bytes: &[u8]) -> Result<(&str, Token<'_>), SvgError> {
+	let xml = std::str::from_utf8(bytes).map_err(|_| SvgError::NotSvg)?;
+	for token in Tokens::new(xml) {
+		match token? {
+			root @ Token::Start { name, .. } if local_name(name) == "svg" => return Ok((xml, root)),
+			Token::Text(text) if text.trim().is_empty() => {},
+			_ => return Err(SvgError::NotSvg),
+		}
+	}
+	Err(SvgError::NotSvg)
+}
+
+/// Chunk types and base64 text of the vanish elements, in document order.
+fn payloads(bytes: &[u8]) -> Result<Vec<(&str, String)>, SvgError> {
+	let (xml, _) = root(bytes)?;
+	let mut payloads = Vec::new();
+	let mut open: Option<(&str, &str, String)> = None;
+	for token in Tokens::new(xml) {
+		match (token?, &mut open) {
+			(Token::Start { name, attributes, empty, .. }, None) => {
+				let ours = match name.split_once(':') {
+					Some((prefix, "payload")) => attribute(attributes, &format!("xmlns:{prefix}")) == Some(NAMESPACE),
+					_ => false,
+				};
+				let chunk_type = attribute(attributes, "type");
+				match (ours, chunk_type, empty) {
+					(true, Some(chunk_type), false) => open = Some((name, chunk_type, String::new())),
+					(true, Some(chunk_type), true) => payloads.push((chunk_type, String::new())),
+					_ => {},
+				}
+			},
+			(Token::Text(text), Some((_, _, data))) => data.push_str(text),
+			(Token::End { name, .. }, Some((payload, chunk_type, data))) if name == *payload => {
+				payloads.push((*chunk_type, std::mem::take(data)));
+				open = None;
+			},
+			_ => {},
+		}
+	}
+	Ok(payloads)
+}
+
+/// `bytes` with `data` in a vanish element labelled `chunk_type`, at the end
+/// of the root.
+pub fn append(bytes: &[u8], chunk_type: &ChunkType, data: &[u8]) -> Result<Vec<u8>, SvgError> {
+	let (xml, root) = root(bytes)?;
+	let Token::Start { name, empty, end, .. } = root else {
+		unreachable!("the root is a start tag");
+	};
+	let element = format!(
+		"<metadata><vanish:payload xmlns:vanish=\"{NAMESPACE}\" type=\"{chunk_type}\">{}</vanish:payload></metadata>",
+		BASE64.encode(data)
+	);
+
+	let (before, after) = match empty {
+		// `<svg/>` gets opened up
+		true => (format!("{}>", &xml[..end - 2]), format!("</{name}>{}", &xml[end..])),
+		false => {
+			// the root closes last
+			let mut depth = 0usize;
+			let mut close = None;
+			for token in Tokens::new(xml) {
+				match token? {
+					Token::Start { empty: false, .. } => depth += 1,
+					Token::End { offset, .. } => {
+						depth = depth.saturating_sub(1);
+						if depth == 0 {
+							close = Some(offset);
+							break;
+						}
+					},
+					_ => {},
+				}
+			}
+			let close = close.ok_or(SvgError::Unterminated(end))?;
+			(xml[..close].to_owned(), xml[close..].to_owned())
+		},
+	};
+	Ok([before.as_bytes(), element.as_bytes(), after.as_bytes()].concat())
+}
+
+/// Data of the first vanish element labelled `chunk_type`.
+pub fn find(bytes: &[u8], chunk_type: &str) -> Result<Option<Vec<u8>>, SvgError> {
+	payloads(bytes)?
+		.into_iter()
+		.find(|(t, _)| *t == chunk_type)
+		.map(|(_, text)| {
+			let text: String = text.split_whitespace().collect();
+			BASE64.decode(text).map_err(|_| SvgError::Base64(chunk_type.to_owned()))
+		})
+		.transpose()
+}
+
+/// Chunk types of all vanish elements, in document order.
+pub fn chunk_types(bytes: &[u8]) -> Result<Vec<String>, SvgError> {
+	Ok(payloads(bytes)?.into_iter().map(|(t, _)| t.to_owned()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+
+	const SVG: &str = "\u{feff}<?xml version=\"1.0\"?>\n<!DOCTYPE svg [<!ENTITY a \"b\">]>\n<!-- <svg> -->\n\
+		<svg xmlns=\"http://www.w3.org/2000/svg\" data-x='a>b'><g><rect/></g><![CDATA[</svg>]]></svg>\n";
+
+	#[test]
+	fn test_is_svg() {
+		assert!(is_svg(SVG.as_bytes()));
+		assert!(is_svg(&SVG.as_bytes()[..SVG.find("<g>").unwrap()]));
+		assert!(is_svg(b"<svg:svg xmlns:svg=\"http://www.w3.org/2000/svg\"/>"));
+		assert!(!is_svg(b"<html><svg/></html>"));
+		assert!(!is_svg(b"some text <svg>"));
+		assert!(!is_svg(b"\x89PNG\r\n\x1a\n"));
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let out = append(SVG.as_bytes(), &ChunkType::from_str("ruSt").unwrap(), b"hidden").unwrap();
+		let out = String::from_utf8(out).unwrap();
+
+		// right before the root closes, not in the CDATA
+		assert!(out.ends_with("</vanish:payload></metadata></svg>\n"));
+		assert!(out.contains("<![CDATA[</svg>]]><metadata>"));
+		assert_eq!(find(out.as_bytes(), "ruSt").unwrap(), Some(b"hidden".to_vec()));
+		assert_eq!(find(out.as_bytes(), "nOpe").unwrap(), None);
+
+		let twice = append(out.as_bytes(), &ChunkType::from_str("tWoo").unwrap(), b"").unwrap();
+		assert_eq!(chunk_types(&twice).unwrap(), ["ruSt", "tWoo"]);
+	}
+
+	#[test]
+	fn test_empty_root() {
+		let out = append(b"<svg/>", &ChunkType::from_str("ruSt").unwrap(), b"x").unwrap();
+		assert!(out.starts_with(b"<svg><metadata>"));
+		assert!(out.ends_with(b"</metadata></svg>"));
+		assert_eq!(find(&out, "ruSt").unwrap(), Some(b"x".to_vec()));
+
+		assert_eq!(append(b"<html/>", &ChunkType::from_str("ruSt").unwrap(), b""), Err(SvgError::NotSvg));
+		assert_eq!(append(b"<svg><!-- ", &ChunkType::from_str("ruSt").unwrap(), b""), Err(SvgError::Unterminated(5)));
+	}
+
+	#[test]
+	fn test_foreign_payload_ignored() {
+		let svg = b"<svg><x:payload xmlns:x=\"urn:other\" type=\"ruSt\">aGk=</x:payload></svg>";
+		assert_eq!(find(svg, "ruSt").unwrap(), None);
+		let ours = b"<svg><v:payload xmlns:v=\"urn:vanish:payload\" type=\"ruSt\">aG\n k=</v:payload></svg>";
+		assert_eq!(find(ours, "ruSt").unwrap(), Some(b"hi".to_vec()));
+	}
+}
@@ -0,0 +1,429 @@
+// The standard text chunks, tEXt, zTXt and iTXt, as the spec lays them out
+// so exiftool, ImageMagick and browsers read what gets written here and the
+// other way around. All three start with a Latin-1 keyword and its nul;
+// tEXt and zTXt text is Latin-1 too, iTXt is UTF-8.
+
+use std::fmt::Display;
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use thiserror::Error;
+
+use crate::analysis::MAX_INFLATED_BYTES;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+/// Longest keyword the spec allows, in bytes.
+pub const MAX_KEYWORD_BYTES: usize = 79;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TextError {
+	#[error("Keyword `{0}` is invalid, 1 to 79 printable Latin-1 characters without leading, trailing or double spaces.")]
+	BadKeyword(String),
+	#[error("`{0}` can't be written in Latin-1, use iTXt.")]
+	NotLatin1(String),
+	#[error("Missing nul separator.")]
+	MissingSeparator,
+	#[error("Unknown compression method {0}.")]
+	UnknownCompression(u8),
+	#[error("Failed to inflate the text. {0}")]
+	Inflate(String),
+	#[error("iTXt text must be UTF-8.")]
+	NotUtf8,
+	#[error("`{0}` is not a text chunk.")]
+	NotText(String),
+}
+
+/// A `tEXt` chunk, uncompressed Latin-1 text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+	keyword: String,
+	text: String,
+}
+
+/// A `zTXt` chunk, zlib compressed Latin-1 text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedTextChunk {
+	keyword: String,
+	text: String,
+}
+
+/// An `iTXt` chunk, UTF-8 text, compressed or not, with an optional
+/// language tag and the keyword translated to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternationalTextChunk {
+	keyword: String,
+	compressed: bool,
+	language_tag: String,
+	translated_keyword: String,
+	text: String,
+}
+
+impl TextChunk {
+	pub fn new(keyword: &str, text: &str) -> Result<Self, TextError> {
+		check_keyword(keyword)?;
+		to_latin1(text)?;
+		Ok(Self {
+			keyword: keyword.to_owned(),
+			text: text.to_owned(),
+		})
+	}
+
+	pub fn to_chunk(&self) -> Chunk {
+		let mut data = keyword_bytes(&self.keyword);
+		data.extend(to_latin1(&self.text).expect("checked in new"));
+		Chunk::new(chunk_type(b"tEXt"), data)
+	}
+
+	pub fn keyword(&self) -> &str {
+		&self.keyword
+	}
+
+	pub fn text(&self) -> &str {
+		&self.text
+	}
+}
+
+impl CompressedTextChunk {
+	pub fn new(keyword: &str, text: &str) -> Result<Self, TextError> {
+		check_keyword(keyword)?;
+		to_latin1(text)?;
+		Ok(Self {
+			keyword: keyword.to_owned(),
+			text: text.to_owned(),
+		})
+	}
+
+	pub fn to_chunk(&self) -> Chunk {
+		let mut data = keyword_bytes(&self.keyword);
+		data.push(0);
+		data.extend(deflate(&to_latin1(&self.text).expect("checked in new")));
+		Chunk::new(chunk_type(b"zTXt"), data)
+	}
+
+	pub fn keyword(&self) -> &str {
+		&self.keyword
+	}
+
+	pub fn text(&self) -> &str {
+		&self.text
+	}
+}
+
+impl InternationalTextChunk {
+	/// Compressed, without a language tag. See `with_language`.
+	pub fn new(keyword: &str, text: &str) -> Result<Self, TextError> {
+		check_keyword(keyword)?;
+		Ok(Self {
+			keyword: keyword.to_owned(),
+			compressed: true,
+			language_tag: String::new(),
+			translated_keyword: String::new(),
+			text: text.to_owned(),
+		})
+	}
+
+	/// Tags the text as `language_tag` (eg: `en-US`), `translated_keyword`
+	/// being the keyword in that language.
+	pub fn with_language(mut self, language_tag: &str, translated_keyword: &str) -> Self {
+		self.language_tag = language_tag.to_owned();
+		self.translated_keyword = translated_keyword.to_owned();
+		self
+	}
+
+	pub fn with_compression(mut self, compressed: bool) -> Self {
+		self.compressed = compressed;
+		self
+	}
+
+	pub fn to_chunk(&self) -> Chunk {
+		let mut data = keyword_bytes(&self.keyword);
+		data.extend([self.compressed as u8, 0]);
+		// language tags are plain ASCII, anything else is dropped
+		data.extend(self.language_tag.bytes().filter(u8::is_ascii));
+		data.push(0);
+		data.extend_from_slice(self.translated_keyword.as_bytes());
+		data.push(0);
+		match self.compressed {
+			true => data.extend(deflate(self.text.as_bytes())),
+			false => data.extend_from_slice(self.text.as_bytes()),
+		}
+		Chunk::new(chunk_type(b"iTXt"), data)
+	}
+
+	pub fn keyword(&self) -> &str {
+		&self.keyword
+	}
+
+	pub fn is_compressed(&self) -> bool {
+		self.compressed
+	}
+
+	pub fn language_tag(&self) -> &str {
+		&self.language_tag
+	}
+
+	pub fn translated_keyword(&self) -> &str {
+		&self.translated_keyword
+	}
+
+	pub fn text(&self) -> &str {
+		&self.text
+	}
+}
+
+/// Any of the three, for reading whatever text chunk comes along.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Text {
+	Plain(TextChunk),
+	Compressed(CompressedTextChunk),
+	International(InternationalTextChunk),
+}
+
+impl Text {
+	/// True for the types of the text chunks.
+	pub fn is_text(chunk_type: &ChunkType) -> bool {
+		matches!(&chunk_type.bytes(), b"tEXt" | b"zTXt" | b"iTXt")
+	}
+
+	/// `text` in a chunk of `chunk_type`, a text chunk type.
+	pub fn new(chunk_type: &ChunkType, keyword: &str, text: &str) -> Result<Text, TextError> {
+		match &chunk_type.bytes() {
+			b"tEXt" => TextChunk::new(keyword, text).map(Text::Plain),
+			b"zTXt" => CompressedTextChunk::new(keyword, text).map(Text::Compressed),
+			b"iTXt" => InternationalTextChunk::new(keyword, text).map(Text::International),
+			_ => Err(TextError::NotText(chunk_type.to_string())),
+		}
+	}
+
+	pub fn keyword(&self) -> &str {
+		match self {
+			Self::Plain(t) => t.keyword(),
+			Self::Compressed(t) => t.keyword(),
+			Self::International(t) => t.keyword(),
+		}
+	}
+
+	pub fn text(&self) -> &str {
+		match self {
+			Self::Plain(t) => t.text(),
+			Self::Compressed(t) => t.text(),
+			Self::International(t) => t.text(),
+		}
+	}
+
+	pub fn to_chunk(&self) -> Chunk {
+		match self {
+			Self::Plain(t) => t.to_chunk(),
+			Self::Compressed(t) => t.to_chunk(),
+			Self::International(t) => t.to_chunk(),
+		}
+	}
+}
+
+impl Display for Text {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::International(t) if !t.language_tag.is_empty() => {
+				write!(f, "{} ({}): {}", t.keyword, t.language_tag, t.text)
+			},
+			_ => write!(f, "{}: {}", self.keyword(), self.text()),
+		}
+	}
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+	type Error = TextError;
+
+	fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+		expect_type(chunk, b"tEXt")?;
+		let (keyword, text) = split_keyword(chunk.data())?;
+		Ok(Self {
+			keyword,
+			text: from_latin1(text),
+		})
+	}
+}
+
+impl TryFrom<&Chunk> for CompressedTextChunk {
+	type Error = TextError;
+
+	fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+		expect_type(chunk, b"zTXt")?;
+		let (keyword, rest) = split_keyword(chunk.data())?;
+		let (&method, compressed) = rest.split_first().ok_or(TextError::MissingSeparator)?;
+		if method != 0 {
+			return Err(TextError::UnknownCompression(method));
+		}
+		Ok(Self {
+			keyword,
+			text: from_latin1(&inflate(compressed)?),
+		})
+	}
+}
+
+impl TryFrom<&Chunk> for InternationalTextChunk {
+	type Error = TextError;
+
+	fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+		expect_type(chunk, b"iTXt")?;
+		let (keyword, rest) = split_keyword(chunk.data())?;
+		let [flag, method, rest @ ..] = rest else {
+			return Err(TextError::MissingSeparator);
+		};
+		let (language_tag, rest) = split_nul(rest)?;
+		let (translated_keyword, text) = split_nul(rest)?;
+		let text = match (flag, method) {
+			(0, _) => text.to_vec(),
+			(_, 0) => inflate(text)?,
+			(_, method) => return Err(TextError::UnknownCompression(*method)),
+		};
+
+		let utf8 = |bytes: Vec<u8>| String::from_utf8(bytes).map_err(|_| TextError::NotUtf8);
+		Ok(Self {
+			keyword,
+			compressed: *flag != 0,
+			language_tag: from_latin1(language_tag),
+			translated_keyword: utf8(translated_keyword.to_vec())?,
+			text: utf8(text)?,
+		})
+	}
+}
+
+impl TryFrom<&Chunk> for Text {
+	type Error = TextError;
+
+	fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+		match &chunk.chunk_type().bytes() {
+			b"tEXt" => TextChunk::try_from(chunk).map(Text::Plain),
+			b"zTXt" => CompressedTextChunk::try_from(chunk).map(Text::Compressed),
+			b"iTXt" => InternationalTextChunk::try_from(chunk).map(Text::International),
+			_ => Err(TextError::NotText(chunk.chunk_type().to_string())),
+		}
+	}
+}
+
+fn chunk_type(bytes: &[u8; 4]) -> ChunkType {
+	ChunkType::try_from(*bytes).expect("valid chunk type")
+}
+
+fn expect_type(chunk: &Chunk, expected: &[u8; 4]) -> Result<(), TextError> {
+	match &chunk.chunk_type().bytes() == expected {
+		true => Ok(()),
+		false => Err(TextError::NotText(chunk.chunk_type().to_string())),
+	}
+}
+
+fn check_keyword(keyword: &str) -> Result<(), TextError> {
+	let bad = || TextError::BadKeyword(keyword.to_owned());
+	let bytes = to_latin1(keyword).map_err(|_| bad())?;
+	let printable = bytes.iter().all(|&b| matches!(b, 32..=126 | 161..=255));
+	if bytes.is_empty()
+		|| bytes.len() > MAX_KEYWORD_BYTES
+		|| !printable
+		|| keyword.starts_with(' ')
+		|| keyword.ends_with(' ')
+		|| keyword.contains("  ")
+	{
+		return Err(bad());
+	}
+	Ok(())
+}
+
+/// `keyword` in Latin-1 and its nul.
+fn keyword_bytes(keyword: &str) -> Vec<u8> {
+	let mut bytes = to_latin1(keyword).expect("checked in new");
+	bytes.push(0);
+	bytes
+}
+
+/// Keyword and whatever follows its nul. The keyword isn't checked, a
+/// reader takes what it finds.
+fn split_keyword(data: &[u8]) -> Result<(String, &[u8]), TextError> {
+	let (keyword, rest) = split_nul(data)?;
+	Ok((from_latin1(keyword), rest))
+}
+
+fn split_nul(data: &[u8]) -> Result<(&[u8], &[u8]), TextError> {
+	let nul = data.iter().position(|&b| b == 0).ok_or(TextError::MissingSeparator)?;
+	Ok((&data[..nul], &data[nul + 1..]))
+}
+
+fn to_latin1(s: &str) -> Result<Vec<u8>, TextError> {
+	s.chars()
+		.map(|c| u8::try_from(c).map_err(|_| TextError::NotLatin1(s.to_owned())))
+		.collect()
+}
+
+fn from_latin1(bytes: &[u8]) -> String {
+	bytes.iter().map(|&b| b as char).collect()
+}
+
+fn inflate(compressed: &[u8]) -> Result<Vec<u8>, TextError> {
+	let mut inflated = Vec::new();
+	ZlibDecoder::new(compressed)
+		.take(MAX_INFLATED_BYTES)
+		.read_to_end(&mut inflated)
+		.map_err(|e| TextError::Inflate(e.to_string()))?;
+	Ok(inflated)
+}
+
+fn deflate(bytes: &[u8]) -> Vec<u8> {
+	let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+	encoder.write_all(bytes).expect("writing to a vec");
+	encoder.finish().expect("writing to a vec")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_text_chunk_roundtrip() {
+		let text = TextChunk::new("Comment", "café").unwrap();
+		let chunk = text.to_chunk();
+		assert_eq!(chunk.data(), b"Comment\0caf\xe9");
+		assert_eq!(TextChunk::try_from(&chunk).unwrap(), text);
+	}
+
+	#[test]
+	fn test_compressed_text_chunk_roundtrip() {
+		let text = CompressedTextChunk::new("Description", &"long ".repeat(100)).unwrap();
+		let chunk = text.to_chunk();
+		assert!(chunk.data().starts_with(b"Description\0\0"));
+		assert!(chunk.data().len() < 100);
+		assert_eq!(CompressedTextChunk::try_from(&chunk).unwrap(), text);
+	}
+
+	#[test]
+	fn test_international_text_chunk_roundtrip() {
+		for compressed in [true, false] {
+			let text = InternationalTextChunk::new("Title", "日本語のタイトル")
+				.unwrap()
+				.with_language("ja", "タイトル")
+				.with_compression(compressed);
+			let chunk = text.to_chunk();
+			assert_eq!(InternationalTextChunk::try_from(&chunk).unwrap(), text);
+
+			let any = Text::try_from(&chunk).unwrap();
+			assert_eq!(any.to_string(), "Title (ja): 日本語のタイトル");
+		}
+	}
+
+	#[test]
+	fn test_text_errors() {
+		assert!(matches!(TextChunk::new("", "x"), Err(TextError::BadKeyword(_))));
+		assert!(matches!(TextChunk::new(" Comment", "x"), Err(TextError::BadKeyword(_))));
+		assert!(matches!(TextChunk::new(&"k".repeat(80), "x"), Err(TextError::BadKeyword(_))));
+		assert!(matches!(TextChunk::new("Comment", "日本"), Err(TextError::NotLatin1(_))));
+
+		let chunk = Chunk::new(chunk_type(b"tEXt"), b"no separator".to_vec());
+		assert_eq!(Text::try_from(&chunk), Err(TextError::MissingSeparator));
+		let chunk = Chunk::new(chunk_type(b"zTXt"), b"Comment\0\x01xx".to_vec());
+		assert_eq!(Text::try_from(&chunk), Err(TextError::UnknownCompression(1)));
+		let chunk = Chunk::new(chunk_type(b"ruSt"), Vec::new());
+		assert_eq!(Text::try_from(&chunk), Err(TextError::NotText("ruSt".into())));
+	}
+}
@@ -0,0 +1,130 @@
+// Payloads hidden in plain text, for channels where an image won't do.
+//
+// Every payload bit becomes a zero width character (U+200B for 0, U+200C for
+// 1), spread over the spaces of the cover so no single spot gets long. The
+// bits are a 4 byte big endian length followed by the payload.
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TextStegoError {
+	#[error("The cover text already contains zero width characters.")]
+	DirtyCover,
+	#[error("The cover text is empty.")]
+	EmptyCover,
+	#[error("No hidden payload found in this text.")]
+	NotFound,
+	#[error("Hidden payload is cut short, expected {0} bytes.")]
+	Truncated(usize),
+}
+
+const ZERO: char = '\u{200B}';
+const ONE: char = '\u{200C}';
+const LENGTH_BYTES: usize = 4;
+
+fn bits(payload: &[u8]) -> impl Iterator<Item = char> + '_ {
+	let len = (payload.len() as u32).to_be_bytes();
+	len.into_iter()
+		.chain(payload.iter().copied())
+		.flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1))
+		.map(|bit| if bit == 1 { ONE } else { ZERO })
+}
+
+/// `cover` with `payload` woven in. Reads the same, only longer in bytes.
+pub fn encode(cover: &str, payload: &[u8]) -> Result<String, TextStegoError> {
+	if cover.is_empty() {
+		return Err(TextStegoError::EmptyCover);
+	}
+	if cover.contains([ZERO, ONE]) {
+		return Err(TextStegoError::DirtyCover);
+	}
+
+	let bit_count = (LENGTH_BYTES + payload.len()) * 8;
+	let mut bits = bits(payload);
+	let mut out = String::with_capacity(cover.len() + bit_count * ZERO.len_utf8());
+	let spaces = cover.matches(' ').count();
+
+	// with no spaces everything goes after the first character
+	if spaces == 0 {
+		let mut chars = cover.chars();
+		out.extend(chars.next());
+		out.extend(bits);
+		out.extend(chars);
+		return Ok(out);
+	}
+
+	let per_space = bit_count.div_ceil(spaces);
+	for c in cover.chars() {
+		out.push(c);
+		if c == ' ' {
+			out.extend(bits.by_ref().take(per_space));
+		}
+	}
+
+	Ok(out)
+}
+
+/// The payload hidden in `text` by `encode`.
+pub fn decode(text: &str) -> Result<Vec<u8>, TextStegoError> {
+	let bits: Vec<u8> = text
+		.chars()
+		.filter_map(|c| match c {
+			ZERO => Some(0),
+			ONE => Some(1),
+			_ => None,
+		})
+		.collect();
+	if bits.is_empty() {
+		return Err(TextStegoError::NotFound);
+	}
+
+	let bytes: Vec<u8> = bits
+		.chunks_exact(8)
+		.map(|byte| byte.iter().fold(0, |acc, bit| acc << 1 | bit))
+		.collect();
+	let len: [u8; LENGTH_BYTES] = bytes
+		.get(..LENGTH_BYTES)
+		.ok_or(TextStegoError::Truncated(LENGTH_BYTES))?
+		.try_into()
+		.expect("slice is 4 bytes");
+	let len = u32::from_be_bytes(len) as usize;
+
+	bytes
+		.get(LENGTH_BYTES..LENGTH_BYTES + len)
+		.map(<[u8]>::to_vec)
+		.ok_or(TextStegoError::Truncated(len))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const COVER: &str = "the quick brown fox jumps over the lazy dog";
+
+	#[test]
+	fn test_round_trip() {
+		let text = encode(COVER, b"hidden").unwrap();
+		assert_eq!(decode(&text).unwrap(), b"hidden");
+
+		let visible: String = text.chars().filter(|c| ![ZERO, ONE].contains(c)).collect();
+		assert_eq!(visible, COVER);
+	}
+
+	#[test]
+	fn test_no_spaces() {
+		let text = encode("word", b"x").unwrap();
+		assert!(text.starts_with('w') && text.ends_with("ord"));
+		assert_eq!(decode(&text).unwrap(), b"x");
+	}
+
+	#[test]
+	fn test_errors() {
+		assert_eq!(encode("", b"x"), Err(TextStegoError::EmptyCover));
+		let text = encode(COVER, b"x").unwrap();
+		assert_eq!(encode(&text, b"x"), Err(TextStegoError::DirtyCover));
+		assert_eq!(decode(COVER), Err(TextStegoError::NotFound));
+
+		let cut: String = text.chars().take(20).collect();
+		assert!(matches!(decode(&cut), Err(TextStegoError::Truncated(_))));
+	}
+}
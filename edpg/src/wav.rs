@@ -0,0 +1,178 @@
+// Payloads in WAV files, for voice memos and podcasts as carriers.
+//
+// A WAV is a RIFF file: `RIFF`, a little endian u32 size, `WAVE` and a list of
+// chunks, each a 4 byte id, a little endian u32 size and the data, padded to
+// an even length. Players skip `LIST` chunks, and the `INFO` ones hold tags
+// under 4 character ids, so a payload goes into an `INFO` entry of its own,
+// with the chunk type as id, in a `LIST` at the end.
+
+use thiserror::Error;
+
+use crate::chunk_type::ChunkType;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum WavError {
+	#[error("Not a WAV file, no `RIFF`/`WAVE` header.")]
+	NotWav,
+	#[error("Chunk at offset {0} runs past the end of the file.")]
+	Truncated(usize),
+	#[error("A WAV can't grow past 4 GiB.")]
+	TooLarge,
+}
+
+const HEADER_BYTES: usize = 12;
+const CHUNK_HEADER_BYTES: usize = 8;
+
+/// A chunk, `offset` is where its header starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkRef {
+	pub offset: usize,
+	pub id: [u8; 4],
+	/// Of the data, without the pad byte.
+	pub len: usize,
+}
+
+impl ChunkRef {
+	fn data<'a>(&self, bytes: &'a [u8]) -> &'a [u8] {
+		let start = self.offset + CHUNK_HEADER_BYTES;
+		&bytes[start..start + self.len]
+	}
+}
+
+/// Whether `bytes` starts like a WAV.
+pub fn is_wav(bytes: &[u8]) -> bool {
+	bytes.len() >= HEADER_BYTES && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WAVE"
+}
+
+/// Chunks in `bytes` from `offset` up to `end`.
+fn chunks_in(bytes: &[u8], mut offset: usize, end: usize) -> Result<Vec<ChunkRef>, WavError> {
+	let mut chunks = Vec::new();
+	while offset + CHUNK_HEADER_BYTES <= end {
+		let header = &bytes[offset..offset + CHUNK_HEADER_BYTES];
+		let len = u32::from_le_bytes(header[4..].try_into().expect("slice is 4 bytes")) as usize;
+		if offset + CHUNK_HEADER_BYTES + len > end {
+			return Err(WavError::Truncated(offset));
+		}
+		chunks.push(ChunkRef {
+			offset,
+			id: header[..4].try_into().expect("slice is 4 bytes"),
+			len,
+		});
+		offset += CHUNK_HEADER_BYTES + len + len % 2;
+	}
+	Ok(chunks)
+}
+
+/// The top level chunks of `bytes`, in file order.
+pub fn chunks(bytes: &[u8]) -> Result<Vec<ChunkRef>, WavError> {
+	if !is_wav(bytes) {
+		return Err(WavError::NotWav);
+	}
+	// some writers get the RIFF size wrong, the file is what there is
+	chunks_in(bytes, HEADER_BYTES, bytes.len())
+}
+
+/// Entries of every `LIST`/`INFO` chunk.
+fn info(bytes: &[u8]) -> Result<Vec<ChunkRef>, WavError> {
+	let mut entries = Vec::new();
+	for list in chunks(bytes)? {
+		if &list.id != b"LIST" || !list.data(bytes).starts_with(b"INFO") {
+			continue;
+		}
+		let start = list.offset + CHUNK_HEADER_BYTES + 4;
+		let end = list.offset + CHUNK_HEADER_BYTES + list.len;
+		entries.extend(chunks_in(bytes, start, end)?);
+	}
+	Ok(entries)
+}
+
+/// `bytes` with `data` appended in a `LIST`/`INFO` chunk, under
+/// `chunk_type`.
+pub fn append(bytes: &[u8], chunk_type: &ChunkType, data: &[u8]) -> Result<Vec<u8>, WavError> {
+	let last = chunks(bytes)?.pop();
+	// a missing pad byte at the end would shift the new chunk
+	let end = last.map_or(HEADER_BYTES, |c| c.offset + CHUNK_HEADER_BYTES + c.len);
+	let padded = data.len() + data.len() % 2;
+	let list_len = 4 + CHUNK_HEADER_BYTES + padded;
+	let riff_len = u32::try_from(end + end % 2 + CHUNK_HEADER_BYTES + list_len - 8)
+		.map_err(|_| WavError::TooLarge)?;
+
+	let mut out = Vec::with_capacity(end + 1 + CHUNK_HEADER_BYTES + list_len);
+	out.extend_from_slice(&bytes[..end]);
+	if end % 2 == 1 {
+		out.push(0);
+	}
+	out[4..8].copy_from_slice(&riff_len.to_le_bytes());
+
+	out.extend_from_slice(b"LIST");
+	out.extend_from_slice(&(list_len as u32).to_le_bytes());
+	out.extend_from_slice(b"INFO");
+	out.extend_from_slice(&chunk_type.bytes());
+	out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+	out.extend_from_slice(data);
+	if data.len() % 2 == 1 {
+		out.push(0);
+	}
+
+	Ok(out)
+}
+
+/// Data of the first `INFO` entry with `chunk_type` as id.
+pub fn find<'a>(bytes: &'a [u8], chunk_type: &str) -> Result<Option<&'a [u8]>, WavError> {
+	Ok(info(bytes)?
+		.into_iter()
+		.find(|entry| entry.id == chunk_type.as_bytes())
+		.map(|entry| entry.data(bytes)))
+}
+
+/// Ids of all `INFO` entries, in file order.
+pub fn chunk_types(bytes: &[u8]) -> Result<Vec<String>, WavError> {
+	Ok(info(bytes)?
+		.into_iter()
+		.map(|entry| String::from_utf8_lossy(&entry.id).into_owned())
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+
+	use super::*;
+
+	fn testing_wav() -> Vec<u8> {
+		let mut bytes = Vec::new();
+		bytes.extend_from_slice(b"RIFF\x27\0\0\0WAVE");
+		bytes.extend_from_slice(b"fmt \x10\0\0\0\x01\0\x01\0\x44\xac\0\0\x88\x58\x01\0\x02\0\x10\0");
+		bytes.extend_from_slice(b"data\x03\0\0\0\x01\x02\x03");
+		bytes
+	}
+
+	#[test]
+	fn test_chunks() {
+		let ids: Vec<_> = chunks(&testing_wav()).unwrap().iter().map(|c| c.id).collect();
+		assert_eq!(ids, [*b"fmt ", *b"data"]);
+		assert_eq!(chunks(b"\x89PNG\r\n\x1a\n"), Err(WavError::NotWav));
+
+		let mut short = testing_wav();
+		short.pop();
+		assert_eq!(chunks(&short), Err(WavError::Truncated(36)));
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let wav = testing_wav();
+		let out = append(&wav, &ChunkType::from_str("ruSt").unwrap(), b"hidden!").unwrap();
+
+		// the odd data chunk got its missing pad byte
+		assert!(out[8..].starts_with(&wav[8..]));
+		assert_eq!(out[wav.len()], 0);
+		assert_eq!(out.len() % 2, 0);
+		assert_eq!(u32::from_le_bytes(out[4..8].try_into().unwrap()) as usize, out.len() - 8);
+		assert_eq!(chunks(&out).unwrap().len(), 3);
+		assert_eq!(find(&out, "ruSt").unwrap(), Some(&b"hidden!"[..]));
+		assert_eq!(find(&out, "nOpe").unwrap(), None);
+
+		let twice = append(&out, &ChunkType::from_str("tWoo").unwrap(), b"").unwrap();
+		assert_eq!(chunk_types(&twice).unwrap(), ["ruSt", "tWoo"]);
+	}
+}